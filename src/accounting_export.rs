@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::error::Error;
+use chrono::{DateTime, Local};
+use serde::{Serialize, Deserialize};
+use crate::models::{Invoice, InvoiceItem};
+
+// Accounting packages we can produce sales import files for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AccountingSystem {
+    Xero,
+    Myob,
+}
+
+impl AccountingSystem {
+    pub const ALL: [AccountingSystem; 2] = [AccountingSystem::Xero, AccountingSystem::Myob];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AccountingSystem::Xero => "Xero",
+            AccountingSystem::Myob => "MYOB AccountRight",
+        }
+    }
+
+    // Xero imports CSV, MYOB imports tab-delimited text
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AccountingSystem::Xero => "csv",
+            AccountingSystem::Myob => "txt",
+        }
+    }
+}
+
+// Account and tax codes used when posting sales to one accounting system
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AccountMapping {
+    pub sales_account: String, // Default income account
+    pub gst_tax_code: String, // Lines that charge GST
+    pub gst_free_tax_code: String, // GST-free lines
+    #[serde(default)]
+    pub customer_accounts: HashMap<String, String>, // Customer code -> income account override
+}
+
+impl AccountMapping {
+    pub fn account_for(&self, customer_code: &str) -> &str {
+        match self.customer_accounts.get(customer_code) {
+            Some(account) if !account.trim().is_empty() => account.trim(),
+            _ => self.sales_account.trim(),
+        }
+    }
+
+    fn tax_code_for(&self, item: &InvoiceItem) -> &str {
+        if item.gst_free { &self.gst_free_tax_code } else { &self.gst_tax_code }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AccountingSettings {
+    pub xero: AccountMapping,
+    pub myob: AccountMapping,
+}
+
+impl Default for AccountingSettings {
+    fn default() -> Self {
+        Self {
+            xero: AccountMapping {
+                sales_account: "200".to_string(), // Xero's default "Sales" account
+                gst_tax_code: "GST on Income".to_string(),
+                gst_free_tax_code: "GST Free Income".to_string(),
+                customer_accounts: HashMap::new(),
+            },
+            myob: AccountMapping {
+                sales_account: "4-1000".to_string(),
+                gst_tax_code: "GST".to_string(),
+                gst_free_tax_code: "FRE".to_string(),
+                customer_accounts: HashMap::new(),
+            },
+        }
+    }
+}
+
+impl AccountingSettings {
+    pub fn mapping(&self, system: AccountingSystem) -> &AccountMapping {
+        match system {
+            AccountingSystem::Xero => &self.xero,
+            AccountingSystem::Myob => &self.myob,
+        }
+    }
+
+    pub fn mapping_mut(&mut self, system: AccountingSystem) -> &mut AccountMapping {
+        match system {
+            AccountingSystem::Xero => &mut self.xero,
+            AccountingSystem::Myob => &mut self.myob,
+        }
+    }
+}
+
+// Records that an invoice was included in an accounting export, so it is not posted twice
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExportRecord {
+    pub invoice_number: String,
+    pub system: AccountingSystem,
+    pub exported_at: DateTime<Local>,
+    pub filename: String,
+}
+
+const XERO_HEADERS: [&str; 17] = [
+    "*ContactName", "EmailAddress", "POAddressLine1", "POCountry", "*InvoiceNumber", "Reference",
+    "*InvoiceDate", "*DueDate", "Total", "InventoryItemCode", "*Description", "*Quantity",
+    "*UnitAmount", "Discount", "*AccountCode", "*TaxType", "Currency",
+];
+
+// Xero sales invoice import CSV: one row per line item, header fields repeated on each row.
+// Unit amounts are GST exclusive, so choose "Tax Exclusive" when importing.
+pub fn xero_csv(invoices: &[Invoice], mapping: &AccountMapping) -> Result<String, Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(XERO_HEADERS)?;
+    for invoice in invoices {
+        let account = mapping.account_for(&invoice.customer.code);
+        for item in &invoice.items {
+            let (quantity, unit_amount) = xero_quantity_and_unit(item);
+            writer.write_record([
+                invoice.customer.name.as_str(),
+                invoice.customer.email.as_str(),
+                invoice.customer.address.as_str(),
+                "Australia",
+                invoice.invoice_number.as_str(),
+                invoice.customer.code.as_str(),
+                &invoice.date.format("%d/%m/%Y").to_string(),
+                &invoice.due_date.format("%d/%m/%Y").to_string(),
+                &format!("{:.2}", invoice.total),
+                "",
+                item.description.as_str(),
+                &quantity,
+                &unit_amount,
+                "",
+                account,
+                mapping.tax_code_for(item),
+                "AUD",
+            ])?;
+        }
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+// Xero works each line out as quantity x unit amount, keeping up to 4 decimal places of the
+// unit amount. Rates that need more are sent as a single unit of the line amount instead,
+// so the imported line always matches ours.
+fn xero_quantity_and_unit(item: &InvoiceItem) -> (String, String) {
+    let unit = (item.rate * 10_000.0).round() / 10_000.0;
+    if ((unit * item.quantity as f64 * 100.0).round() - (item.amount * 100.0).round()).abs() < 0.5 {
+        let mut text = format!("{:.4}", unit);
+        while text.ends_with('0') && text.len() - text.find('.').unwrap_or(0) > 3 {
+            text.pop(); // Two decimal places at least, as before
+        }
+        (item.quantity.to_string(), text)
+    } else {
+        ("1".to_string(), format!("{:.2}", item.amount))
+    }
+}
+
+const MYOB_HEADERS: [&str; 14] = [
+    "Co./Last Name", "Card ID", "Invoice #", "Date", "Customer PO", "Inclusive", "Description",
+    "Account #", "Amount", "Inc-Tax Amount", "Tax Code", "GST Amount", "Sale Status", "Journal Memo",
+];
+
+// MYOB AccountRight service sales import: tab-delimited, one line per item, with a blank
+// line between sales. Amounts are GST exclusive ("Inclusive" left blank).
+pub fn myob_txt(invoices: &[Invoice], mapping: &AccountMapping) -> String {
+    let clean = |value: &str| value.replace(['\t', '\r', '\n'], " ");
+    let mut out = MYOB_HEADERS.join("\t");
+    out.push_str("\r\n");
+    for invoice in invoices {
+        let account = mapping.account_for(&invoice.customer.code);
+        for item in &invoice.items {
+            let fields = [
+                clean(&invoice.customer.name),
+                clean(&invoice.customer.code),
+                clean(&invoice.invoice_number),
+                invoice.date.format("%d/%m/%Y").to_string(),
+                String::new(),
+                String::new(),
+                clean(&item.description),
+                account.to_string(),
+                format!("{:.2}", item.amount),
+                format!("{:.2}", item.amount + item.gst),
+                mapping.tax_code_for(item).to_string(),
+                format!("{:.2}", item.gst),
+                "I".to_string(), // Invoice (as opposed to quote or order)
+                clean(&format!("Sale; {}", invoice.customer.name)),
+            ];
+            out.push_str(&fields.join("\t"));
+            out.push_str("\r\n");
+        }
+        out.push_str("\r\n");
+    }
+    out
+}
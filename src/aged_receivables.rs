@@ -0,0 +1,101 @@
+use chrono::NaiveDate;
+use crate::models::Invoice;
+use crate::spreadsheet::{CellValue, Sheet};
+
+// Days past the due date, in the order shown on the report
+pub const BUCKET_LABELS: [&str; 5] = ["Current", "1-30 Days", "31-60 Days", "61-90 Days", "90+ Days"];
+
+// Bucket for a balance `days_past_due` days after the due date (zero or less is current)
+pub fn bucket_for(days_past_due: i64) -> usize {
+    match days_past_due {
+        d if d <= 0 => 0,
+        1..=30 => 1,
+        31..=60 => 2,
+        61..=90 => 3,
+        _ => 4,
+    }
+}
+
+// Outstanding balance for one customer
+#[derive(Clone)]
+pub struct AgedRow {
+    pub customer_code: String,
+    pub customer_name: String,
+    pub buckets: [f64; 5],
+    pub total: f64,
+    pub invoice_count: usize, // Invoices with a balance owing
+}
+
+#[derive(Clone)]
+pub struct AgedReceivables {
+    pub as_at: NaiveDate,
+    pub rows: Vec<AgedRow>, // Customers owing money, by name
+    pub totals: [f64; 5],
+    pub total: f64,
+}
+
+// Balances owing at the end of `as_at`, bucketed by days past each invoice's due date
+pub fn build_aged_receivables<'a>(invoices: impl IntoIterator<Item = &'a Invoice>, as_at: NaiveDate) -> AgedReceivables {
+    let mut rows: Vec<AgedRow> = Vec::new();
+    let mut totals = [0.0; 5];
+    for invoice in invoices {
+        let outstanding = invoice.balance_as_at(as_at);
+        if outstanding <= 0.005 {
+            continue;
+        }
+        let bucket = bucket_for((as_at - invoice.due_date.date_naive()).num_days());
+        let idx = match rows.iter().position(|r| r.customer_code == invoice.customer.code) {
+            Some(idx) => idx,
+            None => {
+                rows.push(AgedRow {
+                    customer_code: invoice.customer.code.clone(),
+                    customer_name: invoice.customer.name.clone(),
+                    buckets: [0.0; 5],
+                    total: 0.0,
+                    invoice_count: 0,
+                });
+                rows.len() - 1
+            }
+        };
+        let row = &mut rows[idx];
+        row.buckets[bucket] += outstanding;
+        row.total += outstanding;
+        row.invoice_count += 1;
+        totals[bucket] += outstanding;
+    }
+    // Round away floating point noise from adding up many balances
+    let cents = |amount: f64| (amount * 100.0).round() / 100.0;
+    for row in &mut rows {
+        row.buckets = row.buckets.map(cents);
+        row.total = cents(row.total);
+    }
+    rows.sort_by_key(|r| r.customer_name.to_lowercase());
+    let total = cents(totals.iter().sum());
+    AgedReceivables { as_at, rows, totals: totals.map(cents), total }
+}
+
+pub fn aged_receivables_sheet(report: &AgedReceivables) -> Sheet {
+    let mut headers = vec!["Customer Code", "Customer Name", "Invoices"];
+    headers.extend(BUCKET_LABELS);
+    headers.push("Total");
+    let row = |code: &str, name: &str, count: usize, buckets: &[f64; 5], total: f64| {
+        let mut cells = vec![
+            CellValue::Text(code.to_string()),
+            CellValue::Text(name.to_string()),
+            CellValue::Number(count as f64),
+        ];
+        cells.extend(buckets.iter().map(|amount| CellValue::Money(*amount)));
+        cells.push(CellValue::Money(total));
+        cells
+    };
+    let mut rows: Vec<Vec<CellValue>> = report.rows.iter()
+        .map(|r| row(&r.customer_code, &r.customer_name, r.invoice_count, &r.buckets, r.total))
+        .collect();
+    let invoice_count = report.rows.iter().map(|r| r.invoice_count).sum();
+    rows.push(row("", "Total", invoice_count, &report.totals, report.total));
+    Sheet {
+        name: format!("Aged Receivables {}", report.as_at.format("%Y-%m-%d")),
+        headers,
+        rows,
+    }
+}
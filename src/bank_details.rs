@@ -0,0 +1,15 @@
+// Bank details used by the payment instructions, remittance slip, QR code and e-invoices
+pub const BANK_NAME: &str = "Commonwealth Bank Tuggeranong";
+pub const ACCOUNT_NAME: &str = "James Matthews";
+pub const BSB: &str = "062692";
+pub const ACCOUNT_NUMBER: &str = "33455315";
+
+// "Please pay to..." lines printed on invoices and statements
+pub fn payment_instructions() -> Vec<String> {
+    vec![
+        format!("Please Pay to by bank transfer to our bank account {}.", BANK_NAME),
+        format!("Account Name - {}", ACCOUNT_NAME),
+        format!("BSB - {}", BSB),
+        format!("Acct Number - {}", ACCOUNT_NUMBER),
+    ]
+}
@@ -0,0 +1,210 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use chrono::{Datelike, NaiveDate};
+use crate::models::Invoice;
+
+// One line from a bank statement. Credits (money received) have a positive amount.
+#[derive(Clone, Debug)]
+pub struct BankTransaction {
+    pub date: NaiveDate,
+    pub amount: f64,
+    pub description: String,
+    pub bank_id: String, // Bank's transaction ID (OFX FITID / QIF check number) when available
+}
+
+impl BankTransaction {
+    // Reference stored on payments recorded from this transaction, used to spot re-imports
+    pub fn payment_reference(&self) -> String {
+        if self.bank_id.is_empty() {
+            format!("Bank: {}", self.description)
+        } else {
+            format!("Bank: {} [{}]", self.description, self.bank_id)
+        }
+    }
+}
+
+fn parse_amount(value: &str) -> Option<f64> {
+    value.trim().trim_matches('"').replace([',', '$', ' '], "").parse::<f64>().ok()
+}
+
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    let value = value.trim().trim_matches('"');
+    ["%d/%m/%Y", "%d/%m/%y", "%Y-%m-%d", "%d-%m-%Y"]
+        .iter()
+        .filter_map(|format| NaiveDate::parse_from_str(value, format).ok())
+        .find(|date| date.year() >= 1970) // "%Y" also accepts two digit years such as "26"
+}
+
+// CommBank CSV export: no header, columns are date, amount, description, balance
+pub fn parse_commbank_csv(text: &str) -> Result<Vec<BankTransaction>, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let mut transactions = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let (date, amount) = match (record.get(0).and_then(parse_date), record.get(1).and_then(parse_amount)) {
+            (Some(date), Some(amount)) => (date, amount),
+            _ => continue, // Header or summary lines
+        };
+        transactions.push(BankTransaction {
+            date,
+            amount,
+            description: record.get(2).unwrap_or_default().trim().to_string(),
+            bank_id: String::new(),
+        });
+    }
+    Ok(transactions)
+}
+
+// OFX/QFX: reads the <STMTTRN> blocks. Works for both the SGML (OFX 1.x, unclosed tags)
+// and XML (OFX 2.x) variants since only the leaf values are needed.
+pub fn parse_ofx(text: &str) -> Result<Vec<BankTransaction>, Box<dyn Error>> {
+    let mut transactions = Vec::new();
+    for block in text.split("<STMTTRN>").skip(1) {
+        let block = block.split("</STMTTRN>").next().unwrap_or_default();
+        let field = |tag: &str| -> String {
+            block.split(&format!("<{}>", tag)).nth(1)
+                .map(|rest| rest.split('<').next().unwrap_or_default().trim().to_string())
+                .unwrap_or_default()
+        };
+        let date_str = field("DTPOSTED");
+        let date = date_str.get(..8).and_then(|d| NaiveDate::parse_from_str(d, "%Y%m%d").ok())
+            .ok_or_else(|| format!("Invalid OFX date \"{}\"", date_str))?;
+        let amount = parse_amount(&field("TRNAMT")).ok_or("Invalid OFX amount")?;
+        let description = [field("NAME"), field("MEMO")]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        transactions.push(BankTransaction { date, amount, description, bank_id: field("FITID") });
+    }
+    if transactions.is_empty() && !text.contains("<OFX>") {
+        return Err("Not an OFX file.".into());
+    }
+    Ok(transactions)
+}
+
+// QIF: records of single-letter fields terminated by "^"
+pub fn parse_qif(text: &str) -> Result<Vec<BankTransaction>, Box<dyn Error>> {
+    let mut transactions = Vec::new();
+    let (mut date, mut amount) = (None, None);
+    let (mut payee, mut memo, mut number) = (String::new(), String::new(), String::new());
+    for line in text.lines() {
+        let line = line.trim_end();
+        let (code, value) = match line.chars().next() {
+            Some(c) => (c, line[c.len_utf8()..].trim()),
+            None => continue,
+        };
+        match code {
+            'D' => date = parse_date(&value.replace('\'', "/")),
+            'T' | 'U' => amount = parse_amount(value),
+            'P' => payee = value.to_string(),
+            'M' => memo = value.to_string(),
+            'N' => number = value.to_string(),
+            '^' => {
+                if let (Some(date), Some(amount)) = (date, amount) {
+                    let description = [payee.as_str(), memo.as_str()]
+                        .into_iter()
+                        .filter(|s| !s.is_empty())
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    transactions.push(BankTransaction { date, amount, description, bank_id: number.clone() });
+                }
+                (date, amount) = (None, None);
+                payee.clear();
+                memo.clear();
+                number.clear();
+            }
+            _ => {} // Header lines (!Type:Bank) and fields we don't use
+        }
+    }
+    Ok(transactions)
+}
+
+// Reads a statement file, choosing the parser from the file extension
+pub fn read_statement(path: &Path) -> Result<Vec<BankTransaction>, Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    let text = String::from_utf8_lossy(&bytes);
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
+    match extension.as_str() {
+        "ofx" | "qfx" => parse_ofx(&text),
+        "qif" => parse_qif(&text),
+        "csv" => parse_commbank_csv(&text),
+        _ => Err(format!("Unsupported statement format \".{}\". Use CSV, OFX or QIF.", extension).into()),
+    }
+}
+
+// How a suggested match was found, strongest first
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MatchConfidence {
+    ReferenceAndAmount, // Invoice number in the description and the amount equals the balance
+    Reference, // Invoice number in the description, different amount (part payment)
+    Amount, // Only one open invoice has this balance
+}
+
+impl MatchConfidence {
+    pub fn label(&self) -> &'static str {
+        match self {
+            MatchConfidence::ReferenceAndAmount => "Reference + amount",
+            MatchConfidence::Reference => "Reference",
+            MatchConfidence::Amount => "Amount only",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SuggestedMatch {
+    pub invoice_number: String,
+    pub amount: f64, // Amount to record, never more than the invoice balance
+    pub confidence: MatchConfidence,
+}
+
+// True if `number` appears in `text` as a whole token (so "AB7" does not match "AB75")
+fn mentions_invoice(text: &str, number: &str) -> bool {
+    let text = text.to_uppercase();
+    let number = number.to_uppercase();
+    text.match_indices(&number).any(|(idx, _)| {
+        let before = text[..idx].chars().next_back();
+        let after = text[idx + number.len()..].chars().next();
+        !before.is_some_and(|c| c.is_ascii_alphanumeric()) && !after.is_some_and(|c| c.is_ascii_alphanumeric())
+    })
+}
+
+// Suggests an open invoice for each credit. Returns one entry per transaction (None for
+// debits and credits without a match). Each invoice's balance is only allocated once.
+pub fn suggest_matches(transactions: &[BankTransaction], open_invoices: &[Invoice]) -> Vec<Option<SuggestedMatch>> {
+    let mut remaining: Vec<f64> = open_invoices.iter().map(|inv| inv.balance_due()).collect();
+    let same = |a: f64, b: f64| (a - b).abs() < 0.005;
+
+    // Reference matches first so a matching amount elsewhere can't take the invoice
+    let mut suggestions: Vec<Option<SuggestedMatch>> = transactions.iter().map(|tx| {
+        if tx.amount <= 0.0 {
+            return None;
+        }
+        let idx = open_invoices.iter().enumerate()
+            .position(|(i, inv)| remaining[i] > 0.005 && mentions_invoice(&tx.description, &inv.invoice_number))?;
+        let amount = tx.amount.min(remaining[idx]);
+        let confidence = if same(tx.amount, remaining[idx]) { MatchConfidence::ReferenceAndAmount } else { MatchConfidence::Reference };
+        remaining[idx] -= amount;
+        Some(SuggestedMatch { invoice_number: open_invoices[idx].invoice_number.clone(), amount, confidence })
+    }).collect();
+
+    for (tx, suggestion) in transactions.iter().zip(suggestions.iter_mut()) {
+        if suggestion.is_some() || tx.amount <= 0.0 {
+            continue;
+        }
+        let candidates: Vec<usize> = (0..open_invoices.len()).filter(|&i| same(remaining[i], tx.amount)).collect();
+        if let [idx] = candidates[..] {
+            remaining[idx] = 0.0;
+            *suggestion = Some(SuggestedMatch {
+                invoice_number: open_invoices[idx].invoice_number.clone(),
+                amount: tx.amount,
+                confidence: MatchConfidence::Amount,
+            });
+        }
+    }
+    suggestions
+}
@@ -0,0 +1,228 @@
+// GST figures for the Business Activity Statement (BAS).
+//
+// On the accruals basis sales count when invoiced, less credits given in the period. On
+// the cash basis they count when paid, so each payment brings in its share of the
+// invoice's GST. Invoices marked paid without payment records are treated as paid on
+// their due date, as on statements. Each amount is split between GST, GST-free sales and
+// taxable sales in the same proportions as the invoice's recorded line GST.
+use chrono::{Datelike, Months, NaiveDate};
+use crate::models::{Invoice, PaymentKind};
+use crate::spreadsheet::{CellValue, Sheet};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BasBasis {
+    Cash,
+    Accruals,
+}
+
+impl BasBasis {
+    pub const ALL: [BasBasis; 2] = [BasBasis::Accruals, BasBasis::Cash];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            BasBasis::Cash => "Cash",
+            BasBasis::Accruals => "Accruals",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PeriodLength {
+    Month,
+    Quarter,
+}
+
+impl PeriodLength {
+    pub const ALL: [PeriodLength; 2] = [PeriodLength::Quarter, PeriodLength::Month];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PeriodLength::Month => "Monthly",
+            PeriodLength::Quarter => "Quarterly",
+        }
+    }
+}
+
+// A reporting period, dates inclusive
+#[derive(Clone, Debug, PartialEq)]
+pub struct BasPeriod {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+impl BasPeriod {
+    // The month or quarter (Jan-Mar, Apr-Jun, Jul-Sep, Oct-Dec) containing `date`
+    pub fn containing(date: NaiveDate, length: PeriodLength) -> BasPeriod {
+        let (first_month, months) = match length {
+            PeriodLength::Month => (date.month(), 1),
+            PeriodLength::Quarter => ((date.month() - 1) / 3 * 3 + 1, 3),
+        };
+        let from = NaiveDate::from_ymd_opt(date.year(), first_month, 1).unwrap_or(date);
+        let to = from.checked_add_months(Months::new(months)).and_then(|d| d.pred_opt()).unwrap_or(from);
+        BasPeriod { from, to }
+    }
+
+    // The `count` most recent periods up to the one containing `date`, newest first
+    pub fn recent(date: NaiveDate, length: PeriodLength, count: usize) -> Vec<BasPeriod> {
+        let mut periods = Vec::new();
+        let mut period = BasPeriod::containing(date, length);
+        for _ in 0..count {
+            let previous = period.from.pred_opt();
+            periods.push(period);
+            match previous {
+                Some(day) => period = BasPeriod::containing(day, length),
+                None => break,
+            }
+        }
+        periods
+    }
+
+    // "Jul-Sep 2026" or "Aug 2026"
+    pub fn label(&self) -> String {
+        if self.from.month() == self.to.month() && self.from.year() == self.to.year() {
+            self.from.format("%b %Y").to_string()
+        } else {
+            format!("{}-{}", self.from.format("%b"), self.to.format("%b %Y"))
+        }
+    }
+}
+
+// An invoice, payment or credit counted in the period
+#[derive(Clone)]
+pub struct BasLine {
+    pub date: NaiveDate,
+    pub invoice_number: String,
+    pub customer_name: String,
+    pub description: String,
+    pub amount: f64, // Including GST; negative for credits
+    pub gst: f64,
+    pub gst_free: f64, // Part of the amount from GST-free lines
+}
+
+#[derive(Clone)]
+pub struct BasReport {
+    pub period: BasPeriod,
+    pub basis: BasBasis,
+    pub total_sales: f64, // G1, including GST
+    pub gst_free_sales: f64, // G3
+    pub taxable_sales: f64, // G1 less G3, including GST
+    pub gst_on_sales: f64, // 1A
+    pub lines: Vec<BasLine>, // Contributing entries, by date
+}
+
+fn cents(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+// The share of `amount` of `invoice` that is `part` of the invoice total
+fn share(invoice: &Invoice, amount: f64, part: f64) -> f64 {
+    if invoice.total > 0.0 {
+        cents(amount * part / invoice.total) + 0.0 // Never -0.00 on credits
+    } else {
+        0.0
+    }
+}
+
+pub fn build_bas_report<'a>(invoices: impl IntoIterator<Item = &'a Invoice>, period: &BasPeriod, basis: BasBasis) -> BasReport {
+    let in_period = |date: NaiveDate| date >= period.from && date <= period.to;
+    let mut lines = Vec::new();
+    for invoice in invoices {
+        let mut line = |date: NaiveDate, description: String, amount: f64| {
+            lines.push(BasLine {
+                date,
+                invoice_number: invoice.invoice_number.clone(),
+                customer_name: invoice.customer.name.clone(),
+                description,
+                amount: cents(amount),
+                gst: share(invoice, amount, invoice.gst),
+                gst_free: share(invoice, amount, invoice.gst_free_subtotal()),
+            });
+        };
+        match basis {
+            BasBasis::Accruals => {
+                let issued = invoice.date.date_naive();
+                if in_period(issued) {
+                    line(issued, "Invoice issued".to_string(), invoice.total);
+                }
+                for credit in invoice.payments.iter().filter(|p| p.kind == PaymentKind::Credit) {
+                    let date = credit.date.date_naive();
+                    if in_period(date) {
+                        line(date, with_reference("Credit", &credit.reference), -credit.amount);
+                    }
+                }
+            }
+            BasBasis::Cash => {
+                for payment in invoice.payments.iter().filter(|p| p.kind == PaymentKind::Payment) {
+                    let date = payment.date.date_naive();
+                    if in_period(date) {
+                        line(date, with_reference("Payment received", &payment.reference), payment.amount);
+                    }
+                }
+                let unrecorded = if invoice.paid { (invoice.total - invoice.amount_paid()).max(0.0) } else { 0.0 };
+                let due = invoice.due_date.date_naive();
+                if unrecorded > 0.0 && in_period(due) {
+                    line(due, "Marked paid (no payment date, counted on due date)".to_string(), unrecorded);
+                }
+            }
+        }
+    }
+    lines.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.invoice_number.cmp(&b.invoice_number)));
+
+    let total_sales = cents(lines.iter().map(|l| l.amount).sum());
+    let gst_on_sales = cents(lines.iter().map(|l| l.gst).sum());
+    let gst_free_sales = cents(lines.iter().map(|l| l.gst_free).sum());
+    BasReport {
+        period: period.clone(),
+        basis,
+        total_sales,
+        gst_free_sales,
+        taxable_sales: cents(total_sales - gst_free_sales),
+        gst_on_sales,
+        lines,
+    }
+}
+
+fn with_reference(description: &str, reference: &str) -> String {
+    if reference.is_empty() {
+        description.to_string()
+    } else {
+        format!("{} ({})", description, reference)
+    }
+}
+
+// Summary rows as (BAS label, description, amount)
+pub fn bas_summary(report: &BasReport) -> [(&'static str, &'static str, f64); 4] {
+    [
+        ("G1", "Total sales (including GST)", report.total_sales),
+        ("G3", "Other GST-free sales", report.gst_free_sales),
+        ("", "Taxable sales (including GST)", report.taxable_sales),
+        ("1A", "GST on sales", report.gst_on_sales),
+    ]
+}
+
+// The contributing entries followed by the summary, for checking against the BAS
+pub fn bas_sheet(report: &BasReport) -> Sheet {
+    let text = |value: &str| CellValue::Text(value.to_string());
+    let mut rows: Vec<Vec<CellValue>> = report.lines.iter().map(|line| vec![
+        CellValue::Date(line.date),
+        text(&line.invoice_number),
+        text(&line.customer_name),
+        text(&line.description),
+        CellValue::Money(line.amount),
+        CellValue::Money(line.gst),
+        CellValue::Money(line.gst_free),
+    ]).collect();
+    // Summary rows keep the same number of columns, which CSV readers expect
+    rows.push(vec![text(""); 7]);
+    let mut heading = vec![text(&format!("{} basis, {} to {}", report.basis.label(), report.period.from, report.period.to))];
+    heading.resize(7, text(""));
+    rows.push(heading);
+    for (label, description, amount) in bas_summary(report) {
+        rows.push(vec![text(""), text(label), text(description), text(""), CellValue::Money(amount), text(""), text("")]);
+    }
+    Sheet {
+        name: "BAS Summary".to_string(),
+        headers: vec!["Date", "Invoice Number", "Customer", "Description", "Amount (inc GST)", "GST", "GST-free"],
+        rows,
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use chrono::Local;
+use zip::write::SimpleFileOptions;
+use crate::models::{Company, Invoice};
+use crate::pdf_generator::{generate_merged_pdf, generate_pdf, generate_pdf_bytes, PdfOptions};
+
+// Default pattern matches the file name suggested by the single "PDF" button
+pub const DEFAULT_FILENAME_PATTERN: &str = "Invoice-{number}.pdf";
+
+// How the exported invoices are written to the folder
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum BatchOutput {
+    #[default]
+    SeparateFiles, // One PDF per invoice
+    MergedPdf, // All invoices in a single PDF
+    Zip, // One PDF per invoice, packed into a ZIP archive
+}
+
+#[derive(Clone)]
+pub struct BatchExportSettings {
+    pub folder: PathBuf,
+    pub filename_pattern: String,
+    pub output: BatchOutput,
+    pub pdf_options: PdfOptions,
+}
+
+// Messages sent from the export thread back to the GUI
+pub enum BatchProgress {
+    Working { done: usize, total: usize },
+    Finished(Result<String, String>), // Summary message or error
+}
+
+// Expands the filename pattern for an invoice.
+// Supported placeholders: {number}, {code}, {customer}, {date} (issue date) and {due}.
+pub fn format_filename(pattern: &str, invoice: &Invoice) -> String {
+    let name = pattern
+        .replace("{number}", &invoice.invoice_number)
+        .replace("{code}", &invoice.customer.code)
+        .replace("{customer}", invoice.customer.name.trim())
+        .replace("{date}", &invoice.date.format("%Y-%m-%d").to_string())
+        .replace("{due}", &invoice.due_date.format("%Y-%m-%d").to_string());
+
+    // Strip characters that are not allowed in file names on common platforms
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| if matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '\n' | '\r') { '_' } else { c })
+        .collect();
+    if !sanitized.to_lowercase().ends_with(".pdf") {
+        sanitized.push_str(".pdf");
+    }
+    sanitized
+}
+
+// Makes sure two invoices never write to the same file name, and that nothing already
+// in `folder` is overwritten (no folder for names inside a ZIP archive)
+fn unique_filename(name: String, used: &mut HashSet<String>, folder: Option<&Path>) -> String {
+    let mut is_free = |candidate: &str| {
+        !folder.is_some_and(|folder| folder.join(candidate).exists()) && used.insert(candidate.to_string())
+    };
+    if is_free(&name) {
+        return name;
+    }
+    let (stem, extension) = match name.rfind('.') {
+        Some(dot) => name.split_at(dot),
+        None => (name.as_str(), ""),
+    };
+    let mut counter = 2;
+    loop {
+        let candidate = format!("{}-{}{}", stem, counter, extension);
+        if is_free(&candidate) {
+            return candidate;
+        }
+        counter += 1;
+    }
+}
+
+// Writes the invoices according to `settings`, reporting progress after each invoice
+pub fn export_invoices(
+    invoices: &[Invoice],
+    company: &Company,
+    settings: &BatchExportSettings,
+    progress: &Sender<BatchProgress>,
+) -> Result<String, Box<dyn Error>> {
+    if invoices.is_empty() {
+        return Err("No invoices match the selected filter.".into());
+    }
+    fs::create_dir_all(&settings.folder)?;
+    let total = invoices.len();
+    let timestamp = Local::now().format("%Y%m%d-%H%M%S");
+    let mut used_names = HashSet::new();
+
+    match settings.output {
+        BatchOutput::SeparateFiles => {
+            for (idx, invoice) in invoices.iter().enumerate() {
+                let filename = unique_filename(format_filename(&settings.filename_pattern, invoice), &mut used_names, Some(&settings.folder));
+                let path = settings.folder.join(filename);
+                generate_pdf(
+                    invoice,
+                    company,
+                    &path,
+                    &settings.pdf_options,
+                )?;
+                let _ = progress.send(BatchProgress::Working { done: idx + 1, total });
+            }
+            Ok(format!("Exported {} invoice PDFs to {}", total, settings.folder.display()))
+        }
+        BatchOutput::MergedPdf => {
+            let path = settings.folder.join(unique_filename(format!("Invoices-{}.pdf", timestamp), &mut used_names, Some(&settings.folder)));
+            generate_merged_pdf(
+                invoices,
+                company,
+                &path,
+                &settings.pdf_options,
+            )?;
+            let _ = progress.send(BatchProgress::Working { done: total, total });
+            Ok(format!("Exported {} invoices to {}", total, path.display()))
+        }
+        BatchOutput::Zip => {
+            let path = settings.folder.join(unique_filename(format!("Invoices-{}.zip", timestamp), &mut used_names, Some(&settings.folder)));
+            let mut archive = zip::ZipWriter::new(File::create(&path)?);
+            for (idx, invoice) in invoices.iter().enumerate() {
+                let filename = unique_filename(format_filename(&settings.filename_pattern, invoice), &mut used_names, None);
+                let bytes = generate_pdf_bytes(
+                    invoice,
+                    company,
+                    &settings.pdf_options,
+                )?;
+                archive.start_file(filename, SimpleFileOptions::default())?;
+                archive.write_all(&bytes)?;
+                let _ = progress.send(BatchProgress::Working { done: idx + 1, total });
+            }
+            archive.finish()?;
+            Ok(format!("Exported {} invoices to {}", total, path.display()))
+        }
+    }
+}
+
+// Runs the export on a background thread so the GUI stays responsive
+pub fn spawn_export(invoices: Vec<Invoice>, company: Company, settings: BatchExportSettings) -> Receiver<BatchProgress> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = sender.send(BatchProgress::Working { done: 0, total: invoices.len() });
+        let result = export_invoices(&invoices, &company, &settings, &sender).map_err(|e| e.to_string());
+        let _ = sender.send(BatchProgress::Finished(result));
+    });
+    receiver
+}
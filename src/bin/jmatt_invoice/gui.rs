@@ -0,0 +1,4706 @@
+use jmatt_invoice::customer_import::{read_csv, ColumnMapping, CsvData, CustomerField, ImportRow};
+use jmatt_invoice::spreadsheet::{ExportFormat, ExportKind};
+use jmatt_invoice::accounting_export::{AccountingSettings, AccountingSystem};
+use jmatt_invoice::bank_import::{read_statement, suggest_matches, BankTransaction, MatchConfidence};
+use jmatt_invoice::interchange::{read_interchange, write_interchange, ImportPlan, InterchangeSelection, Resolution};
+use jmatt_invoice::batch_export::{spawn_export, BatchExportSettings, BatchOutput, BatchProgress, DEFAULT_FILENAME_PATTERN};
+use jmatt_invoice::email::{spawn_bulk_email, BulkEmailProgress, EmailDraft, EmailSettings, SmtpSecurity, PASSWORD_ENV_VAR, PLACEHOLDERS};
+use jmatt_invoice::reminders::{stage_label, PlannedReminder, ReminderRule, ReminderSettings, REMINDER_PLACEHOLDERS};
+use jmatt_invoice::late_fees::{quote_late_fee, LateFeeMethod, LateFeeSettings, LateFeeTarget};
+use jmatt_invoice::aged_receivables::BUCKET_LABELS;
+use jmatt_invoice::bas::{bas_summary, BasBasis, BasPeriod, PeriodLength};
+use jmatt_invoice::dashboard::{DashboardStats, MonthRevenue};
+use jmatt_invoice::search::customer_matches;
+use jmatt_invoice::database::{Database, InvoiceFilter, InvoiceSort, InvoiceStatusFilter};
+use jmatt_invoice::models::{Company, Customer, Invoice, InvoiceItem, Payment, PaymentKind, SentEmail};
+use jmatt_invoice::statement::Statement;
+use jmatt_invoice::pdf_generator::{standard_font_advance, DrawOp, PageLayout, PdfOptions, FONT_SIZE, PAGE_HEIGHT_MM, PAGE_WIDTH_MM};
+use jmatt_invoice::utils::{open_file, print_file};
+use egui::{CentralPanel, Context, SidePanel, TopBottomPanel, Window, ViewportCommand, TextEdit, Color32, ScrollArea, Grid, RichText, Id};
+use egui::{pos2, vec2, Align2, FontId, Rect, Sense, Stroke, Ui};
+use chrono::{Datelike, Local, Months, NaiveDate};
+use rfd::FileDialog;
+use std::collections::HashSet;
+use std::error::Error; // Import Error trait
+use std::path::PathBuf;
+use std::sync::mpsc::Receiver;
+
+// Function to run the GUI
+pub fn run() -> Result<(), Box<dyn Error>> { // Return Box<dyn Error> for compatibility
+    let options = eframe::NativeOptions::default();
+    // Use Ok(...) and ? to handle the error type conversion
+    Ok(eframe::run_native(
+        "JMATT CLEANING Invoice Manager", // Changed window title
+        options,
+        Box::new(|cc| Ok(Box::new(KmattInvoiceApp::new(cc)))),
+    )?)
+}
+
+#[derive(Default, Clone)]
+pub struct AddCustomerState {
+    name: String,
+    address: String,
+    phone: String,
+    contact_person: String,
+    contact_phone: String,
+    email: String,
+    code: String,
+    abn: String,
+    error_message: Option<String>,
+}
+
+#[derive(Default, Clone)]
+pub struct EditCustomerState {
+    original_name: String,
+    name: String,
+    address: String,
+    phone: String,
+    contact_person: String,
+    contact_phone: String,
+    email: String,
+    code: String,
+    abn: String,
+    error_message: Option<String>,
+}
+
+#[derive(Default, Clone)]
+pub struct InvoiceItemState {
+    description: String,
+    quantity_str: String,
+    rate_str: String,
+    gst_free: bool, // Only offered once the company is registered for GST
+}
+
+#[derive(Clone)]
+pub struct CreateInvoiceState {
+    customer_code: String,
+    customer_name: String,
+    items: Vec<InvoiceItemState>,
+    notes: String,
+    due_date_str: String,
+    error_message: Option<String>,
+}
+
+impl Default for CreateInvoiceState {
+    fn default() -> Self {
+        Self {
+            customer_code: String::new(),
+            customer_name: String::new(),
+            items: vec![InvoiceItemState::default()],
+            notes: String::new(),
+            due_date_str: Local::now().date_naive().format("%Y-%m-%d").to_string(),
+            error_message: None,
+        }
+    }
+}
+
+// Added state for editing invoices
+#[derive(Clone)]
+pub struct EditInvoiceState {
+    original_invoice_number: String,
+    // Removed unused field: customer_code: String, 
+    customer_name: String,
+    items: Vec<InvoiceItemState>,
+    notes: String,
+    due_date_str: String,
+    paid: bool, // Allow editing paid status?
+    error_message: Option<String>,
+}
+
+impl Default for EditInvoiceState {
+    fn default() -> Self {
+        Self {
+            original_invoice_number: String::new(),
+            // Removed unused field: customer_code: String::new(),
+            customer_name: String::new(),
+            items: vec![InvoiceItemState::default()],
+            notes: String::new(),
+            due_date_str: Local::now().date_naive().format("%Y-%m-%d").to_string(),
+            paid: false,
+            error_message: None,
+        }
+    }
+}
+
+// State for the customer account statement window
+#[derive(Clone)]
+pub struct StatementState {
+    customer_code: String,
+    customer_name: String,
+    from_date_str: String,
+    to_date_str: String,
+    preview: Option<Statement>,
+    error_message: Option<String>,
+}
+
+impl Default for StatementState {
+    fn default() -> Self {
+        let today = Local::now().date_naive();
+        Self {
+            customer_code: String::new(),
+            customer_name: String::new(),
+            from_date_str: (today - chrono::Duration::days(90)).format("%Y-%m-%d").to_string(),
+            to_date_str: today.format("%Y-%m-%d").to_string(),
+            preview: None,
+            error_message: None,
+        }
+    }
+}
+
+// State for recording a payment or credit against an invoice
+#[derive(Clone)]
+pub struct RecordPaymentState {
+    invoice_number: String,
+    balance_due: f64,
+    amount_str: String,
+    date_str: String,
+    kind: PaymentKind,
+    reference: String,
+    error_message: Option<String>,
+}
+
+impl Default for RecordPaymentState {
+    fn default() -> Self {
+        Self {
+            invoice_number: String::new(),
+            balance_due: 0.0,
+            amount_str: String::new(),
+            date_str: Local::now().date_naive().format("%Y-%m-%d").to_string(),
+            kind: PaymentKind::Payment,
+            reference: String::new(),
+            error_message: None,
+        }
+    }
+}
+
+// State for the PDF preview window
+#[derive(Clone)]
+pub struct PdfPreviewState {
+    invoice_number: String,
+    pages: Vec<PageLayout>,
+    options: PdfOptions, // Options the pages were laid out with
+    current_page: usize,
+    zoom: f32,
+    error_message: Option<String>,
+}
+
+impl Default for PdfPreviewState {
+    fn default() -> Self {
+        Self {
+            invoice_number: String::new(),
+            pages: Vec::new(),
+            options: PdfOptions::default(),
+            current_page: 0,
+            zoom: 1.0,
+            error_message: None,
+        }
+    }
+}
+
+// Points per millimetre (1pt = 0.3527mm), so a zoom of 1.0 shows the page at its printed size
+const POINTS_PER_MM: f32 = 72.0 / 25.4;
+
+// Paints one laid-out page onto a white sheet. egui's own fonts are the ones embedded in
+// PDF/A output; for the standard fonts each glyph is placed at its Helvetica/Courier advance.
+fn paint_page(ui: &mut egui::Ui, page: &PageLayout, zoom: f32, embedded_fonts: bool) {
+    let scale = POINTS_PER_MM * zoom;
+    let size = egui::vec2(PAGE_WIDTH_MM * scale, PAGE_HEIGHT_MM * scale);
+    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, Color32::WHITE);
+    // Page coordinates start at the bottom-left corner, screen coordinates at the top-left
+    let to_screen = |x: f32, y: f32| egui::pos2(rect.left() + x * scale, rect.top() + (PAGE_HEIGHT_MM - y) * scale);
+    for op in page {
+        match op {
+            DrawOp::Text { x, y, text, mono } => {
+                let font = if *mono {
+                    egui::FontId::monospace(FONT_SIZE * zoom)
+                } else {
+                    egui::FontId::proportional(FONT_SIZE * zoom)
+                };
+                if embedded_fonts {
+                    painter.text(to_screen(*x, *y), egui::Align2::LEFT_BOTTOM, text, font, Color32::BLACK);
+                } else {
+                    let mut pos = to_screen(*x, *y);
+                    for c in text.chars() {
+                        painter.text(pos, egui::Align2::LEFT_BOTTOM, c, font.clone(), Color32::BLACK);
+                        pos.x += standard_font_advance(c, *mono) * zoom;
+                    }
+                }
+            }
+            DrawOp::FilledRect { x, y, width, height } => {
+                let min = to_screen(*x, y + height);
+                let max = to_screen(x + width, *y);
+                painter.rect_filled(egui::Rect::from_min_max(min, max), 0.0, Color32::BLACK);
+            }
+            DrawOp::DashedLine { x1, y1, x2, y2 } => {
+                let points = [to_screen(*x1, *y1), to_screen(*x2, *y2)];
+                let stroke = egui::Stroke::new(1.0, Color32::DARK_GRAY);
+                painter.extend(egui::Shape::dashed_line(&points, stroke, 3.0 * scale, 2.0 * scale));
+            }
+        }
+    }
+}
+
+// State for the batch PDF export window
+pub struct BatchExportState {
+    invoice_numbers: Vec<String>, // Invoices picked in the All Invoices tab; empty means use the filters
+    from_date_str: String, // Empty means no lower bound
+    to_date_str: String, // Empty means no upper bound
+    customer_codes: HashSet<String>, // Empty means all customers
+    status: InvoiceStatusFilter,
+    folder: String,
+    filename_pattern: String,
+    output: BatchOutput,
+    progress: Option<(usize, usize)>, // (done, total) while an export is running
+    receiver: Option<Receiver<BatchProgress>>,
+    error_message: Option<String>,
+}
+
+impl Default for BatchExportState {
+    fn default() -> Self {
+        let today = Local::now().date_naive();
+        Self {
+            invoice_numbers: Vec::new(),
+            from_date_str: today.format("%Y-%m-01").to_string(),
+            to_date_str: today.format("%Y-%m-%d").to_string(),
+            customer_codes: HashSet::new(),
+            status: InvoiceStatusFilter::All,
+            folder: String::new(),
+            filename_pattern: DEFAULT_FILENAME_PATTERN.to_string(),
+            output: BatchOutput::SeparateFiles,
+            progress: None,
+            receiver: None,
+            error_message: None,
+        }
+    }
+}
+
+// State for the CSV customer import window
+#[derive(Clone)]
+pub struct CustomerImportState {
+    filename: String,
+    data: CsvData,
+    mapping: ColumnMapping,
+    generate_codes: bool, // Fill in missing codes from the customer name
+    errors_only: bool, // Only list rows that will be skipped
+    preview: Vec<ImportRow>,
+}
+
+impl Default for CustomerImportState {
+    fn default() -> Self {
+        Self {
+            filename: String::new(),
+            data: CsvData::default(),
+            mapping: ColumnMapping::default(),
+            generate_codes: true,
+            errors_only: false,
+            preview: Vec::new(),
+        }
+    }
+}
+
+// State for the CSV/XLSX data export window
+#[derive(Clone)]
+pub struct DataExportState {
+    kind: ExportKind,
+    format: ExportFormat,
+    from_date_str: String, // Empty means no lower bound
+    to_date_str: String, // Empty means no upper bound
+    status: InvoiceStatusFilter,
+    error_message: Option<String>,
+}
+
+impl Default for DataExportState {
+    fn default() -> Self {
+        Self {
+            kind: ExportKind::Invoices,
+            format: ExportFormat::Xlsx,
+            from_date_str: String::new(),
+            to_date_str: String::new(),
+            status: InvoiceStatusFilter::All,
+            error_message: None,
+        }
+    }
+}
+
+// State for the Xero/MYOB export window
+#[derive(Clone)]
+pub struct AccountingExportState {
+    system: AccountingSystem,
+    from_date_str: String, // Empty means no lower bound
+    to_date_str: String, // Empty means no upper bound
+    status: InvoiceStatusFilter,
+    include_exported: bool, // Export again invoices that were already posted
+    settings: AccountingSettings, // Edited copy of the account mapping
+    error_message: Option<String>,
+}
+
+impl Default for AccountingExportState {
+    fn default() -> Self {
+        Self {
+            system: AccountingSystem::Xero,
+            from_date_str: String::new(),
+            to_date_str: String::new(),
+            status: InvoiceStatusFilter::All,
+            include_exported: false,
+            settings: AccountingSettings::default(),
+            error_message: None,
+        }
+    }
+}
+
+// A bank credit in the reconciliation window and the invoice it will be recorded against
+#[derive(Clone)]
+pub struct ReconcileRow {
+    transaction: BankTransaction,
+    invoice_number: Option<String>,
+    amount_str: String, // Amount to record against the invoice
+    confidence: Option<MatchConfidence>, // None when chosen by hand
+    confirmed: bool,
+    already_recorded: bool, // A payment from this transaction is already on file
+}
+
+// State for the bank reconciliation window
+#[derive(Clone, Default)]
+pub struct ReconciliationState {
+    filename: String,
+    rows: Vec<ReconcileRow>,
+    debit_count: usize, // Debits in the statement, which are ignored
+    open_invoices: Vec<(String, String, f64)>, // Invoice number, label, balance due
+    error_message: Option<String>,
+}
+
+// State for the JSON export window
+#[derive(Clone, Default)]
+pub struct JsonExportState {
+    everything: bool,
+    customer_codes: HashSet<String>, // Customers exported with all their invoices
+    invoice_numbers: HashSet<String>, // Individually selected invoices
+}
+
+// State for the JSON import window
+#[derive(Clone, Default)]
+pub struct JsonImportState {
+    filename: String,
+    plan: ImportPlan,
+    conflicts_only: bool,
+    error_message: Option<String>,
+}
+
+// State for the window that emails an invoice
+#[derive(Clone, Default)]
+pub struct SendEmailState {
+    invoice_number: String,
+    draft: EmailDraft,
+    previous_sends: Vec<SentEmail>,
+    error_message: Option<String>,
+}
+
+// State for the company details window
+#[derive(Clone, Default)]
+pub struct CompanyState {
+    company: Option<Company>, // Edited copy of the business details
+    error_message: Option<String>,
+}
+
+// State for the SMTP settings window
+#[derive(Clone, Default)]
+pub struct EmailSettingsState {
+    settings: EmailSettings,
+    port_str: String,
+    error_message: Option<String>,
+}
+
+// State for the payment reminders window
+#[derive(Clone, Default)]
+pub struct RemindersState {
+    settings: ReminderSettings, // Edited copy of the rules
+    day_strs: Vec<String>, // Days from due being typed for each rule
+    due: Vec<PlannedReminder>, // What would be sent now with the saved rules
+    error_message: Option<String>,
+}
+
+// State for the late fee window
+#[derive(Clone, Default)]
+pub struct LateFeeState {
+    invoice_number: String,
+    as_at_str: String, // Charge fees up to the end of this day
+    settings: LateFeeSettings, // Edited copy of the fee settings
+    follow_ups: Vec<String>, // Unpaid invoices the fee could be added to
+    add_to: Option<String>, // None bills the fee on a new invoice
+    error_message: Option<String>,
+}
+
+// State for the aged receivables report window
+#[derive(Clone, Default)]
+pub struct AgedReceivablesState {
+    as_at_str: String,
+    error_message: Option<String>,
+}
+
+// State for the GST/BAS report window
+#[derive(Clone)]
+pub struct BasState {
+    length: PeriodLength,
+    periods: Vec<BasPeriod>, // Recent periods to choose from, newest first
+    period_index: usize,
+    basis: BasBasis,
+    error_message: Option<String>,
+}
+
+impl Default for BasState {
+    fn default() -> Self {
+        let periods = BasPeriod::recent(Local::now().date_naive(), PeriodLength::Quarter, BAS_PERIODS_SHOWN);
+        Self {
+            length: PeriodLength::Quarter,
+            period_index: 1.min(periods.len() - 1), // The last complete quarter, which is usually the one being lodged
+            periods,
+            basis: BasBasis::Accruals,
+            error_message: None,
+        }
+    }
+}
+
+const BAS_PERIODS_SHOWN: usize = 12;
+
+// What the central panel shows
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum MainView {
+    #[default]
+    CustomerInvoices,
+    AllInvoices,
+    Dashboard,
+    Search,
+}
+
+// Sorting, filter and multi-selection for the All Invoices tab
+#[derive(Clone)]
+pub struct AllInvoicesState {
+    sort: InvoiceSort,
+    descending: bool,
+    status: InvoiceStatusFilter,
+    selected: HashSet<String>, // Invoice numbers ticked for bulk actions
+    anchor: Option<String>, // Last invoice ticked, for shift-click ranges
+}
+
+impl Default for AllInvoicesState {
+    fn default() -> Self {
+        Self {
+            sort: InvoiceSort::Date,
+            descending: true, // Newest first
+            status: InvoiceStatusFilter::All,
+            selected: HashSet::new(),
+            anchor: None,
+        }
+    }
+}
+
+// Column widths in the All Invoices list: tick box, then one per InvoiceSort column
+const ALL_INVOICES_COLUMN_WIDTHS: [f32; 8] = [24.0, 80.0, 200.0, 85.0, 85.0, 85.0, 85.0, 80.0];
+
+// Invoices picked in the All Invoices tab to email to their customers
+#[derive(Default)]
+pub struct BulkEmailState {
+    invoice_numbers: Vec<String>,
+    progress: Option<(usize, usize)>, // (done, total) while the emails are going out
+    receiver: Option<Receiver<BulkEmailProgress>>,
+    sent: usize,
+    skipped: usize, // Customers without an email address
+    problems: Vec<String>, // "number: error" for each email that failed
+}
+
+// Search box text and the filters for invoice results
+#[derive(Clone, Default)]
+pub struct SearchState {
+    query: String,
+    status: InvoiceStatusFilter,
+    from_date_str: String, // Empty means no lower bound
+    to_date_str: String, // Empty means no upper bound
+}
+
+// State for the revenue dashboard
+#[derive(Clone)]
+pub struct DashboardState {
+    from_str: String,
+    to_str: String,
+}
+
+impl Default for DashboardState {
+    fn default() -> Self {
+        let (from, to) = last_twelve_months(Local::now().date_naive());
+        Self { from_str: from.format("%Y-%m-%d").to_string(), to_str: to.format("%Y-%m-%d").to_string() }
+    }
+}
+
+// From the first of the month eleven months ago up to `today`
+fn last_twelve_months(today: NaiveDate) -> (NaiveDate, NaiveDate) {
+    let this_month = today.with_day(1).unwrap_or(today);
+    (this_month.checked_sub_months(Months::new(11)).unwrap_or(this_month), today)
+}
+
+// Australian financial year (1 July to 30 June) containing `date`
+fn financial_year(date: NaiveDate) -> (NaiveDate, NaiveDate) {
+    let start_year = if date.month() >= 7 { date.year() } else { date.year() - 1 };
+    let from = NaiveDate::from_ymd_opt(start_year, 7, 1).unwrap_or(date);
+    let to = NaiveDate::from_ymd_opt(start_year + 1, 6, 30).unwrap_or(date);
+    (from, to)
+}
+
+const INVOICED_COLOR: Color32 = Color32::from_rgb(70, 130, 180);
+const COLLECTED_COLOR: Color32 = Color32::from_rgb(60, 170, 110);
+const OUTSTANDING_COLOR: Color32 = Color32::from_rgb(220, 120, 60);
+
+// Invoiced and collected bars side by side for each month
+fn month_revenue_chart(ui: &mut Ui, months: &[MonthRevenue]) {
+    let height = 200.0;
+    let (rect, response) = ui.allocate_exact_size(vec2(ui.available_width().max(200.0), height), Sense::hover());
+    let painter = ui.painter_at(rect);
+    let text_color = ui.visuals().text_color();
+    let max = months.iter().map(|m| m.invoiced.max(m.collected)).fold(0.0, f64::max);
+    let plot = Rect::from_min_max(pos2(rect.left() + 60.0, rect.top() + 10.0), pos2(rect.right() - 5.0, rect.bottom() - 20.0));
+    painter.line_segment([plot.left_bottom(), plot.right_bottom()], Stroke::new(1.0, ui.visuals().weak_text_color()));
+    if months.is_empty() || max <= 0.0 {
+        painter.text(plot.center(), Align2::CENTER_CENTER, "No invoices or payments in this range", FontId::proportional(12.0), text_color);
+        return;
+    }
+    // Gridlines at quarters of the largest month
+    for step in 1..=4 {
+        let value = max * step as f64 / 4.0;
+        let y = plot.bottom() - (value / max) as f32 * plot.height();
+        painter.line_segment([pos2(plot.left(), y), pos2(plot.right(), y)], Stroke::new(0.5, ui.visuals().faint_bg_color));
+        painter.text(pos2(plot.left() - 5.0, y), Align2::RIGHT_CENTER, format!("{:.0}", value), FontId::proportional(10.0), text_color);
+    }
+    let slot = plot.width() / months.len() as f32;
+    let bar = (slot * 0.35).min(30.0);
+    let label_every = (months.len() / 12).max(1);
+    let mut hovered = None;
+    for (i, month) in months.iter().enumerate() {
+        let centre = plot.left() + slot * (i as f32 + 0.5);
+        for (value, color, offset) in [(month.invoiced, INVOICED_COLOR, -bar), (month.collected, COLLECTED_COLOR, 0.0)] {
+            let top = plot.bottom() - (value / max) as f32 * plot.height();
+            painter.rect_filled(Rect::from_min_max(pos2(centre + offset, top), pos2(centre + offset + bar, plot.bottom())), 1.0, color);
+        }
+        if i % label_every == 0 {
+            let label = if i == 0 || month.month.month() == 1 { month.month.format("%b %y") } else { month.month.format("%b") };
+            painter.text(pos2(centre, plot.bottom() + 3.0), Align2::CENTER_TOP, label.to_string(), FontId::proportional(10.0), text_color);
+        }
+        if response.hover_pos().is_some_and(|p| (p.x - centre).abs() <= slot / 2.0) {
+            hovered = Some(month);
+        }
+    }
+    if let Some(month) = hovered {
+        response.on_hover_text_at_pointer(format!(
+            "{}\nInvoiced: {:.2}\nCollected: {:.2}", month.month.format("%B %Y"), month.invoiced, month.collected
+        ));
+    }
+}
+
+// A labelled horizontal bar scaled against `max`
+fn horizontal_bar(ui: &mut Ui, label: &str, value: f64, max: f64, color: Color32) {
+    let (rect, _) = ui.allocate_exact_size(vec2(ui.available_width().max(200.0), 20.0), Sense::hover());
+    let painter = ui.painter_at(rect);
+    let text_color = ui.visuals().text_color();
+    let bar_left = rect.left() + 180.0;
+    let bar_width = (rect.right() - 90.0 - bar_left).max(10.0);
+    let fraction = if max > 0.0 { (value / max).clamp(0.0, 1.0) as f32 } else { 0.0 };
+    painter.text(pos2(rect.left(), rect.center().y), Align2::LEFT_CENTER, label, FontId::proportional(12.0), text_color);
+    painter.rect_filled(Rect::from_min_max(pos2(bar_left, rect.top() + 3.0), pos2(bar_left + bar_width * fraction, rect.bottom() - 3.0)), 1.0, color);
+    painter.text(pos2(bar_left + bar_width * fraction + 5.0, rect.center().y), Align2::LEFT_CENTER, format!("{:.2}", value), FontId::proportional(12.0), text_color);
+}
+
+// One bar split between collected and outstanding
+fn collected_outstanding_bar(ui: &mut Ui, stats: &DashboardStats) {
+    let (rect, response) = ui.allocate_exact_size(vec2(ui.available_width().max(200.0), 24.0), Sense::hover());
+    let painter = ui.painter_at(rect);
+    let total = stats.collected + stats.outstanding;
+    if total <= 0.0 {
+        painter.rect_filled(rect, 2.0, ui.visuals().faint_bg_color);
+        return;
+    }
+    let split = rect.left() + rect.width() * (stats.collected / total) as f32;
+    painter.rect_filled(Rect::from_min_max(rect.min, pos2(split, rect.bottom())), 2.0, COLLECTED_COLOR);
+    painter.rect_filled(Rect::from_min_max(pos2(split, rect.top()), rect.max), 2.0, OUTSTANDING_COLOR);
+    response.on_hover_text_at_pointer(format!(
+        "Collected {:.2} ({:.0}%)\nOutstanding {:.2} ({:.0}%)",
+        stats.collected, stats.collected / total * 100.0, stats.outstanding, stats.outstanding / total * 100.0
+    ));
+}
+
+fn legend(ui: &mut Ui, entries: &[(&str, Color32)]) {
+    ui.horizontal(|ui| {
+        for (label, color) in entries {
+            let (rect, _) = ui.allocate_exact_size(vec2(12.0, 12.0), Sense::hover());
+            ui.painter().rect_filled(rect, 2.0, *color);
+            ui.label(*label);
+        }
+    });
+}
+
+// Status line after sending reminders
+fn reminder_summary(results: &[(PlannedReminder, Option<String>)]) -> String {
+    let sent = results.iter().filter(|(_, error)| error.is_none()).count();
+    let problems: Vec<String> = results.iter()
+        .filter_map(|(r, error)| error.as_ref().map(|e| format!("{}: {}", r.invoice_number, e)))
+        .collect();
+    if problems.is_empty() {
+        format!("Sent {} payment reminders.", sent)
+    } else {
+        format!("Sent {} payment reminders; {} not sent ({}).", sent, problems.len(), problems.join("; "))
+    }
+}
+
+// Parses an optional YYYY-MM-DD field, treating an empty field as "no limit"
+fn parse_optional_date(value: &str) -> Result<Option<NaiveDate>, String> {
+    if value.trim().is_empty() {
+        return Ok(None);
+    }
+    NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+        .map(Some)
+        .map_err(|_| format!("Invalid date \"{}\". Use YYYY-MM-DD.", value.trim()))
+}
+
+pub struct KmattInvoiceApp {
+    db: Database,
+    customers: Vec<Customer>,
+    selected_customer_code: Option<String>,
+    invoices_for_selected_customer: Vec<Invoice>,
+    selected_invoice_number: Option<String>, // Added to track selected invoice
+    show_add_customer_window: bool,
+    add_customer_state: AddCustomerState,
+    show_edit_customer_window: bool,
+    edit_customer_state: EditCustomerState,
+    show_create_invoice_window: bool,
+    create_invoice_state: CreateInvoiceState,
+    show_view_invoice_window: bool,
+    invoice_to_view: Option<Invoice>,
+    show_delete_customer_confirm_window: bool, 
+    customer_to_delete_code: Option<String>, 
+    show_edit_invoice_window: bool, // Added for edit invoice
+    edit_invoice_state: EditInvoiceState, // Added for edit invoice
+    show_delete_invoice_confirm_window: bool, // Added for delete invoice confirm
+    invoice_to_delete_number: Option<String>, // Added for delete invoice confirm
+    pdf_options: PdfOptions, // Remittance slip / QR code toggles for generated PDFs
+    show_pdf_preview_window: bool,
+    pdf_preview_state: PdfPreviewState,
+    show_batch_export_window: bool,
+    batch_export_state: BatchExportState,
+    show_statement_window: bool,
+    statement_state: StatementState,
+    show_record_payment_window: bool,
+    record_payment_state: RecordPaymentState,
+    show_customer_import_window: bool,
+    customer_import_state: CustomerImportState,
+    show_data_export_window: bool,
+    data_export_state: DataExportState,
+    show_accounting_export_window: bool,
+    accounting_export_state: AccountingExportState,
+    show_reconciliation_window: bool,
+    reconciliation_state: ReconciliationState,
+    show_json_export_window: bool,
+    json_export_state: JsonExportState,
+    show_json_import_window: bool,
+    json_import_state: JsonImportState,
+    show_send_email_window: bool,
+    send_email_state: SendEmailState,
+    show_company_window: bool,
+    company_state: CompanyState,
+    show_email_settings_window: bool,
+    email_settings_state: EmailSettingsState,
+    show_reminders_window: bool,
+    reminders_state: RemindersState,
+    show_late_fee_window: bool,
+    late_fee_state: LateFeeState,
+    show_aged_receivables_window: bool,
+    aged_receivables_state: AgedReceivablesState,
+    show_bas_window: bool,
+    bas_state: BasState,
+    main_view: MainView,
+    all_invoices_state: AllInvoicesState,
+    show_bulk_email_window: bool,
+    bulk_email_state: BulkEmailState,
+    dashboard_state: DashboardState,
+    search_state: SearchState,
+    status_message: String,
+}
+
+impl KmattInvoiceApp {
+    pub fn new(_cc: &eframe::CreationContext<
+'_>) -> Self {
+        let mut db = match Database::load() {
+            Ok(db) => db,
+            Err(e) => {
+                eprintln!("Failed to load database: {}, creating new.", e);
+                Database::new()
+            }
+        };
+        
+        let customers = db.get_customers_vec();
+
+        let mut status_message = "GUI Initialized.".to_string();
+        if db.reminders.run_on_start && db.email.is_configured() {
+            match db.send_reminders(Local::now().date_naive(), &PdfOptions::default()) {
+                Ok(results) if results.is_empty() => {}
+                Ok(results) => status_message = reminder_summary(&results),
+                Err(e) => status_message = format!("Error sending payment reminders: {}", e),
+            }
+        }
+
+        Self {
+            db,
+            customers,
+            selected_customer_code: None,
+            invoices_for_selected_customer: Vec::new(),
+            selected_invoice_number: None, // Init selected invoice
+            show_add_customer_window: false,
+            add_customer_state: AddCustomerState::default(),
+            show_edit_customer_window: false,
+            edit_customer_state: EditCustomerState::default(),
+            show_create_invoice_window: false,
+            create_invoice_state: CreateInvoiceState::default(),
+            show_view_invoice_window: false,
+            invoice_to_view: None,
+            show_delete_customer_confirm_window: false, 
+            customer_to_delete_code: None, 
+            show_edit_invoice_window: false, // Init edit invoice state
+            edit_invoice_state: EditInvoiceState::default(), // Init edit invoice state
+            show_delete_invoice_confirm_window: false, // Init delete invoice confirm state
+            invoice_to_delete_number: None, // Init delete invoice confirm state
+            pdf_options: PdfOptions::default(),
+            show_pdf_preview_window: false,
+            pdf_preview_state: PdfPreviewState::default(),
+            show_batch_export_window: false,
+            batch_export_state: BatchExportState::default(),
+            show_statement_window: false,
+            statement_state: StatementState::default(),
+            show_record_payment_window: false,
+            record_payment_state: RecordPaymentState::default(),
+            show_customer_import_window: false,
+            customer_import_state: CustomerImportState::default(),
+            show_data_export_window: false,
+            data_export_state: DataExportState::default(),
+            show_accounting_export_window: false,
+            accounting_export_state: AccountingExportState::default(),
+            show_reconciliation_window: false,
+            reconciliation_state: ReconciliationState::default(),
+            show_json_export_window: false,
+            json_export_state: JsonExportState::default(),
+            show_json_import_window: false,
+            json_import_state: JsonImportState::default(),
+            show_send_email_window: false,
+            send_email_state: SendEmailState::default(),
+            show_company_window: false,
+            company_state: CompanyState::default(),
+            show_email_settings_window: false,
+            email_settings_state: EmailSettingsState::default(),
+            show_reminders_window: false,
+            reminders_state: RemindersState::default(),
+            show_late_fee_window: false,
+            late_fee_state: LateFeeState::default(),
+            show_aged_receivables_window: false,
+            aged_receivables_state: AgedReceivablesState::default(),
+            show_bas_window: false,
+            bas_state: BasState::default(),
+            main_view: MainView::default(),
+            all_invoices_state: AllInvoicesState::default(),
+            show_bulk_email_window: false,
+            bulk_email_state: BulkEmailState::default(),
+            dashboard_state: DashboardState::default(),
+            search_state: SearchState::default(),
+            status_message,
+        }
+    }
+
+    fn add_customer_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        Window::new("Add New Customer")
+            .id(Id::new("add_customer_window")) // Unique ID
+            .resizable(true)
+            .collapsible(false)
+            .show(ctx, |ui| {
+            Grid::new("add_customer_grid")
+                .num_columns(2)
+                .spacing([10.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Name:");
+                    ui.add(TextEdit::singleline(&mut self.add_customer_state.name).hint_text("Required"));
+                    ui.end_row();
+                    ui.label("Address:");
+                    ui.text_edit_singleline(&mut self.add_customer_state.address);
+                    ui.end_row();
+                    ui.label("Phone:");
+                    ui.text_edit_singleline(&mut self.add_customer_state.phone);
+                    ui.end_row();
+                    ui.label("Contact Person:");
+                    ui.text_edit_singleline(&mut self.add_customer_state.contact_person);
+                    ui.end_row();
+                    ui.label("Contact Phone:");
+                    ui.text_edit_singleline(&mut self.add_customer_state.contact_phone);
+                    ui.end_row();
+                    ui.label("Email:");
+                    ui.text_edit_singleline(&mut self.add_customer_state.email);
+                    ui.end_row();
+                    ui.label("Code (2-3 letters):");
+                    ui.add(TextEdit::singleline(&mut self.add_customer_state.code).hint_text("Required, e.g., ABC"));
+                    ui.end_row();
+                    ui.label("ABN:");
+                    ui.add(TextEdit::singleline(&mut self.add_customer_state.abn).hint_text("Optional, for e-invoicing"));
+                    ui.end_row();
+                });
+            ui.separator();
+            if let Some(err) = &self.add_customer_state.error_message {
+                ui.colored_label(Color32::RED, err);
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Save Customer").clicked() {
+                    let new_customer = Customer {
+                        name: self.add_customer_state.name.trim().to_string(),
+                        address: self.add_customer_state.address.trim().to_string(),
+                        phone: self.add_customer_state.phone.trim().to_string(),
+                        contact_person: self.add_customer_state.contact_person.trim().to_string(),
+                        contact_phone: self.add_customer_state.contact_phone.trim().to_string(),
+                        email: self.add_customer_state.email.trim().to_string(),
+                        code: self.add_customer_state.code.trim().to_uppercase(),
+                        abn: self.add_customer_state.abn.trim().to_string(),
+                    };
+                    match self.db.add_customer_gui(new_customer) {
+                        Ok(_) => {
+                            self.status_message = format!("Customer \"{}\" added successfully.", self.add_customer_state.name.trim());
+                            self.update_customer_list();
+                            self.add_customer_state = AddCustomerState::default();
+                            close_window = true;
+                        },
+                        Err(e) => {
+                            self.add_customer_state.error_message = Some(e.to_string());
+                        }
+                    }
+                }
+                if ui.button("Cancel").clicked() {
+                    self.add_customer_state = AddCustomerState::default();
+                    close_window = true;
+                }
+            });
+        });
+        if close_window {
+            self.show_add_customer_window = false;
+        }
+    }
+
+    fn edit_customer_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        Window::new(format!("Edit Customer: {}", self.edit_customer_state.original_name))
+            .id(Id::new("edit_customer_window")) // Unique ID
+            .resizable(true)
+            .collapsible(false)
+            .show(ctx, |ui| {
+            Grid::new("edit_customer_grid")
+                .num_columns(2)
+                .spacing([10.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Name:");
+                    ui.add(TextEdit::singleline(&mut self.edit_customer_state.name).hint_text("Required"));
+                    ui.end_row();
+                    ui.label("Address:");
+                    ui.text_edit_singleline(&mut self.edit_customer_state.address);
+                    ui.end_row();
+                    ui.label("Phone:");
+                    ui.text_edit_singleline(&mut self.edit_customer_state.phone);
+                    ui.end_row();
+                    ui.label("Contact Person:");
+                    ui.text_edit_singleline(&mut self.edit_customer_state.contact_person);
+                    ui.end_row();
+                    ui.label("Contact Phone:");
+                    ui.text_edit_singleline(&mut self.edit_customer_state.contact_phone);
+                    ui.end_row();
+                    ui.label("Email:");
+                    ui.text_edit_singleline(&mut self.edit_customer_state.email);
+                    ui.end_row();
+                    ui.label("Code (2-3 letters):");
+                    ui.add(TextEdit::singleline(&mut self.edit_customer_state.code).hint_text("Required, e.g., ABC"));
+                    ui.end_row();
+                    ui.label("ABN:");
+                    ui.add(TextEdit::singleline(&mut self.edit_customer_state.abn).hint_text("Optional, for e-invoicing"));
+                    ui.end_row();
+                });
+            ui.separator();
+            if let Some(err) = &self.edit_customer_state.error_message {
+                ui.colored_label(Color32::RED, err);
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Save Changes").clicked() {
+                    let updated_customer = Customer {
+                        name: self.edit_customer_state.name.trim().to_string(),
+                        address: self.edit_customer_state.address.trim().to_string(),
+                        phone: self.edit_customer_state.phone.trim().to_string(),
+                        contact_person: self.edit_customer_state.contact_person.trim().to_string(),
+                        contact_phone: self.edit_customer_state.contact_phone.trim().to_string(),
+                        email: self.edit_customer_state.email.trim().to_string(),
+                        code: self.edit_customer_state.code.trim().to_uppercase(),
+                        abn: self.edit_customer_state.abn.trim().to_string(),
+                    };
+                    match self.db.edit_customer_gui(&self.edit_customer_state.original_name, updated_customer) {
+                        Ok(_) => {
+                            self.status_message = format!("Customer \"{}\" updated successfully.", self.edit_customer_state.name.trim());
+                            self.update_customer_list();
+                            if Some(self.edit_customer_state.original_name.clone()) == self.get_selected_customer_name() {
+                                self.selected_customer_code = Some(self.edit_customer_state.code.trim().to_uppercase());
+                                self.update_invoice_list();
+                            }
+                            self.edit_customer_state = EditCustomerState::default();
+                            close_window = true;
+                        },
+                        Err(e) => {
+                            self.edit_customer_state.error_message = Some(e.to_string());
+                        }
+                    }
+                }
+                if ui.button("Cancel").clicked() {
+                    self.edit_customer_state = EditCustomerState::default();
+                    close_window = true;
+                }
+            });
+        });
+        if close_window {
+            self.show_edit_customer_window = false;
+        }
+    }
+
+    fn delete_customer_confirm_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        let mut confirmed_delete = false;
+        let customer_name = self.customer_to_delete_code.as_ref().and_then(|code| {
+            self.customers.iter().find(|c| c.code == *code).map(|c| c.name.clone())
+        }).unwrap_or_else(|| "Unknown".to_string());
+
+        Window::new("Confirm Delete Customer")
+            .id(Id::new("delete_customer_confirm_window")) // Unique ID
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(format!("Are you sure you want to delete customer \"{}\" ({})?", 
+                                customer_name, 
+                                self.customer_to_delete_code.as_deref().unwrap_or("")));
+                ui.label("This will also delete all associated invoices.");
+                ui.colored_label(Color32::RED, "This action cannot be undone.");
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Yes, Delete Customer").clicked() {
+                        confirmed_delete = true;
+                        close_window = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        close_window = true;
+                    }
+                });
+            });
+
+        if confirmed_delete {
+            if let Some(code) = self.customer_to_delete_code.take() {
+                match self.db.delete_customer_gui(&code) {
+                    Ok(_) => {
+                        self.status_message = format!("Customer \"{}\" ({}) deleted successfully.", customer_name, code);
+                        self.selected_customer_code = None; // Deselect customer
+                        self.invoices_for_selected_customer.clear();
+                        self.update_customer_list();
+                    },
+                    Err(e) => {
+                        self.status_message = format!("Error deleting customer: {}", e);
+                    }
+                }
+            }
+        }
+
+        if close_window {
+            self.show_delete_customer_confirm_window = false;
+            if !confirmed_delete { // Clear the code if cancelled
+                self.customer_to_delete_code = None;
+            }
+        }
+    }
+
+    // Added confirmation window for deleting invoice
+    fn delete_invoice_confirm_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        let mut confirmed_delete = false;
+        let invoice_number = self.invoice_to_delete_number.clone().unwrap_or_default();
+
+        Window::new("Confirm Delete Invoice")
+            .id(Id::new("delete_invoice_confirm_window")) // Unique ID
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(format!("Are you sure you want to delete invoice #{}?", invoice_number));
+                ui.colored_label(Color32::RED, "This action cannot be undone.");
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Yes, Delete Invoice").clicked() {
+                        confirmed_delete = true;
+                        close_window = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        close_window = true;
+                    }
+                });
+            });
+
+        if confirmed_delete {
+            if let Some(num) = self.invoice_to_delete_number.take() {
+                match self.db.delete_invoice_gui(&num) {
+                    Ok(_) => {
+                        self.status_message = format!("Invoice #{} deleted successfully.", num);
+                        self.selected_invoice_number = None; // Deselect invoice
+                        self.update_invoice_list(); // Refresh list
+                    },
+                    Err(e) => {
+                        self.status_message = format!("Error deleting invoice: {}", e);
+                    }
+                }
+            }
+        }
+
+        if close_window {
+            self.show_delete_invoice_confirm_window = false;
+            if !confirmed_delete { // Clear the number if cancelled
+                self.invoice_to_delete_number = None;
+            }
+        }
+    }
+
+    fn create_invoice_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        // Use customer code in the ID to make it unique per customer
+        let window_id = Id::new(format!("create_invoice_window_{}", self.create_invoice_state.customer_code));
+        Window::new(format!("Create Invoice for {}", self.create_invoice_state.customer_name))
+            .id(window_id) // Unique ID for the window
+            .resizable(true)
+            .collapsible(false)
+            .show(ctx, |ui| {
+            ui.label(format!("Customer: {} ({})", self.create_invoice_state.customer_name, self.create_invoice_state.customer_code));
+            ui.separator();
+            ui.label("Invoice Items:");
+            // Use customer code in the ScrollArea ID
+            let scroll_id = Id::new(format!("create_invoice_items_scroll_{}", self.create_invoice_state.customer_code));
+            ScrollArea::vertical().id_source(scroll_id).max_height(200.0).show(ui, |ui| {
+                let mut item_to_remove = None;
+                let num_items = self.create_invoice_state.items.len(); // Get length before loop
+                let gst_registered = self.db.company.gst_registered;
+                for (i, item_state) in self.create_invoice_state.items.iter_mut().enumerate() {
+                    // Keep using index for item ID as it's unique within this window instance
+                    ui.push_id(format!("create_item_{}", i), |ui| {
+                        Grid::new(format!("item_grid_{}", i))
+                            .num_columns(4)
+                            .spacing([10.0, 4.0])
+                            .show(ui, |ui| {
+                                ui.label("Description:");
+                                ui.add(TextEdit::singleline(&mut item_state.description).hint_text("Item/Service"));
+                                ui.label("Quantity:");
+                                ui.add(TextEdit::singleline(&mut item_state.quantity_str).hint_text("e.g., 1"));
+                                ui.end_row();
+                                ui.label("Rate:");
+                                ui.add(TextEdit::singleline(&mut item_state.rate_str).hint_text("e.g., 50.00"));
+                                if gst_registered {
+                                    ui.checkbox(&mut item_state.gst_free, "GST-free");
+                                } else {
+                                    ui.label(""); // Placeholder
+                                }
+                                if num_items > 1 { // Use variable here
+                                    if ui.button("Remove").clicked() {
+                                        item_to_remove = Some(i);
+                                    }
+                                } else {
+                                    ui.label(""); // Placeholder
+                                }
+                                ui.end_row();
+                            });
+                        ui.separator();
+                    });
+                }
+                if let Some(index) = item_to_remove {
+                    self.create_invoice_state.items.remove(index);
+                }
+            });
+            if ui.button("Add Item").clicked() {
+                self.create_invoice_state.items.push(InvoiceItemState::default());
+            }
+            ui.separator();
+            ui.label("Notes:");
+            ui.text_edit_multiline(&mut self.create_invoice_state.notes);
+            ui.separator();
+            ui.label("Due Date (YYYY-MM-DD):");
+            ui.text_edit_singleline(&mut self.create_invoice_state.due_date_str);
+            ui.separator();
+            if let Some(err) = &self.create_invoice_state.error_message {
+                ui.colored_label(Color32::RED, err);
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Create Invoice").clicked() {
+                    let mut items = Vec::new();
+                    let mut valid = true;
+                    for item_state in &self.create_invoice_state.items {
+                        let quantity = match item_state.quantity_str.parse::<u32>() {
+                            Ok(q) if q > 0 => q,
+                            _ => {
+                                self.create_invoice_state.error_message = Some("Invalid quantity. Must be a positive integer.".to_string());
+                                valid = false;
+                                break;
+                            }
+                        };
+                        let rate = match item_state.rate_str.parse::<f64>() {
+                            Ok(r) if r >= 0.0 => r,
+                            _ => {
+                                self.create_invoice_state.error_message = Some("Invalid rate. Must be a non-negative number.".to_string());
+                                valid = false;
+                                break;
+                            }
+                        };
+                        if item_state.description.trim().is_empty() {
+                            self.create_invoice_state.error_message = Some("Item description cannot be empty.".to_string());
+                            valid = false;
+                            break;
+                        }
+                        items.push(InvoiceItem {
+                            description: item_state.description.trim().to_string(),
+                            quantity,
+                            rate,
+                            amount: 0.0, // Will be calculated in backend
+                            gst_free: item_state.gst_free,
+                            gst: 0.0,
+                        });
+                    }
+
+                    let due_date = if valid {
+                        match NaiveDate::parse_from_str(&self.create_invoice_state.due_date_str, "%Y-%m-%d") {
+                            Ok(d) => Some(d),
+                            Err(_) => {
+                                self.create_invoice_state.error_message = Some("Invalid due date format. Use YYYY-MM-DD.".to_string());
+                                valid = false;
+                                None
+                            }
+                        }
+                    } else { None };
+
+                    if valid {
+                        if let Some(due_date_naive) = due_date {
+                            match self.db.create_invoice_gui(
+                                self.create_invoice_state.customer_code.clone(),
+                                items,
+                                self.create_invoice_state.notes.trim().to_string(),
+                                due_date_naive,
+                            ) {
+                                Ok(invoice) => {
+                                    self.status_message = format!("Invoice #{} created successfully.", invoice.invoice_number);
+                                    self.update_invoice_list();
+                                    self.create_invoice_state = CreateInvoiceState::default();
+                                    close_window = true;
+                                },
+                                Err(e) => {
+                                    self.create_invoice_state.error_message = Some(e.to_string());
+                                }
+                            }
+                        }
+                    }
+                }
+                if ui.button("Cancel").clicked() {
+                    self.create_invoice_state = CreateInvoiceState::default();
+                    close_window = true;
+                }
+            });
+        });
+        if close_window {
+            self.show_create_invoice_window = false;
+        }
+    }
+
+    // Added window for editing invoices
+    fn edit_invoice_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        // Get customer code from the main app state, not the edit state
+        let customer_code = self.selected_customer_code.clone().unwrap_or_default(); 
+        // Use invoice number in the ID to make it unique per invoice
+        let window_id = Id::new(format!("edit_invoice_window_{}", self.edit_invoice_state.original_invoice_number));
+        Window::new(format!("Edit Invoice #{} for {}", self.edit_invoice_state.original_invoice_number, self.edit_invoice_state.customer_name))
+            .id(window_id) // Unique ID for the window
+            .resizable(true)
+            .collapsible(false)
+            .show(ctx, |ui| {
+            // Use the retrieved customer_code here
+            ui.label(format!("Customer: {} ({})", self.edit_invoice_state.customer_name, customer_code)); 
+            ui.separator();
+            ui.label("Invoice Items:");
+            // Use invoice number in the ScrollArea ID
+            let scroll_id = Id::new(format!("edit_invoice_items_scroll_{}", self.edit_invoice_state.original_invoice_number));
+            ScrollArea::vertical().id_source(scroll_id).max_height(200.0).show(ui, |ui| {
+                let mut item_to_remove = None;
+                let num_items = self.edit_invoice_state.items.len(); // Get length before loop
+                let gst_registered = self.db.company.gst_registered;
+                for (i, item_state) in self.edit_invoice_state.items.iter_mut().enumerate() {
+                    ui.push_id(format!("edit_item_{}", i), |ui| { // Unique ID for each item
+                        Grid::new(format!("edit_item_grid_{}", i))
+                            .num_columns(4)
+                            .spacing([10.0, 4.0])
+                            .show(ui, |ui| {
+                                ui.label("Description:");
+                                ui.add(TextEdit::singleline(&mut item_state.description).hint_text("Item/Service"));
+                                ui.label("Quantity:");
+                                ui.add(TextEdit::singleline(&mut item_state.quantity_str).hint_text("e.g., 1"));
+                                ui.end_row();
+                                ui.label("Rate:");
+                                ui.add(TextEdit::singleline(&mut item_state.rate_str).hint_text("e.g., 50.00"));
+                                if gst_registered {
+                                    ui.checkbox(&mut item_state.gst_free, "GST-free");
+                                } else {
+                                    ui.label(""); // Placeholder
+                                }
+                                if num_items > 1 { // Use variable here
+                                    if ui.button("Remove").clicked() {
+                                        item_to_remove = Some(i);
+                                    }
+                                } else {
+                                    ui.label(""); // Placeholder
+                                }
+                                ui.end_row();
+                            });
+                        ui.separator();
+                    });
+                }
+                if let Some(index) = item_to_remove {
+                    self.edit_invoice_state.items.remove(index);
+                }
+            });
+            if ui.button("Add Item").clicked() {
+                self.edit_invoice_state.items.push(InvoiceItemState::default());
+            }
+            ui.separator();
+            ui.label("Notes:");
+            ui.text_edit_multiline(&mut self.edit_invoice_state.notes);
+            ui.separator();
+            ui.label("Due Date (YYYY-MM-DD):");
+            ui.text_edit_singleline(&mut self.edit_invoice_state.due_date_str);
+            ui.checkbox(&mut self.edit_invoice_state.paid, "Mark as Paid");
+            ui.separator();
+            if let Some(err) = &self.edit_invoice_state.error_message {
+                ui.colored_label(Color32::RED, err);
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Save Changes").clicked() {
+                    let mut items = Vec::new();
+                    let mut valid = true;
+                    for item_state in &self.edit_invoice_state.items {
+                        let quantity = match item_state.quantity_str.parse::<u32>() {
+                            Ok(q) if q > 0 => q,
+                            _ => {
+                                self.edit_invoice_state.error_message = Some("Invalid quantity. Must be a positive integer.".to_string());
+                                valid = false;
+                                break;
+                            }
+                        };
+                        let rate = match item_state.rate_str.parse::<f64>() {
+                            Ok(r) if r >= 0.0 => r,
+                            _ => {
+                                self.edit_invoice_state.error_message = Some("Invalid rate. Must be a non-negative number.".to_string());
+                                valid = false;
+                                break;
+                            }
+                        };
+                        if item_state.description.trim().is_empty() {
+                            self.edit_invoice_state.error_message = Some("Item description cannot be empty.".to_string());
+                            valid = false;
+                            break;
+                        }
+                        items.push(InvoiceItem {
+                            description: item_state.description.trim().to_string(),
+                            quantity,
+                            rate,
+                            amount: 0.0, // Will be calculated in backend
+                            gst_free: item_state.gst_free,
+                            gst: 0.0,
+                        });
+                    }
+
+                    let due_date = if valid {
+                        match NaiveDate::parse_from_str(&self.edit_invoice_state.due_date_str, "%Y-%m-%d") {
+                            Ok(d) => Some(d),
+                            Err(_) => {
+                                self.edit_invoice_state.error_message = Some("Invalid due date format. Use YYYY-MM-DD.".to_string());
+                                valid = false;
+                                None
+                            }
+                        }
+                    } else { None };
+
+                    if valid {
+                        if let Some(due_date_naive) = due_date {
+                            match self.db.edit_invoice_gui(
+                                &self.edit_invoice_state.original_invoice_number,
+                                items,
+                                self.edit_invoice_state.notes.trim().to_string(),
+                                due_date_naive,
+                                self.edit_invoice_state.paid,
+                            ) {
+                                Ok(_) => {
+                                    self.status_message = format!("Invoice #{} updated successfully.", self.edit_invoice_state.original_invoice_number);
+                                    self.update_invoice_list();
+                                    self.edit_invoice_state = EditInvoiceState::default();
+                                    close_window = true;
+                                },
+                                Err(e) => {
+                                    self.edit_invoice_state.error_message = Some(e.to_string());
+                                }
+                            }
+                        }
+                    }
+                }
+                if ui.button("Cancel").clicked() {
+                    self.edit_invoice_state = EditInvoiceState::default();
+                    close_window = true;
+                }
+            });
+        });
+        if close_window {
+            self.show_edit_invoice_window = false;
+        }
+    }
+
+    fn view_invoice_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        let mut copy_text = false;
+        let mut save_html = false;
+        let mut export_ubl = false;
+        let mut send_email = false;
+        if let Some(invoice) = &self.invoice_to_view {
+            // Use invoice number in the ID to make it unique per invoice
+            let window_id = Id::new(format!("view_invoice_window_{}", invoice.invoice_number));
+            Window::new(format!("View Invoice #{}", invoice.invoice_number))
+                .id(window_id) // Unique ID for the window
+                .resizable(true)
+                .collapsible(true)
+                .default_width(500.0)
+                .show(ctx, |ui| {
+                ui.heading(format!("Invoice #{} for {}", invoice.invoice_number, invoice.customer.name));
+                ui.separator();
+                Grid::new("view_invoice_details_grid")
+                    .num_columns(2)
+                    .spacing([40.0, 4.0])
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("Customer:");
+                        ui.label(format!("{} ({})", invoice.customer.name, invoice.customer.code));
+                        ui.end_row();
+                        ui.label("Date Issued:");
+                        ui.label(invoice.date.format("%Y-%m-%d %H:%M").to_string());
+                        ui.end_row();
+                        ui.label("Date Due:");
+                        ui.label(invoice.due_date.format("%Y-%m-%d").to_string());
+                        ui.end_row();
+                        ui.label("Status:");
+                        ui.label(if invoice.paid { "Paid" } else { "Unpaid" });
+                        ui.end_row();
+                        if let Some(sent) = invoice.emails.last() {
+                            ui.label("Last Emailed:");
+                            ui.label(format!("{} to {}", sent.sent_at.format("%Y-%m-%d %H:%M"), sent.to));
+                            ui.end_row();
+                        }
+                        for (i, sent) in invoice.reminders.iter().enumerate() {
+                            ui.label(if i == 0 { "Reminders Sent:" } else { "" });
+                            ui.label(format!("{} ({}) to {}", sent.sent_at.format("%Y-%m-%d"), stage_label(sent.days_from_due), sent.to));
+                            ui.end_row();
+                        }
+                        for (i, fee) in invoice.late_fees.iter().enumerate() {
+                            ui.label(if i == 0 { "Late Fees:" } else { "" });
+                            ui.label(format!("{:.2} to {} on invoice #{}", fee.amount, fee.period_to.format("%Y-%m-%d"), fee.billed_on));
+                            ui.end_row();
+                        }
+                    });
+                ui.separator();
+                ui.heading("Items");
+                // Use invoice number in the ScrollArea ID
+                let scroll_id = Id::new(format!("view_invoice_items_scroll_{}", invoice.invoice_number));
+                ScrollArea::vertical().id_source(scroll_id).max_height(200.0).show(ui, |ui| {
+                    ui.push_id("view_items_scroll", |ui| { // Unique ID for the scroll area content (redundant? maybe remove)
+                        Grid::new("view_invoice_items_grid")
+                            .num_columns(4)
+                            .spacing([10.0, 4.0])
+                            .striped(true)
+                            .min_col_width(100.0)
+                            .show(ui, |ui| {
+                                ui.label(RichText::new("Description").strong());
+                                ui.label(RichText::new("Quantity").strong());
+                                ui.label(RichText::new("Rate").strong());
+                                ui.label(RichText::new("Amount").strong());
+                                ui.end_row();
+                                for (i, item) in invoice.items.iter().enumerate() {
+                                    ui.push_id(format!("view_item_{}", i), |ui| { // Unique ID for each item row
+                                        if invoice.gst > 0.0 && item.gst_free {
+                                            ui.label(format!("{} (GST-free)", item.description));
+                                        } else {
+                                            ui.label(&item.description);
+                                        }
+                                        ui.label(item.quantity.to_string());
+                                        ui.label(format!("{:.2}", item.rate));
+                                        ui.label(format!("{:.2}", item.amount));
+                                        ui.end_row();
+                                    });
+                                }
+                            });
+                    });
+                });
+                ui.separator();
+                Grid::new("view_invoice_totals_grid")
+                    .num_columns(2)
+                    .spacing([40.0, 4.0])
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("Subtotal:");
+                        ui.label(format!("{:.2}", invoice.subtotal));
+                        ui.end_row();
+                        if invoice.gst > 0.0 {
+                            ui.label("GST:");
+                            ui.label(format!("{:.2}", invoice.gst));
+                            ui.end_row();
+                        }
+                        ui.label(RichText::new("Total:").strong());
+                        ui.label(RichText::new(format!("{:.2}", invoice.total)).strong());
+                        ui.end_row();
+                        if !invoice.payments.is_empty() {
+                            ui.label("Paid / Credited:");
+                            ui.label(format!("{:.2}", invoice.amount_paid()));
+                            ui.end_row();
+                            ui.label("Balance Due:");
+                            ui.label(format!("{:.2}", invoice.balance_due()));
+                            ui.end_row();
+                        }
+                    });
+                if !invoice.notes.is_empty() {
+                    ui.separator();
+                    ui.label("Notes:");
+                    ScrollArea::vertical().max_height(60.0).show(ui, |ui| {
+                        ui.label(&invoice.notes);
+                    });
+                }
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Copy as Text").clicked() {
+                        copy_text = true;
+                    }
+                    if ui.button("Save HTML...").clicked() {
+                        save_html = true;
+                    }
+                    if ui.button("Export e-Invoice (UBL)...").on_hover_text("Peppol A-NZ PINT XML; both ABNs are required").clicked() {
+                        export_ubl = true;
+                    }
+                    if ui.button("Send by Email...").clicked() {
+                        send_email = true;
+                    }
+                    if ui.button("Close").clicked() {
+                        close_window = true;
+                    }
+                });
+            });
+        } else {
+            // Should not happen if window is shown, but handle gracefully
+            close_window = true;
+        }
+        if let Some(invoice) = &self.invoice_to_view {
+            let num = invoice.invoice_number.clone();
+            if copy_text {
+                match self.db.render_invoice_text(&num) {
+                    Ok(text) => {
+                        ctx.output_mut(|o| o.copied_text = text);
+                        self.status_message = format!("Invoice #{} copied to clipboard as text.", num);
+                    }
+                    Err(e) => self.status_message = format!("Error rendering invoice: {}", e),
+                }
+            }
+            if save_html {
+                if let Some(path) = FileDialog::new()
+                    .set_file_name(format!("Invoice-{}.html", num))
+                    .add_filter("HTML", &["html", "htm"])
+                    .save_file() {
+                    match self.db.render_invoice_html(&num).and_then(|html| std::fs::write(&path, html).map_err(Into::into)) {
+                        Ok(_) => self.status_message = format!("HTML generated: {}", path.display()),
+                        Err(e) => self.status_message = format!("Error generating HTML: {}", e),
+                    }
+                }
+            }
+            if export_ubl {
+                if let Some(path) = FileDialog::new()
+                    .set_file_name(format!("Invoice-{}.xml", num))
+                    .add_filter("UBL XML", &["xml"])
+                    .save_file() {
+                    match self.db.export_ubl_gui(&num, &path.to_string_lossy()) {
+                        Ok(_) => self.status_message = format!("E-invoice exported: {}", path.display()),
+                        Err(e) => self.status_message = format!("Error exporting e-invoice: {}", e),
+                    }
+                }
+            }
+            if send_email {
+                self.open_send_email_window(&num);
+            }
+        }
+        if close_window {
+            self.show_view_invoice_window = false;
+            self.invoice_to_view = None;
+        }
+    }
+
+    fn open_send_email_window(&mut self, invoice_number: &str) {
+        match self.db.email_draft(invoice_number) {
+            Ok(draft) => {
+                self.send_email_state = SendEmailState {
+                    invoice_number: invoice_number.to_string(),
+                    draft,
+                    previous_sends: self.db.invoices.get(invoice_number).map(|inv| inv.emails.clone()).unwrap_or_default(),
+                    error_message: if self.db.email.is_configured() {
+                        None
+                    } else {
+                        Some("Email is not set up yet. Use File > Email Settings... first.".to_string())
+                    },
+                };
+                self.show_send_email_window = true;
+            }
+            Err(e) => self.status_message = format!("Error preparing email: {}", e),
+        }
+    }
+
+    fn send_email_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        let mut send = false;
+        let mut open_settings = false;
+        let state = &mut self.send_email_state;
+        Window::new(format!("Email Invoice #{}", state.invoice_number))
+            .id(Id::new("send_email_window"))
+            .resizable(true)
+            .collapsible(false)
+            .default_width(500.0)
+            .show(ctx, |ui| {
+            Grid::new("send_email_grid")
+                .num_columns(2)
+                .spacing([10.0, 4.0])
+                .show(ui, |ui| {
+                    ui.label("To:");
+                    ui.add(TextEdit::singleline(&mut state.draft.to).hint_text("name@example.com, ...").desired_width(380.0));
+                    ui.end_row();
+                    ui.label("Subject:");
+                    ui.add(TextEdit::singleline(&mut state.draft.subject).desired_width(380.0));
+                    ui.end_row();
+                });
+            ui.label("Message:");
+            ui.add(TextEdit::multiline(&mut state.draft.body).desired_rows(10).desired_width(f32::INFINITY));
+            ui.label(format!("Attachment: Invoice-{}.pdf (uses the PDF options from the Invoices menu)", state.invoice_number));
+            if !state.previous_sends.is_empty() {
+                ui.separator();
+                ui.label(RichText::new("Previously Sent").strong());
+                for sent in state.previous_sends.iter().rev() {
+                    ui.label(format!("{}  to {}", sent.sent_at.format("%Y-%m-%d %H:%M"), sent.to));
+                }
+            }
+            if let Some(err) = &state.error_message {
+                ui.colored_label(Color32::RED, err);
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Send").clicked() {
+                    send = true;
+                }
+                if ui.button("Email Settings...").clicked() {
+                    open_settings = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    close_window = true;
+                }
+            });
+        });
+
+        if send {
+            let invoice_number = self.send_email_state.invoice_number.clone();
+            match self.db.email_invoice_gui(&invoice_number, &self.send_email_state.draft, &self.pdf_options) {
+                Ok(sent) => {
+                    self.status_message = format!("Invoice #{} emailed to {}", invoice_number, sent.to);
+                    if let Some(invoice) = self.invoice_to_view.as_mut().filter(|inv| inv.invoice_number == invoice_number) {
+                        invoice.emails.push(sent);
+                    }
+                    self.update_invoice_list();
+                    close_window = true;
+                }
+                Err(e) => self.send_email_state.error_message = Some(e.to_string()),
+            }
+        }
+        if open_settings {
+            self.open_email_settings_window();
+        }
+        if close_window {
+            self.show_send_email_window = false;
+        }
+    }
+
+    fn open_email_settings_window(&mut self) {
+        self.email_settings_state = EmailSettingsState {
+            settings: self.db.email.clone(),
+            port_str: self.db.email.smtp_port.to_string(),
+            error_message: None,
+        };
+        self.show_email_settings_window = true;
+    }
+
+    fn email_settings_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        let mut save = false;
+        Window::new("Email Settings")
+            .id(Id::new("email_settings_window"))
+            .resizable(true)
+            .collapsible(false)
+            .default_width(450.0)
+            .show(ctx, |ui| {
+            let state = &mut self.email_settings_state;
+            let settings = &mut state.settings;
+            Grid::new("email_settings_grid")
+                .num_columns(2)
+                .spacing([10.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("SMTP Server:");
+                    ui.add(TextEdit::singleline(&mut settings.smtp_host).hint_text("smtp.example.com"));
+                    ui.end_row();
+                    ui.label("Security:");
+                    let before = settings.security;
+                    egui::ComboBox::from_id_source("email_security")
+                        .selected_text(settings.security.label())
+                        .show_ui(ui, |ui| {
+                            for security in SmtpSecurity::ALL {
+                                ui.selectable_value(&mut settings.security, security, security.label());
+                            }
+                        });
+                    // Follow the usual port for the chosen security unless the user typed their own
+                    if settings.security != before && state.port_str.trim() == before.default_port().to_string() {
+                        state.port_str = settings.security.default_port().to_string();
+                    }
+                    ui.end_row();
+                    ui.label("Port:");
+                    ui.add(TextEdit::singleline(&mut state.port_str).desired_width(60.0));
+                    ui.end_row();
+                    ui.label("Username:");
+                    ui.add(TextEdit::singleline(&mut settings.username).hint_text("Blank to send without logging in"));
+                    ui.end_row();
+                    ui.label("Password:");
+                    ui.add(TextEdit::singleline(&mut settings.password).password(true).hint_text(format!("Blank to use {}", PASSWORD_ENV_VAR)));
+                    ui.end_row();
+                    ui.label("From Name:");
+                    ui.text_edit_singleline(&mut settings.from_name);
+                    ui.end_row();
+                    ui.label("From Address:");
+                    ui.add(TextEdit::singleline(&mut settings.from_address).hint_text("accounts@example.com"));
+                    ui.end_row();
+                    ui.label("Subject:");
+                    ui.text_edit_singleline(&mut settings.subject_template);
+                    ui.end_row();
+                });
+            ui.label("Message:");
+            ui.add(TextEdit::multiline(&mut settings.body_template).desired_rows(8).desired_width(f32::INFINITY));
+            egui::CollapsingHeader::new("Placeholders")
+                .id_source("email_placeholders")
+                .show(ui, |ui| {
+                    Grid::new("email_placeholders_grid").num_columns(2).spacing([10.0, 2.0]).show(ui, |ui| {
+                        for (placeholder, meaning) in PLACEHOLDERS {
+                            ui.monospace(placeholder);
+                            ui.label(meaning);
+                            ui.end_row();
+                        }
+                    });
+                });
+            if std::env::var(PASSWORD_ENV_VAR).is_ok_and(|p| !p.is_empty()) {
+                ui.label(format!("{} is set, so it is used instead of the password above.", PASSWORD_ENV_VAR));
+            } else if !settings.password.is_empty() {
+                ui.colored_label(Color32::from_rgb(200, 120, 0), format!(
+                    "Warning: the password is saved unencrypted in database.json. Leave it blank and set {} to keep it out of the file.",
+                    PASSWORD_ENV_VAR,
+                ));
+            }
+            if let Some(err) = &state.error_message {
+                ui.colored_label(Color32::RED, err);
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    save = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    close_window = true;
+                }
+            });
+        });
+
+        if save {
+            let state = &mut self.email_settings_state;
+            match state.port_str.trim().parse::<u16>() {
+                Ok(port) => {
+                    state.settings.smtp_port = port;
+                    match self.db.update_email_settings_gui(state.settings.clone()) {
+                        Ok(_) => {
+                            self.status_message = "Email settings saved.".to_string();
+                            // Clear the "not set up" warning in an open send window
+                            if self.db.email.is_configured() {
+                                self.send_email_state.error_message = None;
+                            }
+                            close_window = true;
+                        }
+                        Err(e) => state.error_message = Some(e.to_string()),
+                    }
+                }
+                Err(_) => state.error_message = Some("Port must be a number between 1 and 65535.".to_string()),
+            }
+        }
+        if close_window {
+            self.show_email_settings_window = false;
+        }
+    }
+
+    fn company_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        let mut save = false;
+        Window::new("Company Details")
+            .id(Id::new("company_window"))
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+            let state = &mut self.company_state;
+            let company = match &mut state.company {
+                Some(company) => company,
+                None => return,
+            };
+            Grid::new("company_grid")
+                .num_columns(2)
+                .spacing([10.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Business Name:");
+                    ui.text_edit_singleline(&mut company.name);
+                    ui.end_row();
+                    ui.label("ABN:");
+                    ui.text_edit_singleline(&mut company.abn);
+                    ui.end_row();
+                    ui.label("Address:");
+                    ui.text_edit_singleline(&mut company.address);
+                    ui.end_row();
+                    ui.label("Phone:");
+                    ui.text_edit_singleline(&mut company.phone);
+                    ui.end_row();
+                });
+            ui.checkbox(&mut company.gst_registered, "Registered for GST");
+            ui.label("New invoice lines carry 10% GST unless ticked GST-free. Existing invoices keep their GST until edited.");
+            if let Some(err) = &state.error_message {
+                ui.colored_label(Color32::RED, err);
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    save = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    close_window = true;
+                }
+            });
+        });
+
+        if save {
+            if let Some(company) = self.company_state.company.clone() {
+                match self.db.update_company_gui(company) {
+                    Ok(_) => {
+                        self.status_message = "Company details saved.".to_string();
+                        close_window = true;
+                    }
+                    Err(e) => self.company_state.error_message = Some(e.to_string()),
+                }
+            }
+        }
+        if close_window {
+            self.show_company_window = false;
+        }
+    }
+
+    fn open_reminders_window(&mut self) {
+        let settings = self.db.reminders.clone();
+        self.reminders_state = RemindersState {
+            day_strs: settings.rules.iter().map(|r| r.days_from_due.to_string()).collect(),
+            settings,
+            due: self.db.due_reminders(Local::now().date_naive()),
+            error_message: None,
+        };
+        self.show_reminders_window = true;
+    }
+
+    // Saves the edited reminder rules, showing any problem in the window
+    fn save_reminder_settings(&mut self) -> bool {
+        let state = &mut self.reminders_state;
+        for (rule, days) in state.settings.rules.iter_mut().zip(&state.day_strs) {
+            match days.trim().parse::<i64>() {
+                Ok(days) => rule.days_from_due = days,
+                Err(_) => {
+                    state.error_message = Some(format!("\"{}\" is not a number of days. Use a negative number for days before the due date.", days.trim()));
+                    return false;
+                }
+            }
+        }
+        match self.db.update_reminder_settings_gui(state.settings.clone()) {
+            Ok(_) => {
+                state.due = self.db.due_reminders(Local::now().date_naive());
+                true
+            }
+            Err(e) => {
+                state.error_message = Some(e.to_string());
+                false
+            }
+        }
+    }
+
+    fn reminders_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        let mut save = false;
+        let mut send = false;
+        let email_configured = self.db.email.is_configured();
+        Window::new("Payment Reminders")
+            .id(Id::new("reminders_window"))
+            .resizable(true)
+            .collapsible(false)
+            .default_width(600.0)
+            .show(ctx, |ui| {
+            let state = &mut self.reminders_state;
+            ui.label("Reminders go to the customer's email address with the invoice PDF attached. Only the latest stage an invoice has reached is sent, and each stage only once.");
+            ui.separator();
+            let mut remove = None;
+            ScrollArea::vertical().id_source("reminder_rules_scroll").max_height(300.0).show(ui, |ui| {
+                for (i, (rule, days)) in state.settings.rules.iter_mut().zip(state.day_strs.iter_mut()).enumerate() {
+                    ui.push_id(format!("reminder_rule_{}", i), |ui| {
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut rule.enabled, "");
+                            ui.add(TextEdit::singleline(days).desired_width(40.0));
+                            let label = days.trim().parse::<i64>().map(stage_label).unwrap_or_else(|_| "days from due".to_string());
+                            ui.label(label);
+                            if ui.small_button("Remove").clicked() {
+                                remove = Some(i);
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Subject:");
+                            ui.add(TextEdit::singleline(&mut rule.subject_template).desired_width(f32::INFINITY));
+                        });
+                        egui::CollapsingHeader::new("Message").id_source("reminder_body").show(ui, |ui| {
+                            ui.add(TextEdit::multiline(&mut rule.body_template).desired_rows(6).desired_width(f32::INFINITY));
+                        });
+                        ui.separator();
+                    });
+                }
+            });
+            if let Some(i) = remove {
+                state.settings.rules.remove(i);
+                state.day_strs.remove(i);
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Add Reminder").clicked() {
+                    let rule = ReminderRule::default();
+                    state.day_strs.push(rule.days_from_due.to_string());
+                    state.settings.rules.push(rule);
+                }
+                ui.checkbox(&mut state.settings.run_on_start, "Send due reminders when the app starts");
+            });
+            egui::CollapsingHeader::new("Placeholders")
+                .id_source("reminder_placeholders")
+                .show(ui, |ui| {
+                    Grid::new("reminder_placeholders_grid").num_columns(2).spacing([10.0, 2.0]).show(ui, |ui| {
+                        for (placeholder, meaning) in PLACEHOLDERS.iter().chain(REMINDER_PLACEHOLDERS.iter()) {
+                            ui.monospace(*placeholder);
+                            ui.label(*meaning);
+                            ui.end_row();
+                        }
+                    });
+                });
+
+            ui.separator();
+            ui.heading(format!("Due Today ({})", state.due.len()));
+            if state.due.is_empty() {
+                ui.label("No reminders are due with the saved rules.");
+            } else {
+                ScrollArea::vertical().id_source("reminders_due_scroll").max_height(200.0).show(ui, |ui| {
+                    Grid::new("reminders_due_grid")
+                        .num_columns(5)
+                        .spacing([10.0, 4.0])
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.label(RichText::new("Invoice").strong());
+                            ui.label(RichText::new("Customer").strong());
+                            ui.label(RichText::new("Balance").strong());
+                            ui.label(RichText::new("Reminder").strong());
+                            ui.label(RichText::new("To").strong());
+                            ui.end_row();
+                            for reminder in &state.due {
+                                ui.label(&reminder.invoice_number);
+                                ui.label(&reminder.customer_name);
+                                ui.label(format!("{:.2}", reminder.balance_due));
+                                ui.label(stage_label(reminder.days_from_due)).on_hover_text(&reminder.draft.subject);
+                                match &reminder.skip_reason {
+                                    Some(reason) => ui.colored_label(Color32::RED, reason),
+                                    None => ui.label(&reminder.draft.to),
+                                };
+                                ui.end_row();
+                            }
+                        });
+                });
+            }
+            if !email_configured {
+                ui.colored_label(Color32::RED, "Email is not set up yet. Use File > Email Settings... before sending.");
+            }
+            if let Some(err) = &state.error_message {
+                ui.colored_label(Color32::RED, err);
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save Rules").clicked() {
+                    save = true;
+                }
+                let sendable = state.due.iter().filter(|r| r.skip_reason.is_none()).count();
+                if ui.add_enabled(email_configured && sendable > 0, egui::Button::new(format!("Send {} Reminders", sendable))).clicked() {
+                    send = true;
+                }
+                if ui.button("Close").clicked() {
+                    close_window = true;
+                }
+            });
+        });
+
+        if save || send {
+            self.reminders_state.error_message = None;
+            if !self.save_reminder_settings() {
+                return;
+            }
+            if save {
+                self.status_message = "Reminder rules saved.".to_string();
+            }
+        }
+        if send {
+            match self.db.send_reminders(Local::now().date_naive(), &self.pdf_options) {
+                Ok(results) => {
+                    self.status_message = reminder_summary(&results);
+                    self.reminders_state.due = self.db.due_reminders(Local::now().date_naive());
+                    self.update_invoice_list();
+                }
+                Err(e) => self.reminders_state.error_message = Some(e.to_string()),
+            }
+        }
+        if close_window {
+            self.show_reminders_window = false;
+        }
+    }
+
+    fn open_late_fee_window(&mut self, invoice_number: &str) {
+        let follow_ups = self.db.late_fee_follow_ups(invoice_number);
+        self.late_fee_state = LateFeeState {
+            invoice_number: invoice_number.to_string(),
+            as_at_str: Local::now().format("%Y-%m-%d").to_string(),
+            settings: self.db.late_fees.clone(),
+            follow_ups,
+            add_to: None,
+            error_message: None,
+        };
+        self.show_late_fee_window = true;
+    }
+
+    fn late_fee_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        let mut apply = false;
+        let mut save_settings = false;
+        let invoice = match self.db.invoices.get(&self.late_fee_state.invoice_number) {
+            Some(inv) => inv,
+            None => {
+                self.show_late_fee_window = false;
+                return;
+            }
+        };
+        let state = &mut self.late_fee_state;
+        let as_at = NaiveDate::parse_from_str(state.as_at_str.trim(), "%Y-%m-%d");
+        let quote = match as_at {
+            Ok(date) => quote_late_fee(invoice, &state.settings, date),
+            Err(_) => Err("Invalid date. Use YYYY-MM-DD.".to_string()),
+        };
+        Window::new(format!("Late Fee on Invoice #{}", state.invoice_number))
+            .id(Id::new("late_fee_window"))
+            .resizable(true)
+            .collapsible(false)
+            .default_width(450.0)
+            .show(ctx, |ui| {
+            ui.label(format!("{}: due {}, balance {:.2}", invoice.customer.name, invoice.due_date.format("%Y-%m-%d"), invoice.balance_due()));
+            egui::CollapsingHeader::new("Fee Settings")
+                .id_source("late_fee_settings")
+                .show(ui, |ui| {
+                    let settings = &mut state.settings;
+                    Grid::new("late_fee_settings_grid")
+                        .num_columns(2)
+                        .spacing([10.0, 4.0])
+                        .show(ui, |ui| {
+                            ui.label("Charge:");
+                            ui.horizontal(|ui| {
+                                for method in LateFeeMethod::ALL {
+                                    ui.radio_value(&mut settings.method, method, method.label());
+                                }
+                            });
+                            ui.end_row();
+                            match settings.method {
+                                LateFeeMethod::FlatFee => {
+                                    ui.label("Fee ($):");
+                                    ui.add(egui::DragValue::new(&mut settings.flat_fee).speed(1.0).range(0.0..=f64::MAX).fixed_decimals(2));
+                                }
+                                LateFeeMethod::DailyInterest => {
+                                    ui.label("Rate (% per day):");
+                                    ui.add(egui::DragValue::new(&mut settings.daily_rate).speed(0.01).range(0.0..=100.0));
+                                }
+                                LateFeeMethod::AnnualInterest => {
+                                    ui.label("Rate (% per year):");
+                                    ui.add(egui::DragValue::new(&mut settings.annual_rate).speed(0.1).range(0.0..=1000.0));
+                                }
+                            }
+                            ui.end_row();
+                            ui.label("Grace Period (days):");
+                            ui.add(egui::DragValue::new(&mut settings.grace_days).range(0..=365));
+                            ui.end_row();
+                            ui.label("Fee Invoice Due In (days):");
+                            ui.add(egui::DragValue::new(&mut settings.fee_due_days).range(0..=365));
+                            ui.end_row();
+                        });
+                    if ui.button("Save Settings").clicked() {
+                        save_settings = true;
+                    }
+                });
+            ui.separator();
+            Grid::new("late_fee_grid")
+                .num_columns(2)
+                .spacing([10.0, 4.0])
+                .show(ui, |ui| {
+                    ui.label("Charge Up To (YYYY-MM-DD):");
+                    ui.text_edit_singleline(&mut state.as_at_str);
+                    ui.end_row();
+                    ui.label("Bill On:");
+                    egui::ComboBox::from_id_source("late_fee_target")
+                        .selected_text(match &state.add_to {
+                            Some(number) => format!("Invoice #{}", number),
+                            None => "New fee invoice".to_string(),
+                        })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut state.add_to, None, "New fee invoice");
+                            for number in &state.follow_ups {
+                                ui.selectable_value(&mut state.add_to, Some(number.clone()), format!("Invoice #{}", number));
+                            }
+                        });
+                    ui.end_row();
+                });
+            ui.separator();
+            match &quote {
+                Ok(quote) => {
+                    ui.label(&quote.description);
+                    ui.label(RichText::new(format!("Fee: {:.2}", quote.amount)).strong());
+                }
+                Err(e) => {
+                    ui.colored_label(Color32::RED, e);
+                }
+            }
+            if !invoice.late_fees.is_empty() {
+                ui.separator();
+                ui.label(RichText::new("Fees Already Charged").strong());
+                for fee in &invoice.late_fees {
+                    ui.label(format!("{}  {:.2} on invoice #{}  ({})", fee.applied_at.format("%Y-%m-%d"), fee.amount, fee.billed_on, fee.description));
+                }
+            }
+            if let Some(err) = &state.error_message {
+                ui.colored_label(Color32::RED, err);
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.add_enabled(quote.is_ok(), egui::Button::new("Apply Fee")).clicked() {
+                    apply = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    close_window = true;
+                }
+            });
+        });
+
+        if save_settings || apply {
+            self.late_fee_state.error_message = None;
+            if let Err(e) = self.db.update_late_fee_settings_gui(self.late_fee_state.settings.clone()) {
+                self.late_fee_state.error_message = Some(e.to_string());
+                return;
+            }
+            if save_settings {
+                self.status_message = "Late fee settings saved.".to_string();
+            }
+        }
+        if apply {
+            if let Ok(as_at) = as_at {
+                let state = &self.late_fee_state;
+                let target = match &state.add_to {
+                    Some(number) => LateFeeTarget::AddTo(number.clone()),
+                    None => LateFeeTarget::NewInvoice,
+                };
+                let invoice_number = state.invoice_number.clone();
+                match self.db.apply_late_fee_gui(&invoice_number, as_at, &target) {
+                    Ok(billed_on) => {
+                        self.status_message = format!("Late fee on invoice #{} charged on invoice #{}.", invoice_number, billed_on);
+                        self.update_invoice_list();
+                        close_window = true;
+                    }
+                    Err(e) => self.late_fee_state.error_message = Some(e.to_string()),
+                }
+            }
+        }
+        if close_window {
+            self.show_late_fee_window = false;
+        }
+    }
+
+    fn aged_receivables_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        let mut export_csv = false;
+        let mut export_pdf = false;
+        let mut select_customer = None;
+        let as_at = NaiveDate::parse_from_str(self.aged_receivables_state.as_at_str.trim(), "%Y-%m-%d");
+        let report = as_at.ok().map(|date| self.db.aged_receivables(date));
+        Window::new("Aged Receivables")
+            .id(Id::new("aged_receivables_window"))
+            .resizable(true)
+            .collapsible(true)
+            .default_width(700.0)
+            .show(ctx, |ui| {
+            let state = &mut self.aged_receivables_state;
+            ui.horizontal(|ui| {
+                ui.label("As At (YYYY-MM-DD):");
+                ui.text_edit_singleline(&mut state.as_at_str);
+            });
+            ui.label("Outstanding balances by days past the invoice due date. Click a customer to show their invoices.");
+            ui.separator();
+            match &report {
+                None => {
+                    ui.colored_label(Color32::RED, "Invalid date. Use YYYY-MM-DD.");
+                }
+                Some(report) if report.rows.is_empty() => {
+                    ui.label("Nothing is owing.");
+                }
+                Some(report) => {
+                    ScrollArea::vertical().id_source("aged_receivables_scroll").max_height(400.0).show(ui, |ui| {
+                        Grid::new("aged_receivables_grid")
+                            .num_columns(BUCKET_LABELS.len() + 2)
+                            .spacing([14.0, 4.0])
+                            .striped(true)
+                            .show(ui, |ui| {
+                                ui.label(RichText::new("Customer").strong());
+                                for label in BUCKET_LABELS {
+                                    ui.label(RichText::new(label).strong());
+                                }
+                                ui.label(RichText::new("Total").strong());
+                                ui.end_row();
+                                for row in &report.rows {
+                                    if ui.link(format!("{} ({})", row.customer_name, row.customer_code))
+                                        .on_hover_text(format!("{} invoices owing", row.invoice_count))
+                                        .clicked() {
+                                        select_customer = Some(row.customer_code.clone());
+                                    }
+                                    for (i, amount) in row.buckets.iter().enumerate() {
+                                        let text = if *amount > 0.0 { format!("{:.2}", amount) } else { "-".to_string() };
+                                        // Anything over 60 days past due stands out
+                                        if i >= 3 && *amount > 0.0 {
+                                            ui.colored_label(Color32::RED, text);
+                                        } else {
+                                            ui.label(text);
+                                        }
+                                    }
+                                    ui.label(format!("{:.2}", row.total));
+                                    ui.end_row();
+                                }
+                                ui.label(RichText::new("Total").strong());
+                                for amount in &report.totals {
+                                    ui.label(RichText::new(format!("{:.2}", amount)).strong());
+                                }
+                                ui.label(RichText::new(format!("{:.2}", report.total)).strong());
+                                ui.end_row();
+                            });
+                    });
+                }
+            }
+            if let Some(err) = &state.error_message {
+                ui.colored_label(Color32::RED, err);
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.add_enabled(report.is_some(), egui::Button::new("Export CSV...")).clicked() {
+                    export_csv = true;
+                }
+                if ui.add_enabled(report.is_some(), egui::Button::new("Export PDF...")).clicked() {
+                    export_pdf = true;
+                }
+                if ui.button("Close").clicked() {
+                    close_window = true;
+                }
+            });
+        });
+
+        if let Some(code) = select_customer {
+            self.selected_customer_code = Some(code);
+            self.update_invoice_list();
+        }
+        if let Ok(as_at) = as_at {
+            let (extension, filter_name) = if export_pdf { ("pdf", "PDF") } else { ("csv", "CSV") };
+            if export_csv || export_pdf {
+                if let Some(path) = FileDialog::new()
+                    .set_file_name(format!("Aged-Receivables-{}.{}", as_at.format("%Y-%m-%d"), extension))
+                    .add_filter(filter_name, &[extension])
+                    .save_file() {
+                    let filename = path.to_string_lossy();
+                    let result = if export_pdf {
+                        self.db.generate_aged_receivables_pdf_gui(as_at, &filename, &self.pdf_options)
+                    } else {
+                        self.db.export_aged_receivables_csv_gui(as_at, &filename)
+                    };
+                    match result {
+                        Ok(_) => {
+                            self.aged_receivables_state.error_message = None;
+                            self.status_message = format!("Aged receivables saved: {}", path.display());
+                        }
+                        Err(e) => self.aged_receivables_state.error_message = Some(e.to_string()),
+                    }
+                }
+            }
+        }
+        if close_window {
+            self.show_aged_receivables_window = false;
+        }
+    }
+
+    fn bas_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        let mut export_csv = false;
+        let state = &self.bas_state;
+        let period = state.periods[state.period_index].clone();
+        let report = self.db.bas_report(&period, state.basis);
+        Window::new("GST / BAS Summary")
+            .id(Id::new("bas_window"))
+            .resizable(true)
+            .collapsible(true)
+            .default_width(650.0)
+            .show(ctx, |ui| {
+            let state = &mut self.bas_state;
+            Grid::new("bas_options_grid")
+                .num_columns(2)
+                .spacing([10.0, 4.0])
+                .show(ui, |ui| {
+                    ui.label("Reporting:");
+                    ui.horizontal(|ui| {
+                        for length in PeriodLength::ALL {
+                            if ui.radio_value(&mut state.length, length, length.label()).changed() {
+                                state.periods = BasPeriod::recent(Local::now().date_naive(), length, BAS_PERIODS_SHOWN);
+                                state.period_index = 1.min(state.periods.len() - 1);
+                            }
+                        }
+                    });
+                    ui.end_row();
+                    ui.label("Period:");
+                    egui::ComboBox::from_id_source("bas_period")
+                        .selected_text(state.periods[state.period_index].label())
+                        .show_ui(ui, |ui| {
+                            for (i, period) in state.periods.iter().enumerate() {
+                                ui.selectable_value(&mut state.period_index, i, period.label());
+                            }
+                        });
+                    ui.end_row();
+                    ui.label("Basis:");
+                    ui.horizontal(|ui| {
+                        for basis in BasBasis::ALL {
+                            ui.radio_value(&mut state.basis, basis, basis.label());
+                        }
+                    });
+                    ui.end_row();
+                });
+            ui.separator();
+            match &report {
+                Ok(report) => {
+                    Grid::new("bas_summary_grid")
+                        .num_columns(3)
+                        .spacing([14.0, 4.0])
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for (label, description, amount) in bas_summary(report) {
+                                ui.label(RichText::new(label).strong());
+                                ui.label(description);
+                                ui.label(RichText::new(format!("{:.2}", amount)).strong());
+                                ui.end_row();
+                            }
+                        });
+                    ui.separator();
+                    ui.label(format!("{} contributing entries, {} to {}:", report.lines.len(), report.period.from.format("%d/%m/%Y"), report.period.to.format("%d/%m/%Y")));
+                    ScrollArea::vertical().id_source("bas_lines_scroll").max_height(300.0).show(ui, |ui| {
+                        Grid::new("bas_lines_grid")
+                            .num_columns(6)
+                            .spacing([10.0, 4.0])
+                            .striped(true)
+                            .show(ui, |ui| {
+                                ui.label(RichText::new("Date").strong());
+                                ui.label(RichText::new("Invoice").strong());
+                                ui.label(RichText::new("Description").strong());
+                                ui.label(RichText::new("Amount").strong());
+                                ui.label(RichText::new("GST").strong());
+                                ui.label(RichText::new("GST-free").strong());
+                                ui.end_row();
+                                for line in &report.lines {
+                                    ui.label(line.date.format("%d/%m/%Y").to_string());
+                                    ui.label(format!("{} ({})", line.invoice_number, line.customer_name));
+                                    ui.label(&line.description);
+                                    ui.label(format!("{:.2}", line.amount));
+                                    ui.label(format!("{:.2}", line.gst));
+                                    ui.label(format!("{:.2}", line.gst_free));
+                                    ui.end_row();
+                                }
+                            });
+                    });
+                }
+                Err(e) => {
+                    ui.colored_label(Color32::RED, e.to_string());
+                }
+            }
+            if let Some(err) = &state.error_message {
+                ui.colored_label(Color32::RED, err);
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Export CSV...").clicked() {
+                    export_csv = true;
+                }
+                if ui.button("Close").clicked() {
+                    close_window = true;
+                }
+            });
+        });
+
+        if export_csv {
+            let basis = self.bas_state.basis;
+            if let Some(path) = FileDialog::new()
+                .set_file_name(format!("BAS-{}-{}.csv", period.from.format("%Y-%m"), basis.label()))
+                .add_filter("CSV", &["csv"])
+                .save_file() {
+                match self.db.export_bas_csv_gui(&period, basis, &path.to_string_lossy()) {
+                    Ok(_) => {
+                        self.bas_state.error_message = None;
+                        self.status_message = format!("BAS summary saved: {}", path.display());
+                    }
+                    Err(e) => self.bas_state.error_message = Some(e.to_string()),
+                }
+            }
+        }
+        if close_window {
+            self.show_bas_window = false;
+        }
+    }
+
+    fn all_invoices_panel(&mut self, ui: &mut Ui) {
+        let state = &mut self.all_invoices_state;
+        let filter = InvoiceFilter { status: state.status, ..InvoiceFilter::default() };
+        let mut invoices: Vec<&Invoice> = self.db.invoices.values().filter(|inv| filter.matches(inv)).collect();
+        invoices.sort_by(|a, b| {
+            let order = state.sort.compare(a, b);
+            if state.descending { order.reverse() } else { order }
+        });
+        // Forget ticks on invoices that aren't listed (deleted, renumbered or filtered out), so
+        // the bulk actions only ever apply to rows you can see
+        let listed: HashSet<&str> = invoices.iter().map(|inv| inv.invoice_number.as_str()).collect();
+        state.selected.retain(|number| listed.contains(number.as_str()));
+
+        let mut bulk_mark_paid = false;
+        let mut bulk_export = false;
+        let mut bulk_email = false;
+        ui.horizontal(|ui| {
+            ui.label("Status:");
+            egui::ComboBox::from_id_source("all_invoices_status")
+                .selected_text(state.status.label())
+                .show_ui(ui, |ui| {
+                    for status in InvoiceStatusFilter::ALL {
+                        ui.selectable_value(&mut state.status, status, status.label());
+                    }
+                });
+            ui.label(format!("{} invoices", invoices.len()));
+            ui.separator();
+            ui.label(format!("{} selected", state.selected.len()));
+            let any = !state.selected.is_empty();
+            bulk_mark_paid = ui.add_enabled(any, egui::Button::new("Mark Paid")).clicked();
+            bulk_export = ui.add_enabled(any, egui::Button::new("Export PDFs...")).clicked();
+            bulk_email = ui.add_enabled(any, egui::Button::new("Send by Email...")).clicked();
+            if ui.add_enabled(any, egui::Button::new("Clear Selection")).clicked() {
+                state.selected.clear();
+                state.anchor = None;
+            }
+        });
+        ui.small("Click a column heading to sort. Shift-click a tick box to select a range.");
+        ui.separator();
+
+        let widths = ALL_INVOICES_COLUMN_WIDTHS;
+        let row_height = ui.spacing().interact_size.y;
+        let cell = |ui: &mut Ui, width: f32, right: bool, add: &mut dyn FnMut(&mut Ui)| {
+            let layout = if right {
+                egui::Layout::right_to_left(egui::Align::Center)
+            } else {
+                egui::Layout::left_to_right(egui::Align::Center)
+            };
+            ui.allocate_ui_with_layout(vec2(width, row_height), layout, |ui| {
+                ui.set_width(width);
+                add(ui);
+            });
+        };
+
+        // Header: tick box for every listed invoice, then the sortable columns
+        ui.horizontal(|ui| {
+            cell(ui, widths[0], false, &mut |ui| {
+                let all_ticked = !invoices.is_empty() && invoices.iter().all(|inv| state.selected.contains(&inv.invoice_number));
+                let mut ticked = all_ticked;
+                if ui.checkbox(&mut ticked, "").on_hover_text("Select all listed invoices").changed() {
+                    if ticked {
+                        state.selected.extend(invoices.iter().map(|inv| inv.invoice_number.clone()));
+                    } else {
+                        for invoice in &invoices {
+                            state.selected.remove(&invoice.invoice_number);
+                        }
+                    }
+                }
+            });
+            for (i, column) in InvoiceSort::ALL.iter().enumerate() {
+                let right = matches!(column, InvoiceSort::Total | InvoiceSort::Balance);
+                cell(ui, widths[i + 1], right, &mut |ui| {
+                    let active = state.sort == *column;
+                    let arrow = match (active, state.descending) {
+                        (false, _) => "",
+                        (true, false) => " ^",
+                        (true, true) => " v",
+                    };
+                    if ui.selectable_label(active, RichText::new(format!("{}{}", column.label(), arrow)).strong()).clicked() {
+                        if active {
+                            state.descending = !state.descending;
+                        } else {
+                            state.sort = *column;
+                            state.descending = false;
+                        }
+                    }
+                });
+            }
+        });
+        ui.separator();
+
+        // Only the rows in view are laid out, so long lists stay fast
+        let today = Local::now().date_naive();
+        let shift = ui.input(|i| i.modifiers.shift);
+        let mut invoice_to_open = None;
+        let mut ticked_row = None;
+        ScrollArea::vertical()
+            .id_source("all_invoices_scroll")
+            .auto_shrink([false, false])
+            .show_rows(ui, row_height, invoices.len(), |ui, rows| {
+                for (index, invoice) in invoices.iter().enumerate().skip(rows.start).take(rows.len()) {
+                    ui.horizontal(|ui| {
+                        cell(ui, widths[0], false, &mut |ui| {
+                            let mut ticked = state.selected.contains(&invoice.invoice_number);
+                            if ui.checkbox(&mut ticked, "").changed() {
+                                ticked_row = Some((index, ticked));
+                            }
+                        });
+                        cell(ui, widths[1], false, &mut |ui| {
+                            if ui.link(&invoice.invoice_number).on_hover_text("Open invoice").clicked() {
+                                invoice_to_open = Some((*invoice).clone());
+                            }
+                        });
+                        cell(ui, widths[2], false, &mut |ui| {
+                            ui.add(egui::Label::new(&invoice.customer.name).truncate());
+                        });
+                        cell(ui, widths[3], false, &mut |ui| {
+                            ui.label(invoice.date.format("%Y-%m-%d").to_string());
+                        });
+                        cell(ui, widths[4], false, &mut |ui| {
+                            ui.label(invoice.due_date.format("%Y-%m-%d").to_string());
+                        });
+                        cell(ui, widths[5], true, &mut |ui| {
+                            ui.label(format!("{:.2}", invoice.total));
+                        });
+                        cell(ui, widths[6], true, &mut |ui| {
+                            ui.label(format!("{:.2}", invoice.balance_due()));
+                        });
+                        cell(ui, widths[7], false, &mut |ui| {
+                            if invoice.is_overdue(today) {
+                                ui.colored_label(Color32::RED, invoice.status_label()).on_hover_text("Overdue");
+                            } else {
+                                ui.label(invoice.status_label());
+                            }
+                        });
+                    });
+                }
+            });
+
+        if let Some((index, ticked)) = ticked_row {
+            let number = invoices[index].invoice_number.clone();
+            let anchor = state.anchor.as_ref().and_then(|a| invoices.iter().position(|inv| inv.invoice_number == *a));
+            let range = match anchor {
+                Some(anchor) if shift => anchor.min(index)..=anchor.max(index),
+                _ => index..=index,
+            };
+            for invoice in &invoices[range] {
+                if ticked {
+                    state.selected.insert(invoice.invoice_number.clone());
+                } else {
+                    state.selected.remove(&invoice.invoice_number);
+                }
+            }
+            state.anchor = Some(number);
+        }
+
+        // Bulk actions work on the ticked invoices, listed in the current order
+        let picked: Vec<String> = invoices.iter()
+            .filter(|inv| state.selected.contains(&inv.invoice_number))
+            .map(|inv| inv.invoice_number.clone())
+            .collect();
+        if bulk_mark_paid {
+            match self.db.mark_invoices_paid_gui(&picked) {
+                Ok(marked) => {
+                    self.status_message = format!("Marked {} invoices as paid.", marked);
+                    self.update_invoice_list();
+                }
+                Err(e) => self.status_message = format!("Error marking invoices paid: {}", e),
+            }
+        }
+        if bulk_export {
+            self.batch_export_state.invoice_numbers = picked.clone();
+            self.batch_export_state.error_message = None;
+            self.show_batch_export_window = true;
+        }
+        if bulk_email {
+            self.bulk_email_state = BulkEmailState { invoice_numbers: picked, ..Default::default() };
+            self.show_bulk_email_window = true;
+        }
+        if let Some(invoice) = invoice_to_open {
+            self.invoice_to_view = Some(invoice);
+            self.show_view_invoice_window = true;
+        }
+    }
+
+    fn bulk_email_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        let mut send = false;
+        let email_configured = self.db.email.is_configured();
+        let running = self.bulk_email_state.receiver.is_some();
+        let (sendable, no_address): (Vec<&String>, Vec<&String>) = self.bulk_email_state.invoice_numbers.iter()
+            .filter(|number| self.db.invoices.contains_key(number.as_str()))
+            .partition(|number| self.db.invoices.get(number.as_str()).is_some_and(|inv| !inv.customer.email.trim().is_empty()));
+        Window::new("Send Invoices by Email")
+            .id(Id::new("bulk_email_window"))
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+            ui.label(format!(
+                "{} invoices will be emailed to their customers as PDFs, using the subject and message templates in the email settings.",
+                sendable.len()
+            ));
+            if !no_address.is_empty() {
+                let numbers: Vec<&str> = no_address.iter().map(|n| n.as_str()).collect();
+                ui.label(format!("Skipped, customer has no email address: {}", numbers.join(", ")));
+            }
+            if !email_configured {
+                ui.colored_label(Color32::RED, "Email is not set up yet. Use File > Email Settings... first.");
+            }
+            ui.separator();
+            if let Some((done, total)) = self.bulk_email_state.progress {
+                let fraction = if total == 0 { 0.0 } else { done as f32 / total as f32 };
+                ui.add(egui::ProgressBar::new(fraction).text(format!("{} / {}", done, total)));
+            }
+            ui.horizontal(|ui| {
+                let label = format!("Send {} Emails", sendable.len());
+                if ui.add_enabled(!running && email_configured && !sendable.is_empty(), egui::Button::new(label)).clicked() {
+                    send = true;
+                }
+                if ui.add_enabled(!running, egui::Button::new("Cancel")).clicked() {
+                    close_window = true;
+                }
+            });
+        });
+
+        if send {
+            let jobs: Vec<(Invoice, EmailDraft)> = sendable.iter()
+                .filter_map(|number| {
+                    let draft = self.db.email_draft(number).ok()?;
+                    Some((self.db.invoices.get(number.as_str())?.clone(), draft))
+                })
+                .collect();
+            let skipped = no_address.len();
+            let state = &mut self.bulk_email_state;
+            state.skipped = skipped;
+            state.progress = Some((0, jobs.len()));
+            state.receiver = Some(spawn_bulk_email(jobs, self.db.company.clone(), self.db.email.clone(), self.pdf_options.clone()));
+        }
+
+        // Pick up progress from the email thread, recording each send as it arrives
+        if let Some(receiver) = &self.bulk_email_state.receiver {
+            let mut finished = false;
+            while let Ok(message) = receiver.try_recv() {
+                match message {
+                    BulkEmailProgress::Working { done, total } => self.bulk_email_state.progress = Some((done, total)),
+                    BulkEmailProgress::Sent(number, sent) => match self.db.record_sent_email_gui(&number, sent) {
+                        Ok(()) => self.bulk_email_state.sent += 1,
+                        Err(e) => self.bulk_email_state.problems.push(format!("{}: sent, but not recorded ({})", number, e)),
+                    },
+                    BulkEmailProgress::Failed(number, error) => self.bulk_email_state.problems.push(format!("{}: {}", number, error)),
+                    BulkEmailProgress::Finished => finished = true,
+                }
+            }
+            if finished {
+                let state = &self.bulk_email_state;
+                self.status_message = format!("Emailed {} invoices", state.sent);
+                if state.skipped > 0 {
+                    self.status_message.push_str(&format!("; {} skipped without an email address", state.skipped));
+                }
+                if state.problems.is_empty() {
+                    self.status_message.push('.');
+                } else {
+                    self.status_message.push_str(&format!("; {} not sent ({}).", state.problems.len(), state.problems.join("; ")));
+                }
+                close_window = true;
+            } else {
+                ctx.request_repaint(); // Keep polling while the emails go out
+            }
+        }
+        if close_window {
+            self.show_bulk_email_window = false;
+            self.bulk_email_state = BulkEmailState::default();
+        }
+    }
+
+    fn search_panel(&mut self, ui: &mut Ui) {
+        let state = &mut self.search_state;
+        ui.horizontal(|ui| {
+            ui.label("Status:");
+            egui::ComboBox::from_id_source("search_status")
+                .selected_text(state.status.label())
+                .show_ui(ui, |ui| {
+                    for status in InvoiceStatusFilter::ALL {
+                        ui.selectable_value(&mut state.status, status, status.label());
+                    }
+                });
+            ui.label("Issued from:");
+            ui.add(TextEdit::singleline(&mut state.from_date_str).hint_text("YYYY-MM-DD").desired_width(90.0));
+            ui.label("to:");
+            ui.add(TextEdit::singleline(&mut state.to_date_str).hint_text("YYYY-MM-DD").desired_width(90.0));
+            if ui.button("Clear Filters").clicked() {
+                *state = SearchState { query: std::mem::take(&mut state.query), ..SearchState::default() };
+            }
+        });
+        ui.separator();
+        if state.query.trim().is_empty() {
+            ui.label("Type in the search box on the left to find customers by name, code, email or phone, and invoices by number, line description, notes or amount.");
+            return;
+        }
+        let filter = match (parse_optional_date(&state.from_date_str), parse_optional_date(&state.to_date_str)) {
+            (Ok(from_date), Ok(to_date)) => InvoiceFilter { from_date, to_date, customer_codes: Vec::new(), status: state.status },
+            (Err(e), _) | (_, Err(e)) => {
+                ui.colored_label(Color32::RED, e);
+                return;
+            }
+        };
+        let results = self.db.search(&state.query, &filter);
+
+        let mut customer_to_select = None;
+        let mut invoice_to_open = None;
+        ScrollArea::vertical().id_source("search_scroll").show(ui, |ui| {
+            ui.heading(format!("Customers ({})", results.customers.len()));
+            if results.customers.is_empty() {
+                ui.label("No matching customers.");
+            }
+            for customer in &results.customers {
+                let details: Vec<&str> = [&customer.email, &customer.phone].into_iter()
+                    .map(|s| s.as_str())
+                    .filter(|s| !s.is_empty())
+                    .collect();
+                let label = format!("{} ({})  {}", customer.name, customer.code, details.join("  "));
+                if ui.selectable_label(false, label).on_hover_text("Show this customer's invoices").clicked() {
+                    customer_to_select = Some(customer.code.clone());
+                }
+            }
+            ui.add_space(8.0);
+            if results.invoice_total > results.invoices.len() {
+                ui.heading(format!("Invoices (newest {} of {})", results.invoices.len(), results.invoice_total));
+            } else {
+                ui.heading(format!("Invoices ({})", results.invoice_total));
+            }
+            if results.invoices.is_empty() {
+                ui.label("No matching invoices with these filters.");
+                return;
+            }
+            Grid::new("search_results_grid")
+                .num_columns(8)
+                .spacing([10.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    for heading in ["Number", "Customer", "Date", "Due", "Total", "Balance", "Status", "Matched"] {
+                        ui.label(RichText::new(heading).strong());
+                    }
+                    ui.end_row();
+                    for hit in &results.invoices {
+                        let invoice = &hit.invoice;
+                        if ui.link(&invoice.invoice_number).on_hover_text("Open invoice").clicked() {
+                            invoice_to_open = Some(invoice.clone());
+                        }
+                        ui.label(&invoice.customer.name);
+                        ui.label(invoice.date.format("%Y-%m-%d").to_string());
+                        ui.label(invoice.due_date.format("%Y-%m-%d").to_string());
+                        ui.label(format!("{:.2}", invoice.total));
+                        ui.label(format!("{:.2}", invoice.balance_due()));
+                        ui.label(invoice.status_label());
+                        ui.label(&hit.matched_on);
+                        ui.end_row();
+                    }
+                });
+        });
+
+        if let Some(code) = customer_to_select {
+            self.selected_customer_code = Some(code);
+            self.update_invoice_list();
+            self.main_view = MainView::CustomerInvoices;
+        }
+        if let Some(invoice) = invoice_to_open {
+            // Select the invoice's customer too, so the invoice list is there once it is closed
+            self.selected_customer_code = Some(invoice.customer.code.clone());
+            self.update_invoice_list();
+            self.selected_invoice_number = Some(invoice.invoice_number.clone());
+            self.invoice_to_view = Some(invoice);
+            self.show_view_invoice_window = true;
+        }
+    }
+
+    fn dashboard_panel(&mut self, ui: &mut Ui) {
+        let today = Local::now().date_naive();
+        let state = &mut self.dashboard_state;
+        ui.horizontal(|ui| {
+            ui.label("From:");
+            ui.add(TextEdit::singleline(&mut state.from_str).desired_width(90.0));
+            ui.label("To:");
+            ui.add(TextEdit::singleline(&mut state.to_str).desired_width(90.0));
+            let (this_fy_from, this_fy_to) = financial_year(today);
+            let presets = [
+                ("Last 12 Months", last_twelve_months(today)),
+                ("This Financial Year", (this_fy_from, this_fy_to)),
+                ("Last Financial Year", financial_year(this_fy_from - chrono::Duration::days(1))),
+            ];
+            for (label, (from, to)) in presets {
+                if ui.button(label).clicked() {
+                    state.from_str = from.format("%Y-%m-%d").to_string();
+                    state.to_str = to.format("%Y-%m-%d").to_string();
+                }
+            }
+        });
+        let range = (
+            NaiveDate::parse_from_str(state.from_str.trim(), "%Y-%m-%d"),
+            NaiveDate::parse_from_str(state.to_str.trim(), "%Y-%m-%d"),
+        );
+        let stats = match range {
+            (Ok(from), Ok(to)) => self.db.dashboard(from, to).map_err(|e| e.to_string()),
+            _ => Err("Invalid date. Use YYYY-MM-DD.".to_string()),
+        };
+        let stats = match stats {
+            Ok(stats) => stats,
+            Err(e) => {
+                ui.colored_label(Color32::RED, e);
+                return;
+            }
+        };
+        ui.separator();
+
+        ScrollArea::vertical().id_source("dashboard_scroll").show(ui, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                let figures = [
+                    ("Invoiced", format!("{:.2}", stats.invoiced), format!("{} invoices", stats.invoice_count)),
+                    ("Collected", format!("{:.2}", stats.collected), "Paid or credited".to_string()),
+                    ("Outstanding", format!("{:.2}", stats.outstanding), "Still owing".to_string()),
+                    ("Average Days to Pay", stats.average_days_to_pay.map_or("-".to_string(), |d| format!("{:.1}", d)), "Issue to final payment".to_string()),
+                    ("Overdue Now", format!("{}", stats.overdue_count), format!("{:.2} owing", stats.overdue_amount)),
+                ];
+                for (title, value, note) in figures {
+                    ui.group(|ui| {
+                        ui.set_min_width(120.0);
+                        ui.vertical(|ui| {
+                            ui.label(title);
+                            let value = RichText::new(value).heading().strong();
+                            if title == "Overdue Now" && stats.overdue_count > 0 {
+                                ui.label(value.color(Color32::RED));
+                            } else {
+                                ui.label(value);
+                            }
+                            ui.small(note);
+                        });
+                    });
+                }
+            });
+            ui.add_space(8.0);
+            ui.heading("Revenue by Month");
+            legend(ui, &[("Invoiced", INVOICED_COLOR), ("Collected (payments received)", COLLECTED_COLOR)]);
+            month_revenue_chart(ui, &stats.months);
+            ui.add_space(8.0);
+            ui.heading("Collected vs Outstanding");
+            ui.label("For invoices issued in the range");
+            legend(ui, &[("Collected", COLLECTED_COLOR), ("Outstanding", OUTSTANDING_COLOR)]);
+            collected_outstanding_bar(ui, &stats);
+            ui.add_space(8.0);
+            ui.heading("Top Customers by Revenue");
+            if stats.top_customers.is_empty() {
+                ui.label("No invoices in this range.");
+            }
+            let max = stats.top_customers.first().map_or(0.0, |c| c.invoiced);
+            for customer in &stats.top_customers {
+                horizontal_bar(ui, &format!("{} ({})", customer.name, customer.code), customer.invoiced, max, INVOICED_COLOR);
+            }
+        });
+    }
+
+    fn open_pdf_preview(&mut self, invoice_number: &str) {
+        self.pdf_preview_state = PdfPreviewState {
+            invoice_number: invoice_number.to_string(),
+            ..Default::default()
+        };
+        self.refresh_pdf_preview();
+        self.show_pdf_preview_window = true;
+    }
+
+    // Lays the invoice out again with the current PDF options
+    fn refresh_pdf_preview(&mut self) {
+        let state = &mut self.pdf_preview_state;
+        state.options = self.pdf_options.clone();
+        match self.db.layout_invoice_gui(&state.invoice_number, &state.options) {
+            Ok(pages) => {
+                state.current_page = state.current_page.min(pages.len().saturating_sub(1));
+                state.pages = pages;
+                state.error_message = None;
+            }
+            Err(e) => {
+                state.pages.clear();
+                state.error_message = Some(e.to_string());
+            }
+        }
+    }
+
+    // Asks for a file name and writes the invoice PDF, returning the saved path
+    fn save_invoice_pdf(&mut self, invoice_number: &str) -> Option<PathBuf> {
+        let path = FileDialog::new()
+            .set_file_name(format!("Invoice-{}.pdf", invoice_number))
+            .add_filter("PDF", &["pdf"])
+            .save_file();
+        match path {
+            Some(path) => match self.db.generate_pdf_gui(invoice_number, &path, &self.pdf_options) {
+                Ok(filename) => {
+                    self.status_message = format!("PDF generated: {}", filename);
+                    Some(path)
+                }
+                Err(e) => {
+                    self.status_message = format!("Error generating PDF: {}", e);
+                    None
+                }
+            },
+            None => {
+                self.status_message = "PDF generation cancelled.".to_string();
+                None
+            }
+        }
+    }
+
+    fn pdf_preview_window(&mut self, ctx: &Context) {
+        // Keep the preview in step with the PDF options in the Invoice menu
+        if self.pdf_preview_state.options != self.pdf_options {
+            self.refresh_pdf_preview();
+        }
+        let mut close_window = false;
+        let mut save = false;
+        let mut save_and_open = false;
+        let mut print = false;
+        let state = &mut self.pdf_preview_state;
+        let window_id = Id::new(format!("pdf_preview_window_{}", state.invoice_number));
+        Window::new(format!("PDF Preview: Invoice #{}", state.invoice_number))
+            .id(window_id) // Unique ID per invoice
+            .resizable(true)
+            .collapsible(false)
+            .default_size([650.0, 750.0])
+            .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let page_count = state.pages.len();
+                if ui.add_enabled(state.current_page > 0, egui::Button::new("< Prev")).clicked() {
+                    state.current_page -= 1;
+                }
+                ui.label(format!("Page {} of {}", (state.current_page + 1).min(page_count), page_count));
+                if ui.add_enabled(state.current_page + 1 < page_count, egui::Button::new("Next >")).clicked() {
+                    state.current_page += 1;
+                }
+                ui.separator();
+                if ui.button("-").clicked() {
+                    state.zoom = (state.zoom - 0.25).max(0.25);
+                }
+                ui.add(egui::Slider::new(&mut state.zoom, 0.25..=3.0).text("Zoom"));
+                if ui.button("+").clicked() {
+                    state.zoom = (state.zoom + 0.25).min(3.0);
+                }
+            });
+            ui.horizontal(|ui| {
+                save = ui.button("Save").clicked();
+                save_and_open = ui.button("Save & Open").clicked();
+                print = ui.button("Print").clicked();
+                if ui.button("Close").clicked() {
+                    close_window = true;
+                }
+            });
+            if let Some(err) = &state.error_message {
+                ui.colored_label(Color32::RED, err);
+            }
+            ui.separator();
+            ScrollArea::both().id_source("pdf_preview_scroll").show(ui, |ui| {
+                if let Some(page) = state.pages.get(state.current_page) {
+                    paint_page(ui, page, state.zoom, state.options.embeds_fonts());
+                }
+            });
+        });
+
+        let invoice_number = self.pdf_preview_state.invoice_number.clone();
+        if save {
+            self.save_invoice_pdf(&invoice_number);
+        }
+        if save_and_open {
+            if let Some(path) = self.save_invoice_pdf(&invoice_number) {
+                if let Err(e) = open_file(&path) {
+                    self.status_message = format!("Error opening PDF: {}", e);
+                }
+            }
+        }
+        if print {
+            // Print from a temporary copy so nothing has to be saved first
+            let path = std::env::temp_dir().join(format!("Invoice-{}.pdf", invoice_number));
+            let result = self.db.generate_pdf_gui(&invoice_number, &path, &self.pdf_options)
+                .map_err(|e| e.to_string())
+                .and_then(|_| print_file(&path).map_err(|e| e.to_string()));
+            self.status_message = match result {
+                Ok(_) => format!("Invoice #{} sent to the printer.", invoice_number),
+                Err(e) => format!("Error printing invoice: {}", e),
+            };
+        }
+        if close_window {
+            self.show_pdf_preview_window = false;
+            self.pdf_preview_state = PdfPreviewState::default();
+        }
+    }
+
+    fn batch_export_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        let running = self.batch_export_state.receiver.is_some();
+        let picked = self.batch_export_state.invoice_numbers.len();
+        Window::new("Batch PDF Export")
+            .id(Id::new("batch_export_window")) // Unique ID
+            .resizable(true)
+            .collapsible(false)
+            .default_width(450.0)
+            .show(ctx, |ui| {
+            ui.add_enabled_ui(!running, |ui| {
+                Grid::new("batch_export_grid")
+                    .num_columns(2)
+                    .spacing([10.0, 4.0])
+                    .striped(true)
+                    .show(ui, |ui| {
+                        if picked > 0 {
+                            ui.label("Invoices:");
+                            ui.horizontal(|ui| {
+                                ui.label(format!("{} selected in All Invoices", picked));
+                                if ui.button("Use Filters Instead").clicked() {
+                                    self.batch_export_state.invoice_numbers.clear();
+                                }
+                            });
+                            ui.end_row();
+                        } else {
+                            ui.label("From (YYYY-MM-DD):");
+                            ui.add(TextEdit::singleline(&mut self.batch_export_state.from_date_str).hint_text("Any date"));
+                            ui.end_row();
+                            ui.label("To (YYYY-MM-DD):");
+                            ui.add(TextEdit::singleline(&mut self.batch_export_state.to_date_str).hint_text("Any date"));
+                            ui.end_row();
+                            ui.label("Status:");
+                            egui::ComboBox::from_id_source("batch_export_status")
+                                .selected_text(self.batch_export_state.status.label())
+                                .show_ui(ui, |ui| {
+                                    for status in InvoiceStatusFilter::ALL {
+                                        ui.selectable_value(&mut self.batch_export_state.status, status, status.label());
+                                    }
+                                });
+                            ui.end_row();
+                        }
+                        ui.label("Folder:");
+                        ui.horizontal(|ui| {
+                            ui.text_edit_singleline(&mut self.batch_export_state.folder);
+                            if ui.button("Browse...").clicked() {
+                                if let Some(folder) = FileDialog::new().pick_folder() {
+                                    self.batch_export_state.folder = folder.display().to_string();
+                                }
+                            }
+                        });
+                        ui.end_row();
+                        ui.label("File Name Pattern:");
+                        ui.add(TextEdit::singleline(&mut self.batch_export_state.filename_pattern).hint_text(DEFAULT_FILENAME_PATTERN));
+                        ui.end_row();
+                        ui.label("");
+                        ui.small("Placeholders: {number} {code} {customer} {date} {due}");
+                        ui.end_row();
+                        ui.label("Output:");
+                        ui.horizontal(|ui| {
+                            ui.radio_value(&mut self.batch_export_state.output, BatchOutput::SeparateFiles, "Separate PDFs");
+                            ui.radio_value(&mut self.batch_export_state.output, BatchOutput::MergedPdf, "Single merged PDF");
+                            ui.radio_value(&mut self.batch_export_state.output, BatchOutput::Zip, "ZIP archive");
+                        });
+                        ui.end_row();
+                    });
+                if picked == 0 {
+                    ui.separator();
+                    ui.label("Customers (none ticked = all customers):");
+                    ScrollArea::vertical().id_source("batch_export_customers_scroll").max_height(150.0).show(ui, |ui| {
+                        for customer in &self.customers {
+                            let mut checked = self.batch_export_state.customer_codes.contains(&customer.code);
+                            if ui.checkbox(&mut checked, format!("{} ({})", customer.name, customer.code)).changed() {
+                                if checked {
+                                    self.batch_export_state.customer_codes.insert(customer.code.clone());
+                                } else {
+                                    self.batch_export_state.customer_codes.remove(&customer.code);
+                                }
+                            }
+                        }
+                    });
+                }
+            });
+            ui.separator();
+            if let Some((done, total)) = self.batch_export_state.progress {
+                let fraction = if total == 0 { 0.0 } else { done as f32 / total as f32 };
+                ui.add(egui::ProgressBar::new(fraction).text(format!("{} / {}", done, total)));
+            }
+            if let Some(err) = &self.batch_export_state.error_message {
+                ui.colored_label(Color32::RED, err);
+            }
+            ui.horizontal(|ui| {
+                if ui.add_enabled(!running, egui::Button::new("Export")).clicked() {
+                    self.start_batch_export();
+                }
+                if ui.add_enabled(!running, egui::Button::new("Close")).clicked() {
+                    close_window = true;
+                }
+            });
+        });
+
+        // Pick up progress from the export thread
+        if let Some(receiver) = &self.batch_export_state.receiver {
+            let mut finished = None;
+            while let Ok(message) = receiver.try_recv() {
+                match message {
+                    BatchProgress::Working { done, total } => self.batch_export_state.progress = Some((done, total)),
+                    BatchProgress::Finished(result) => finished = Some(result),
+                }
+            }
+            match finished {
+                Some(Ok(summary)) => {
+                    self.status_message = summary;
+                    self.batch_export_state.receiver = None;
+                    close_window = true;
+                }
+                Some(Err(e)) => {
+                    self.batch_export_state.error_message = Some(e);
+                    self.batch_export_state.receiver = None;
+                    self.batch_export_state.progress = None;
+                }
+                None => ctx.request_repaint(), // Keep polling while the export runs
+            }
+        }
+
+        if close_window {
+            self.show_batch_export_window = false;
+            self.batch_export_state.progress = None;
+            self.batch_export_state.error_message = None;
+        }
+    }
+
+    fn start_batch_export(&mut self) {
+        let state = &mut self.batch_export_state;
+        state.error_message = None;
+        let (from_date, to_date) = match (parse_optional_date(&state.from_date_str), parse_optional_date(&state.to_date_str)) {
+            (Ok(from), Ok(to)) => (from, to),
+            (Err(e), _) if state.invoice_numbers.is_empty() => {
+                state.error_message = Some(e);
+                return;
+            }
+            (_, Err(e)) if state.invoice_numbers.is_empty() => {
+                state.error_message = Some(e);
+                return;
+            }
+            _ => (None, None), // Dates are not used for picked invoices
+        };
+        if state.folder.trim().is_empty() {
+            state.error_message = Some("Please choose a folder to export to.".to_string());
+            return;
+        }
+        let invoices = if state.invoice_numbers.is_empty() {
+            let filter = InvoiceFilter {
+                from_date,
+                to_date,
+                customer_codes: state.customer_codes.iter().cloned().collect(),
+                status: state.status,
+            };
+            self.db.get_invoices_filtered(&filter)
+        } else {
+            let mut invoices: Vec<Invoice> = state.invoice_numbers.iter()
+                .filter_map(|number| self.db.invoices.get(number))
+                .cloned()
+                .collect();
+            invoices.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.invoice_number.cmp(&b.invoice_number)));
+            invoices
+        };
+        if invoices.is_empty() {
+            state.error_message = Some("No invoices match the selected filter.".to_string());
+            return;
+        }
+        let pattern = if state.filename_pattern.trim().is_empty() {
+            DEFAULT_FILENAME_PATTERN.to_string()
+        } else {
+            state.filename_pattern.trim().to_string()
+        };
+        let settings = BatchExportSettings {
+            folder: PathBuf::from(state.folder.trim()),
+            filename_pattern: pattern,
+            output: state.output,
+            pdf_options: self.pdf_options.clone(),
+        };
+        state.progress = Some((0, invoices.len()));
+        state.receiver = Some(spawn_export(invoices, self.db.company.clone(), settings));
+    }
+
+    fn open_statement_window(&mut self, customer_code: &str) {
+        if let Some(customer) = self.customers.iter().find(|c| c.code == customer_code) {
+            self.statement_state = StatementState {
+                customer_code: customer.code.clone(),
+                customer_name: customer.name.clone(),
+                ..Default::default()
+            };
+            self.refresh_statement_preview();
+            self.show_statement_window = true;
+        }
+    }
+
+    // Rebuilds the statement shown in the window from the entered period
+    fn refresh_statement_preview(&mut self) {
+        let state = &mut self.statement_state;
+        state.preview = None;
+        state.error_message = None;
+        let from = NaiveDate::parse_from_str(state.from_date_str.trim(), "%Y-%m-%d");
+        let to = NaiveDate::parse_from_str(state.to_date_str.trim(), "%Y-%m-%d");
+        match (from, to) {
+            (Ok(from), Ok(to)) => match self.db.get_statement(&state.customer_code, from, to) {
+                Ok(statement) => state.preview = Some(statement),
+                Err(e) => state.error_message = Some(e.to_string()),
+            },
+            _ => state.error_message = Some("Invalid date format. Use YYYY-MM-DD.".to_string()),
+        }
+    }
+
+    fn statement_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        let mut refresh = false;
+        let mut save_pdf = false;
+        let window_id = Id::new(format!("statement_window_{}", self.statement_state.customer_code));
+        Window::new(format!("Account Statement for {}", self.statement_state.customer_name))
+            .id(window_id) // Unique ID per customer
+            .resizable(true)
+            .collapsible(false)
+            .default_width(500.0)
+            .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("From:");
+                refresh |= ui.add(TextEdit::singleline(&mut self.statement_state.from_date_str).desired_width(90.0)).lost_focus();
+                ui.label("To:");
+                refresh |= ui.add(TextEdit::singleline(&mut self.statement_state.to_date_str).desired_width(90.0)).lost_focus();
+                if ui.button("Refresh").clicked() {
+                    refresh = true;
+                }
+            });
+            ui.separator();
+            if let Some(statement) = &self.statement_state.preview {
+                ScrollArea::vertical().id_source("statement_lines_scroll").max_height(250.0).show(ui, |ui| {
+                    Grid::new("statement_lines_grid")
+                        .num_columns(5)
+                        .spacing([10.0, 4.0])
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.label(RichText::new("Date").strong());
+                            ui.label(RichText::new("Description").strong());
+                            ui.label(RichText::new("Debit").strong());
+                            ui.label(RichText::new("Credit").strong());
+                            ui.label(RichText::new("Balance").strong());
+                            ui.end_row();
+                            ui.label(statement.from_date.format("%Y-%m-%d").to_string());
+                            ui.label("Opening balance");
+                            ui.label("");
+                            ui.label("");
+                            ui.label(format!("{:.2}", statement.opening_balance));
+                            ui.end_row();
+                            for line in &statement.lines {
+                                ui.label(line.date.format("%Y-%m-%d").to_string());
+                                ui.label(&line.description);
+                                ui.label(if line.debit > 0.0 { format!("{:.2}", line.debit) } else { String::new() });
+                                ui.label(if line.credit > 0.0 { format!("{:.2}", line.credit) } else { String::new() });
+                                ui.label(format!("{:.2}", line.balance));
+                                ui.end_row();
+                            }
+                        });
+                });
+                ui.separator();
+                ui.label(RichText::new(format!("Closing Balance: {:.2}", statement.closing_balance)).strong());
+                ui.label(format!(
+                    "Current: {:.2}   30 Days: {:.2}   60 Days: {:.2}   90+ Days: {:.2}",
+                    statement.ageing.current, statement.ageing.days_30, statement.ageing.days_60, statement.ageing.days_90_plus
+                ));
+            }
+            if let Some(err) = &self.statement_state.error_message {
+                ui.colored_label(Color32::RED, err);
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.add_enabled(self.statement_state.preview.is_some(), egui::Button::new("Save PDF")).clicked() {
+                    save_pdf = true;
+                }
+                if ui.button("Close").clicked() {
+                    close_window = true;
+                }
+            });
+        });
+
+        if refresh {
+            self.refresh_statement_preview();
+        }
+        if save_pdf {
+            if let Some(statement) = &self.statement_state.preview {
+                if let Some(path) = FileDialog::new()
+                    .set_file_name(format!("Statement-{}-{}.pdf", statement.customer.code, statement.to_date.format("%Y-%m-%d")))
+                    .add_filter("PDF", &["pdf"])
+                    .save_file() {
+                    match self.db.generate_statement_pdf_gui(&statement.customer.code, statement.from_date, statement.to_date, path.to_str().unwrap_or_default(), &self.pdf_options) {
+                        Ok(filename) => self.status_message = format!("Statement generated: {}", filename),
+                        Err(e) => self.statement_state.error_message = Some(format!("Error generating statement: {}", e)),
+                    }
+                }
+            }
+        }
+        if close_window {
+            self.show_statement_window = false;
+            self.statement_state = StatementState::default();
+        }
+    }
+
+    fn record_payment_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        let window_id = Id::new(format!("record_payment_window_{}", self.record_payment_state.invoice_number));
+        Window::new(format!("Record Payment for Invoice #{}", self.record_payment_state.invoice_number))
+            .id(window_id) // Unique ID per invoice
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+            ui.label(format!("Balance Due: {:.2}", self.record_payment_state.balance_due));
+            ui.separator();
+            Grid::new("record_payment_grid")
+                .num_columns(2)
+                .spacing([10.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Type:");
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut self.record_payment_state.kind, PaymentKind::Payment, PaymentKind::Payment.label());
+                        ui.radio_value(&mut self.record_payment_state.kind, PaymentKind::Credit, PaymentKind::Credit.label());
+                    });
+                    ui.end_row();
+                    ui.label("Amount:");
+                    ui.add(TextEdit::singleline(&mut self.record_payment_state.amount_str).hint_text("e.g., 50.00"));
+                    ui.end_row();
+                    ui.label("Date (YYYY-MM-DD):");
+                    ui.text_edit_singleline(&mut self.record_payment_state.date_str);
+                    ui.end_row();
+                    ui.label("Reference:");
+                    ui.add(TextEdit::singleline(&mut self.record_payment_state.reference).hint_text("Optional"));
+                    ui.end_row();
+                });
+            ui.separator();
+            if let Some(err) = &self.record_payment_state.error_message {
+                ui.colored_label(Color32::RED, err);
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    let state = &mut self.record_payment_state;
+                    let amount = match state.amount_str.trim().parse::<f64>() {
+                        Ok(a) if a > 0.0 => Some(a),
+                        _ => {
+                            state.error_message = Some("Invalid amount. Must be a positive number.".to_string());
+                            None
+                        }
+                    };
+                    let date = match NaiveDate::parse_from_str(state.date_str.trim(), "%Y-%m-%d") {
+                        Ok(d) => d.and_hms_opt(12, 0, 0).and_then(|dt| dt.and_local_timezone(Local).single()),
+                        Err(_) => None,
+                    };
+                    if date.is_none() {
+                        state.error_message = Some("Invalid date format. Use YYYY-MM-DD.".to_string());
+                    }
+                    if let (Some(amount), Some(date)) = (amount, date) {
+                        let payment = Payment {
+                            date,
+                            amount,
+                            kind: state.kind,
+                            reference: state.reference.trim().to_string(),
+                        };
+                        let invoice_number = state.invoice_number.clone();
+                        match self.db.record_payment_gui(&invoice_number, payment) {
+                            Ok(_) => {
+                                self.status_message = format!("{} of {:.2} recorded against invoice #{}.", self.record_payment_state.kind.label(), amount, invoice_number);
+                                self.update_invoice_list();
+                                close_window = true;
+                            }
+                            Err(e) => self.record_payment_state.error_message = Some(e.to_string()),
+                        }
+                    }
+                }
+                if ui.button("Cancel").clicked() {
+                    close_window = true;
+                }
+            });
+        });
+        if close_window {
+            self.show_record_payment_window = false;
+            self.record_payment_state = RecordPaymentState::default();
+        }
+    }
+
+    // Reads a CSV file chosen by the user and opens the import preview
+    fn start_customer_import(&mut self) {
+        let path = match FileDialog::new().add_filter("CSV", &["csv", "txt"]).pick_file() {
+            Some(path) => path,
+            None => return,
+        };
+        match read_csv(&path) {
+            Ok(data) if data.rows.is_empty() => {
+                self.status_message = format!("No customer rows found in {}.", path.display());
+            }
+            Ok(data) => {
+                self.customer_import_state = CustomerImportState {
+                    filename: path.display().to_string(),
+                    mapping: ColumnMapping::guess(&data.headers),
+                    data,
+                    ..CustomerImportState::default()
+                };
+                self.refresh_customer_import_preview();
+                self.show_customer_import_window = true;
+            }
+            Err(e) => self.status_message = format!("Error reading CSV: {}", e),
+        }
+    }
+
+    fn refresh_customer_import_preview(&mut self) {
+        let state = &mut self.customer_import_state;
+        state.preview = self.db.preview_customer_import(&state.data, &state.mapping, state.generate_codes);
+    }
+
+    fn customer_import_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        let mut changed = false;
+        let mut import = false;
+        let valid_count = self.customer_import_state.preview.iter().filter(|r| r.is_valid()).count();
+        Window::new("Import Customers from CSV")
+            .id(Id::new("customer_import_window"))
+            .resizable(true)
+            .collapsible(false)
+            .default_width(650.0)
+            .show(ctx, |ui| {
+            let state = &mut self.customer_import_state;
+            ui.label(format!("File: {}", state.filename));
+            ui.separator();
+            ui.label(RichText::new("Column Mapping").strong());
+            Grid::new("customer_import_mapping_grid")
+                .num_columns(4)
+                .spacing([10.0, 4.0])
+                .show(ui, |ui| {
+                    for (idx, field) in CustomerField::ALL.iter().enumerate() {
+                        ui.label(format!("{}:", field.label()));
+                        let current = state.mapping.get(*field);
+                        let selected_text = current
+                            .and_then(|col| state.data.headers.get(col).cloned())
+                            .unwrap_or_else(|| "(not imported)".to_string());
+                        egui::ComboBox::from_id_source(("customer_import_column", idx))
+                            .selected_text(selected_text)
+                            .show_ui(ui, |ui| {
+                                if ui.selectable_label(current.is_none(), "(not imported)").clicked() {
+                                    state.mapping.set(*field, None);
+                                    changed = true;
+                                }
+                                for (col, header) in state.data.headers.iter().enumerate() {
+                                    if ui.selectable_label(current == Some(col), header).clicked() {
+                                        state.mapping.set(*field, Some(col));
+                                        changed = true;
+                                    }
+                                }
+                            });
+                        if idx % 2 == 1 {
+                            ui.end_row();
+                        }
+                    }
+                });
+            changed |= ui.checkbox(&mut state.generate_codes, "Generate missing codes from customer names").changed();
+            ui.checkbox(&mut state.errors_only, "Show only rows with errors");
+            ui.separator();
+
+            ScrollArea::vertical().id_source("customer_import_preview_scroll").max_height(300.0).show(ui, |ui| {
+                Grid::new("customer_import_preview_grid")
+                    .num_columns(4)
+                    .spacing([10.0, 4.0])
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label(RichText::new("Line").strong());
+                        ui.label(RichText::new("Name").strong());
+                        ui.label(RichText::new("Code").strong());
+                        ui.label(RichText::new("Status").strong());
+                        ui.end_row();
+                        for row in state.preview.iter().filter(|r| !state.errors_only || !r.is_valid()) {
+                            ui.label(row.line.to_string());
+                            ui.label(&row.customer.name);
+                            if row.code_generated {
+                                ui.label(format!("{} (auto)", row.customer.code));
+                            } else {
+                                ui.label(&row.customer.code);
+                            }
+                            if row.is_valid() {
+                                ui.colored_label(Color32::DARK_GREEN, "OK");
+                            } else {
+                                ui.colored_label(Color32::RED, row.errors.join(" "));
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+            ui.separator();
+            ui.label(format!(
+                "{} of {} rows are valid; rows with errors will be skipped.",
+                valid_count,
+                state.preview.len()
+            ));
+            ui.horizontal(|ui| {
+                if ui.add_enabled(valid_count > 0, egui::Button::new(format!("Import {} Customers", valid_count))).clicked() {
+                    import = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    close_window = true;
+                }
+            });
+        });
+
+        if changed {
+            self.refresh_customer_import_preview();
+        }
+        if import {
+            match self.db.import_customers_gui(&self.customer_import_state.preview) {
+                Ok(count) => {
+                    let skipped = self.customer_import_state.preview.len() - count;
+                    self.status_message = format!("Imported {} customers ({} rows skipped).", count, skipped);
+                    self.update_customer_list();
+                    close_window = true;
+                }
+                Err(e) => self.status_message = format!("Error importing customers: {}", e),
+            }
+        }
+        if close_window {
+            self.show_customer_import_window = false;
+            self.customer_import_state = CustomerImportState::default();
+        }
+    }
+
+    fn data_export_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        let mut export = false;
+        Window::new("Export Data")
+            .id(Id::new("data_export_window"))
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+            let state = &mut self.data_export_state;
+            let uses_filter = state.kind != ExportKind::Customers;
+            Grid::new("data_export_grid")
+                .num_columns(2)
+                .spacing([10.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Export:");
+                    ui.horizontal(|ui| {
+                        for kind in ExportKind::ALL {
+                            ui.radio_value(&mut state.kind, kind, kind.label());
+                        }
+                    });
+                    ui.end_row();
+                    ui.label("Format:");
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut state.format, ExportFormat::Xlsx, "Excel (XLSX)");
+                        ui.radio_value(&mut state.format, ExportFormat::Csv, "CSV");
+                    });
+                    ui.end_row();
+                    ui.label("From (YYYY-MM-DD):");
+                    ui.add_enabled(uses_filter, TextEdit::singleline(&mut state.from_date_str).hint_text("Any date"));
+                    ui.end_row();
+                    ui.label("To (YYYY-MM-DD):");
+                    ui.add_enabled(uses_filter, TextEdit::singleline(&mut state.to_date_str).hint_text("Any date"));
+                    ui.end_row();
+                    ui.label("Status:");
+                    ui.add_enabled_ui(uses_filter, |ui| {
+                        egui::ComboBox::from_id_source("data_export_status")
+                            .selected_text(state.status.label())
+                            .show_ui(ui, |ui| {
+                                for status in InvoiceStatusFilter::ALL {
+                                    ui.selectable_value(&mut state.status, status, status.label());
+                                }
+                            });
+                    });
+                    ui.end_row();
+                });
+            if !uses_filter {
+                ui.label("All customers are exported; the date and status filters apply to invoices.");
+            }
+            if let Some(err) = &state.error_message {
+                ui.colored_label(Color32::RED, err);
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Export...").clicked() {
+                    export = true;
+                }
+                if ui.button("Close").clicked() {
+                    close_window = true;
+                }
+            });
+        });
+
+        if export {
+            let state = &mut self.data_export_state;
+            state.error_message = None;
+            let (from_date, to_date) = match (parse_optional_date(&state.from_date_str), parse_optional_date(&state.to_date_str)) {
+                (Ok(from), Ok(to)) => (from, to),
+                (Err(e), _) | (_, Err(e)) => {
+                    state.error_message = Some(e);
+                    return;
+                }
+            };
+            let filter = InvoiceFilter {
+                from_date,
+                to_date,
+                customer_codes: Vec::new(),
+                status: state.status,
+            };
+            let extension = state.format.extension();
+            if let Some(path) = FileDialog::new()
+                .set_file_name(format!("{}-{}.{}", state.kind.label().replace(' ', ""), Local::now().format("%Y-%m-%d"), extension))
+                .add_filter(extension.to_uppercase(), &[extension])
+                .save_file() {
+                match self.db.export_data_gui(state.kind, state.format, &filter, path.to_str().unwrap_or_default()) {
+                    Ok(rows) => {
+                        self.status_message = format!("Exported {} rows to {}", rows, path.display());
+                        close_window = true;
+                    }
+                    Err(e) => state.error_message = Some(format!("Export failed: {}", e)),
+                }
+            }
+        }
+        if close_window {
+            self.show_data_export_window = false;
+        }
+    }
+
+    // Filter for the accounting export, or an error for invalid dates
+    fn accounting_export_filter(&self) -> Result<InvoiceFilter, String> {
+        let state = &self.accounting_export_state;
+        Ok(InvoiceFilter {
+            from_date: parse_optional_date(&state.from_date_str)?,
+            to_date: parse_optional_date(&state.to_date_str)?,
+            customer_codes: Vec::new(),
+            status: state.status,
+        })
+    }
+
+    // Saves the edited account mapping if it differs from the stored one
+    fn save_accounting_settings(&mut self) -> bool {
+        let mut settings = self.accounting_export_state.settings.clone();
+        for system in AccountingSystem::ALL {
+            settings.mapping_mut(system).customer_accounts.retain(|_, account| !account.trim().is_empty());
+        }
+        if settings == self.db.accounting {
+            return true;
+        }
+        match self.db.update_accounting_settings_gui(settings) {
+            Ok(_) => {
+                self.status_message = "Account mapping saved.".to_string();
+                true
+            }
+            Err(e) => {
+                self.accounting_export_state.error_message = Some(e.to_string());
+                false
+            }
+        }
+    }
+
+    fn accounting_export_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        let mut export = false;
+        let mut save_mapping = false;
+        let system = self.accounting_export_state.system;
+        let counts = self.accounting_export_filter().ok().map(|filter| {
+            let invoices = self.db.get_invoices_filtered(&filter);
+            let exported = invoices.iter().filter(|inv| self.db.is_exported_to(&inv.invoice_number, system)).count();
+            (invoices.len() - exported, exported)
+        });
+        Window::new("Accounting Export")
+            .id(Id::new("accounting_export_window"))
+            .resizable(true)
+            .collapsible(false)
+            .default_width(450.0)
+            .show(ctx, |ui| {
+            let state = &mut self.accounting_export_state;
+            Grid::new("accounting_export_grid")
+                .num_columns(2)
+                .spacing([10.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Export For:");
+                    ui.horizontal(|ui| {
+                        for option in AccountingSystem::ALL {
+                            ui.radio_value(&mut state.system, option, option.label());
+                        }
+                    });
+                    ui.end_row();
+                    ui.label("From (YYYY-MM-DD):");
+                    ui.add(TextEdit::singleline(&mut state.from_date_str).hint_text("Any date"));
+                    ui.end_row();
+                    ui.label("To (YYYY-MM-DD):");
+                    ui.add(TextEdit::singleline(&mut state.to_date_str).hint_text("Any date"));
+                    ui.end_row();
+                    ui.label("Status:");
+                    egui::ComboBox::from_id_source("accounting_export_status")
+                        .selected_text(state.status.label())
+                        .show_ui(ui, |ui| {
+                            for status in InvoiceStatusFilter::ALL {
+                                ui.selectable_value(&mut state.status, status, status.label());
+                            }
+                        });
+                    ui.end_row();
+                });
+            ui.checkbox(&mut state.include_exported, "Include invoices already exported (re-post)");
+            match counts {
+                Some((ready, exported)) => ui.label(format!(
+                    "{} invoices ready to export; {} already exported to {}.",
+                    ready, exported, system.label()
+                )),
+                None => ui.colored_label(Color32::RED, "Invalid date. Use YYYY-MM-DD."),
+            };
+
+            ui.separator();
+            egui::CollapsingHeader::new(format!("{} Account Mapping", system.label()))
+                .id_source("accounting_mapping")
+                .show(ui, |ui| {
+                    let mapping = state.settings.mapping_mut(system);
+                    Grid::new("accounting_mapping_grid")
+                        .num_columns(2)
+                        .spacing([10.0, 4.0])
+                        .show(ui, |ui| {
+                            ui.label("Sales Account:");
+                            ui.text_edit_singleline(&mut mapping.sales_account);
+                            ui.end_row();
+                            ui.label("Tax Code (GST):");
+                            ui.text_edit_singleline(&mut mapping.gst_tax_code);
+                            ui.end_row();
+                            ui.label("Tax Code (GST Free):");
+                            ui.text_edit_singleline(&mut mapping.gst_free_tax_code);
+                            ui.end_row();
+                        });
+                    ui.label("Customer account overrides (blank uses the sales account):");
+                    ScrollArea::vertical().id_source("accounting_mapping_scroll").max_height(150.0).show(ui, |ui| {
+                        Grid::new("accounting_customer_accounts_grid")
+                            .num_columns(2)
+                            .spacing([10.0, 4.0])
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for customer in &self.customers {
+                                    ui.label(format!("{} ({})", customer.name, customer.code));
+                                    let account = mapping.customer_accounts.entry(customer.code.clone()).or_default();
+                                    ui.add(TextEdit::singleline(account).hint_text(mapping.sales_account.clone()).desired_width(80.0));
+                                    ui.end_row();
+                                }
+                            });
+                    });
+                    if ui.button("Save Mapping").clicked() {
+                        save_mapping = true;
+                    }
+                });
+
+            if let Some(err) = &state.error_message {
+                ui.colored_label(Color32::RED, err);
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Export...").clicked() {
+                    export = true;
+                }
+                if ui.button("Close").clicked() {
+                    close_window = true;
+                }
+            });
+        });
+
+        if save_mapping {
+            self.accounting_export_state.error_message = None;
+            self.save_accounting_settings();
+        }
+        if export {
+            self.accounting_export_state.error_message = None;
+            let filter = match self.accounting_export_filter() {
+                Ok(filter) => filter,
+                Err(e) => {
+                    self.accounting_export_state.error_message = Some(e);
+                    return;
+                }
+            };
+            if !self.save_accounting_settings() {
+                return;
+            }
+            let extension = system.extension();
+            if let Some(path) = FileDialog::new()
+                .set_file_name(format!("{}-Sales-{}.{}", system.label().replace(' ', "-"), Local::now().format("%Y-%m-%d"), extension))
+                .add_filter(extension.to_uppercase(), &[extension])
+                .save_file() {
+                let include_exported = self.accounting_export_state.include_exported;
+                match self.db.export_accounting_gui(system, &filter, include_exported, path.to_str().unwrap_or_default()) {
+                    Ok(count) => self.status_message = format!("Exported {} invoices for {} to {}", count, system.label(), path.display()),
+                    Err(e) => self.accounting_export_state.error_message = Some(e.to_string()),
+                }
+            }
+        }
+        if close_window {
+            self.show_accounting_export_window = false;
+        }
+    }
+
+    // Reads a bank statement chosen by the user and suggests invoice matches for its credits
+    fn start_reconciliation(&mut self) {
+        let path = match FileDialog::new()
+            .add_filter("Bank Statements", &["csv", "ofx", "qfx", "qif"])
+            .pick_file() {
+            Some(path) => path,
+            None => return,
+        };
+        let transactions = match read_statement(&path) {
+            Ok(transactions) => transactions,
+            Err(e) => {
+                self.status_message = format!("Error reading bank statement: {}", e);
+                return;
+            }
+        };
+        let open_invoices = self.db.get_open_invoices();
+        // Credits already recorded are shown but not matched again. `suggest_matches` returns
+        // one entry per transaction passed in, so it only sees the unrecorded credits.
+        let credits: Vec<BankTransaction> = transactions.iter().filter(|tx| tx.amount > 0.0).cloned().collect();
+        let recorded = self.db.recorded_bank_transactions(&credits);
+        let unrecorded: Vec<BankTransaction> = credits.iter().zip(&recorded)
+            .filter(|(_, &recorded)| !recorded)
+            .map(|(tx, _)| tx.clone())
+            .collect();
+        let mut suggestions = suggest_matches(&unrecorded, &open_invoices).into_iter();
+        let rows = credits.iter().zip(recorded).map(|(tx, already_recorded)| {
+            let suggestion = if already_recorded { None } else { suggestions.next().flatten() };
+            ReconcileRow {
+                transaction: tx.clone(),
+                invoice_number: suggestion.as_ref().map(|m| m.invoice_number.clone()),
+                amount_str: format!("{:.2}", suggestion.as_ref().map_or(tx.amount, |m| m.amount)),
+                confidence: suggestion.as_ref().map(|m| m.confidence),
+                confirmed: suggestion.is_some_and(|m| m.confidence == MatchConfidence::ReferenceAndAmount),
+                already_recorded,
+            }
+        }).collect();
+        let debit_count = transactions.iter().filter(|tx| tx.amount <= 0.0).count();
+        self.reconciliation_state = ReconciliationState {
+            filename: path.display().to_string(),
+            rows,
+            debit_count,
+            open_invoices: open_invoices.iter().map(|inv| (
+                inv.invoice_number.clone(),
+                format!("#{} {} ({:.2})", inv.invoice_number, inv.customer.name, inv.balance_due()),
+                inv.balance_due(),
+            )).collect(),
+            error_message: None,
+        };
+        self.show_reconciliation_window = true;
+    }
+
+    fn reconciliation_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        let mut record = false;
+        Window::new("Bank Reconciliation")
+            .id(Id::new("reconciliation_window"))
+            .resizable(true)
+            .collapsible(false)
+            .default_width(800.0)
+            .show(ctx, |ui| {
+            let state = &mut self.reconciliation_state;
+            ui.label(format!("Statement: {}", state.filename));
+            ui.label(format!(
+                "{} credits, {} debits (ignored). Tick the matches to record as payments.",
+                state.rows.len(), state.debit_count
+            ));
+            ui.separator();
+            ScrollArea::vertical().id_source("reconciliation_scroll").max_height(350.0).show(ui, |ui| {
+                Grid::new("reconciliation_grid")
+                    .num_columns(7)
+                    .spacing([10.0, 4.0])
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for heading in ["", "Date", "Description", "Amount", "Invoice", "Record", "Match"] {
+                            ui.label(RichText::new(heading).strong());
+                        }
+                        ui.end_row();
+                        for (idx, row) in state.rows.iter_mut().enumerate() {
+                            let tx = &row.transaction;
+                            ui.add_enabled(!row.already_recorded && row.invoice_number.is_some(), egui::Checkbox::without_text(&mut row.confirmed));
+                            ui.label(tx.date.format("%Y-%m-%d").to_string());
+                            ui.label(&tx.description);
+                            ui.label(format!("{:.2}", tx.amount));
+                            if row.already_recorded {
+                                ui.label("");
+                                ui.label("");
+                                ui.colored_label(Color32::GRAY, "Already recorded");
+                                ui.end_row();
+                                continue;
+                            }
+                            let selected_text = row.invoice_number.as_ref()
+                                .and_then(|num| state.open_invoices.iter().find(|(n, _, _)| n == num))
+                                .map_or("(no match)".to_string(), |(_, label, _)| label.clone());
+                            egui::ComboBox::from_id_source(("reconcile_invoice", idx))
+                                .selected_text(selected_text)
+                                .width(220.0)
+                                .show_ui(ui, |ui| {
+                                    if ui.selectable_label(row.invoice_number.is_none(), "(no match)").clicked() {
+                                        row.invoice_number = None;
+                                        row.confidence = None;
+                                        row.confirmed = false;
+                                    }
+                                    for (number, label, balance) in &state.open_invoices {
+                                        if ui.selectable_label(row.invoice_number.as_ref() == Some(number), label).clicked() {
+                                            row.invoice_number = Some(number.clone());
+                                            row.amount_str = format!("{:.2}", tx.amount.min(*balance));
+                                            row.confidence = None;
+                                            row.confirmed = true;
+                                        }
+                                    }
+                                });
+                            ui.add(TextEdit::singleline(&mut row.amount_str).desired_width(70.0));
+                            match (&row.invoice_number, row.confidence) {
+                                (Some(_), Some(confidence)) => ui.label(confidence.label()),
+                                (Some(_), None) => ui.label("Manual"),
+                                (None, _) => ui.label(""),
+                            };
+                            ui.end_row();
+                        }
+                    });
+            });
+            ui.separator();
+            if let Some(err) = &state.error_message {
+                ui.colored_label(Color32::RED, err);
+            }
+            let selected = state.rows.iter().filter(|r| r.confirmed && !r.already_recorded).count();
+            ui.horizontal(|ui| {
+                if ui.button("Tick All Suggestions").clicked() {
+                    for row in state.rows.iter_mut().filter(|r| !r.already_recorded && r.invoice_number.is_some()) {
+                        row.confirmed = true;
+                    }
+                }
+                if ui.add_enabled(selected > 0, egui::Button::new(format!("Record {} Payments", selected))).clicked() {
+                    record = true;
+                }
+                if ui.button("Close").clicked() {
+                    close_window = true;
+                }
+            });
+        });
+
+        if record {
+            let state = &mut self.reconciliation_state;
+            state.error_message = None;
+            let mut payments = Vec::new();
+            for row in state.rows.iter().filter(|r| r.confirmed && !r.already_recorded) {
+                let invoice_number = match &row.invoice_number {
+                    Some(num) => num.clone(),
+                    None => continue,
+                };
+                let amount = match row.amount_str.trim().parse::<f64>() {
+                    Ok(a) if a > 0.0 => a,
+                    _ => {
+                        state.error_message = Some(format!("Invalid amount \"{}\" for {}.", row.amount_str, row.transaction.description));
+                        return;
+                    }
+                };
+                let date = row.transaction.date.and_hms_opt(12, 0, 0)
+                    .and_then(|dt| dt.and_local_timezone(Local).single())
+                    .unwrap_or_else(Local::now);
+                payments.push((invoice_number, Payment {
+                    date,
+                    amount,
+                    kind: PaymentKind::Payment,
+                    reference: row.transaction.payment_reference(),
+                }));
+            }
+            match self.db.record_payments_gui(payments) {
+                Ok(count) => {
+                    self.status_message = format!("Recorded {} payments from the bank statement.", count);
+                    self.update_invoice_list();
+                    close_window = true;
+                }
+                Err(e) => self.reconciliation_state.error_message = Some(e.to_string()),
+            }
+        }
+        if close_window {
+            self.show_reconciliation_window = false;
+            self.reconciliation_state = ReconciliationState::default();
+        }
+    }
+
+    fn json_export_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        let mut export = false;
+        Window::new("Export JSON")
+            .id(Id::new("json_export_window"))
+            .resizable(true)
+            .collapsible(false)
+            .default_width(400.0)
+            .show(ctx, |ui| {
+            let state = &mut self.json_export_state;
+            ui.radio_value(&mut state.everything, true, "All customers and invoices");
+            ui.radio_value(&mut state.everything, false, "Selected customers and invoices");
+            if !state.everything {
+                ScrollArea::vertical().id_source("json_export_scroll").max_height(300.0).show(ui, |ui| {
+                    for customer in &self.customers {
+                        let mut checked = state.customer_codes.contains(&customer.code);
+                        ui.horizontal(|ui| {
+                            if ui.checkbox(&mut checked, "").changed() {
+                                if checked {
+                                    state.customer_codes.insert(customer.code.clone());
+                                } else {
+                                    state.customer_codes.remove(&customer.code);
+                                }
+                            }
+                            egui::CollapsingHeader::new(format!("{} ({})", customer.name, customer.code))
+                                .id_source(("json_export_customer", &customer.code))
+                                .show(ui, |ui| {
+                                    for invoice in self.db.get_invoices_for_customer(&customer.code) {
+                                        let mut selected = checked || state.invoice_numbers.contains(&invoice.invoice_number);
+                                        let label = format!("#{} {} {:.2}", invoice.invoice_number, invoice.date.format("%Y-%m-%d"), invoice.total);
+                                        if ui.add_enabled(!checked, egui::Checkbox::new(&mut selected, label)).changed() {
+                                            if selected {
+                                                state.invoice_numbers.insert(invoice.invoice_number.clone());
+                                            } else {
+                                                state.invoice_numbers.remove(&invoice.invoice_number);
+                                            }
+                                        }
+                                    }
+                                });
+                        });
+                    }
+                });
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                let has_selection = state.everything || !state.customer_codes.is_empty() || !state.invoice_numbers.is_empty();
+                if ui.add_enabled(has_selection, egui::Button::new("Export...")).clicked() {
+                    export = true;
+                }
+                if ui.button("Close").clicked() {
+                    close_window = true;
+                }
+            });
+        });
+
+        if export {
+            let state = &self.json_export_state;
+            let selection = if state.everything {
+                InterchangeSelection::default()
+            } else {
+                InterchangeSelection {
+                    customer_codes: state.customer_codes.iter().cloned().collect(),
+                    invoice_numbers: state.invoice_numbers.iter().cloned().collect(),
+                }
+            };
+            if let Some(path) = FileDialog::new()
+                .set_file_name(format!("invoices-export-{}.json", Local::now().format("%Y-%m-%d")))
+                .add_filter("JSON", &["json"])
+                .save_file() {
+                let file = self.db.export_interchange(&selection);
+                match write_interchange(&file, &path) {
+                    Ok(_) => {
+                        self.status_message = format!(
+                            "Exported {} customers and {} invoices to {}",
+                            file.customers.len(), file.invoices.len(), path.display()
+                        );
+                        close_window = true;
+                    }
+                    Err(e) => self.status_message = format!("Error exporting JSON: {}", e),
+                }
+            }
+        }
+        if close_window {
+            self.show_json_export_window = false;
+        }
+    }
+
+    // Reads an interchange file and opens the import window with the conflicts found
+    fn start_json_import(&mut self) {
+        let path = match FileDialog::new().add_filter("JSON", &["json"]).pick_file() {
+            Some(path) => path,
+            None => return,
+        };
+        match read_interchange(&path) {
+            Ok(file) => {
+                let plan = self.db.plan_interchange_import(&file);
+                self.json_import_state = JsonImportState {
+                    filename: path.display().to_string(),
+                    conflicts_only: plan.conflict_count() > 0,
+                    plan,
+                    error_message: None,
+                };
+                self.show_json_import_window = true;
+            }
+            Err(e) => self.status_message = format!("Error reading JSON: {}", e),
+        }
+    }
+
+    fn json_import_window(&mut self, ctx: &Context) {
+        let mut close_window = false;
+        let mut import = false;
+        Window::new("Import JSON")
+            .id(Id::new("json_import_window"))
+            .resizable(true)
+            .collapsible(false)
+            .default_width(650.0)
+            .show(ctx, |ui| {
+            let state = &mut self.json_import_state;
+            let conflicts = state.plan.conflict_count();
+            ui.label(format!("File: {}", state.filename));
+            ui.label(format!(
+                "{} customers and {} invoices, {} conflicts. Conflicting records are skipped unless you choose otherwise.",
+                state.plan.customers.len(), state.plan.invoices.len(), conflicts
+            ));
+            ui.checkbox(&mut state.conflicts_only, "Show only conflicts");
+            ui.separator();
+            let conflicts_only = state.conflicts_only;
+            ScrollArea::vertical().id_source("json_import_scroll").max_height(350.0).show(ui, |ui| {
+                ui.label(RichText::new("Customers").strong());
+                Grid::new("json_import_customers_grid")
+                    .num_columns(4)
+                    .spacing([10.0, 4.0])
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (idx, planned) in state.plan.customers.iter_mut().enumerate() {
+                            if conflicts_only && planned.conflict.is_none() {
+                                continue;
+                            }
+                            ui.label(format!("{} ({})", planned.customer.name, planned.customer.code));
+                            match &planned.conflict {
+                                Some(conflict) => {
+                                    ui.colored_label(Color32::RED, conflict);
+                                    egui::ComboBox::from_id_source(("json_import_customer", idx))
+                                        .selected_text(planned.resolution.label())
+                                        .show_ui(ui, |ui| {
+                                            for resolution in [Resolution::Skip, Resolution::Overwrite, Resolution::Rename] {
+                                                ui.selectable_value(&mut planned.resolution, resolution, resolution.label());
+                                            }
+                                        });
+                                    ui.horizontal(|ui| {
+                                        if planned.resolution == Resolution::Rename {
+                                            ui.add(TextEdit::singleline(&mut planned.new_code).desired_width(40.0));
+                                            ui.add(TextEdit::singleline(&mut planned.new_name).desired_width(150.0));
+                                        }
+                                    });
+                                }
+                                None => {
+                                    ui.label(if planned.identical { "Already present" } else { "New" });
+                                    ui.label("");
+                                    ui.label("");
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+                ui.separator();
+                ui.label(RichText::new("Invoices").strong());
+                Grid::new("json_import_invoices_grid")
+                    .num_columns(4)
+                    .spacing([10.0, 4.0])
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (idx, planned) in state.plan.invoices.iter_mut().enumerate() {
+                            if conflicts_only && planned.conflict.is_none() {
+                                continue;
+                            }
+                            let invoice = &planned.invoice;
+                            ui.label(format!("#{} {} {:.2}", invoice.invoice_number, invoice.customer.name, invoice.total));
+                            match &planned.conflict {
+                                Some(conflict) => {
+                                    ui.colored_label(Color32::RED, conflict);
+                                    egui::ComboBox::from_id_source(("json_import_invoice", idx))
+                                        .selected_text(planned.resolution.label())
+                                        .show_ui(ui, |ui| {
+                                            for resolution in [Resolution::Skip, Resolution::Overwrite, Resolution::Rename] {
+                                                ui.selectable_value(&mut planned.resolution, resolution, resolution.label());
+                                            }
+                                        });
+                                    if planned.resolution == Resolution::Rename {
+                                        ui.add(TextEdit::singleline(&mut planned.new_number).desired_width(80.0));
+                                    } else {
+                                        ui.label("");
+                                    }
+                                }
+                                None => {
+                                    ui.label(if planned.identical { "Already present" } else { "New" });
+                                    ui.label("");
+                                    ui.label("");
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+            ui.separator();
+            if let Some(err) = &state.error_message {
+                ui.colored_label(Color32::RED, err);
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Import").clicked() {
+                    import = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    close_window = true;
+                }
+            });
+        });
+
+        if import {
+            match self.db.apply_interchange_import_gui(&self.json_import_state.plan) {
+                Ok(summary) => {
+                    self.status_message = format!(
+                        "Import complete: {} customers added, {} updated; {} invoices added, {} updated; {} skipped.",
+                        summary.customers_added, summary.customers_updated, summary.invoices_added, summary.invoices_updated, summary.skipped
+                    );
+                    self.update_customer_list();
+                    self.update_invoice_list();
+                    close_window = true;
+                }
+                Err(e) => self.json_import_state.error_message = Some(e.to_string()),
+            }
+        }
+        if close_window {
+            self.show_json_import_window = false;
+            self.json_import_state = JsonImportState::default();
+        }
+    }
+
+    // Picks up changes saved by another program, e.g. `jmatt_invoice_cli serve`.
+    // Unsaved changes made here are dropped.
+    fn reload_database(&mut self) {
+        match Database::open() {
+            Ok(db) => {
+                self.db = db;
+                self.update_customer_list();
+                if self.selected_customer_code.as_ref().is_some_and(|code| !self.customers.iter().any(|c| c.code == *code)) {
+                    self.selected_customer_code = None;
+                }
+                self.update_invoice_list();
+                self.status_message = "Database reloaded from disk.".to_string();
+            }
+            Err(e) => self.status_message = format!("Error reloading database: {}", e),
+        }
+    }
+
+    fn update_customer_list(&mut self) {
+        self.customers = self.db.get_customers_vec();
+    }
+
+    fn update_invoice_list(&mut self) {
+        if let Some(code) = &self.selected_customer_code {
+            self.invoices_for_selected_customer = self.db.get_invoices_for_customer(code);
+        } else {
+            self.invoices_for_selected_customer.clear();
+        }
+        self.selected_invoice_number = None; // Deselect invoice when list updates
+    }
+
+    fn get_selected_customer_name(&self) -> Option<String> {
+        self.selected_customer_code.as_ref().and_then(|code| {
+            self.customers.iter().find(|c| c.code == *code).map(|c| c.name.clone())
+        })
+    }
+}
+
+impl eframe::App for KmattInvoiceApp {
+    fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) { // Changed frame to _frame as it's not used directly
+        // Menu Bar
+        TopBottomPanel::top("top_panel").show(ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                ui.menu_button("File", |ui| {
+                    if ui.button("Save Database").clicked() {
+                        match self.db.save() {
+                            Ok(_) => self.status_message = "Database saved successfully.".to_string(),
+                            Err(e) => self.status_message = format!("Error saving database: {}", e),
+                        }
+                        ui.close_menu();
+                    }
+                    if ui.button("Reload from Disk").clicked() {
+                        self.reload_database();
+                        ui.close_menu();
+                    }
+                    if ui.button("Import Customers from CSV...").clicked() {
+                        ui.close_menu();
+                        self.start_customer_import();
+                    }
+                    if ui.button("Export Data (CSV/XLSX)...").clicked() {
+                        self.show_data_export_window = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Export to Xero / MYOB...").clicked() {
+                        self.accounting_export_state = AccountingExportState {
+                            settings: self.db.accounting.clone(),
+                            ..AccountingExportState::default()
+                        };
+                        self.show_accounting_export_window = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Reconcile Bank Statement...").clicked() {
+                        ui.close_menu();
+                        self.start_reconciliation();
+                    }
+                    if ui.button("Export JSON...").clicked() {
+                        self.json_export_state = JsonExportState { everything: true, ..JsonExportState::default() };
+                        self.show_json_export_window = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Import JSON...").clicked() {
+                        ui.close_menu();
+                        self.start_json_import();
+                    }
+                    if ui.button("Company Details...").clicked() {
+                        self.company_state = CompanyState { company: Some(self.db.company.clone()), error_message: None };
+                        self.show_company_window = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Email Settings...").clicked() {
+                        ui.close_menu();
+                        self.open_email_settings_window();
+                    }
+                    if ui.button("Payment Reminders...").clicked() {
+                        ui.close_menu();
+                        self.open_reminders_window();
+                    }
+                    if ui.button("Batch Export PDFs...").clicked() {
+                        self.batch_export_state.invoice_numbers.clear();
+                        self.show_batch_export_window = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Exit").clicked() {
+                        // Save is handled by Drop trait
+                        // Use ViewportCommand to request close
+                        ctx.send_viewport_cmd(ViewportCommand::Close);
+                        ui.close_menu(); // Close menu after clicking exit
+                    }
+                });
+                ui.menu_button("Customer", |ui| {
+                    if ui.button("Add New Customer").clicked() {
+                        self.add_customer_state = AddCustomerState::default();
+                        self.show_add_customer_window = true;
+                        ui.close_menu();
+                    }
+                    let edit_enabled = self.selected_customer_code.is_some();
+                    if ui.add_enabled(edit_enabled, egui::Button::new("Edit Selected Customer")).clicked() {
+                        if let Some(code) = &self.selected_customer_code {
+                            if let Some(customer) = self.customers.iter().find(|c| c.code == *code) {
+                                self.edit_customer_state = EditCustomerState {
+                                    original_name: customer.name.clone(),
+                                    name: customer.name.clone(),
+                                    address: customer.address.clone(),
+                                    phone: customer.phone.clone(),
+                                    contact_person: customer.contact_person.clone(),
+                                    contact_phone: customer.contact_phone.clone(),
+                                    email: customer.email.clone(),
+                                    code: customer.code.clone(),
+                                    abn: customer.abn.clone(),
+                                    error_message: None,
+                                };
+                                self.show_edit_customer_window = true;
+                            }
+                        }
+                        ui.close_menu();
+                    }
+                    let statement_enabled = self.selected_customer_code.is_some();
+                    if ui.add_enabled(statement_enabled, egui::Button::new("Account Statement...")).clicked() {
+                        if let Some(code) = self.selected_customer_code.clone() {
+                            self.open_statement_window(&code);
+                        }
+                        ui.close_menu();
+                    }
+                    let delete_enabled = self.selected_customer_code.is_some();
+                    if ui.add_enabled(delete_enabled, egui::Button::new("Delete Selected Customer")).clicked() {
+                        self.customer_to_delete_code = self.selected_customer_code.clone();
+                        self.show_delete_customer_confirm_window = true;
+                        ui.close_menu();
+                    }
+                });
+                ui.menu_button("Invoice", |ui| {
+                    let create_enabled = self.selected_customer_code.is_some();
+                    if ui.add_enabled(create_enabled, egui::Button::new("Create New Invoice")).clicked() {
+                        if let Some(code) = &self.selected_customer_code {
+                            if let Some(customer) = self.customers.iter().find(|c| c.code == *code) {
+                                self.create_invoice_state = CreateInvoiceState {
+                                    customer_code: customer.code.clone(),
+                                    customer_name: customer.name.clone(),
+                                    ..Default::default()
+                                };
+                                self.show_create_invoice_window = true;
+                            }
+                        }
+                        ui.close_menu();
+                    }
+                    let invoice_selected = self.selected_invoice_number.is_some();
+                    if ui.add_enabled(invoice_selected, egui::Button::new("Edit Selected Invoice")).clicked() {
+                        if let Some(inv_num) = &self.selected_invoice_number {
+                            if let Some(invoice) = self.invoices_for_selected_customer.iter().find(|inv| inv.invoice_number == *inv_num) {
+                                self.edit_invoice_state = EditInvoiceState {
+                                    original_invoice_number: invoice.invoice_number.clone(),
+                                    // Removed unused field: customer_code: invoice.customer.code.clone(),
+                                    customer_name: invoice.customer.name.clone(),
+                                    items: invoice.items.iter().map(|item| InvoiceItemState {
+                                        description: item.description.clone(),
+                                        quantity_str: item.quantity.to_string(),
+                                        rate_str: format!("{:.2}", item.rate),
+                                        gst_free: item.gst_free,
+                                    }).collect(),
+                                    notes: invoice.notes.clone(),
+                                    due_date_str: invoice.due_date.format("%Y-%m-%d").to_string(),
+                                    paid: invoice.paid,
+                                    error_message: None,
+                                };
+                                self.show_edit_invoice_window = true;
+                            }
+                        }
+                        ui.close_menu();
+                    }
+                    if ui.add_enabled(invoice_selected, egui::Button::new("Delete Selected Invoice")).clicked() {
+                        self.invoice_to_delete_number = self.selected_invoice_number.clone();
+                        self.show_delete_invoice_confirm_window = true;
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    ui.checkbox(&mut self.pdf_options.include_remittance, "PDF: Include Remittance Slip");
+                    ui.checkbox(&mut self.pdf_options.include_payment_qr, "PDF: Include Payment Details QR Code (plain text)");
+                    ui.checkbox(&mut self.pdf_options.pdf_a, "PDF: Archival PDF/A Output");
+                    ui.checkbox(&mut self.pdf_options.embed_einvoice, "PDF: Embed e-Invoice XML (PDF/A-3)")
+                        .on_hover_text("Attaches the Peppol UBL invoice so accounts payable systems can read it; both ABNs are required");
+                });
+                ui.menu_button("Reports", |ui| {
+                    if ui.button("Aged Receivables...").clicked() {
+                        self.aged_receivables_state = AgedReceivablesState {
+                            as_at_str: Local::now().format("%Y-%m-%d").to_string(),
+                            error_message: None,
+                        };
+                        self.show_aged_receivables_window = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("GST / BAS Summary...").clicked() {
+                        self.bas_state = BasState::default();
+                        self.show_bas_window = true;
+                        ui.close_menu();
+                    }
+                });
+            });
+        });
+
+        // Status Bar
+        TopBottomPanel::bottom("bottom_panel").show(ctx, |ui| {
+            ui.label(&self.status_message);
+        });
+
+        // Left Panel (Customer List)
+        SidePanel::left("left_panel").resizable(true).show(ctx, |ui| {
+            ui.heading("Customers");
+            let search = ui.add(TextEdit::singleline(&mut self.search_state.query).hint_text("Search customers and invoices"));
+            if search.changed() && !self.search_state.query.trim().is_empty() {
+                self.main_view = MainView::Search;
+            }
+            let mut clicked_customer_code = None; // Variable to store clicked customer code
+            let mut statement_customer_code = None; // Customer picked from the context menu
+            ScrollArea::vertical().show(ui, |ui| {
+                for customer in self.customers.iter().filter(|c| customer_matches(c, &self.search_state.query)) {
+                    let is_selected = self.selected_customer_code.as_ref() == Some(&customer.code);
+                    let response = ui.selectable_label(is_selected, format!("{} ({})", customer.name, customer.code));
+                    if response.clicked() {
+                        // Store the clicked code instead of updating immediately
+                        clicked_customer_code = Some(customer.code.clone());
+                    }
+                    response.context_menu(|ui| {
+                        if ui.button("Account Statement...").clicked() {
+                            statement_customer_code = Some(customer.code.clone());
+                            ui.close_menu();
+                        }
+                    });
+                }
+            });
+
+            // Update selection and invoice list *after* the loop
+            if let Some(code) = clicked_customer_code {
+                self.selected_customer_code = Some(code);
+                self.update_invoice_list();
+            }
+            if let Some(code) = statement_customer_code {
+                self.open_statement_window(&code);
+            }
+        });
+
+        // Central Panel (Invoice List for Selected Customer)
+        CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut self.main_view, MainView::CustomerInvoices, "Customer Invoices");
+                ui.selectable_value(&mut self.main_view, MainView::AllInvoices, "All Invoices");
+                ui.selectable_value(&mut self.main_view, MainView::Dashboard, "Dashboard");
+                ui.selectable_value(&mut self.main_view, MainView::Search, "Search");
+            });
+            ui.separator();
+            match self.main_view {
+                MainView::AllInvoices => {
+                    self.all_invoices_panel(ui);
+                    return;
+                }
+                MainView::Dashboard => {
+                    self.dashboard_panel(ui);
+                    return;
+                }
+                MainView::Search => {
+                    self.search_panel(ui);
+                    return;
+                }
+                MainView::CustomerInvoices => {}
+            }
+            if let Some(name) = self.get_selected_customer_name() {
+                ui.heading(format!("Invoices for {}", name));
+                ScrollArea::vertical().show(ui, |ui| {
+                    Grid::new("invoice_list_grid")
+                        .num_columns(6) // Added columns for Edit/Delete
+                        .spacing([10.0, 4.0])
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.label(RichText::new("Number").strong());
+                            ui.label(RichText::new("Date").strong());
+                            ui.label(RichText::new("Total").strong());
+                            ui.label(RichText::new("Status").strong());
+                            ui.label(RichText::new("Actions").strong()); // Combined actions
+                            ui.label(""); // PDF Action
+                            ui.end_row();
+
+                            let mut invoice_to_mark_paid = None;
+                            let mut invoice_to_view_details = None;
+                            let mut invoice_to_generate_pdf = None;
+                            let mut invoice_to_edit = None; // For Edit button
+                            let mut invoice_to_delete = None; // For Delete button
+                            let mut invoice_to_record_payment = None;
+                            let mut invoice_to_charge_late_fee = None;
+                            let today = Local::now().date_naive();
+
+                            for invoice in &self.invoices_for_selected_customer {
+                                let is_selected = self.selected_invoice_number.as_ref() == Some(&invoice.invoice_number);
+                                let response = ui.selectable_label(is_selected, &invoice.invoice_number);
+                                if response.clicked() {
+                                    self.selected_invoice_number = Some(invoice.invoice_number.clone());
+                                }
+                                ui.label(invoice.date.format("%Y-%m-%d").to_string());
+                                ui.label(format!("{:.2}", invoice.total));
+                                ui.label(invoice.status_label());
+                                
+                                // Action buttons in one cell
+                                ui.horizontal(|ui| {
+                                    if ui.button("View").clicked() {
+                                        invoice_to_view_details = Some(invoice.clone());
+                                    }
+                                    if !invoice.paid && ui.button("Mark Paid").clicked() {
+                                        invoice_to_mark_paid = Some(invoice.invoice_number.clone());
+                                    }
+                                    if !invoice.paid && ui.button("Record Payment").clicked() {
+                                        invoice_to_record_payment = Some(invoice.clone());
+                                    }
+                                    if invoice.is_overdue(today) && ui.button("Late Fee").clicked() {
+                                        invoice_to_charge_late_fee = Some(invoice.invoice_number.clone());
+                                    }
+                                    // Edit Button
+                                    if ui.button("Edit").clicked() {
+                                        invoice_to_edit = Some(invoice.clone());
+                                    }
+                                    // Delete Button
+                                    if ui.button("Delete").clicked() {
+                                        invoice_to_delete = Some(invoice.invoice_number.clone());
+                                    }
+                                });
+                                // PDF Button in separate cell
+                                if ui.button("PDF").clicked() {
+                                    invoice_to_generate_pdf = Some(invoice.invoice_number.clone());
+                                }
+                                ui.end_row();
+                            }
+
+                            if let Some(num) = invoice_to_mark_paid {
+                                match self.db.mark_invoice_paid_gui(&num) {
+                                    Ok(_) => {
+                                        self.status_message = format!("Invoice #{} marked as paid.", num);
+                                        self.update_invoice_list();
+                                    },
+                                    Err(e) => self.status_message = format!("Error marking invoice paid: {}", e),
+                                }
+                            }
+                            if let Some(invoice) = invoice_to_view_details {
+                                self.invoice_to_view = Some(invoice);
+                                self.show_view_invoice_window = true;
+                            }
+                            if let Some(num) = invoice_to_generate_pdf {
+                                self.open_pdf_preview(&num);
+                            }
+                            // Handle Edit Invoice action
+                            if let Some(invoice) = invoice_to_edit {
+                                self.edit_invoice_state = EditInvoiceState {
+                                    original_invoice_number: invoice.invoice_number.clone(),
+                                    // Removed unused field: customer_code: invoice.customer.code.clone(),
+                                    customer_name: invoice.customer.name.clone(),
+                                    items: invoice.items.iter().map(|item| InvoiceItemState {
+                                        description: item.description.clone(),
+                                        quantity_str: item.quantity.to_string(),
+                                        rate_str: format!("{:.2}", item.rate),
+                                        gst_free: item.gst_free,
+                                    }).collect(),
+                                    notes: invoice.notes.clone(),
+                                    due_date_str: invoice.due_date.format("%Y-%m-%d").to_string(),
+                                    paid: invoice.paid,
+                                    error_message: None,
+                                };
+                                self.show_edit_invoice_window = true;
+                            }
+                            if let Some(invoice) = invoice_to_record_payment {
+                                self.record_payment_state = RecordPaymentState {
+                                    invoice_number: invoice.invoice_number.clone(),
+                                    balance_due: invoice.balance_due(),
+                                    amount_str: format!("{:.2}", invoice.balance_due()),
+                                    ..Default::default()
+                                };
+                                self.show_record_payment_window = true;
+                            }
+                            if let Some(num) = invoice_to_charge_late_fee {
+                                self.open_late_fee_window(&num);
+                            }
+                            // Handle Delete Invoice action
+                            if let Some(num) = invoice_to_delete {
+                                self.invoice_to_delete_number = Some(num);
+                                self.show_delete_invoice_confirm_window = true;
+                            }
+                        });
+                });
+            } else {
+                ui.label("Select a customer from the left panel to view invoices.");
+            }
+        });
+
+        // Modal Windows
+        if self.show_add_customer_window {
+            self.add_customer_window(ctx);
+        }
+        if self.show_edit_customer_window {
+            self.edit_customer_window(ctx);
+        }
+        if self.show_delete_customer_confirm_window {
+            self.delete_customer_confirm_window(ctx);
+        }
+        if self.show_create_invoice_window {
+            self.create_invoice_window(ctx);
+        }
+        if self.show_view_invoice_window {
+            self.view_invoice_window(ctx);
+        }
+        if self.show_edit_invoice_window {
+            self.edit_invoice_window(ctx);
+        }
+        if self.show_delete_invoice_confirm_window {
+            self.delete_invoice_confirm_window(ctx);
+        }
+        if self.show_pdf_preview_window {
+            self.pdf_preview_window(ctx);
+        }
+        if self.show_batch_export_window {
+            self.batch_export_window(ctx);
+        }
+        if self.show_statement_window {
+            self.statement_window(ctx);
+        }
+        if self.show_record_payment_window {
+            self.record_payment_window(ctx);
+        }
+        if self.show_customer_import_window {
+            self.customer_import_window(ctx);
+        }
+        if self.show_data_export_window {
+            self.data_export_window(ctx);
+        }
+        if self.show_accounting_export_window {
+            self.accounting_export_window(ctx);
+        }
+        if self.show_reconciliation_window {
+            self.reconciliation_window(ctx);
+        }
+        if self.show_json_export_window {
+            self.json_export_window(ctx);
+        }
+        if self.show_json_import_window {
+            self.json_import_window(ctx);
+        }
+        if self.show_send_email_window {
+            self.send_email_window(ctx);
+        }
+        if self.show_company_window {
+            self.company_window(ctx);
+        }
+        if self.show_email_settings_window {
+            self.email_settings_window(ctx);
+        }
+        if self.show_reminders_window {
+            self.reminders_window(ctx);
+        }
+        if self.show_late_fee_window {
+            self.late_fee_window(ctx);
+        }
+        if self.show_aged_receivables_window {
+            self.aged_receivables_window(ctx);
+        }
+        if self.show_bas_window {
+            self.bas_window(ctx);
+        }
+        if self.show_bulk_email_window {
+            self.bulk_email_window(ctx);
+        }
+    }
+}
+
+// Implement Drop to save database on exit
+impl Drop for KmattInvoiceApp {
+    fn drop(&mut self) {
+        println!("Saving database before exit...");
+        if let Err(e) = self.db.save() {
+            eprintln!("Error saving database on exit: {}", e);
+        }
+    }
+}
+
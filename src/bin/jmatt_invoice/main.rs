@@ -0,0 +1,14 @@
+mod gui;
+
+fn main() {
+    if std::env::args().len() > 1 {
+        eprintln!("The GUI takes no arguments; use jmatt_invoice_cli for command line use.");
+        std::process::exit(2);
+    }
+
+    println!("Launching GUI...");
+    if let Err(e) = gui::run() {
+        eprintln!("Error running GUI: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,1105 @@
+// Headless command line interface for scripts and scheduled jobs.
+//
+//   jmatt_invoice_cli [--json] <command> [arguments]
+//
+// `--json` prints machine-readable output on stdout; messages and errors go to stderr.
+// Exit codes: 0 success, 1 the command failed, 2 bad usage.
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::path::Path;
+use chrono::{Duration, Local, NaiveDate};
+use prettytable::{format, Cell, Row, Table};
+use serde_json::{json, Value};
+use jmatt_invoice::accounting_export::AccountingSystem;
+use jmatt_invoice::aged_receivables::BUCKET_LABELS;
+use jmatt_invoice::bas::{bas_summary, BasBasis, BasPeriod, PeriodLength};
+use jmatt_invoice::database::{Database, DatabaseError, InvoiceFilter, InvoiceStatusFilter};
+use jmatt_invoice::interchange::{write_interchange, InterchangeSelection};
+use jmatt_invoice::models::{Company, Customer, Invoice, InvoiceItem};
+use jmatt_invoice::late_fees::LateFeeTarget;
+use jmatt_invoice::pdf_generator::PdfOptions;
+use jmatt_invoice::reminders::{stage_label, PlannedReminder};
+use jmatt_invoice::spreadsheet::{ExportFormat, ExportKind};
+use crate::server::{generate_token, ServeOptions};
+
+const USAGE: &str = "\
+Usage: jmatt_invoice_cli [--json] <command> [arguments]
+
+Customers:
+  customer list
+  customer add --name NAME --code CODE [--address A] [--phone P] [--contact NAME]
+               [--contact-phone P] [--email E] [--abn ABN]
+  customer edit CODE [--name NAME] [--code NEW_CODE] [--address A] ...
+
+Business details:
+  company show
+  company edit [--name NAME] [--abn ABN] [--address A] [--phone P] [--gst-registered yes|no]
+                Once registered for GST, new invoice lines carry 10% GST
+
+Invoices:
+  invoice create --customer CODE --item \"DESCRIPTION:QTY:RATE[:gst-free]\" [--item ...]
+                 [--notes TEXT] [--due YYYY-MM-DD | --due-in DAYS]   (default: due in 30 days)
+  invoice list [FILTERS]
+  invoice show NUMBER
+  invoice mark-paid NUMBER
+  invoice pdf NUMBER [--output FILE] [--remittance] [--qr] [--pdf-a] [--embed-xml]
+                --qr adds a plain-text QR code with the bank details for the payer to copy
+  invoice email NUMBER [--to ADDRESSES] [--subject TEXT] [--body TEXT] [PDF OPTIONS]
+                Sends the PDF through the SMTP server in the email settings; the subject
+                and body default to the templates, --to to the customer's email
+  invoice late-fee NUMBER [--preview] [--date YYYY-MM-DD] [--add-to NUMBER]
+                Charges the late fee or interest owing under the late fee settings, on a new
+                fee invoice or as a line on another unpaid invoice for the same customer;
+                a --date after today can only be used with --preview
+
+Search:
+  search TEXT [FILTERS]
+                Customers by name, code, email or phone, and invoices by number, line
+                description, notes or amount (e.g. \"$85.00\"); filters narrow the invoices
+
+Payment reminders:
+  reminders [--dry-run] [--date YYYY-MM-DD] [PDF OPTIONS]
+                Emails the reminders that are due under the rules in the reminder settings;
+                --dry-run lists them without sending, --date checks as at another day
+
+Reports and exports:
+  report [FILTERS]
+  report aged [--date YYYY-MM-DD] [--output FILE.csv|FILE.pdf]
+                Balances owing per customer by days past due: current, 1-30, 31-60, 61-90, 90+
+  report bas [--quarter YYYY-QN | --month YYYY-MM | --from YYYY-MM-DD --to YYYY-MM-DD]
+             [--basis accruals|cash] [--output FILE.csv]
+                GST totals for the BAS (G1, G3, 1A) with the contributing entries;
+                defaults to the last complete quarter on the accruals basis
+  export --kind customers|invoices|line-items|xero|myob|json --output FILE
+         [--format csv|xlsx] [--include-exported] [FILTERS]
+
+API server and web UI:
+  serve [--bind ADDRESS] [--port PORT] [--token TOKEN]
+        HTTP JSON API under /api and a browser front end at / (default 127.0.0.1:8765;
+        use --bind 0.0.0.0 to reach it from other devices). The token can also be set
+        with JMATT_INVOICE_TOKEN; a random one is printed if neither is given.
+
+Filters (by invoice issue date, repeat --customer for several):
+  --from YYYY-MM-DD  --to YYYY-MM-DD  --customer CODE  --status all|paid|unpaid|overdue
+
+Options:
+  --json   Print JSON instead of tables
+  --help   Show this message";
+
+// Options that never take a value
+const FLAGS: [&str; 9] = ["json", "help", "remittance", "qr", "pdf-a", "embed-xml", "include-exported", "dry-run", "preview"];
+
+const FILTER_OPTIONS: [&str; 4] = ["from", "to", "customer", "status"];
+const CUSTOMER_OPTIONS: [&str; 8] = ["name", "code", "address", "phone", "contact", "contact-phone", "email", "abn"];
+
+pub enum CliError {
+    Usage(String),
+    Database(DatabaseError),
+}
+
+impl From<DatabaseError> for CliError {
+    fn from(err: DatabaseError) -> CliError {
+        CliError::Database(err)
+    }
+}
+
+fn usage<T>(message: impl Into<String>) -> Result<T, CliError> {
+    Err(CliError::Usage(message.into()))
+}
+
+// Parsed command line: positional words, `--name value` options and `--flag` switches
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, Vec<String>>,
+    flags: HashSet<String>,
+}
+
+impl Args {
+    fn parse(raw: &[String]) -> Result<Args, CliError> {
+        let mut args = Args { positional: Vec::new(), options: HashMap::new(), flags: HashSet::new() };
+        let mut iter = raw.iter();
+        while let Some(arg) = iter.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                args.positional.push(arg.clone());
+                continue;
+            };
+            let (name, inline_value) = match name.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (name, None),
+            };
+            if FLAGS.contains(&name) {
+                if inline_value.is_some() {
+                    return usage(format!("--{} does not take a value", name));
+                }
+                args.flags.insert(name.to_string());
+                continue;
+            }
+            let value = match inline_value {
+                Some(value) => value,
+                None => match iter.next() {
+                    Some(value) => value.clone(),
+                    None => return usage(format!("--{} needs a value", name)),
+                },
+            };
+            args.options.entry(name.to_string()).or_default().push(value);
+        }
+        Ok(args)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.contains(name)
+    }
+
+    // Last value given for an option
+    fn value(&self, name: &str) -> Option<&str> {
+        self.options.get(name).and_then(|values| values.last()).map(|v| v.as_str())
+    }
+
+    fn values(&self, name: &str) -> Vec<String> {
+        self.options.get(name).cloned().unwrap_or_default()
+    }
+
+    fn required(&self, name: &str) -> Result<&str, CliError> {
+        match self.value(name) {
+            Some(value) => Ok(value),
+            None => usage(format!("--{} is required", name)),
+        }
+    }
+
+    // Rejects options the command doesn't understand, so typos don't pass silently
+    fn allow(&self, allowed: &[&str]) -> Result<(), CliError> {
+        let unknown = self.options.keys().chain(self.flags.iter())
+            .find(|name| name.as_str() != "json" && !allowed.contains(&name.as_str()));
+        match unknown {
+            Some(name) => usage(format!("Unknown option --{}", name)),
+            None => Ok(()),
+        }
+    }
+
+    // The positional argument after the command words, e.g. the number in `invoice show AB76`
+    fn target(&self, index: usize, what: &str) -> Result<&str, CliError> {
+        if self.positional.len() > index + 1 {
+            return usage(format!("Unexpected argument \"{}\"", self.positional[index + 1]));
+        }
+        match self.positional.get(index) {
+            Some(value) => Ok(value),
+            None => usage(format!("Missing {}", what)),
+        }
+    }
+
+    fn no_targets(&self, index: usize) -> Result<(), CliError> {
+        match self.positional.get(index) {
+            Some(extra) => usage(format!("Unexpected argument \"{}\"", extra)),
+            None => Ok(()),
+        }
+    }
+}
+
+fn parse_date(value: &str, option: &str) -> Result<NaiveDate, CliError> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .or_else(|_| usage(format!("--{} must be a date in YYYY-MM-DD format, got \"{}\"", option, value)))
+}
+
+fn parse_filter(args: &Args) -> Result<InvoiceFilter, CliError> {
+    let status = match args.value("status") {
+        None => InvoiceStatusFilter::All,
+        Some(value) => match InvoiceStatusFilter::ALL.iter().find(|s| s.label().eq_ignore_ascii_case(value)) {
+            Some(status) => *status,
+            None => return usage(format!("Unknown status \"{}\" (use all, paid, unpaid or overdue)", value)),
+        },
+    };
+    Ok(InvoiceFilter {
+        from_date: args.value("from").map(|v| parse_date(v, "from")).transpose()?,
+        to_date: args.value("to").map(|v| parse_date(v, "to")).transpose()?,
+        customer_codes: args.values("customer").iter().map(|c| c.trim().to_uppercase()).collect(),
+        status,
+    })
+}
+
+// "DESCRIPTION:QTY:RATE", optionally followed by ":gst-free"; the description may itself contain colons
+fn parse_item(value: &str) -> Result<InvoiceItem, CliError> {
+    let (line, gst_free) = match value.rsplit_once(':') {
+        Some((line, flag)) if flag.trim().eq_ignore_ascii_case("gst-free") => (line, true),
+        _ => (value, false),
+    };
+    let mut parts = line.rsplitn(3, ':');
+    let (rate, quantity, description) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(rate), Some(quantity), Some(description)) => (rate, quantity, description),
+        _ => return usage(format!("--item must look like \"DESCRIPTION:QTY:RATE\", got \"{}\"", value)),
+    };
+    let quantity = match quantity.trim().parse::<u32>() {
+        Ok(q) if q > 0 => q,
+        _ => return usage(format!("Invalid quantity \"{}\" in --item \"{}\"", quantity, value)),
+    };
+    let rate = match rate.trim().trim_start_matches('$').parse::<f64>() {
+        Ok(r) if r >= 0.0 && r.is_finite() => r,
+        _ => return usage(format!("Invalid rate \"{}\" in --item \"{}\"", rate, value)),
+    };
+    if description.trim().is_empty() {
+        return usage(format!("Missing description in --item \"{}\"", value));
+    }
+    Ok(InvoiceItem { description: description.trim().to_string(), quantity, rate, amount: 0.0, gst_free, gst: 0.0 }) // Amount and GST are calculated on creation
+}
+
+fn table(headers: &[&str]) -> Table {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(Row::new(headers.iter().map(|h| Cell::new(h)).collect()));
+    table
+}
+
+// Writes through a locked stdout rather than println!, which panics when the reader has gone
+// (e.g. `| head`). A closed pipe ends the program quietly.
+fn print_json(value: &Value) {
+    let mut out = io::stdout().lock();
+    if let Err(e) = writeln!(out, "{}", serde_json::to_string_pretty(value).unwrap_or_default()) {
+        if e.kind() == io::ErrorKind::BrokenPipe {
+            std::process::exit(0);
+        }
+        eprintln!("Error: could not write the output: {}", e);
+        std::process::exit(1);
+    }
+}
+
+// Invoice as stored, plus the derived status and balance
+pub fn invoice_json(invoice: &Invoice) -> Value {
+    let mut value = serde_json::to_value(invoice).unwrap_or(Value::Null);
+    if let Value::Object(map) = &mut value {
+        map.insert("status".to_string(), json!(invoice.status_label()));
+        map.insert("amount_paid".to_string(), json!(invoice.amount_paid()));
+        map.insert("balance_due".to_string(), json!(invoice.balance_due()));
+    }
+    value
+}
+
+fn find_customer(db: &Database, code: &str) -> Result<Customer, CliError> {
+    let code = code.trim().to_uppercase();
+    match db.customers.values().find(|c| c.code == code) {
+        Some(c) => Ok(c.clone()),
+        None => Err(DatabaseError::CustomerNotFound(code).into()),
+    }
+}
+
+fn find_invoice(db: &Database, number: &str) -> Result<Invoice, CliError> {
+    match db.invoices.get(number) {
+        Some(invoice) => Ok(invoice.clone()),
+        None => Err(DatabaseError::InvoiceNotFound(number.to_string()).into()),
+    }
+}
+
+// Runs the command line in `raw` (without the program name) and returns the exit code
+pub fn run(raw: &[String]) -> i32 {
+    let args = match Args::parse(raw) {
+        Ok(args) => args,
+        Err(err) => return report_error(err),
+    };
+    if args.flag("help") || args.positional.first().is_some_and(|c| c == "help") {
+        println!("{}", USAGE);
+        return 0;
+    }
+    if args.positional.is_empty() {
+        eprintln!("{}", USAGE);
+        return 2;
+    }
+    let mut db = match if modifies_database(&args) { Database::load() } else { Database::open() } {
+        Ok(db) => db,
+        Err(e) => return report_error(e.into()),
+    };
+    match dispatch(&mut db, &args) {
+        Ok(()) => 0,
+        Err(err) => report_error(err),
+    }
+}
+
+// Commands that change the database; only these take a backup when loading it
+fn modifies_database(args: &Args) -> bool {
+    let words: Vec<&str> = args.positional.iter().take(2).map(|s| s.as_str()).collect();
+    match words.as_slice() {
+        ["company", "edit"] | ["customer", "add"] | ["customer", "edit"] | ["invoice", "create"] | ["invoice", "mark-paid"] | ["invoice", "email"] => true,
+        ["serve", ..] => true, // Backed up once at startup
+        ["reminders", ..] => !args.flag("dry-run"),
+        ["invoice", "late-fee", ..] => !args.flag("preview"),
+        ["export", ..] => matches!(args.value("kind"), Some(kind) if kind.eq_ignore_ascii_case("xero") || kind.eq_ignore_ascii_case("myob")), // Records the export
+        _ => false,
+    }
+}
+
+fn report_error(err: CliError) -> i32 {
+    match err {
+        CliError::Usage(message) => {
+            eprintln!("Error: {}\nRun with --help for usage.", message);
+            2
+        }
+        CliError::Database(e) => {
+            eprintln!("Error: {}", e);
+            1
+        }
+    }
+}
+
+fn dispatch(db: &mut Database, args: &Args) -> Result<(), CliError> {
+    let words: Vec<&str> = args.positional.iter().map(|s| s.as_str()).collect();
+    match words.as_slice() {
+        ["company", "show", ..] => company_show(db, args),
+        ["company", "edit", ..] => company_edit(db, args),
+        ["customer", "list", ..] => customer_list(db, args),
+        ["customer", "add", ..] => customer_add(db, args),
+        ["customer", "edit", ..] => customer_edit(db, args),
+        ["invoice", "create", ..] => invoice_create(db, args),
+        ["invoice", "list", ..] => invoice_list(db, args),
+        ["invoice", "show", ..] => invoice_show(db, args),
+        ["invoice", "mark-paid", ..] => invoice_mark_paid(db, args),
+        ["invoice", "pdf", ..] => invoice_pdf(db, args),
+        ["invoice", "email", ..] => invoice_email(db, args),
+        ["invoice", "late-fee", ..] => invoice_late_fee(db, args),
+        ["search", ..] => search(db, args),
+        ["reminders", ..] => reminders(db, args),
+        ["report", "aged", ..] => report_aged(db, args),
+        ["report", "bas", ..] => report_bas(db, args),
+        ["report", ..] => report(db, args),
+        ["export", ..] => export(db, args),
+        ["serve", ..] => serve(args),
+        ["company", other, ..] | ["customer", other, ..] | ["invoice", other, ..] => usage(format!("Unknown subcommand \"{}\"", other)),
+        ["company"] | ["customer"] | ["invoice"] => usage(format!("\"{}\" needs a subcommand", words[0])),
+        [other, ..] => usage(format!("Unknown command \"{}\"", other)),
+        [] => usage("Missing command"), // Handled in run()
+    }
+}
+
+fn customer_list(db: &Database, args: &Args) -> Result<(), CliError> {
+    args.allow(&[])?;
+    args.no_targets(2)?;
+    let customers = db.get_customers_vec();
+    if args.flag("json") {
+        print_json(&json!(customers));
+        return Ok(());
+    }
+    let mut t = table(&["Code", "Name", "Contact", "Phone", "Email"]);
+    for c in &customers {
+        t.add_row(Row::new(vec![
+            Cell::new(&c.code),
+            Cell::new(&c.name),
+            Cell::new(&c.contact_person),
+            Cell::new(&c.phone),
+            Cell::new(&c.email),
+        ]));
+    }
+    t.printstd();
+    Ok(())
+}
+
+// Copies the customer fields given on the command line onto `customer`
+fn apply_customer_options(customer: &mut Customer, args: &Args) {
+    let fields: [(&str, &mut String); 8] = [
+        ("name", &mut customer.name),
+        ("code", &mut customer.code),
+        ("address", &mut customer.address),
+        ("phone", &mut customer.phone),
+        ("contact", &mut customer.contact_person),
+        ("contact-phone", &mut customer.contact_phone),
+        ("email", &mut customer.email),
+        ("abn", &mut customer.abn),
+    ];
+    for (option, field) in fields {
+        if let Some(value) = args.value(option) {
+            *field = value.to_string();
+        }
+    }
+}
+
+fn print_customer(customer: &Customer, args: &Args, message: &str) {
+    if args.flag("json") {
+        print_json(&json!(customer));
+    } else {
+        println!("{} {} ({})", message, customer.name, customer.code);
+    }
+}
+
+fn customer_add(db: &mut Database, args: &Args) -> Result<(), CliError> {
+    args.allow(&CUSTOMER_OPTIONS)?;
+    args.no_targets(2)?;
+    args.required("name")?;
+    args.required("code")?;
+    let mut customer = Customer {
+        name: String::new(),
+        address: String::new(),
+        phone: String::new(),
+        contact_person: String::new(),
+        contact_phone: String::new(),
+        email: String::new(),
+        code: String::new(),
+        abn: String::new(),
+    };
+    apply_customer_options(&mut customer, args);
+    db.add_customer_gui(customer.clone())?;
+    // Report the stored record, which has the name and code normalised
+    let code = customer.code.trim().to_uppercase();
+    print_customer(&find_customer(db, &code)?, args, "Added customer");
+    Ok(())
+}
+
+fn print_company(company: &Company, args: &Args) {
+    if args.flag("json") {
+        print_json(&json!(company));
+        return;
+    }
+    println!("{}", company.name);
+    println!("ABN: {}", company.abn);
+    println!("Address: {}", company.address);
+    println!("Phone: {}", company.phone);
+    println!("GST registered: {}", if company.gst_registered { "yes" } else { "no" });
+}
+
+fn company_show(db: &Database, args: &Args) -> Result<(), CliError> {
+    args.allow(&[])?;
+    args.no_targets(2)?;
+    print_company(&db.company, args);
+    Ok(())
+}
+
+fn company_edit(db: &mut Database, args: &Args) -> Result<(), CliError> {
+    args.allow(&["name", "abn", "address", "phone", "gst-registered"])?;
+    args.no_targets(2)?;
+    let mut company = db.company.clone();
+    for (option, field) in [("name", &mut company.name), ("abn", &mut company.abn), ("address", &mut company.address), ("phone", &mut company.phone)] {
+        if let Some(value) = args.value(option) {
+            *field = value.trim().to_string();
+        }
+    }
+    if let Some(value) = args.value("gst-registered") {
+        company.gst_registered = match value.to_lowercase().as_str() {
+            "yes" | "true" => true,
+            "no" | "false" => false,
+            _ => return usage(format!("--gst-registered must be yes or no, got \"{}\"", value)),
+        };
+    }
+    db.update_company_gui(company)?;
+    print_company(&db.company, args);
+    Ok(())
+}
+
+fn customer_edit(db: &mut Database, args: &Args) -> Result<(), CliError> {
+    args.allow(&CUSTOMER_OPTIONS)?;
+    let existing = find_customer(db, args.target(2, "customer code")?)?;
+    let mut updated = existing.clone();
+    apply_customer_options(&mut updated, args);
+    db.edit_customer_gui(&existing.name, updated.clone())?;
+    let code = updated.code.trim().to_uppercase();
+    print_customer(&find_customer(db, &code)?, args, "Updated customer");
+    Ok(())
+}
+
+fn search(db: &Database, args: &Args) -> Result<(), CliError> {
+    args.allow(&FILTER_OPTIONS)?;
+    let query = args.target(1, "search text")?;
+    if query.trim().is_empty() {
+        return usage("The search text is empty");
+    }
+    let results = db.search(query, &parse_filter(args)?);
+    if args.flag("json") {
+        print_json(&json!({
+            "customers": results.customers,
+            "invoices": results.invoices.iter().map(|hit| {
+                let mut value = invoice_json(&hit.invoice);
+                if let Value::Object(map) = &mut value {
+                    map.insert("matched_on".to_string(), json!(hit.matched_on));
+                }
+                value
+            }).collect::<Vec<_>>(),
+            "invoice_total": results.invoice_total,
+        }));
+        return Ok(());
+    }
+    if results.customers.is_empty() && results.invoices.is_empty() {
+        println!("Nothing matches \"{}\"", query);
+        return Ok(());
+    }
+    if !results.customers.is_empty() {
+        let mut t = table(&["Code", "Name", "Phone", "Email"]);
+        for c in &results.customers {
+            t.add_row(Row::new(vec![Cell::new(&c.code), Cell::new(&c.name), Cell::new(&c.phone), Cell::new(&c.email)]));
+        }
+        t.printstd();
+    }
+    if !results.invoices.is_empty() {
+        if !results.customers.is_empty() {
+            println!();
+        }
+        let mut t = table(&["Invoice", "Date", "Customer", "Total", "Balance", "Status", "Matched"]);
+        for hit in &results.invoices {
+            let invoice = &hit.invoice;
+            t.add_row(Row::new(vec![
+                Cell::new(&invoice.invoice_number),
+                Cell::new(&invoice.date.format("%Y-%m-%d").to_string()),
+                Cell::new(&invoice.customer.name),
+                Cell::new_align(&format!("${:.2}", invoice.total), format::Alignment::RIGHT),
+                Cell::new_align(&format!("${:.2}", invoice.balance_due()), format::Alignment::RIGHT),
+                Cell::new(invoice.status_label()),
+                Cell::new(&hit.matched_on),
+            ]));
+        }
+        t.printstd();
+        if results.invoice_total > results.invoices.len() {
+            println!("Showing the newest {} of {} matching invoices", results.invoices.len(), results.invoice_total);
+        }
+    }
+    Ok(())
+}
+
+fn invoice_create(db: &mut Database, args: &Args) -> Result<(), CliError> {
+    args.allow(&["customer", "item", "notes", "due", "due-in"])?;
+    args.no_targets(2)?;
+    let customer = find_customer(db, args.required("customer")?)?;
+    let items = args.values("item").iter().map(|v| parse_item(v)).collect::<Result<Vec<_>, _>>()?;
+    if items.is_empty() {
+        return usage("At least one --item is required");
+    }
+    let today = Local::now().date_naive();
+    let due_date = match (args.value("due"), args.value("due-in")) {
+        (Some(_), Some(_)) => return usage("Use either --due or --due-in, not both"),
+        (Some(date), None) => parse_date(date, "due")?,
+        (None, Some(days)) => match days.parse::<i64>() {
+            Ok(days) if (0..=3650).contains(&days) => today + Duration::days(days),
+            _ => return usage(format!("--due-in must be a number of days, got \"{}\"", days)),
+        },
+        (None, None) => today + Duration::days(30),
+    };
+    let notes = args.value("notes").unwrap_or_default().to_string();
+    let invoice = db.create_invoice_gui(customer.code, items, notes, due_date)?;
+    if args.flag("json") {
+        print_json(&invoice_json(&invoice));
+    } else {
+        println!("Created invoice {} for {} (${:.2}, due {})", invoice.invoice_number, invoice.customer.name, invoice.total, invoice.due_date.format("%Y-%m-%d"));
+    }
+    Ok(())
+}
+
+fn invoice_list(db: &Database, args: &Args) -> Result<(), CliError> {
+    args.allow(&FILTER_OPTIONS)?;
+    args.no_targets(2)?;
+    let invoices = db.get_invoices_filtered(&parse_filter(args)?);
+    if args.flag("json") {
+        print_json(&Value::Array(invoices.iter().map(invoice_json).collect()));
+        return Ok(());
+    }
+    let mut t = table(&["Invoice", "Date", "Due", "Customer", "Total", "Balance", "Status"]);
+    for invoice in &invoices {
+        t.add_row(Row::new(vec![
+            Cell::new(&invoice.invoice_number),
+            Cell::new(&invoice.date.format("%Y-%m-%d").to_string()),
+            Cell::new(&invoice.due_date.format("%Y-%m-%d").to_string()),
+            Cell::new(&invoice.customer.name),
+            Cell::new_align(&format!("${:.2}", invoice.total), format::Alignment::RIGHT),
+            Cell::new_align(&format!("${:.2}", invoice.balance_due()), format::Alignment::RIGHT),
+            Cell::new(invoice.status_label()),
+        ]));
+    }
+    t.printstd();
+    Ok(())
+}
+
+fn invoice_show(db: &Database, args: &Args) -> Result<(), CliError> {
+    args.allow(&[])?;
+    let number = args.target(2, "invoice number")?;
+    if args.flag("json") {
+        print_json(&invoice_json(&find_invoice(db, number)?));
+    } else {
+        println!("{}", db.render_invoice_text(number)?);
+    }
+    Ok(())
+}
+
+fn invoice_mark_paid(db: &mut Database, args: &Args) -> Result<(), CliError> {
+    args.allow(&[])?;
+    let number = args.target(2, "invoice number")?.to_string();
+    db.mark_invoice_paid_gui(&number)?;
+    if args.flag("json") {
+        print_json(&invoice_json(&find_invoice(db, &number)?));
+    } else {
+        println!("Invoice {} marked as paid", number);
+    }
+    Ok(())
+}
+
+fn invoice_pdf(db: &Database, args: &Args) -> Result<(), CliError> {
+    args.allow(&["output", "remittance", "qr", "pdf-a", "embed-xml"])?;
+    let number = args.target(2, "invoice number")?;
+    let filename = match args.value("output") {
+        Some(path) => path.to_string(),
+        None => format!("Invoice-{}.pdf", number),
+    };
+    let saved = db.generate_pdf_gui(number, Path::new(&filename), &pdf_options(args))?;
+    if args.flag("json") {
+        print_json(&json!({ "invoice_number": number, "file": saved }));
+    } else {
+        println!("Saved {}", saved);
+    }
+    Ok(())
+}
+
+fn pdf_options(args: &Args) -> PdfOptions {
+    PdfOptions {
+        include_remittance: args.flag("remittance"),
+        include_payment_qr: args.flag("qr"),
+        pdf_a: args.flag("pdf-a"),
+        embed_einvoice: args.flag("embed-xml"),
+    }
+}
+
+fn invoice_email(db: &mut Database, args: &Args) -> Result<(), CliError> {
+    args.allow(&["to", "subject", "body", "remittance", "qr", "pdf-a", "embed-xml"])?;
+    let number = args.target(2, "invoice number")?.to_string();
+    let mut draft = db.email_draft(&number)?;
+    if let Some(to) = args.value("to") {
+        draft.to = to.to_string();
+    }
+    if let Some(subject) = args.value("subject") {
+        draft.subject = subject.to_string();
+    }
+    if let Some(body) = args.value("body") {
+        draft.body = body.to_string();
+    }
+    let sent = db.email_invoice_gui(&number, &draft, &pdf_options(args))?;
+    if args.flag("json") {
+        print_json(&json!({ "invoice_number": number, "sent_at": sent.sent_at, "to": sent.to, "subject": sent.subject }));
+    } else {
+        println!("Invoice {} emailed to {}", number, sent.to);
+    }
+    Ok(())
+}
+
+fn invoice_late_fee(db: &mut Database, args: &Args) -> Result<(), CliError> {
+    args.allow(&["preview", "date", "add-to"])?;
+    let number = args.target(2, "invoice number")?.to_string();
+    let as_at = match args.value("date") {
+        Some(value) => parse_date(value, "date")?,
+        None => Local::now().date_naive(),
+    };
+    let quote = db.late_fee_quote(&number, as_at)?;
+    let billed_on = if args.flag("preview") {
+        None
+    } else {
+        let target = match args.value("add-to") {
+            Some(other) => LateFeeTarget::AddTo(other.to_string()),
+            None => LateFeeTarget::NewInvoice,
+        };
+        Some(db.apply_late_fee_gui(&number, as_at, &target)?)
+    };
+    if args.flag("json") {
+        print_json(&json!({
+            "invoice_number": number,
+            "balance_due": quote.balance_due,
+            "period_from": quote.period_from.to_string(),
+            "period_to": quote.period_to.to_string(),
+            "days": quote.days,
+            "amount": quote.amount,
+            "description": quote.description,
+            "billed_on": billed_on,
+        }));
+    } else {
+        println!("{}: ${:.2}", quote.description, quote.amount);
+        match billed_on {
+            Some(other) => println!("Charged on invoice {}", other),
+            None => println!("Preview only; nothing was charged"),
+        }
+    }
+    Ok(())
+}
+
+fn reminders(db: &mut Database, args: &Args) -> Result<(), CliError> {
+    args.allow(&["dry-run", "date", "remittance", "qr", "pdf-a", "embed-xml"])?;
+    args.no_targets(1)?;
+    let today = match args.value("date") {
+        Some(value) => parse_date(value, "date")?,
+        None => Local::now().date_naive(),
+    };
+    let dry_run = args.flag("dry-run");
+    let results: Vec<(PlannedReminder, Option<String>)> = if dry_run {
+        db.due_reminders(today).into_iter().map(|r| {
+            let skip = r.skip_reason.clone();
+            (r, skip)
+        }).collect()
+    } else {
+        db.send_reminders(today, &pdf_options(args))?
+    };
+    let failed = results.iter().filter(|(r, error)| error.is_some() && r.skip_reason.is_none()).count();
+
+    let status = |reminder: &PlannedReminder, error: &Option<String>| match (error, &reminder.skip_reason) {
+        (Some(reason), Some(_)) => format!("Skipped: {}", reason),
+        (Some(error), None) => format!("Failed: {}", error),
+        (None, _) if dry_run => "Would send".to_string(),
+        (None, _) => "Sent".to_string(),
+    };
+    if args.flag("json") {
+        print_json(&Value::Array(results.iter().map(|(r, error)| json!({
+            "invoice_number": r.invoice_number,
+            "customer": r.customer_name,
+            "due_date": r.due_date.to_string(),
+            "balance_due": r.balance_due,
+            "stage": stage_label(r.days_from_due),
+            "days_from_due": r.days_from_due,
+            "to": r.draft.to,
+            "subject": r.draft.subject,
+            "sent": !dry_run && error.is_none(),
+            "error": error,
+        })).collect()));
+    } else if results.is_empty() {
+        println!("No reminders due on {}", today);
+    } else {
+        let mut t = table(&["Invoice", "Customer", "Due", "Balance", "Reminder", "To", "Status"]);
+        for (reminder, error) in &results {
+            t.add_row(Row::new(vec![
+                Cell::new(&reminder.invoice_number),
+                Cell::new(&reminder.customer_name),
+                Cell::new(&reminder.due_date.to_string()),
+                Cell::new_align(&format!("${:.2}", reminder.balance_due), format::Alignment::RIGHT),
+                Cell::new(&stage_label(reminder.days_from_due)),
+                Cell::new(&reminder.draft.to),
+                Cell::new(&status(reminder, error)),
+            ]));
+        }
+        t.printstd();
+    }
+    if failed > 0 {
+        return Err(CliError::Database(DatabaseError::Email(format!("{} of {} reminders could not be sent", failed, results.len()))));
+    }
+    Ok(())
+}
+
+fn report_aged(db: &Database, args: &Args) -> Result<(), CliError> {
+    args.allow(&["date", "output"])?;
+    args.no_targets(2)?;
+    let as_at = match args.value("date") {
+        Some(value) => parse_date(value, "date")?,
+        None => Local::now().date_naive(),
+    };
+    if let Some(output) = args.value("output") {
+        let saved = match Path::new(output).extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()).as_deref() {
+            Some("pdf") => db.generate_aged_receivables_pdf_gui(as_at, output, &PdfOptions::default())?,
+            Some("csv") => db.export_aged_receivables_csv_gui(as_at, output)?,
+            _ => return usage("--output must end in .csv or .pdf"),
+        };
+        if args.flag("json") {
+            print_json(&json!({ "as_at": as_at.to_string(), "file": saved }));
+        } else {
+            println!("Saved {}", saved);
+        }
+        return Ok(());
+    }
+
+    let report = db.aged_receivables(as_at);
+    if args.flag("json") {
+        let buckets = |amounts: &[f64; 5]| json!({
+            "current": amounts[0],
+            "days_1_30": amounts[1],
+            "days_31_60": amounts[2],
+            "days_61_90": amounts[3],
+            "days_90_plus": amounts[4],
+        });
+        print_json(&json!({
+            "as_at": as_at.to_string(),
+            "customers": report.rows.iter().map(|r| json!({
+                "code": r.customer_code,
+                "name": r.customer_name,
+                "invoices": r.invoice_count,
+                "buckets": buckets(&r.buckets),
+                "total": r.total,
+            })).collect::<Vec<Value>>(),
+            "totals": buckets(&report.totals),
+            "total": report.total,
+        }));
+        return Ok(());
+    }
+    let mut headers = vec!["Code", "Customer"];
+    headers.extend(BUCKET_LABELS);
+    headers.push("Total");
+    let mut t = table(&headers);
+    let money = |amount: f64| Cell::new_align(&format!("${:.2}", amount), format::Alignment::RIGHT);
+    for row in &report.rows {
+        let mut cells = vec![Cell::new(&row.customer_code), Cell::new(&row.customer_name)];
+        cells.extend(row.buckets.iter().map(|amount| money(*amount)));
+        cells.push(money(row.total));
+        t.add_row(Row::new(cells));
+    }
+    let mut cells = vec![Cell::new(""), Cell::new("Total")];
+    cells.extend(report.totals.iter().map(|amount| money(*amount)));
+    cells.push(money(report.total));
+    t.add_row(Row::new(cells));
+    println!("Aged receivables as at {}", as_at);
+    t.printstd();
+    Ok(())
+}
+
+// Period for `report bas` from --quarter, --month or --from/--to
+fn parse_bas_period(args: &Args) -> Result<BasPeriod, CliError> {
+    let chosen = ["quarter", "month", "from"].iter().filter(|o| args.value(o).is_some()).count();
+    if chosen > 1 {
+        return usage("Use only one of --quarter, --month or --from/--to");
+    }
+    if let Some(value) = args.value("quarter") {
+        let parsed = value.split_once("-Q")
+            .or_else(|| value.split_once("-q"))
+            .and_then(|(year, quarter)| Some((year.parse::<i32>().ok()?, quarter.parse::<u32>().ok()?)))
+            .filter(|(_, quarter)| (1..=4).contains(quarter))
+            .and_then(|(year, quarter)| NaiveDate::from_ymd_opt(year, quarter * 3 - 2, 1));
+        return match parsed {
+            Some(date) => Ok(BasPeriod::containing(date, PeriodLength::Quarter)),
+            None => usage(format!("--quarter must look like 2025-Q3 (Q3 is Jul-Sep), got \"{}\"", value)),
+        };
+    }
+    if let Some(value) = args.value("month") {
+        return match NaiveDate::parse_from_str(&format!("{}-01", value), "%Y-%m-%d") {
+            Ok(date) => Ok(BasPeriod::containing(date, PeriodLength::Month)),
+            Err(_) => usage(format!("--month must look like 2025-07, got \"{}\"", value)),
+        };
+    }
+    match (args.value("from"), args.value("to")) {
+        (Some(from), Some(to)) => Ok(BasPeriod { from: parse_date(from, "from")?, to: parse_date(to, "to")? }),
+        (None, None) => {
+            let this_quarter = BasPeriod::containing(Local::now().date_naive(), PeriodLength::Quarter);
+            Ok(BasPeriod::containing(this_quarter.from - Duration::days(1), PeriodLength::Quarter))
+        }
+        _ => usage("--from and --to must be given together"),
+    }
+}
+
+fn report_bas(db: &Database, args: &Args) -> Result<(), CliError> {
+    args.allow(&["quarter", "month", "from", "to", "basis", "output"])?;
+    args.no_targets(2)?;
+    let period = parse_bas_period(args)?;
+    let basis = match args.value("basis") {
+        None => BasBasis::Accruals,
+        Some(value) => match BasBasis::ALL.iter().find(|b| b.label().eq_ignore_ascii_case(value)) {
+            Some(basis) => *basis,
+            None => return usage(format!("Unknown basis \"{}\" (use accruals or cash)", value)),
+        },
+    };
+    if let Some(output) = args.value("output") {
+        let saved = db.export_bas_csv_gui(&period, basis, output)?;
+        if args.flag("json") {
+            print_json(&json!({ "from": period.from.to_string(), "to": period.to.to_string(), "file": saved }));
+        } else {
+            println!("Saved {}", saved);
+        }
+        return Ok(());
+    }
+
+    let report = db.bas_report(&period, basis)?;
+    if args.flag("json") {
+        print_json(&json!({
+            "from": period.from.to_string(),
+            "to": period.to.to_string(),
+            "basis": basis.label().to_lowercase(),
+            "g1_total_sales": report.total_sales,
+            "g3_gst_free_sales": report.gst_free_sales,
+            "taxable_sales": report.taxable_sales,
+            "1a_gst_on_sales": report.gst_on_sales,
+            "lines": report.lines.iter().map(|l| json!({
+                "date": l.date.to_string(),
+                "invoice_number": l.invoice_number,
+                "customer": l.customer_name,
+                "description": l.description,
+                "amount": l.amount,
+                "gst": l.gst,
+                "gst_free": l.gst_free,
+            })).collect::<Vec<Value>>(),
+        }));
+        return Ok(());
+    }
+    println!("GST summary for {} ({} to {}), {} basis", period.label(), period.from, period.to, basis.label().to_lowercase());
+    let mut t = table(&["Date", "Invoice", "Customer", "Description", "Amount", "GST", "GST-free"]);
+    for line in &report.lines {
+        t.add_row(Row::new(vec![
+            Cell::new(&line.date.to_string()),
+            Cell::new(&line.invoice_number),
+            Cell::new(&line.customer_name),
+            Cell::new(&line.description),
+            Cell::new_align(&format!("${:.2}", line.amount), format::Alignment::RIGHT),
+            Cell::new_align(&format!("${:.2}", line.gst), format::Alignment::RIGHT),
+            Cell::new_align(&format!("${:.2}", line.gst_free), format::Alignment::RIGHT),
+        ]));
+    }
+    t.printstd();
+    println!();
+    let mut summary = table(&["", "", "Amount"]);
+    for (label, description, amount) in bas_summary(&report) {
+        summary.add_row(Row::new(vec![
+            Cell::new(label),
+            Cell::new(description),
+            Cell::new_align(&format!("${:.2}", amount), format::Alignment::RIGHT),
+        ]));
+    }
+    summary.printstd();
+    Ok(())
+}
+
+// Totals for the filtered invoices, overall and per customer
+// One customer's line in the report, or the totals line
+#[derive(Default)]
+struct ReportRow {
+    code: String,
+    name: String,
+    invoices: usize,
+    invoiced: f64,
+    paid: f64,
+    outstanding: f64,
+    overdue: f64,
+}
+
+impl ReportRow {
+    fn add(&mut self, invoice: &Invoice, overdue: bool) {
+        let balance = invoice.balance_due();
+        self.invoices += 1;
+        self.invoiced += invoice.total;
+        self.paid += invoice.total - balance;
+        self.outstanding += balance;
+        if overdue {
+            self.overdue += balance;
+        }
+    }
+}
+
+fn report(db: &Database, args: &Args) -> Result<(), CliError> {
+    args.allow(&FILTER_OPTIONS)?;
+    args.no_targets(1)?;
+    let invoices = db.get_invoices_filtered(&parse_filter(args)?);
+    let today = Local::now().date_naive();
+
+    let mut rows: Vec<ReportRow> = Vec::new();
+    for invoice in &invoices {
+        let idx = match rows.iter().position(|r| r.code == invoice.customer.code) {
+            Some(idx) => idx,
+            None => {
+                rows.push(ReportRow { code: invoice.customer.code.clone(), name: invoice.customer.name.clone(), ..Default::default() });
+                rows.len() - 1
+            }
+        };
+        rows[idx].add(invoice, invoice.is_overdue(today));
+    }
+    rows.sort_by(|a, b| a.name.cmp(&b.name));
+    let total = rows.iter().fold(ReportRow { name: "Total".to_string(), ..Default::default() }, |t, r| ReportRow {
+        invoices: t.invoices + r.invoices,
+        invoiced: t.invoiced + r.invoiced,
+        paid: t.paid + r.paid,
+        outstanding: t.outstanding + r.outstanding,
+        overdue: t.overdue + r.overdue,
+        ..t
+    });
+
+    if args.flag("json") {
+        let customers: Vec<Value> = rows.iter().map(|r| json!({
+            "code": r.code,
+            "name": r.name,
+            "invoices": r.invoices,
+            "invoiced": r.invoiced,
+            "paid": r.paid,
+            "outstanding": r.outstanding,
+            "overdue": r.overdue,
+        })).collect();
+        print_json(&json!({
+            "from": args.value("from"),
+            "to": args.value("to"),
+            "invoices": total.invoices,
+            "invoiced": total.invoiced,
+            "paid": total.paid,
+            "outstanding": total.outstanding,
+            "overdue": total.overdue,
+            "customers": customers,
+        }));
+        return Ok(());
+    }
+
+    let money = |amount: f64| Cell::new_align(&format!("${:.2}", amount), format::Alignment::RIGHT);
+    let mut t = table(&["Code", "Customer", "Invoices", "Invoiced", "Paid", "Outstanding", "Overdue"]);
+    for r in rows.iter().chain([&total]) {
+        t.add_row(Row::new(vec![
+            Cell::new(&r.code),
+            Cell::new(&r.name),
+            Cell::new_align(&r.invoices.to_string(), format::Alignment::RIGHT),
+            money(r.invoiced),
+            money(r.paid),
+            money(r.outstanding),
+            money(r.overdue),
+        ]));
+    }
+    t.printstd();
+    Ok(())
+}
+
+fn export(db: &mut Database, args: &Args) -> Result<(), CliError> {
+    args.allow(&["kind", "output", "format", "include-exported", "from", "to", "customer", "status"])?;
+    args.no_targets(1)?;
+    let kind = args.required("kind")?.to_lowercase();
+    let output = args.required("output")?.to_string();
+    let filter = parse_filter(args)?;
+
+    let format_option = args.value("format").map(|f| f.to_lowercase());
+    let format = match format_option.as_deref() {
+        None => match Path::new(&output).extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("xlsx") => ExportFormat::Xlsx,
+            _ => ExportFormat::Csv,
+        },
+        Some("csv") => ExportFormat::Csv,
+        Some("xlsx") => ExportFormat::Xlsx,
+        Some(other) => return usage(format!("Unknown format \"{}\" (use csv or xlsx)", other)),
+    };
+    if format_option.is_some() && !matches!(kind.as_str(), "customers" | "invoices" | "line-items") {
+        return usage("--format only applies to customers, invoices and line-items exports");
+    }
+    if args.flag("include-exported") && !matches!(kind.as_str(), "xero" | "myob") {
+        return usage("--include-exported only applies to xero and myob exports");
+    }
+
+    let count = match kind.as_str() {
+        "customers" => db.export_data_gui(ExportKind::Customers, format, &filter, &output)?,
+        "invoices" => db.export_data_gui(ExportKind::Invoices, format, &filter, &output)?,
+        "line-items" => db.export_data_gui(ExportKind::LineItems, format, &filter, &output)?,
+        "xero" => db.export_accounting_gui(AccountingSystem::Xero, &filter, args.flag("include-exported"), &output)?,
+        "myob" => db.export_accounting_gui(AccountingSystem::Myob, &filter, args.flag("include-exported"), &output)?,
+        "json" => {
+            // Interchange exports select by customer, or by invoice when dates or status are given
+            let selection = if filter.from_date.is_none() && filter.to_date.is_none() && filter.status == InvoiceStatusFilter::All {
+                InterchangeSelection { customer_codes: filter.customer_codes.clone(), invoice_numbers: Vec::new() }
+            } else {
+                let invoice_numbers: Vec<String> = db.get_invoices_filtered(&filter).into_iter().map(|i| i.invoice_number).collect();
+                if invoice_numbers.is_empty() {
+                    return Err(DatabaseError::InvalidInput("No invoices match the filter.".to_string()).into());
+                }
+                InterchangeSelection { customer_codes: Vec::new(), invoice_numbers }
+            };
+            let file = db.export_interchange(&selection);
+            write_interchange(&file, Path::new(&output)).map_err(DatabaseError::from)?;
+            file.customers.len() + file.invoices.len()
+        }
+        other => return usage(format!("Unknown export kind \"{}\"", other)),
+    };
+
+    if args.flag("json") {
+        print_json(&json!({ "kind": kind, "file": output, "records": count }));
+    } else {
+        println!("Exported {} record(s) to {}", count, output);
+    }
+    Ok(())
+}
+
+fn serve(args: &Args) -> Result<(), CliError> {
+    args.allow(&["bind", "port", "token"])?;
+    args.no_targets(1)?;
+    let port = match args.value("port").unwrap_or("8765").parse::<u16>() {
+        Ok(port) if port > 0 => port,
+        _ => return usage("--port must be a number between 1 and 65535"),
+    };
+    let bind = args.value("bind").unwrap_or("127.0.0.1");
+    let token = match args.value("token").map(|t| t.to_string()).or_else(|| std::env::var("JMATT_INVOICE_TOKEN").ok()) {
+        Some(token) if token.trim().len() >= 16 => token.trim().to_string(),
+        Some(_) => return usage("The API token must be at least 16 characters"),
+        None => {
+            let token = generate_token().map_err(DatabaseError::from)?;
+            eprintln!("No token configured; using {}", token);
+            token
+        }
+    };
+    let options = ServeOptions { address: format!("{}:{}", bind, port), token };
+    crate::server::serve(&options).map_err(DatabaseError::from)?;
+    Ok(())
+}
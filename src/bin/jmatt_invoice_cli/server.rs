@@ -0,0 +1,407 @@
+// Local HTTP JSON API and browser front end (`jmatt_invoice_cli serve`).
+//
+// The web UI (web/index.html) is a single static page that calls the API with the
+// token the user enters, so it goes through exactly the same validation. Requests are
+// handled one at a time and database.json is re-read for each one, so changes made from
+// the GUI or other commands are picked up. Every /api route except /api/openapi.json
+// needs an `Authorization: Bearer <token>` header. The routes and bodies are described
+// in openapi.json next to this file.
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{Cursor, Read};
+use chrono::{DateTime, Duration, Local, NaiveDate};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+use jmatt_invoice::database::{Database, DatabaseError, InvoiceFilter, InvoiceStatusFilter};
+use jmatt_invoice::models::{Customer, InvoiceItem, Payment, PaymentKind};
+use jmatt_invoice::pdf_generator::PdfOptions;
+use jmatt_invoice::renderer::PdfRenderer;
+use crate::cli::invoice_json;
+
+const OPENAPI: &str = include_str!("openapi.json");
+const WEB_UI: &str = include_str!("web/index.html");
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+pub struct ServeOptions {
+    pub address: String, // e.g. "127.0.0.1:8765"
+    pub token: String,
+}
+
+// Random token for when none is configured
+pub fn generate_token() -> Result<String, Box<dyn Error>> {
+    let mut bytes = [0u8; 24];
+    getrandom::getrandom(&mut bytes).map_err(|e| format!("Could not generate a token: {}", e))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+struct Reply {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+    filename: Option<String>, // Sent as Content-Disposition for downloads
+}
+
+impl Reply {
+    fn json(status: u16, value: &Value) -> Reply {
+        Reply {
+            status,
+            content_type: "application/json",
+            body: serde_json::to_vec_pretty(value).unwrap_or_default(),
+            filename: None,
+        }
+    }
+}
+
+struct ApiError {
+    status: u16,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: u16, message: impl Into<String>) -> ApiError {
+        ApiError { status, message: message.into() }
+    }
+}
+
+impl From<DatabaseError> for ApiError {
+    fn from(err: DatabaseError) -> ApiError {
+        let status = match err {
+            DatabaseError::CustomerNotFound(_) | DatabaseError::InvoiceNotFound(_) => 404,
+            DatabaseError::CustomerExists(_) | DatabaseError::ChangedOnDisk => 409,
+            DatabaseError::InvalidInput(_) => 422,
+            DatabaseError::Email(_) => 502,
+            DatabaseError::Io(_) | DatabaseError::Serialization(_) | DatabaseError::PdfGeneration(_) => 500,
+        };
+        ApiError::new(status, err.to_string())
+    }
+}
+
+type ApiResult = Result<Reply, ApiError>;
+
+pub fn serve(options: &ServeOptions) -> Result<(), Box<dyn Error>> {
+    let server = Server::http(&options.address).map_err(|e| format!("Could not listen on {}: {}", options.address, e))?;
+    eprintln!("Serving the invoice API on http://{}/api (OpenAPI description at /api/openapi.json)", options.address);
+    eprintln!("Press Ctrl+C to stop.");
+
+    for mut request in server.incoming_requests() {
+        let reply = handle(&mut request, &options.token).unwrap_or_else(|err| {
+            Reply::json(err.status, &json!({ "error": err.message }))
+        });
+        eprintln!("{} {} -> {}", request.method(), request.url(), reply.status);
+        let mut response = Response::new(
+            reply.status.into(),
+            Vec::new(),
+            Cursor::new(reply.body.clone()),
+            Some(reply.body.len()),
+            None,
+        );
+        response.add_header(header("Content-Type", reply.content_type));
+        if let Some(filename) = &reply.filename {
+            response.add_header(header("Content-Disposition", &format!("attachment; filename=\"{}\"", filename)));
+        }
+        if reply.status == 401 {
+            response.add_header(header("WWW-Authenticate", "Bearer"));
+        }
+        if let Err(e) = request.respond(response) {
+            eprintln!("Error sending response: {}", e);
+        }
+    }
+    Ok(())
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("valid header")
+}
+
+// Compares in constant time so the token can't be guessed byte by byte from response times
+fn token_matches(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given.bytes().zip(expected.bytes()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn authorized(request: &Request, token: &str) -> bool {
+    request.headers().iter()
+        .find(|h| h.field.equiv("Authorization"))
+        .and_then(|h| h.value.as_str().strip_prefix("Bearer "))
+        .is_some_and(|given| token_matches(given.trim(), token))
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes.get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 2;
+            }
+            (b'+', _) => out.push(b' '),
+            (b, _) => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn split_url(url: &str) -> (Vec<String>, HashMap<String, String>) {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let segments = path.split('/').filter(|s| !s.is_empty()).map(percent_decode).collect();
+    let params = query.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect();
+    (segments, params)
+}
+
+fn read_body<T: for<'de> Deserialize<'de>>(request: &mut Request) -> Result<T, ApiError> {
+    let mut body = Vec::new();
+    request.as_reader().take(MAX_BODY_BYTES as u64 + 1).read_to_end(&mut body)
+        .map_err(|e| ApiError::new(400, format!("Could not read request body: {}", e)))?;
+    if body.len() > MAX_BODY_BYTES {
+        return Err(ApiError::new(413, "Request body is too large."));
+    }
+    serde_json::from_slice(&body).map_err(|e| ApiError::new(400, format!("Invalid JSON body: {}", e)))
+}
+
+fn handle(request: &mut Request, token: &str) -> ApiResult {
+    let (segments, params) = split_url(request.url());
+    let segments: Vec<&str> = segments.iter().map(|s| s.as_str()).collect();
+    let method = request.method().clone();
+
+    match (&method, segments.as_slice()) {
+        (Method::Get, ["api", "openapi.json"]) => {
+            return Ok(Reply { status: 200, content_type: "application/json", body: OPENAPI.as_bytes().to_vec(), filename: None });
+        }
+        (Method::Get, [] | ["index.html"]) => {
+            return Ok(Reply { status: 200, content_type: "text/html; charset=utf-8", body: WEB_UI.as_bytes().to_vec(), filename: None });
+        }
+        (_, ["api", ..]) => {}
+        _ => return Err(ApiError::new(404, "Not found. The web UI is at / and the API under /api.")),
+    }
+    if !authorized(request, token) {
+        return Err(ApiError::new(401, "Missing or invalid bearer token."));
+    }
+
+    let mut db = Database::open()?;
+    match (method, &segments[1..]) {
+        (Method::Get, ["customers"]) => Ok(Reply::json(200, &json!(db.get_customers_vec()))),
+        (Method::Post, ["customers"]) => create_customer(&mut db, read_body(request)?),
+        (Method::Get, ["customers", code]) => Ok(Reply::json(200, &json!(find_customer(&db, code)?))),
+        (Method::Put, ["customers", code]) => update_customer(&mut db, code, read_body(request)?),
+        (Method::Get, ["invoices"]) => list_invoices(&db, &params),
+        (Method::Post, ["invoices"]) => create_invoice(&mut db, read_body(request)?),
+        (Method::Get, ["invoices", number]) => Ok(Reply::json(200, &invoice_json(find_invoice(&db, number)?))),
+        (Method::Put, ["invoices", number]) => update_invoice(&mut db, number, read_body(request)?),
+        (Method::Get, ["invoices", number, "pdf"]) => invoice_pdf(&db, number, &params),
+        (Method::Get, ["invoices", number, "payments"]) => Ok(Reply::json(200, &json!(find_invoice(&db, number)?.payments))),
+        (Method::Post, ["invoices", number, "payments"]) => record_payment(&mut db, number, read_body(request)?),
+        (Method::Post, ["invoices", number, "mark-paid"]) => {
+            db.mark_invoice_paid_gui(number)?;
+            Ok(Reply::json(200, &invoice_json(find_invoice(&db, number)?)))
+        }
+        (_, ["customers"] | ["customers", _] | ["invoices"] | ["invoices", _] | ["invoices", _, "pdf" | "payments" | "mark-paid"]) => {
+            Err(ApiError::new(405, format!("Method {} is not allowed here.", request.method())))
+        }
+        _ => Err(ApiError::new(404, "Not found.")),
+    }
+}
+
+fn find_customer(db: &Database, code: &str) -> Result<Customer, ApiError> {
+    let code = code.trim().to_uppercase();
+    match db.customers.values().find(|c| c.code == code) {
+        Some(c) => Ok(c.clone()),
+        None => Err(DatabaseError::CustomerNotFound(code).into()),
+    }
+}
+
+fn find_invoice<'a>(db: &'a Database, number: &str) -> Result<&'a jmatt_invoice::models::Invoice, ApiError> {
+    db.invoices.get(number).ok_or_else(|| DatabaseError::InvoiceNotFound(number.to_string()).into())
+}
+
+// Customer fields in a request body. Omitted fields are left empty on create and
+// unchanged on update.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct CustomerBody {
+    name: Option<String>,
+    code: Option<String>,
+    address: Option<String>,
+    phone: Option<String>,
+    contact_person: Option<String>,
+    contact_phone: Option<String>,
+    email: Option<String>,
+    abn: Option<String>,
+}
+
+impl CustomerBody {
+    fn apply(self, customer: &mut Customer) {
+        let fields = [
+            (self.name, &mut customer.name),
+            (self.code, &mut customer.code),
+            (self.address, &mut customer.address),
+            (self.phone, &mut customer.phone),
+            (self.contact_person, &mut customer.contact_person),
+            (self.contact_phone, &mut customer.contact_phone),
+            (self.email, &mut customer.email),
+            (self.abn, &mut customer.abn),
+        ];
+        for (value, field) in fields {
+            if let Some(value) = value {
+                *field = value.trim().to_string();
+            }
+        }
+    }
+}
+
+fn create_customer(db: &mut Database, body: CustomerBody) -> ApiResult {
+    let mut customer = Customer {
+        name: String::new(),
+        address: String::new(),
+        phone: String::new(),
+        contact_person: String::new(),
+        contact_phone: String::new(),
+        email: String::new(),
+        code: String::new(),
+        abn: String::new(),
+    };
+    body.apply(&mut customer);
+    db.add_customer_gui(customer.clone())?;
+    Ok(Reply::json(201, &json!(find_customer(db, &customer.code)?)))
+}
+
+fn update_customer(db: &mut Database, code: &str, body: CustomerBody) -> ApiResult {
+    let existing = find_customer(db, code)?;
+    let mut updated = existing.clone();
+    body.apply(&mut updated);
+    db.edit_customer_gui(&existing.name, updated.clone())?;
+    Ok(Reply::json(200, &json!(find_customer(db, &updated.code)?)))
+}
+
+fn parse_date(value: &str, name: &str) -> Result<NaiveDate, ApiError> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| ApiError::new(400, format!("{} must be a date in YYYY-MM-DD format.", name)))
+}
+
+fn list_invoices(db: &Database, params: &HashMap<String, String>) -> ApiResult {
+    let status = match params.get("status") {
+        None => InvoiceStatusFilter::All,
+        Some(value) => *InvoiceStatusFilter::ALL.iter()
+            .find(|s| s.label().eq_ignore_ascii_case(value))
+            .ok_or_else(|| ApiError::new(400, "status must be all, paid, unpaid or overdue."))?,
+    };
+    let filter = InvoiceFilter {
+        from_date: params.get("from").map(|v| parse_date(v, "from")).transpose()?,
+        to_date: params.get("to").map(|v| parse_date(v, "to")).transpose()?,
+        customer_codes: params.get("customer")
+            .map(|codes| codes.split(',').map(|c| c.trim().to_uppercase()).filter(|c| !c.is_empty()).collect())
+            .unwrap_or_default(),
+        status,
+    };
+    let invoices: Vec<Value> = db.get_invoices_filtered(&filter).iter().map(invoice_json).collect();
+    Ok(Reply::json(200, &Value::Array(invoices)))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ItemBody {
+    description: String,
+    quantity: u32,
+    rate: f64,
+    #[serde(default)] // Lines are taxable unless marked GST-free
+    gst_free: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InvoiceBody {
+    customer_code: String,
+    items: Vec<ItemBody>,
+    #[serde(default)]
+    notes: String,
+    due_date: Option<NaiveDate>,
+    due_in_days: Option<i64>, // Alternative to due_date; 30 days when neither is given
+}
+
+fn create_invoice(db: &mut Database, body: InvoiceBody) -> ApiResult {
+    let today = Local::now().date_naive();
+    let due_date = match (body.due_date, body.due_in_days) {
+        (Some(_), Some(_)) => return Err(ApiError::new(400, "Give either due_date or due_in_days, not both.")),
+        (Some(date), None) => date,
+        (None, Some(days)) if (0..=3650).contains(&days) => today + Duration::days(days),
+        (None, Some(_)) => return Err(ApiError::new(422, "due_in_days must be between 0 and 3650.")),
+        (None, None) => today + Duration::days(30),
+    };
+    let items = body.items.into_iter().map(item_from_body).collect();
+    let invoice = db.create_invoice_gui(body.customer_code.trim().to_uppercase(), items, body.notes.trim().to_string(), due_date)?;
+    Ok(Reply::json(201, &invoice_json(&invoice)))
+}
+
+// Invoice changes; omitted fields are unchanged. The issue date and number never change.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InvoiceUpdateBody {
+    items: Option<Vec<ItemBody>>,
+    notes: Option<String>,
+    due_date: Option<NaiveDate>,
+    paid: Option<bool>, // true records the remaining balance as a payment today
+}
+
+fn item_from_body(item: ItemBody) -> InvoiceItem {
+    InvoiceItem { description: item.description.trim().to_string(), quantity: item.quantity, rate: item.rate, amount: 0.0, gst_free: item.gst_free, gst: 0.0 }
+}
+
+fn update_invoice(db: &mut Database, number: &str, body: InvoiceUpdateBody) -> ApiResult {
+    let existing = find_invoice(db, number)?.clone();
+    let items = match body.items {
+        Some(items) => items.into_iter().map(item_from_body).collect(),
+        None => existing.items,
+    };
+    let notes = body.notes.map(|n| n.trim().to_string()).unwrap_or(existing.notes);
+    let due_date = body.due_date.unwrap_or(existing.due_date.date_naive());
+    db.edit_invoice_gui(number, items, notes, due_date, body.paid.unwrap_or(existing.paid))?;
+    Ok(Reply::json(200, &invoice_json(find_invoice(db, number)?)))
+}
+
+fn invoice_pdf(db: &Database, number: &str, params: &HashMap<String, String>) -> ApiResult {
+    let flag = |name: &str| params.get(name).is_some_and(|v| v == "true" || v == "1");
+    let options = PdfOptions {
+        include_remittance: flag("remittance"),
+        include_payment_qr: flag("qr"),
+        pdf_a: flag("pdf_a"),
+        embed_einvoice: flag("embed_xml"),
+    };
+    let bytes = db.render_invoice(number, &PdfRenderer { options })?;
+    Ok(Reply { status: 200, content_type: "application/pdf", body: bytes, filename: Some(format!("Invoice-{}.pdf", number)) })
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PaymentBody {
+    amount: f64,
+    date: Option<NaiveDate>, // Defaults to today
+    #[serde(default)]
+    kind: PaymentKind,
+    #[serde(default)]
+    reference: String,
+}
+
+fn record_payment(db: &mut Database, number: &str, body: PaymentBody) -> ApiResult {
+    // Recorded at midday like payments entered in the GUI
+    let date: DateTime<Local> = match body.date {
+        Some(date) => date.and_hms_opt(12, 0, 0)
+            .and_then(|dt| dt.and_local_timezone(Local).single())
+            .ok_or_else(|| ApiError::new(422, "Invalid payment date."))?,
+        None => Local::now(),
+    };
+    let payment = Payment { date, amount: body.amount, kind: body.kind, reference: body.reference.trim().to_string() };
+    db.record_payment_gui(number, payment)?;
+    Ok(Reply::json(201, &invoice_json(find_invoice(db, number)?)))
+}
@@ -0,0 +1,173 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::path::Path;
+use crate::models::Customer;
+
+// Customer fields a CSV column can be mapped to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CustomerField {
+    Name,
+    Code,
+    Address,
+    Phone,
+    ContactPerson,
+    ContactPhone,
+    Email,
+    Abn,
+}
+
+impl CustomerField {
+    pub const ALL: [CustomerField; 8] = [
+        CustomerField::Name,
+        CustomerField::Code,
+        CustomerField::Address,
+        CustomerField::Phone,
+        CustomerField::ContactPerson,
+        CustomerField::ContactPhone,
+        CustomerField::Email,
+        CustomerField::Abn,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CustomerField::Name => "Name",
+            CustomerField::Code => "Code",
+            CustomerField::Address => "Address",
+            CustomerField::Phone => "Phone",
+            CustomerField::ContactPerson => "Contact Person",
+            CustomerField::ContactPhone => "Contact Phone",
+            CustomerField::Email => "Email",
+            CustomerField::Abn => "ABN",
+        }
+    }
+
+    // Header names (lowercase, without spaces or punctuation) recognised when guessing the mapping
+    fn aliases(&self) -> &'static [&'static str] {
+        match self {
+            CustomerField::Name => &["name", "customer", "customername", "company", "business", "businessname"],
+            CustomerField::Code => &["code", "customercode", "prefix"],
+            CustomerField::Address => &["address", "streetaddress", "postaladdress"],
+            CustomerField::Phone => &["phone", "telephone", "phonenumber", "businessphone"],
+            CustomerField::ContactPerson => &["contact", "contactperson", "contactname"],
+            CustomerField::ContactPhone => &["contactphone", "mobile", "contactmobile", "contactnumber"],
+            CustomerField::Email => &["email", "emailaddress", "contactemail"],
+            CustomerField::Abn => &["abn", "australianbusinessnumber"],
+        }
+    }
+}
+
+// Raw CSV contents: the header row and the data rows
+#[derive(Clone, Default)]
+pub struct CsvData {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+pub fn read_csv(path: &Path) -> Result<CsvData, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true) // Spreadsheet exports often drop trailing empty cells
+        .trim(csv::Trim::All)
+        .from_path(path)?;
+    let headers = reader.headers()?.iter().map(|h| h.trim_start_matches('\u{feff}').to_string()).collect();
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        if record.iter().all(|cell| cell.is_empty()) {
+            continue; // Skip blank lines
+        }
+        rows.push(record.iter().map(|cell| cell.to_string()).collect());
+    }
+    Ok(CsvData { headers, rows })
+}
+
+// Which CSV column (by index) feeds each customer field
+#[derive(Clone, Default, PartialEq)]
+pub struct ColumnMapping {
+    columns: HashMap<CustomerField, usize>,
+}
+
+impl ColumnMapping {
+    // Matches header names against the known aliases for each field
+    pub fn guess(headers: &[String]) -> Self {
+        let normalise = |h: &str| h.chars().filter(|c| c.is_alphanumeric()).collect::<String>().to_lowercase();
+        let mut mapping = ColumnMapping::default();
+        for field in CustomerField::ALL {
+            if let Some(idx) = headers.iter().position(|h| field.aliases().contains(&normalise(h).as_str())) {
+                mapping.columns.insert(field, idx);
+            }
+        }
+        mapping
+    }
+
+    pub fn get(&self, field: CustomerField) -> Option<usize> {
+        self.columns.get(&field).copied()
+    }
+
+    pub fn set(&mut self, field: CustomerField, column: Option<usize>) {
+        match column {
+            Some(idx) => self.columns.insert(field, idx),
+            None => self.columns.remove(&field),
+        };
+    }
+
+    fn value(&self, row: &[String], field: CustomerField) -> String {
+        self.get(field).and_then(|idx| row.get(idx)).cloned().unwrap_or_default()
+    }
+
+    pub fn customer_from_row(&self, row: &[String]) -> Customer {
+        Customer {
+            name: self.value(row, CustomerField::Name),
+            address: self.value(row, CustomerField::Address),
+            phone: self.value(row, CustomerField::Phone),
+            contact_person: self.value(row, CustomerField::ContactPerson),
+            contact_phone: self.value(row, CustomerField::ContactPhone),
+            email: self.value(row, CustomerField::Email),
+            code: self.value(row, CustomerField::Code).to_uppercase(),
+            abn: self.value(row, CustomerField::Abn),
+        }
+    }
+}
+
+// One previewed CSV row with the customer it would create and any problems found
+#[derive(Clone)]
+pub struct ImportRow {
+    pub line: usize, // Line number in the file (the header is line 1)
+    pub customer: Customer,
+    pub code_generated: bool,
+    pub errors: Vec<String>,
+}
+
+impl ImportRow {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+// Suggests an unused 2-3 letter code for a customer name: the initials of the words first,
+// then the leading letters of the name, then the first two letters followed by A-Z.
+pub fn generate_code(name: &str, taken: &HashSet<String>) -> Option<String> {
+    let words: Vec<Vec<char>> = name
+        .split_whitespace()
+        .map(|w| w.chars().filter(|c| c.is_ascii_alphabetic()).map(|c| c.to_ascii_uppercase()).collect::<Vec<_>>())
+        .filter(|w| !w.is_empty())
+        .collect();
+    let letters: Vec<char> = words.iter().flatten().copied().collect();
+    if letters.len() < 2 {
+        return None;
+    }
+
+    let mut candidates = Vec::new();
+    let initials: String = words.iter().take(3).map(|w| w[0]).collect();
+    if initials.len() >= 2 {
+        candidates.push(initials);
+    }
+    candidates.push(letters.iter().take(3).collect());
+    candidates.push(letters.iter().take(2).collect());
+    for &c in &letters[1..] {
+        candidates.push(format!("{}{}", letters[0], c));
+    }
+    let prefix: String = letters.iter().take(2).collect();
+    candidates.extend(('A'..='Z').map(|c| format!("{}{}", prefix, c)));
+
+    candidates.into_iter().find(|code| code.len() >= 2 && !taken.contains(code))
+}
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use chrono::{Datelike, Local, Months, NaiveDate};
+use crate::models::{Invoice, PaymentKind};
+
+// Invoiced and collected amounts for one calendar month
+#[derive(Clone)]
+pub struct MonthRevenue {
+    pub month: NaiveDate, // First day of the month
+    pub invoiced: f64, // Totals of invoices issued in the month
+    pub collected: f64, // Payments received in the month
+}
+
+#[derive(Clone)]
+pub struct CustomerRevenue {
+    pub code: String,
+    pub name: String,
+    pub invoiced: f64,
+}
+
+// Figures for the dashboard over a date range (inclusive)
+#[derive(Clone)]
+pub struct DashboardStats {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub months: Vec<MonthRevenue>, // Every month in the range, oldest first
+    pub invoiced: f64, // Invoices issued in the range
+    pub invoice_count: usize,
+    pub collected: f64, // Paid or credited against those invoices so far
+    pub outstanding: f64, // Still owing on those invoices
+    pub top_customers: Vec<CustomerRevenue>, // Highest invoiced first
+    pub average_days_to_pay: Option<f64>, // Issue date to final payment, for invoices settled by recorded payments
+    pub overdue_count: usize, // Across all invoices, as of today
+    pub overdue_amount: f64,
+}
+
+pub const TOP_CUSTOMERS_SHOWN: usize = 5;
+
+fn month_start(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).unwrap_or(date)
+}
+
+pub fn build_dashboard<'a>(invoices: impl IntoIterator<Item = &'a Invoice>, from: NaiveDate, to: NaiveDate) -> DashboardStats {
+    let mut months = Vec::new();
+    let mut month = month_start(from);
+    while month <= to {
+        months.push(MonthRevenue { month, invoiced: 0.0, collected: 0.0 });
+        month = match month.checked_add_months(Months::new(1)) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+    let month_index = |date: NaiveDate| months.iter().position(|m| m.month == month_start(date));
+
+    let today = Local::now().date_naive();
+    let mut stats = DashboardStats {
+        from,
+        to,
+        months: Vec::new(),
+        invoiced: 0.0,
+        invoice_count: 0,
+        collected: 0.0,
+        outstanding: 0.0,
+        top_customers: Vec::new(),
+        average_days_to_pay: None,
+        overdue_count: 0,
+        overdue_amount: 0.0,
+    };
+    let mut invoiced_by_month = vec![0.0; months.len()];
+    let mut collected_by_month = vec![0.0; months.len()];
+    let mut by_customer: HashMap<String, CustomerRevenue> = HashMap::new();
+    let mut days_to_pay = Vec::new();
+
+    for invoice in invoices {
+        let balance = invoice.balance_due();
+        if balance > 0.0 && invoice.is_overdue(today) {
+            stats.overdue_count += 1;
+            stats.overdue_amount += balance;
+        }
+        for payment in invoice.payments.iter().filter(|p| p.kind == PaymentKind::Payment) {
+            let paid_on = payment.date.date_naive();
+            if paid_on >= from && paid_on <= to {
+                if let Some(i) = month_index(paid_on) {
+                    collected_by_month[i] += payment.amount;
+                }
+            }
+        }
+        // Invoices marked paid before payments were recorded count as collected on their due date
+        let unrecorded = invoice.total - balance - invoice.amount_paid();
+        let due = invoice.due_date.date_naive();
+        if unrecorded > 0.005 && due >= from && due <= to {
+            if let Some(i) = month_index(due) {
+                collected_by_month[i] += unrecorded;
+            }
+        }
+
+        let issued = invoice.date.date_naive();
+        if issued < from || issued > to {
+            continue;
+        }
+        stats.invoice_count += 1;
+        stats.invoiced += invoice.total;
+        stats.outstanding += balance;
+        stats.collected += invoice.total - balance;
+        if let Some(i) = month_index(issued) {
+            invoiced_by_month[i] += invoice.total;
+        }
+        by_customer.entry(invoice.customer.code.clone())
+            .or_insert_with(|| CustomerRevenue { code: invoice.customer.code.clone(), name: invoice.customer.name.clone(), invoiced: 0.0 })
+            .invoiced += invoice.total;
+        if invoice.paid && invoice.amount_paid() >= invoice.total - 0.005 {
+            if let Some(last) = invoice.payments.iter().map(|p| p.date.date_naive()).max() {
+                days_to_pay.push((last - issued).num_days().max(0) as f64);
+            }
+        }
+    }
+
+    stats.months = months.into_iter().enumerate().map(|(i, m)| MonthRevenue {
+        invoiced: invoiced_by_month[i],
+        collected: collected_by_month[i],
+        ..m
+    }).collect();
+    let mut customers: Vec<CustomerRevenue> = by_customer.into_values().collect();
+    customers.sort_by(|a, b| b.invoiced.total_cmp(&a.invoiced).then_with(|| a.name.cmp(&b.name)));
+    customers.truncate(TOP_CUSTOMERS_SHOWN);
+    stats.top_customers = customers;
+    if !days_to_pay.is_empty() {
+        stats.average_days_to_pay = Some(days_to_pay.iter().sum::<f64>() / days_to_pay.len() as f64);
+    }
+    stats
+}
@@ -1,12 +1,30 @@
-use std::collections::HashMap;
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, File, OpenOptions};
 use std::io;
 use std::path::Path;
+use std::time::SystemTime;
 use serde::{Serialize, Deserialize};
 use chrono::{Local, DateTime, NaiveDate, Utc, TimeZone}; // Added TimeZone import
-use crate::models::{Company, Customer, InvoiceItem, Invoice};
+use crate::models::{Company, Customer, InvoiceItem, GST_RATE, Invoice, LateFee, Payment, PaymentKind, SentEmail, SentReminder};
+use crate::late_fees::{quote_late_fee, LateFeeQuote, LateFeeSettings, LateFeeTarget};
+use crate::reminders::{plan_reminders, PlannedReminder, ReminderSettings};
 // Removed unused utils import: use crate::utils::*;
-use crate::pdf_generator::generate_pdf;
+use crate::pdf_generator::{generate_aged_receivables_pdf, generate_statement_pdf, layout_invoice, PageLayout, PdfOptions};
+use crate::renderer::{HtmlRenderer, InvoiceRenderer, PdfRenderer, TextRenderer};
+use crate::statement::{build_statement, Statement};
+use crate::aged_receivables::{aged_receivables_sheet, build_aged_receivables, AgedReceivables};
+use crate::bas::{bas_sheet, build_bas_report, BasBasis, BasPeriod, BasReport};
+use crate::dashboard::{build_dashboard, DashboardStats};
+use crate::search::{search, SearchResults};
+use crate::peppol;
+use crate::spreadsheet::{customers_sheet, invoices_sheet, line_items_sheet, write_csv, write_xlsx, ExportFormat, ExportKind};
+use crate::accounting_export::{myob_txt, xero_csv, AccountingSettings, AccountingSystem, ExportRecord};
+use crate::bank_import::BankTransaction;
+use crate::interchange::{ImportPlan, ImportSummary, InterchangeFile, InterchangeSelection, PlannedCustomer, PlannedInvoice, Resolution};
+use crate::customer_import::{generate_code, ColumnMapping, CsvData, ImportRow};
+use crate::email::{build_message, fill_template, parse_recipients, send_message, Attachment, EmailDraft, EmailSettings};
 
 const DB_FILENAME: &str = "database.json";
 const MAX_BACKUPS: usize = 5;
@@ -20,6 +38,8 @@ pub enum DatabaseError {
     InvoiceNotFound(String),
     InvalidInput(String),
     PdfGeneration(String),
+    Email(String),
+    ChangedOnDisk, // Another program saved database.json after this copy was read
 }
 
 impl std::fmt::Display for DatabaseError {
@@ -33,6 +53,8 @@ impl std::fmt::Display for DatabaseError {
             DatabaseError::InvoiceNotFound(num) => write!(f, "Invoice not found: {}", num),
             DatabaseError::InvalidInput(msg) => write!(f, "Invalid Input: {}", msg),
             DatabaseError::PdfGeneration(msg) => write!(f, "PDF Generation Error: {}", msg),
+            DatabaseError::Email(msg) => write!(f, "Email Error: {}", msg),
+            DatabaseError::ChangedOnDisk => write!(f, "{} was changed by another program after it was read. Reload it and make the change again.", DB_FILENAME),
         }
     }
 }
@@ -58,12 +80,248 @@ impl From<Box<dyn std::error::Error>> for DatabaseError {
 }
 
 
+// Payment status used when filtering invoices
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum InvoiceStatusFilter {
+    #[default]
+    All,
+    Paid,
+    Unpaid,
+    Overdue, // Unpaid and past the due date
+}
+
+impl InvoiceStatusFilter {
+    pub const ALL: [InvoiceStatusFilter; 4] = [
+        InvoiceStatusFilter::All,
+        InvoiceStatusFilter::Paid,
+        InvoiceStatusFilter::Unpaid,
+        InvoiceStatusFilter::Overdue,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            InvoiceStatusFilter::All => "All",
+            InvoiceStatusFilter::Paid => "Paid",
+            InvoiceStatusFilter::Unpaid => "Unpaid",
+            InvoiceStatusFilter::Overdue => "Overdue",
+        }
+    }
+}
+
+// Criteria for selecting invoices across all customers
+#[derive(Clone, Default)]
+pub struct InvoiceFilter {
+    pub from_date: Option<NaiveDate>, // Inclusive, compared against the issue date
+    pub to_date: Option<NaiveDate>, // Inclusive, compared against the issue date
+    pub customer_codes: Vec<String>, // Empty means every customer
+    pub status: InvoiceStatusFilter,
+}
+
+impl InvoiceFilter {
+    pub fn matches(&self, invoice: &Invoice) -> bool {
+        let issued = invoice.date.date_naive();
+        if self.from_date.is_some_and(|from| issued < from) {
+            return false;
+        }
+        if self.to_date.is_some_and(|to| issued > to) {
+            return false;
+        }
+        if !self.customer_codes.is_empty() && !self.customer_codes.contains(&invoice.customer.code) {
+            return false;
+        }
+        match self.status {
+            InvoiceStatusFilter::All => true,
+            InvoiceStatusFilter::Paid => invoice.paid,
+            InvoiceStatusFilter::Unpaid => !invoice.paid,
+            InvoiceStatusFilter::Overdue => invoice.is_overdue(Local::now().date_naive()),
+        }
+    }
+}
+
+// Column an invoice list is sorted by
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum InvoiceSort {
+    Number,
+    Customer,
+    #[default]
+    Date,
+    Due,
+    Total,
+    Balance,
+    Status,
+}
+
+impl InvoiceSort {
+    pub const ALL: [InvoiceSort; 7] = [
+        InvoiceSort::Number,
+        InvoiceSort::Customer,
+        InvoiceSort::Date,
+        InvoiceSort::Due,
+        InvoiceSort::Total,
+        InvoiceSort::Balance,
+        InvoiceSort::Status,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            InvoiceSort::Number => "Number",
+            InvoiceSort::Customer => "Customer",
+            InvoiceSort::Date => "Date",
+            InvoiceSort::Due => "Due",
+            InvoiceSort::Total => "Total",
+            InvoiceSort::Balance => "Balance",
+            InvoiceSort::Status => "Status",
+        }
+    }
+
+    // Ascending order; ties fall back to the invoice number so the order is stable
+    pub fn compare(&self, a: &Invoice, b: &Invoice) -> Ordering {
+        let order = match self {
+            InvoiceSort::Number => Ordering::Equal,
+            InvoiceSort::Customer => a.customer.name.to_lowercase().cmp(&b.customer.name.to_lowercase()),
+            InvoiceSort::Date => a.date.cmp(&b.date),
+            InvoiceSort::Due => a.due_date.cmp(&b.due_date),
+            InvoiceSort::Total => a.total.total_cmp(&b.total),
+            InvoiceSort::Balance => a.balance_due().total_cmp(&b.balance_due()),
+            InvoiceSort::Status => status_rank(a).cmp(&status_rank(b)).then_with(|| a.due_date.cmp(&b.due_date)),
+        };
+        order.then_with(|| invoice_number_key(&a.invoice_number).cmp(&invoice_number_key(&b.invoice_number)))
+    }
+}
+
+// Unpaid first, then part paid, then paid
+fn status_rank(invoice: &Invoice) -> u8 {
+    if invoice.paid {
+        2
+    } else if invoice.amount_paid() > 0.0 {
+        1
+    } else {
+        0
+    }
+}
+
+// Customer code then number, so AC100 sorts after AC99
+fn invoice_number_key(number: &str) -> (&str, u64, &str) {
+    let digits_at = number.find(|c: char| c.is_ascii_digit()).unwrap_or(number.len());
+    let (prefix, rest) = number.split_at(digits_at);
+    let digits_end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    (prefix, rest[..digits_end].parse().unwrap_or(0), &rest[digits_end..])
+}
+
+// Reference on the payment recorded when an invoice is ticked as paid, so unticking it can
+// take that payment off again
+const MARKED_PAID_REFERENCE: &str = "Marked paid";
+
+// Settles the remaining balance with a payment received today
+fn settle_invoice(invoice: &mut Invoice) {
+    let remaining = invoice.balance_due();
+    if remaining > 0.0 {
+        invoice.payments.push(Payment {
+            date: Local::now(),
+            amount: remaining,
+            kind: PaymentKind::Payment,
+            reference: MARKED_PAID_REFERENCE.to_string(),
+        });
+    }
+    invoice.paid = true;
+}
+
+// Checks invoice lines the same way for every front end (the GUI also checks as you type)
+fn validate_items(items: &[InvoiceItem]) -> Result<(), DatabaseError> {
+    if items.is_empty() {
+        return Err(DatabaseError::InvalidInput("Invoice must have at least one item.".to_string()));
+    }
+    for item in items {
+        if item.description.trim().is_empty() {
+            return Err(DatabaseError::InvalidInput("Item description cannot be empty.".to_string()));
+        }
+        if item.quantity == 0 {
+            return Err(DatabaseError::InvalidInput("Invalid quantity. Must be a positive integer.".to_string()));
+        }
+        if !(item.rate >= 0.0 && item.rate.is_finite()) {
+            return Err(DatabaseError::InvalidInput("Invalid rate. Must be a non-negative number.".to_string()));
+        }
+    }
+    Ok(())
+}
+
+fn cents(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+// Works out each line's amount and GST. GST is 10% of the taxable lines, rounded once for the
+// whole invoice; any rounding difference goes on the last taxable line so the lines add up.
+// Nothing is taxable unless the company is registered for GST.
+// Returns the priced lines, the subtotal and the GST.
+fn price_items(items: Vec<InvoiceItem>, gst_registered: bool) -> (Vec<InvoiceItem>, f64, f64) {
+    let mut items: Vec<InvoiceItem> = items.into_iter().map(|item| {
+        let amount = item.quantity as f64 * item.rate;
+        let gst_free = item.gst_free || !gst_registered;
+        let gst = if gst_free { 0.0 } else { cents(cents(amount) * GST_RATE) };
+        InvoiceItem { amount, gst_free, gst, ..item }
+    }).collect();
+    let subtotal = items.iter().fold(0.0, |sum, item| sum + item.amount);
+    let taxable = items.iter().filter(|item| !item.gst_free).fold(0.0, |sum, item| sum + cents(item.amount));
+    let gst = cents(taxable * GST_RATE);
+    let line_gst = items.iter().fold(0.0, |sum, item| sum + item.gst);
+    if let Some(last) = items.iter_mut().rev().find(|item| !item.gst_free) {
+        last.gst = cents(last.gst + gst - line_gst);
+    }
+    (items, subtotal, gst)
+}
+
+// Compares two records by their serialized form (the models don't implement PartialEq)
+fn same_record<T: Serialize>(a: &T, b: &T) -> bool {
+    matches!((serde_json::to_value(a), serde_json::to_value(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+// Sends the invoice PDF with the given message, without recording it.
+// Takes copies of what it needs so it can also run on a background thread.
+pub fn send_invoice_email(settings: &EmailSettings, company: &Company, invoice: &Invoice, draft: &EmailDraft, options: &PdfOptions) -> Result<SentEmail, DatabaseError> {
+    if !settings.is_configured() {
+        return Err(DatabaseError::InvalidInput("Email is not set up. Enter the SMTP server and sender address in the email settings.".to_string()));
+    }
+    if draft.subject.trim().is_empty() {
+        return Err(DatabaseError::InvalidInput("The email needs a subject.".to_string()));
+    }
+    let recipients = parse_recipients(&draft.to).map_err(DatabaseError::InvalidInput)?;
+    let pdf = PdfRenderer { options: options.clone() }.render(invoice, company)?;
+    let attachment = Attachment {
+        filename: format!("Invoice-{}.pdf", invoice.invoice_number),
+        content_type: "application/pdf".to_string(),
+        data: pdf,
+    };
+    let sent_at = Local::now();
+    let message = build_message(settings, draft, &[attachment], sent_at).map_err(DatabaseError::InvalidInput)?;
+    send_message(settings, settings.from_address.trim(), &recipients, &message)
+        .map_err(|e| DatabaseError::Email(e.to_string()))?;
+    Ok(SentEmail { sent_at, to: recipients.join(", "), subject: draft.subject.trim().to_string() })
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Database {
     pub company: Company,
     pub customers: HashMap<String, Customer>,
     pub invoices: HashMap<String, Invoice>,
     pub last_invoice_nums: HashMap<String, u32>,
+    #[serde(default)]
+    pub accounting: AccountingSettings, // Account/tax code mapping for Xero and MYOB exports
+    #[serde(default)]
+    pub accounting_exports: Vec<ExportRecord>, // Invoices already exported to accounting
+    #[serde(default)]
+    pub email: EmailSettings, // SMTP server and message templates for emailing invoices
+    #[serde(default)]
+    pub reminders: ReminderSettings, // When to send payment reminders, and what they say
+    #[serde(default)]
+    pub late_fees: LateFeeSettings, // How late fees and interest are worked out
+    #[serde(skip)]
+    loaded_modified: Cell<Option<SystemTime>>, // database.json's modified time when read or last saved
+}
+
+impl Default for Database {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Database {
@@ -74,10 +332,17 @@ impl Database {
                 abn: "78734213681".to_string(),
                 address: "40 Wyndham Avenue Denman Prospect, ACT, 2611".to_string(),
                 phone: "0403-491446".to_string(),
+                gst_registered: false,
             },
             customers: HashMap::new(),
             invoices: HashMap::new(),
             last_invoice_nums: HashMap::new(),
+            accounting: AccountingSettings::default(),
+            accounting_exports: Vec::new(),
+            email: EmailSettings::default(),
+            reminders: ReminderSettings::default(),
+            late_fees: LateFeeSettings::default(),
+            loaded_modified: Cell::new(None),
         }
     }
 
@@ -92,7 +357,7 @@ impl Database {
         let timestamp = Utc::now().format("%Y%m%d%H%M%S");
         let backup_filename = format!("{}.{}.bak", DB_FILENAME, timestamp);
         fs::copy(db_path, &backup_filename)?;
-        println!("Database backed up to {}", backup_filename);
+        eprintln!("Database backed up to {}", backup_filename);
 
         // Manage old backups
         let mut backups = Vec::new();
@@ -114,14 +379,14 @@ impl Database {
         // Remove oldest backups if count exceeds MAX_BACKUPS
         if backups.len() > MAX_BACKUPS {
             let num_to_remove = backups.len() - MAX_BACKUPS;
-            for i in 0..num_to_remove {
-                if let Some(filename) = backups[i].file_name().and_then(|n| n.to_str()) {
-                     match fs::remove_file(&backups[i]) {
-                        Ok(_) => println!("Removed old backup: {}", filename),
+            for backup in backups.iter().take(num_to_remove) {
+                if let Some(filename) = backup.file_name().and_then(|n| n.to_str()) {
+                     match fs::remove_file(backup) {
+                        Ok(_) => eprintln!("Removed old backup: {}", filename),
                         Err(e) => eprintln!("Error removing backup {}: {}", filename, e),
                     }
                 } else {
-                    eprintln!("Error getting filename for backup: {:?}", backups[i]);
+                    eprintln!("Error getting filename for backup: {:?}", backup);
                 }
             }
         }
@@ -135,32 +400,52 @@ impl Database {
             eprintln!("Warning: Failed to create database backup: {}", e);
             // Continue loading even if backup fails
         }
+        Self::open()
+    }
 
+    // Loads without taking a backup, for read-only use (backups rotate, so frequent
+    // read-only loads would push out the useful ones)
+    pub fn open() -> Result<Self, DatabaseError> {
+        let modified = Self::file_modified();
         match File::open(DB_FILENAME) {
             Ok(file) => {
-                serde_json::from_reader(file).map_err(DatabaseError::from)
+                let db: Database = serde_json::from_reader(file)?;
+                db.loaded_modified.set(modified);
+                Ok(db)
             },
             Err(e) if e.kind() == io::ErrorKind::NotFound => {
-                println!("Database file not found, creating new one.");
+                eprintln!("Database file not found, starting with an empty database.");
                 Ok(Database::new())
             }
             Err(e) => Err(DatabaseError::from(e)),
         }
     }
 
+    fn file_modified() -> Option<SystemTime> {
+        fs::metadata(DB_FILENAME).and_then(|m| m.modified()).ok()
+    }
+
+    // Refuses to overwrite the file if another program (e.g. `jmatt_invoice_cli serve` or a
+    // second window) saved it since this copy was read, rather than losing its changes
     pub fn save(&self) -> Result<(), DatabaseError> {
+        if Self::file_modified() != self.loaded_modified.get() {
+            return Err(DatabaseError::ChangedOnDisk);
+        }
         let file = OpenOptions::new()
             .write(true)
             .create(true)
             .truncate(true)
             .open(DB_FILENAME)?;
         serde_json::to_writer_pretty(file, self)?;
+        self.loaded_modified.set(Self::file_modified());
         Ok(())
     }
 
     // Removed add_customer_cli
 
-    pub fn add_customer_gui(&mut self, customer: Customer) -> Result<(), DatabaseError> {
+    // Checks a new customer against the rules shared by manual entry and CSV import,
+    // returning it with the name trimmed and the code normalised
+    fn validate_new_customer(&self, customer: &Customer) -> Result<Customer, DatabaseError> {
         if customer.name.trim().is_empty() {
             return Err(DatabaseError::InvalidInput("Customer name cannot be empty.".to_string()));
         }
@@ -175,17 +460,82 @@ impl Database {
              return Err(DatabaseError::InvalidInput(format!("Customer code \"{}\" is already in use.", code)));
         }
 
-        let mut validated_customer = customer;
+        let mut validated_customer = customer.clone();
         validated_customer.name = validated_customer.name.trim().to_string();
         validated_customer.code = code;
-        self.customers.insert(validated_customer.name.clone(), validated_customer.clone());
-        self.last_invoice_nums.entry(validated_customer.code.clone()).or_insert(75);
+        Ok(validated_customer)
+    }
+
+    fn insert_customer(&mut self, customer: Customer) {
+        self.last_invoice_nums.entry(customer.code.clone()).or_insert(75);
+        self.customers.insert(customer.name.clone(), customer);
+    }
+
+    pub fn add_customer_gui(&mut self, customer: Customer) -> Result<(), DatabaseError> {
+        let validated_customer = self.validate_new_customer(&customer)?;
+        self.insert_customer(validated_customer);
 
         self.save()?;
 
         Ok(())
     }
 
+    // Validates every CSV row as if it were added through the Add Customer form, also rejecting
+    // names and codes repeated within the file. Missing codes can be generated from the name.
+    pub fn preview_customer_import(&self, data: &CsvData, mapping: &ColumnMapping, generate_codes: bool) -> Vec<ImportRow> {
+        let mut taken_codes: HashSet<String> = self.customers.values().map(|c| c.code.clone()).collect();
+        // Codes given explicitly in the file are reserved so generated codes never collide with later rows
+        taken_codes.extend(data.rows.iter().map(|row| mapping.customer_from_row(row).code).filter(|code| !code.is_empty()));
+        let mut names_in_file: HashMap<String, usize> = HashMap::new();
+        let mut codes_in_file: HashMap<String, usize> = HashMap::new();
+
+        data.rows.iter().enumerate().map(|(idx, row)| {
+            let line = idx + 2;
+            let mut customer = mapping.customer_from_row(row);
+            let mut code_generated = false;
+            let mut errors = Vec::new();
+            if customer.code.trim().is_empty() && generate_codes {
+                if let Some(code) = generate_code(&customer.name, &taken_codes) {
+                    taken_codes.insert(code.clone());
+                    customer.code = code;
+                    code_generated = true;
+                }
+            }
+            match self.validate_new_customer(&customer) {
+                Ok(validated) => customer = validated,
+                Err(DatabaseError::InvalidInput(msg)) => errors.push(msg),
+                Err(e) => errors.push(e.to_string()),
+            }
+            if let Some(first) = names_in_file.get(customer.name.trim()) {
+                errors.push(format!("Same name as line {}.", first));
+            }
+            if let Some(first) = codes_in_file.get(&customer.code) {
+                errors.push(format!("Code \"{}\" is also used on line {}.", customer.code, first));
+            }
+            if errors.is_empty() {
+                names_in_file.insert(customer.name.clone(), line);
+                codes_in_file.insert(customer.code.clone(), line);
+            }
+            ImportRow { line, customer, code_generated, errors }
+        }).collect()
+    }
+
+    // Adds the valid rows from a preview and saves once. Returns the number imported.
+    pub fn import_customers_gui(&mut self, rows: &[ImportRow]) -> Result<usize, DatabaseError> {
+        let mut imported = 0;
+        for row in rows.iter().filter(|r| r.is_valid()) {
+            // Re-check in case the database changed since the preview was built
+            if let Ok(customer) = self.validate_new_customer(&row.customer) {
+                self.insert_customer(customer);
+                imported += 1;
+            }
+        }
+        if imported > 0 {
+            self.save()?;
+        }
+        Ok(imported)
+    }
+
     // Removed edit_customer_cli
 
     pub fn edit_customer_gui(&mut self, original_name: &str, updated_customer: Customer) -> Result<(), DatabaseError> {
@@ -208,10 +558,8 @@ impl Database {
             return Err(DatabaseError::CustomerExists(new_name));
         }
 
-        if original_customer.code != new_code {
-            if self.customers.values().any(|c| c.name != original_name && c.code == new_code) {
-                return Err(DatabaseError::InvalidInput(format!("Customer code \"{}\" is already in use by another customer.", new_code)));
-            }
+        if original_customer.code != new_code && self.customers.values().any(|c| c.name != original_name && c.code == new_code) {
+            return Err(DatabaseError::InvalidInput(format!("Customer code \"{}\" is already in use by another customer.", new_code)));
         }
 
         let mut final_customer = updated_customer;
@@ -274,14 +622,19 @@ impl Database {
     // Removed create_invoice_cli
 
     pub fn create_invoice_gui(&mut self, customer_code: String, items: Vec<InvoiceItem>, notes: String, due_date_naive: NaiveDate) -> Result<Invoice, DatabaseError> {
+        let invoice = self.add_invoice(customer_code, items, notes, due_date_naive)?;
+        self.save()?;
+        Ok(invoice)
+    }
+
+    // Numbers, prices and stores a new invoice without saving
+    fn add_invoice(&mut self, customer_code: String, items: Vec<InvoiceItem>, notes: String, due_date_naive: NaiveDate) -> Result<Invoice, DatabaseError> {
         let customer = match self.customers.values().find(|c| c.code == customer_code) {
             Some(c) => c.clone(),
             None => return Err(DatabaseError::CustomerNotFound(customer_code)),
         };
 
-        if items.is_empty() {
-            return Err(DatabaseError::InvalidInput("Invoice must have at least one item.".to_string()));
-        }
+        validate_items(&items)?;
 
         let invoice_number = self.generate_next_invoice_number(&customer_code);
         // Use DateTime<Local> for date
@@ -293,21 +646,8 @@ impl Database {
             None => return Err(DatabaseError::InvalidInput("Invalid due date provided.".to_string())),
         };
 
-        let mut calculated_items = Vec::new();
-        let mut subtotal = 0.0;
-
-        for item in items {
-            let amount = item.quantity as f64 * item.rate;
-            subtotal += amount;
-            calculated_items.push(InvoiceItem {
-                description: item.description,
-                quantity: item.quantity,
-                rate: item.rate,
-                amount,
-            });
-        }
-
-        let total = subtotal; // Assuming no tax for now
+        let (calculated_items, subtotal, gst) = price_items(items, self.company.gst_registered);
+        let total = subtotal + gst;
 
         let invoice = Invoice {
             invoice_number: invoice_number.clone(),
@@ -317,26 +657,28 @@ impl Database {
             items: calculated_items,
             notes,
             subtotal,
+            gst,
             total,
             paid: false,
+            payments: Vec::new(),
+            emails: Vec::new(),
+            reminders: Vec::new(),
+            late_fees: Vec::new(),
         };
 
         self.invoices.insert(invoice_number.clone(), invoice.clone());
-        self.save()?;
-
         Ok(invoice)
     }
 
     // Added function to edit an existing invoice
     pub fn edit_invoice_gui(&mut self, invoice_number: &str, items: Vec<InvoiceItem>, notes: String, due_date_naive: NaiveDate, paid: bool) -> Result<(), DatabaseError> {
+        let gst_registered = self.company.gst_registered;
         let invoice = match self.invoices.get_mut(invoice_number) {
             Some(inv) => inv,
             None => return Err(DatabaseError::InvoiceNotFound(invoice_number.to_string())),
         };
 
-        if items.is_empty() {
-            return Err(DatabaseError::InvalidInput("Invoice must have at least one item.".to_string()));
-        }
+        validate_items(&items)?;
 
         // Convert NaiveDate to DateTime<Local> (assuming midnight)
         let due_date: DateTime<Local> = match due_date_naive.and_hms_opt(0, 0, 0) {
@@ -345,28 +687,53 @@ impl Database {
             None => return Err(DatabaseError::InvalidInput("Invalid due date provided.".to_string())),
         };
 
-        let mut calculated_items = Vec::new();
-        let mut subtotal = 0.0;
-
-        for item in items {
-            let amount = item.quantity as f64 * item.rate;
-            subtotal += amount;
-            calculated_items.push(InvoiceItem {
-                description: item.description,
-                quantity: item.quantity,
-                rate: item.rate,
-                amount,
-            });
+        let (calculated_items, subtotal, gst) = price_items(items, gst_registered);
+        let total = subtotal + gst;
+
+        // The payment recorded when "paid" was ticked settles whatever the other payments
+        // don't cover, so it follows the invoice total: unticking takes it off, and changing
+        // the lines of a paid invoice resizes it to the new balance.
+        let settling_payment = invoice.payments.iter().rposition(|p| p.reference == MARKED_PAID_REFERENCE);
+        let received = invoice.payments.iter().enumerate()
+            .filter(|(i, _)| Some(*i) != settling_payment)
+            .fold(0.0, |sum, (_, p)| sum + p.amount);
+        if !paid && invoice.paid && received > 0.0 && total - received < 0.005 {
+            return Err(DatabaseError::InvalidInput(format!(
+                "Payments and credits of {:.2} cover this invoice, so it can't be marked unpaid.", received
+            )));
+        }
+        if paid && invoice.paid && received - total >= 0.005 {
+            return Err(DatabaseError::InvalidInput(format!(
+                "Payments and credits of {:.2} are more than the new total of {:.2}.", received, total
+            )));
         }
-
-        let total = subtotal; // Assuming no tax for now
 
         invoice.items = calculated_items;
         invoice.notes = notes;
         invoice.due_date = due_date; // Use DateTime<Local>
-        invoice.paid = paid;
         invoice.subtotal = subtotal;
+        invoice.gst = gst;
         invoice.total = total;
+        // Invoices marked paid before payments were recorded have none; they stay settled as they were
+        let was_paid = invoice.paid;
+        let legacy_paid = was_paid && invoice.payments.is_empty();
+        if was_paid {
+            if let Some(index) = settling_payment {
+                let remaining = total - received;
+                if paid && remaining >= 0.005 {
+                    invoice.payments[index].amount = remaining;
+                } else {
+                    invoice.payments.remove(index);
+                }
+            }
+        }
+        // Ticking "paid" records the remaining balance as a payment received today, and so
+        // does raising the total of a paid invoice the other payments settled on their own
+        if paid && !legacy_paid {
+            invoice.paid = false;
+            settle_invoice(invoice);
+        }
+        invoice.paid = paid;
         // invoice.date remains the original issue date
 
         self.save()?;
@@ -390,7 +757,7 @@ impl Database {
     pub fn mark_invoice_paid_gui(&mut self, invoice_number: &str) -> Result<(), DatabaseError> {
         match self.invoices.get_mut(invoice_number) {
             Some(invoice) => {
-                invoice.paid = true;
+                settle_invoice(invoice);
                 self.save()?;
                 Ok(())
             }
@@ -398,34 +765,683 @@ impl Database {
         }
     }
 
+    // Marks several invoices paid with a single save; returns how many were unpaid
+    pub fn mark_invoices_paid_gui(&mut self, invoice_numbers: &[String]) -> Result<usize, DatabaseError> {
+        if let Some(missing) = invoice_numbers.iter().find(|n| !self.invoices.contains_key(n.as_str())) {
+            return Err(DatabaseError::InvoiceNotFound(missing.clone()));
+        }
+        let mut marked = 0;
+        for number in invoice_numbers {
+            if let Some(invoice) = self.invoices.get_mut(number).filter(|inv| !inv.paid) {
+                settle_invoice(invoice);
+                marked += 1;
+            }
+        }
+        if marked > 0 {
+            self.save()?;
+        }
+        Ok(marked)
+    }
+
+    // Adds a payment to an invoice without saving, marking it paid once settled
+    fn apply_payment(&mut self, invoice_number: &str, payment: Payment) -> Result<(), DatabaseError> {
+        let invoice = match self.invoices.get_mut(invoice_number) {
+            Some(inv) => inv,
+            None => return Err(DatabaseError::InvoiceNotFound(invoice_number.to_string())),
+        };
+        if payment.amount <= 0.0 {
+            return Err(DatabaseError::InvalidInput("Payment amount must be greater than zero.".to_string()));
+        }
+        let remaining = invoice.balance_due();
+        if payment.amount > remaining + 0.005 {
+            return Err(DatabaseError::InvalidInput(format!("Payment of {:.2} exceeds the balance due of {:.2}.", payment.amount, remaining)));
+        }
+        invoice.payments.push(payment);
+        if invoice.balance_due() < 0.005 {
+            invoice.paid = true;
+        }
+        Ok(())
+    }
+
+    pub fn record_payment_gui(&mut self, invoice_number: &str, payment: Payment) -> Result<(), DatabaseError> {
+        self.apply_payment(invoice_number, payment)?;
+        self.save()?;
+        Ok(())
+    }
+
+    // Records several payments (e.g. from bank reconciliation) in one go.
+    // Nothing is recorded if any of them is invalid.
+    pub fn record_payments_gui(&mut self, payments: Vec<(String, Payment)>) -> Result<usize, DatabaseError> {
+        let original = self.invoices.clone();
+        let count = payments.len();
+        for (invoice_number, payment) in payments {
+            if let Err(e) = self.apply_payment(&invoice_number, payment) {
+                self.invoices = original;
+                return Err(match e {
+                    DatabaseError::InvalidInput(msg) => DatabaseError::InvalidInput(format!("Invoice #{}: {}", invoice_number, msg)),
+                    other => other,
+                });
+            }
+        }
+        self.save()?;
+        Ok(count)
+    }
+
+    // Unpaid and part-paid invoices, oldest first
+    pub fn get_open_invoices(&self) -> Vec<Invoice> {
+        let mut invoices: Vec<Invoice> = self.invoices.values()
+            .filter(|inv| inv.balance_due() > 0.005)
+            .cloned()
+            .collect();
+        invoices.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.invoice_number.cmp(&b.invoice_number)));
+        invoices
+    }
+
+    // For each transaction, whether a payment was already recorded from it. Statements
+    // without transaction IDs can list the same description, date and amount more than once,
+    // so each recorded payment accounts for one occurrence only.
+    pub fn recorded_bank_transactions(&self, transactions: &[BankTransaction]) -> Vec<bool> {
+        let mut payments: Vec<&Payment> = self.invoices.values().flat_map(|inv| inv.payments.iter()).collect();
+        transactions.iter().map(|transaction| {
+            let reference = transaction.payment_reference();
+            let matching = payments.iter().position(|p| {
+                p.reference == reference
+                    && p.date.date_naive() == transaction.date
+                    && (p.amount - transaction.amount).abs() < 0.005
+            });
+            match matching {
+                Some(index) => {
+                    payments.swap_remove(index);
+                    true
+                }
+                None => false,
+            }
+        }).collect()
+    }
+
     pub fn get_invoices_for_customer(&self, customer_code: &str) -> Vec<Invoice> {
         let mut invoices: Vec<Invoice> = self.invoices.values()
             .filter(|inv| inv.customer.code == customer_code)
             .cloned()
             .collect();
         // Sort by date descending (DateTime<Local> comparison works)
-        invoices.sort_by(|a, b| b.date.cmp(&a.date)); 
+        invoices.sort_by_key(|inv| std::cmp::Reverse(inv.date));
         invoices
     }
 
-    // Removed generate_pdf_cli
+    // Invoices matching `filter`, oldest first
+    pub fn get_invoices_filtered(&self, filter: &InvoiceFilter) -> Vec<Invoice> {
+        let mut invoices: Vec<Invoice> = self.invoices.values()
+            .filter(|inv| filter.matches(inv))
+            .cloned()
+            .collect();
+        invoices.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.invoice_number.cmp(&b.invoice_number)));
+        invoices
+    }
 
-    pub fn generate_pdf_gui(&self, invoice_number: &str, filename: &str) -> Result<String, DatabaseError> {
+    // Renders an invoice with any renderer (PDF, HTML or plain text)
+    pub fn render_invoice<R: InvoiceRenderer>(&self, invoice_number: &str, renderer: &R) -> Result<R::Output, DatabaseError> {
         match self.invoices.get(invoice_number) {
-            Some(invoice) => {
-                // Pass individual company details
-                generate_pdf(
-                    invoice,
-                    &self.company.name,
-                    &self.company.abn,
-                    &self.company.address,
-                    &self.company.phone,
-                    filename
-                )?;
-                Ok(filename.to_string())
+            Some(invoice) => Ok(renderer.render(invoice, &self.company)?),
+            None => Err(DatabaseError::InvoiceNotFound(invoice_number.to_string())),
+        }
+    }
+
+    // Page layouts for the on-screen PDF preview
+    pub fn layout_invoice_gui(&self, invoice_number: &str, options: &PdfOptions) -> Result<Vec<PageLayout>, DatabaseError> {
+        match self.invoices.get(invoice_number) {
+            Some(invoice) => Ok(layout_invoice(
+                invoice,
+                &self.company.name,
+                &self.company.abn,
+                &self.company.address,
+                &self.company.phone,
+                options,
+            )?),
+            None => Err(DatabaseError::InvoiceNotFound(invoice_number.to_string())),
+        }
+    }
+
+    pub fn render_invoice_html(&self, invoice_number: &str) -> Result<String, DatabaseError> {
+        self.render_invoice(invoice_number, &HtmlRenderer)
+    }
+
+    pub fn render_invoice_text(&self, invoice_number: &str) -> Result<String, DatabaseError> {
+        self.render_invoice(invoice_number, &TextRenderer)
+    }
+
+    // Account statement for a customer over a period (dates inclusive)
+    pub fn get_statement(&self, customer_code: &str, from_date: NaiveDate, to_date: NaiveDate) -> Result<Statement, DatabaseError> {
+        let customer = match self.customers.values().find(|c| c.code == customer_code) {
+            Some(c) => c,
+            None => return Err(DatabaseError::CustomerNotFound(customer_code.to_string())),
+        };
+        if from_date > to_date {
+            return Err(DatabaseError::InvalidInput("Statement start date must be on or before the end date.".to_string()));
+        }
+        let invoices = self.get_invoices_for_customer(customer_code);
+        Ok(build_statement(customer, &invoices, from_date, to_date))
+    }
+
+    pub fn generate_statement_pdf_gui(&self, customer_code: &str, from_date: NaiveDate, to_date: NaiveDate, filename: &str, options: &PdfOptions) -> Result<String, DatabaseError> {
+        let statement = self.get_statement(customer_code, from_date, to_date)?;
+        generate_statement_pdf(
+            &statement,
+            &self.company.name,
+            &self.company.abn,
+            &self.company.address,
+            &self.company.phone,
+            filename,
+            options,
+        )?;
+        Ok(filename.to_string())
+    }
+
+    // Who owes what at the end of `as_at`, across all invoices
+    pub fn aged_receivables(&self, as_at: NaiveDate) -> AgedReceivables {
+        build_aged_receivables(self.invoices.values(), as_at)
+    }
+
+    pub fn export_aged_receivables_csv_gui(&self, as_at: NaiveDate, filename: &str) -> Result<String, DatabaseError> {
+        write_csv(&aged_receivables_sheet(&self.aged_receivables(as_at)), filename)?;
+        Ok(filename.to_string())
+    }
+
+    pub fn generate_aged_receivables_pdf_gui(&self, as_at: NaiveDate, filename: &str, options: &PdfOptions) -> Result<String, DatabaseError> {
+        generate_aged_receivables_pdf(&self.aged_receivables(as_at), &self.company.name, &self.company.abn, filename, options)?;
+        Ok(filename.to_string())
+    }
+
+    // GST totals for a BAS period, with the invoices and payments behind them
+    pub fn bas_report(&self, period: &BasPeriod, basis: BasBasis) -> Result<BasReport, DatabaseError> {
+        if period.from > period.to {
+            return Err(DatabaseError::InvalidInput("The period start date must be on or before the end date.".to_string()));
+        }
+        Ok(build_bas_report(self.invoices.values(), period, basis))
+    }
+
+    pub fn export_bas_csv_gui(&self, period: &BasPeriod, basis: BasBasis, filename: &str) -> Result<String, DatabaseError> {
+        write_csv(&bas_sheet(&self.bas_report(period, basis)?), filename)?;
+        Ok(filename.to_string())
+    }
+
+    // Customers and invoices matching `query`, with the invoices narrowed by `filter`
+    pub fn search(&self, query: &str, filter: &InvoiceFilter) -> SearchResults {
+        search(self.customers.values(), self.invoices.values(), query, |inv| filter.matches(inv))
+    }
+
+    // Revenue figures for invoices issued from `from` to `to` (inclusive)
+    pub fn dashboard(&self, from: NaiveDate, to: NaiveDate) -> Result<DashboardStats, DatabaseError> {
+        if from > to {
+            return Err(DatabaseError::InvalidInput("The start date must be on or before the end date.".to_string()));
+        }
+        if (to - from).num_days() > 3660 {
+            return Err(DatabaseError::InvalidInput("Choose a range of ten years or less.".to_string()));
+        }
+        Ok(build_dashboard(self.invoices.values(), from, to))
+    }
+
+    // Removed generate_pdf_cli
+
+    pub fn generate_pdf_gui(&self, invoice_number: &str, filename: &Path, options: &PdfOptions) -> Result<String, DatabaseError> {
+        let bytes = self.render_invoice(invoice_number, &PdfRenderer { options: options.clone() })?;
+        fs::write(filename, bytes)?;
+        Ok(filename.display().to_string())
+    }
+
+    // Exports customers, invoices or line items to CSV or XLSX. Invoice exports use the
+    // date and status filter; customer exports only use its customer list. Returns the row count.
+    pub fn export_data_gui(&self, kind: ExportKind, format: ExportFormat, filter: &InvoiceFilter, filename: &str) -> Result<usize, DatabaseError> {
+        let sheet = match kind {
+            ExportKind::Customers => {
+                let customers: Vec<Customer> = self.get_customers_vec().into_iter()
+                    .filter(|c| filter.customer_codes.is_empty() || filter.customer_codes.contains(&c.code))
+                    .collect();
+                customers_sheet(&customers)
+            }
+            ExportKind::Invoices => invoices_sheet(&self.get_invoices_filtered(filter)),
+            ExportKind::LineItems => line_items_sheet(&self.get_invoices_filtered(filter)),
+        };
+        match format {
+            ExportFormat::Csv => write_csv(&sheet, filename)?,
+            ExportFormat::Xlsx => write_xlsx(std::slice::from_ref(&sheet), filename)?,
+        }
+        Ok(sheet.rows.len())
+    }
+
+    pub fn is_exported_to(&self, invoice_number: &str, system: AccountingSystem) -> bool {
+        self.accounting_exports.iter().any(|r| r.system == system && r.invoice_number == invoice_number)
+    }
+
+    pub fn update_accounting_settings_gui(&mut self, settings: AccountingSettings) -> Result<(), DatabaseError> {
+        for system in AccountingSystem::ALL {
+            let mapping = settings.mapping(system);
+            if mapping.sales_account.trim().is_empty() || mapping.gst_tax_code.trim().is_empty() || mapping.gst_free_tax_code.trim().is_empty() {
+                return Err(DatabaseError::InvalidInput(format!("{} account and tax codes cannot be empty.", system.label())));
+            }
+        }
+        self.accounting = settings;
+        self.save()?;
+        Ok(())
+    }
+
+    // Writes a Xero or MYOB sales import file for the filtered invoices and records them as
+    // exported. Invoices already exported to that system are skipped unless `include_exported`.
+    // Returns the number of invoices written.
+    pub fn export_accounting_gui(&mut self, system: AccountingSystem, filter: &InvoiceFilter, include_exported: bool, filename: &str) -> Result<usize, DatabaseError> {
+        let invoices: Vec<Invoice> = self.get_invoices_filtered(filter).into_iter()
+            .filter(|inv| include_exported || !self.is_exported_to(&inv.invoice_number, system))
+            .collect();
+        if invoices.is_empty() {
+            return Err(DatabaseError::InvalidInput("No invoices to export; all matching invoices have already been exported.".to_string()));
+        }
+        let mapping = self.accounting.mapping(system);
+        let contents = match system {
+            AccountingSystem::Xero => xero_csv(&invoices, mapping)?,
+            AccountingSystem::Myob => myob_txt(&invoices, mapping),
+        };
+        fs::write(filename, contents)?;
+
+        let now = Local::now();
+        for invoice in &invoices {
+            self.accounting_exports.push(ExportRecord {
+                invoice_number: invoice.invoice_number.clone(),
+                system,
+                exported_at: now,
+                filename: filename.to_string(),
+            });
+        }
+        self.save()?;
+        Ok(invoices.len())
+    }
+
+    // Builds an interchange file with the selected customers and invoices (everything if the
+    // selection is empty). Customers of the selected invoices are always included.
+    pub fn export_interchange(&self, selection: &InterchangeSelection) -> InterchangeFile {
+        let everything = selection.customer_codes.is_empty() && selection.invoice_numbers.is_empty();
+        let mut invoices: Vec<Invoice> = self.invoices.values()
+            .filter(|inv| everything
+                || selection.customer_codes.contains(&inv.customer.code)
+                || selection.invoice_numbers.contains(&inv.invoice_number))
+            .cloned()
+            .collect();
+        invoices.sort_by(|a, b| a.invoice_number.cmp(&b.invoice_number));
+        let customers: Vec<Customer> = self.get_customers_vec().into_iter()
+            .filter(|c| everything
+                || selection.customer_codes.contains(&c.code)
+                || invoices.iter().any(|inv| inv.customer.code == c.code))
+            .collect();
+        InterchangeFile::new(Some(self.company.clone()), customers, invoices)
+    }
+
+    // Compares incoming records with this database. Records equal to the existing ones are
+    // skipped; other clashes are reported as conflicts and default to Skip.
+    pub fn plan_interchange_import(&self, file: &InterchangeFile) -> ImportPlan {
+        let mut taken_codes: HashSet<String> = self.customers.values().map(|c| c.code.clone()).collect();
+        taken_codes.extend(file.customers.iter().map(|c| c.code.clone()));
+
+        let customers = file.customers.iter().map(|incoming| {
+            let by_code = self.customers.values().find(|c| c.code == incoming.code);
+            let by_name = self.customers.get(incoming.name.trim());
+            let identical = by_code.is_some_and(|existing| same_record(existing, incoming));
+            let conflict = match (by_code, by_name) {
+                _ if identical => None,
+                (Some(existing), _) => Some(format!("Code {} is already used by \"{}\".", incoming.code, existing.name)),
+                (None, Some(existing)) => Some(format!("Name already used by customer {}.", existing.code)),
+                (None, None) => None,
+            };
+            let new_code = if conflict.is_some() {
+                let code = generate_code(&incoming.name, &taken_codes).unwrap_or_default();
+                taken_codes.insert(code.clone());
+                code
+            } else {
+                incoming.code.clone()
+            };
+            let new_name = if by_name.is_some() && !identical { format!("{} (imported)", incoming.name.trim()) } else { incoming.name.clone() };
+            PlannedCustomer {
+                customer: incoming.clone(),
+                resolution: if identical || conflict.is_some() { Resolution::Skip } else { Resolution::Import },
+                conflict,
+                identical,
+                new_code,
+                new_name,
+            }
+        }).collect();
+
+        let mut next_nums = self.last_invoice_nums.clone();
+        let invoices = file.invoices.iter().map(|incoming| {
+            let existing = self.invoices.get(&incoming.invoice_number);
+            let identical = existing.is_some_and(|existing| same_record(existing, incoming));
+            let conflict = match existing {
+                Some(existing) if !identical => Some(format!(
+                    "Invoice #{} already exists ({}, {:.2}).",
+                    existing.invoice_number, existing.date.format("%Y-%m-%d"), existing.total
+                )),
+                _ => None,
+            };
+            let new_number = if conflict.is_some() {
+                // Next free number for the customer, skipping numbers in use or suggested already
+                let code = &incoming.customer.code;
+                loop {
+                    let num = next_nums.entry(code.clone()).or_insert(75);
+                    *num += 1;
+                    let candidate = format!("{}{}", code, num);
+                    if !self.invoices.contains_key(&candidate) && !file.invoices.iter().any(|i| i.invoice_number == candidate) {
+                        break candidate;
+                    }
+                }
+            } else {
+                incoming.invoice_number.clone()
+            };
+            PlannedInvoice {
+                invoice: incoming.clone(),
+                resolution: if identical || conflict.is_some() { Resolution::Skip } else { Resolution::Import },
+                conflict,
+                identical,
+                new_number,
+            }
+        }).collect();
+
+        ImportPlan { customers, invoices }
+    }
+
+    // Applies an import plan and saves once. Nothing changes if any record is invalid.
+    pub fn apply_interchange_import_gui(&mut self, plan: &ImportPlan) -> Result<ImportSummary, DatabaseError> {
+        let original = (self.customers.clone(), self.invoices.clone(), self.last_invoice_nums.clone());
+        match self.apply_import_plan(plan) {
+            Ok(summary) => {
+                self.save()?;
+                Ok(summary)
+            }
+            Err(e) => {
+                (self.customers, self.invoices, self.last_invoice_nums) = original;
+                Err(e)
+            }
+        }
+    }
+
+    fn apply_import_plan(&mut self, plan: &ImportPlan) -> Result<ImportSummary, DatabaseError> {
+        let mut summary = ImportSummary::default();
+        // Incoming customer code -> customer its invoices should point at
+        let mut linked: HashMap<String, Customer> = HashMap::new();
+
+        for planned in &plan.customers {
+            let incoming = &planned.customer;
+            match planned.resolution {
+                Resolution::Import | Resolution::Rename => {
+                    let mut customer = incoming.clone();
+                    if planned.resolution == Resolution::Rename {
+                        customer.code = planned.new_code.clone();
+                        customer.name = planned.new_name.clone();
+                    }
+                    let customer = self.validate_new_customer(&customer)
+                        .map_err(|e| DatabaseError::InvalidInput(format!("Customer \"{}\": {}", customer.name, e)))?;
+                    linked.insert(incoming.code.clone(), customer.clone());
+                    self.insert_customer(customer);
+                    summary.customers_added += 1;
+                }
+                Resolution::Overwrite => {
+                    let existing_name = match self.customers.values().find(|c| c.code == incoming.code) {
+                        Some(c) => c.name.clone(),
+                        None => return Err(DatabaseError::CustomerNotFound(incoming.code.clone())),
+                    };
+                    let name = incoming.name.trim().to_string();
+                    if name != existing_name && self.customers.contains_key(&name) {
+                        return Err(DatabaseError::CustomerExists(name));
+                    }
+                    let mut customer = incoming.clone();
+                    customer.name = name;
+                    self.customers.remove(&existing_name);
+                    self.customers.insert(customer.name.clone(), customer.clone());
+                    linked.insert(incoming.code.clone(), customer);
+                    summary.customers_updated += 1;
+                }
+                Resolution::Skip => {
+                    let existing = self.customers.values()
+                        .find(|c| c.code == incoming.code)
+                        .or_else(|| self.customers.get(incoming.name.trim()));
+                    if let Some(existing) = existing {
+                        linked.insert(incoming.code.clone(), existing.clone());
+                    }
+                    if !planned.identical {
+                        summary.skipped += 1;
+                    }
+                }
+            }
+        }
+
+        for planned in &plan.invoices {
+            let mut invoice = planned.invoice.clone();
+            if let Some(customer) = linked.get(&invoice.customer.code) {
+                invoice.customer = customer.clone();
             }
+            match planned.resolution {
+                Resolution::Skip => {
+                    if !planned.identical {
+                        summary.skipped += 1;
+                    }
+                    continue;
+                }
+                Resolution::Rename => {
+                    let number = planned.new_number.trim().to_string();
+                    if number.is_empty() || self.invoices.contains_key(&number) {
+                        return Err(DatabaseError::InvalidInput(format!("Invoice number \"{}\" is empty or already in use.", number)));
+                    }
+                    invoice.invoice_number = number;
+                    summary.invoices_added += 1;
+                }
+                Resolution::Import => {
+                    if self.invoices.contains_key(&invoice.invoice_number) {
+                        return Err(DatabaseError::InvalidInput(format!("Invoice #{} already exists.", invoice.invoice_number)));
+                    }
+                    summary.invoices_added += 1;
+                }
+                Resolution::Overwrite => summary.invoices_updated += 1,
+            }
+            if !self.customers.values().any(|c| c.code == invoice.customer.code) {
+                return Err(DatabaseError::InvalidInput(format!(
+                    "Invoice #{} belongs to customer {}, which is not in this database or the import.",
+                    invoice.invoice_number, invoice.customer.code
+                )));
+            }
+            // Keep numbering ahead of imported numbers for whichever customer code prefixes them
+            let prefix: String = invoice.invoice_number.chars().take_while(|c| c.is_ascii_alphabetic()).collect();
+            let number = invoice.invoice_number[prefix.len()..].parse::<u32>().ok();
+            if let (Some(num), Some(last)) = (number, self.last_invoice_nums.get_mut(&prefix)) {
+                *last = (*last).max(num);
+            }
+            self.invoices.insert(invoice.invoice_number.clone(), invoice);
+        }
+        Ok(summary)
+    }
+
+    // Saves the business details shown on invoices, and whether new invoices charge GST.
+    // Existing invoices keep the GST they were issued with until they are edited.
+    pub fn update_company_gui(&mut self, company: Company) -> Result<(), DatabaseError> {
+        if company.name.trim().is_empty() {
+            return Err(DatabaseError::InvalidInput("Business name cannot be empty.".to_string()));
+        }
+        let abn = company.abn.replace(' ', "");
+        if !abn.is_empty() && !peppol::is_valid_abn(&abn) {
+            return Err(DatabaseError::InvalidInput(format!("\"{}\" is not a valid ABN.", company.abn)));
+        }
+        if company.gst_registered && abn.is_empty() {
+            return Err(DatabaseError::InvalidInput("An ABN is needed to charge GST.".to_string()));
+        }
+        self.company = company;
+        self.save()
+    }
+
+    pub fn update_email_settings_gui(&mut self, settings: EmailSettings) -> Result<(), DatabaseError> {
+        if settings.smtp_port == 0 {
+            return Err(DatabaseError::InvalidInput("SMTP port must be between 1 and 65535.".to_string()));
+        }
+        self.email = settings;
+        self.save()
+    }
+
+    // Email for an invoice filled in from the templates, for the user to review before sending
+    pub fn email_draft(&self, invoice_number: &str) -> Result<EmailDraft, DatabaseError> {
+        let invoice = match self.invoices.get(invoice_number) {
+            Some(inv) => inv,
+            None => return Err(DatabaseError::InvoiceNotFound(invoice_number.to_string())),
+        };
+        Ok(EmailDraft {
+            to: invoice.customer.email.trim().to_string(),
+            subject: fill_template(&self.email.subject_template, invoice, &self.company),
+            body: fill_template(&self.email.body_template, invoice, &self.company),
+        })
+    }
+
+    // Renders the invoice PDF, emails it and records the send on the invoice
+    pub fn email_invoice_gui(&mut self, invoice_number: &str, draft: &EmailDraft, options: &PdfOptions) -> Result<SentEmail, DatabaseError> {
+        let sent = self.send_invoice_email(invoice_number, draft, options)?;
+        self.record_sent_email_gui(invoice_number, sent.clone())?;
+        Ok(sent)
+    }
+
+    // Logs an email sent elsewhere, e.g. by the bulk send thread, on the invoice
+    pub fn record_sent_email_gui(&mut self, invoice_number: &str, sent: SentEmail) -> Result<(), DatabaseError> {
+        match self.invoices.get_mut(invoice_number) {
+            Some(invoice) => invoice.emails.push(sent),
+            None => return Err(DatabaseError::InvoiceNotFound(invoice_number.to_string())),
+        }
+        self.save()
+    }
+
+    fn send_invoice_email(&self, invoice_number: &str, draft: &EmailDraft, options: &PdfOptions) -> Result<SentEmail, DatabaseError> {
+        match self.invoices.get(invoice_number) {
+            Some(invoice) => send_invoice_email(&self.email, &self.company, invoice, draft, options),
             None => Err(DatabaseError::InvoiceNotFound(invoice_number.to_string())),
         }
     }
-}
 
+    pub fn update_late_fee_settings_gui(&mut self, settings: LateFeeSettings) -> Result<(), DatabaseError> {
+        settings.validate().map_err(DatabaseError::InvalidInput)?;
+        self.late_fees = settings;
+        self.save()
+    }
+
+    pub fn late_fee_quote(&self, invoice_number: &str, as_at: NaiveDate) -> Result<LateFeeQuote, DatabaseError> {
+        let invoice = self.invoices.get(invoice_number)
+            .ok_or_else(|| DatabaseError::InvoiceNotFound(invoice_number.to_string()))?;
+        quote_late_fee(invoice, &self.late_fees, as_at).map_err(DatabaseError::InvalidInput)
+    }
+
+    // Unpaid invoices for the same customer that a late fee on `invoice_number` could be added to
+    pub fn late_fee_follow_ups(&self, invoice_number: &str) -> Vec<String> {
+        let code = match self.invoices.get(invoice_number) {
+            Some(inv) => &inv.customer.code,
+            None => return Vec::new(),
+        };
+        let mut numbers: Vec<String> = self.invoices.values()
+            .filter(|inv| inv.customer.code == *code && inv.invoice_number != invoice_number && !inv.paid && inv.amount_paid() == 0.0)
+            .map(|inv| inv.invoice_number.clone())
+            .collect();
+        numbers.sort();
+        numbers
+    }
+
+    // Bills the late fee owing on `invoice_number` as at `as_at` and logs it on the invoice,
+    // saving both in one go. Returns the number of the invoice carrying the fee.
+    pub fn apply_late_fee_gui(&mut self, invoice_number: &str, as_at: NaiveDate, target: &LateFeeTarget) -> Result<String, DatabaseError> {
+        // A later date can be previewed, but charging it would bill days that haven't passed
+        // and log a period that blocks the correct charge later
+        if as_at > Local::now().date_naive() {
+            return Err(DatabaseError::InvalidInput(format!(
+                "Late fees can only be charged up to today, not {}.", as_at.format("%d/%m/%Y")
+            )));
+        }
+        let quote = self.late_fee_quote(invoice_number, as_at)?;
+        // Late payment fees and interest are not a taxable supply
+        let fee_item = InvoiceItem { description: quote.description.clone(), quantity: 1, rate: quote.amount, amount: quote.amount, gst_free: true, gst: 0.0 };
+        let billed_on = match target {
+            LateFeeTarget::NewInvoice => {
+                let code = self.invoices[invoice_number].customer.code.clone();
+                let due = Local::now().date_naive() + chrono::Duration::days(self.late_fees.fee_due_days);
+                let notes = format!("Late payment fee on overdue invoice {}.", invoice_number);
+                self.add_invoice(code, vec![fee_item], notes, due)?.invoice_number
+            }
+            LateFeeTarget::AddTo(number) => {
+                if !self.late_fee_follow_ups(invoice_number).contains(number) {
+                    return Err(DatabaseError::InvalidInput(format!(
+                        "The fee can only be added to an unpaid invoice for the same customer, and {} isn't one.", number
+                    )));
+                }
+                // The lines already issued keep their prices and GST
+                let follow_up = self.invoices.get_mut(number).ok_or_else(|| DatabaseError::InvoiceNotFound(number.clone()))?;
+                follow_up.subtotal += fee_item.amount;
+                follow_up.total = follow_up.subtotal + follow_up.gst;
+                follow_up.items.push(fee_item);
+                number.clone()
+            }
+        };
+        if let Some(invoice) = self.invoices.get_mut(invoice_number) {
+            invoice.late_fees.push(LateFee {
+                applied_at: Local::now(),
+                amount: quote.amount,
+                period_from: quote.period_from,
+                period_to: quote.period_to,
+                billed_on: billed_on.clone(),
+                description: quote.description,
+            });
+        }
+        self.save()?;
+        Ok(billed_on)
+    }
+
+    pub fn update_reminder_settings_gui(&mut self, settings: ReminderSettings) -> Result<(), DatabaseError> {
+        settings.validate().map_err(DatabaseError::InvalidInput)?;
+        self.reminders = settings;
+        self.save()
+    }
+
+    // Reminders that would be sent on `today`, for a dry run or to confirm before sending
+    pub fn due_reminders(&self, today: NaiveDate) -> Vec<PlannedReminder> {
+        plan_reminders(self.invoices.values(), &self.reminders, &self.company, today)
+    }
+
+    // Sends each reminder due on `today` and logs it on the invoice.
+    // Returns every due reminder with the error for those that were skipped or failed.
+    pub fn send_reminders(&mut self, today: NaiveDate, options: &PdfOptions) -> Result<Vec<(PlannedReminder, Option<String>)>, DatabaseError> {
+        if !self.email.is_configured() {
+            return Err(DatabaseError::InvalidInput("Email is not set up. Enter the SMTP server and sender address in the email settings.".to_string()));
+        }
+        let mut results = Vec::new();
+        for reminder in self.due_reminders(today) {
+            if let Some(reason) = reminder.skip_reason.clone() {
+                results.push((reminder, Some(reason)));
+                continue;
+            }
+            match self.send_invoice_email(&reminder.invoice_number, &reminder.draft, options) {
+                Ok(sent) => {
+                    if let Some(invoice) = self.invoices.get_mut(&reminder.invoice_number) {
+                        invoice.reminders.push(SentReminder { sent_at: sent.sent_at, days_from_due: reminder.days_from_due, to: sent.to });
+                    }
+                    // Save after each one so a later failure can't lose the record of what went out
+                    self.save()?;
+                    results.push((reminder, None));
+                }
+                Err(e) => {
+                    let message = e.to_string();
+                    results.push((reminder, Some(message)));
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    // Peppol A-NZ PINT (UBL 2.1) XML for an invoice, validated before it is returned
+    pub fn export_ubl_xml(&self, invoice_number: &str) -> Result<String, DatabaseError> {
+        let invoice = self.invoices.get(invoice_number)
+            .ok_or_else(|| DatabaseError::InvoiceNotFound(invoice_number.to_string()))?;
+        peppol::export_invoice(invoice, &self.company)
+            .map_err(|errors| DatabaseError::InvalidInput(format!("E-invoice failed validation:\n- {}", errors.join("\n- "))))
+    }
+
+    pub fn export_ubl_gui(&self, invoice_number: &str, filename: &str) -> Result<String, DatabaseError> {
+        let xml = self.export_ubl_xml(invoice_number)?;
+        fs::write(filename, xml)?;
+        Ok(filename.to_string())
+    }
+}
@@ -0,0 +1,555 @@
+// Sending invoices by email through an SMTP server.
+//
+// A small SMTP client (EHLO, STARTTLS or implicit TLS, AUTH PLAIN/LOGIN, one message per
+// connection) is enough here. With SmtpSecurity::None it talks plain SMTP, which is what
+// local stand-ins such as MailHog, smtp4dev or `python -m aiosmtpd -n` expect.
+use std::error::Error;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{DateTime, Local};
+use rustls::pki_types::ServerName;
+use rustls::{ClientConnection, StreamOwned};
+use serde::{Serialize, Deserialize};
+use crate::database::send_invoice_email;
+use crate::models::{Company, Invoice, SentEmail};
+use crate::pdf_generator::PdfOptions;
+use crate::renderer::format_money;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+const IO_TIMEOUT: Duration = Duration::from_secs(60);
+pub const PASSWORD_ENV_VAR: &str = "JMATT_SMTP_PASSWORD";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SmtpSecurity {
+    #[default]
+    StartTls, // Upgrade a plain connection, usually port 587
+    Tls, // TLS from the start, usually port 465
+    None, // Unencrypted; only for a local test server
+}
+
+impl SmtpSecurity {
+    pub const ALL: [SmtpSecurity; 3] = [SmtpSecurity::StartTls, SmtpSecurity::Tls, SmtpSecurity::None];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SmtpSecurity::StartTls => "STARTTLS",
+            SmtpSecurity::Tls => "TLS",
+            SmtpSecurity::None => "None (local testing only)",
+        }
+    }
+
+    pub fn default_port(&self) -> u16 {
+        match self {
+            SmtpSecurity::StartTls => 587,
+            SmtpSecurity::Tls => 465,
+            SmtpSecurity::None => 1025,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmailSettings {
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub security: SmtpSecurity,
+    pub username: String, // Empty to send without logging in
+    pub password: String, // Stored in plain text; JMATT_SMTP_PASSWORD is used instead when set
+    pub from_address: String,
+    pub from_name: String,
+    pub subject_template: String,
+    pub body_template: String,
+}
+
+impl Default for EmailSettings {
+    fn default() -> Self {
+        Self {
+            smtp_host: String::new(),
+            smtp_port: SmtpSecurity::StartTls.default_port(),
+            security: SmtpSecurity::StartTls,
+            username: String::new(),
+            password: String::new(),
+            from_address: String::new(),
+            from_name: String::new(),
+            subject_template: "Invoice {invoice_number} from {company_name}".to_string(),
+            body_template: "Dear {contact_name},\n\n\
+                Please find attached invoice {invoice_number} for {total}, due on {due_date}.\n\n\
+                Thank you for your business.\n\n\
+                {company_name}\n\
+                {company_phone}\n".to_string(),
+        }
+    }
+}
+
+impl EmailSettings {
+    pub fn is_configured(&self) -> bool {
+        !self.smtp_host.trim().is_empty() && !self.from_address.trim().is_empty()
+    }
+
+    // JMATT_SMTP_PASSWORD wins over the password stored (in plain text) in database.json
+    fn password(&self) -> String {
+        match std::env::var(PASSWORD_ENV_VAR) {
+            Ok(password) if !password.is_empty() => password,
+            _ => self.password.clone(),
+        }
+    }
+}
+
+// Placeholders available in the subject and body templates
+pub const PLACEHOLDERS: [(&str, &str); 9] = [
+    ("{invoice_number}", "Invoice number"),
+    ("{customer_name}", "Customer name"),
+    ("{contact_name}", "Contact person, or the customer name"),
+    ("{total}", "Invoice total, e.g. AU $1250.00"),
+    ("{balance_due}", "Amount still owing"),
+    ("{issue_date}", "Date issued, e.g. 01/05/2024"),
+    ("{due_date}", "Due date"),
+    ("{company_name}", "Your business name"),
+    ("{company_phone}", "Your phone number"),
+];
+
+pub fn fill_template(template: &str, invoice: &Invoice, company: &Company) -> String {
+    let contact = if invoice.customer.contact_person.trim().is_empty() { &invoice.customer.name } else { &invoice.customer.contact_person };
+    let values = [
+        invoice.invoice_number.clone(),
+        invoice.customer.name.clone(),
+        contact.clone(),
+        format_money(invoice.total),
+        format_money(invoice.balance_due()),
+        invoice.date.format("%d/%m/%Y").to_string(),
+        invoice.due_date.format("%d/%m/%Y").to_string(),
+        company.name.clone(),
+        company.phone.clone(),
+    ];
+    // Templates saved before amounts carried their currency put a "$" in front of them
+    let template = template.replace("${total}", "{total}").replace("${balance_due}", "{balance_due}");
+    PLACEHOLDERS.iter().zip(values).fold(template, |text, ((placeholder, _), value)| text.replace(placeholder, &value))
+}
+
+// An email ready to be reviewed and sent
+#[derive(Clone, Debug, Default)]
+pub struct EmailDraft {
+    pub to: String, // One or more addresses separated by commas or semicolons
+    pub subject: String,
+    pub body: String,
+}
+
+pub struct Attachment {
+    pub filename: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+// Splits "a@x.com; b@y.com" into addresses, rejecting anything that could break the headers
+pub fn parse_recipients(to: &str) -> Result<Vec<String>, String> {
+    let addresses: Vec<String> = to.split([',', ';']).map(|a| a.trim().to_string()).filter(|a| !a.is_empty()).collect();
+    if addresses.is_empty() {
+        return Err("No recipient email address.".to_string());
+    }
+    for address in &addresses {
+        if !is_valid_address(address) {
+            return Err(format!("\"{}\" is not a valid email address.", address));
+        }
+    }
+    Ok(addresses)
+}
+
+fn is_valid_address(address: &str) -> bool {
+    let Some((local, domain)) = address.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && !domain.contains('@')
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && !address.chars().any(|c| c.is_whitespace() || c.is_control() || "<>()[]\\,;:\"".contains(c))
+}
+
+// RFC 2047 encoded-word for header text that isn't plain ASCII
+fn encode_header(text: &str) -> String {
+    let text = text.replace(['\r', '\n'], " ");
+    if text.chars().all(|c| c.is_ascii() && !c.is_ascii_control()) {
+        text
+    } else {
+        format!("=?UTF-8?B?{}?=", BASE64.encode(text.as_bytes()))
+    }
+}
+
+fn format_mailbox(name: &str, address: &str) -> String {
+    let name = name.trim();
+    if name.is_empty() {
+        address.to_string()
+    } else if name.is_ascii() {
+        format!("\"{}\" <{}>", name.replace(['\\', '"', '\r', '\n'], ""), address)
+    } else {
+        format!("{} <{}>", encode_header(name), address)
+    }
+}
+
+// Base64 wrapped at 76 characters as MIME requires
+fn base64_lines(data: &[u8]) -> String {
+    let encoded = BASE64.encode(data);
+    let mut out = String::with_capacity(encoded.len() + encoded.len() / 38);
+    for chunk in encoded.as_bytes().chunks(76) {
+        out.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+        out.push_str("\r\n");
+    }
+    out
+}
+
+fn random_hex(bytes: usize) -> String {
+    let mut buf = vec![0u8; bytes];
+    if getrandom::getrandom(&mut buf).is_err() {
+        // Uniqueness only matters for boundaries and message IDs; fall back to the clock
+        return format!("{:x}", Local::now().timestamp_nanos_opt().unwrap_or_default());
+    }
+    buf.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Builds a multipart/mixed message: the text body followed by the attachments
+pub fn build_message(settings: &EmailSettings, draft: &EmailDraft, attachments: &[Attachment], date: DateTime<Local>) -> Result<String, String> {
+    let recipients = parse_recipients(&draft.to)?;
+    let from = settings.from_address.trim();
+    if !is_valid_address(from) {
+        return Err(format!("The sender address \"{}\" is not valid. Check the email settings.", from));
+    }
+    let boundary = format!("=_jmatt_{}", random_hex(12));
+    let domain = from.split_once('@').map(|(_, d)| d).unwrap_or("localhost");
+
+    let mut message = String::new();
+    message.push_str(&format!("Date: {}\r\n", date.to_rfc2822()));
+    message.push_str(&format!("From: {}\r\n", format_mailbox(&settings.from_name, from)));
+    message.push_str(&format!("To: {}\r\n", recipients.join(", ")));
+    message.push_str(&format!("Subject: {}\r\n", encode_header(draft.subject.trim())));
+    message.push_str(&format!("Message-ID: <{}@{}>\r\n", random_hex(16), domain));
+    message.push_str("MIME-Version: 1.0\r\n");
+    message.push_str(&format!("Content-Type: multipart/mixed; boundary=\"{}\"\r\n\r\n", boundary));
+    message.push_str("This is a multi-part message in MIME format.\r\n");
+
+    message.push_str(&format!("--{}\r\n", boundary));
+    message.push_str("Content-Type: text/plain; charset=utf-8\r\n");
+    message.push_str("Content-Transfer-Encoding: base64\r\n\r\n");
+    message.push_str(&base64_lines(draft.body.replace("\r\n", "\n").replace('\n', "\r\n").as_bytes()));
+
+    for attachment in attachments {
+        let filename = attachment.filename.replace(['"', '\\', '\r', '\n'], "");
+        message.push_str(&format!("--{}\r\n", boundary));
+        message.push_str(&format!("Content-Type: {}; name=\"{}\"\r\n", attachment.content_type, filename));
+        message.push_str("Content-Transfer-Encoding: base64\r\n");
+        message.push_str(&format!("Content-Disposition: attachment; filename=\"{}\"\r\n\r\n", filename));
+        message.push_str(&base64_lines(&attachment.data));
+    }
+    message.push_str(&format!("--{}--\r\n", boundary));
+    Ok(message)
+}
+
+// Plain or TLS connection; STARTTLS swaps one for the other mid-session
+enum Connection {
+    Plain(TcpStream),
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Connection::Plain(s) => s.read(buf),
+            Connection::Tls(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Connection::Plain(s) => s.write(buf),
+            Connection::Tls(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Connection::Plain(s) => s.flush(),
+            Connection::Tls(s) => s.flush(),
+        }
+    }
+}
+
+fn start_tls(tcp: TcpStream, host: &str) -> Result<Connection, Box<dyn Error>> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let config = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let name = ServerName::try_from(host.to_string())?;
+    let connection = ClientConnection::new(Arc::new(config), name)?;
+    Ok(Connection::Tls(Box::new(StreamOwned::new(connection, tcp))))
+}
+
+struct SmtpClient {
+    connection: Connection,
+}
+
+impl SmtpClient {
+    // Reads one byte at a time so nothing is buffered past a reply when STARTTLS switches streams
+    fn read_line(&mut self) -> Result<String, Box<dyn Error>> {
+        let mut line = Vec::new();
+        let mut byte = [0u8; 1];
+        loop {
+            if self.connection.read(&mut byte)? == 0 {
+                return Err("The SMTP server closed the connection.".into());
+            }
+            if byte[0] == b'\n' {
+                break;
+            }
+            line.push(byte[0]);
+            if line.len() > 4096 {
+                return Err("The SMTP server sent an overlong reply.".into());
+            }
+        }
+        Ok(String::from_utf8_lossy(&line).trim_end().to_string())
+    }
+
+    // Reads a possibly multi-line reply ("250-...", "250 ...")
+    fn read_reply(&mut self) -> Result<(u16, String), Box<dyn Error>> {
+        let mut text = Vec::new();
+        loop {
+            let line = self.read_line()?;
+            let code = line.get(..3).and_then(|c| c.parse::<u16>().ok())
+                .ok_or_else(|| format!("Unexpected SMTP reply \"{}\"", line))?;
+            text.push(line.get(4..).unwrap_or_default().to_string());
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok((code, text.join("\n")));
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: &[u16], step: &str) -> Result<String, Box<dyn Error>> {
+        let (code, text) = self.read_reply()?;
+        if expected.contains(&code) {
+            Ok(text)
+        } else {
+            Err(format!("SMTP server rejected {}: {} {}", step, code, text).into())
+        }
+    }
+
+    // `step` names the command in errors, so credentials never end up in messages
+    fn command(&mut self, line: &str, expected: &[u16], step: &str) -> Result<String, Box<dyn Error>> {
+        self.connection.write_all(line.as_bytes())?;
+        self.connection.write_all(b"\r\n")?;
+        self.connection.flush()?;
+        self.expect(expected, step)
+    }
+}
+
+// Sends one message. `message` is the full RFC 5322 text from build_message.
+pub fn send_message(settings: &EmailSettings, from: &str, recipients: &[String], message: &str) -> Result<(), Box<dyn Error>> {
+    let host = settings.smtp_host.trim();
+    let address = (host, settings.smtp_port).to_socket_addrs()?.next()
+        .ok_or_else(|| format!("Could not resolve the SMTP server \"{}\"", host))?;
+    // Without encryption the login would cross the network in the clear
+    if settings.security == SmtpSecurity::None && !settings.username.is_empty() && !address.ip().is_loopback() {
+        return Err(format!(
+            "Refusing to log in to {} without encryption. Choose STARTTLS or TLS, or leave the user name blank.", host
+        ).into());
+    }
+    let tcp = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)
+        .map_err(|e| format!("Could not connect to {}:{}: {}", host, settings.smtp_port, e))?;
+    tcp.set_read_timeout(Some(IO_TIMEOUT))?;
+    tcp.set_write_timeout(Some(IO_TIMEOUT))?;
+
+    let connection = match settings.security {
+        SmtpSecurity::Tls => start_tls(tcp, host)?,
+        SmtpSecurity::StartTls | SmtpSecurity::None => Connection::Plain(tcp),
+    };
+    let mut client = SmtpClient { connection };
+    client.expect(&[220], "the connection")?;
+    let mut features = client.command("EHLO localhost", &[250], "EHLO")?;
+
+    if settings.security == SmtpSecurity::StartTls {
+        if !features.lines().any(|l| l.eq_ignore_ascii_case("STARTTLS")) {
+            return Err("The SMTP server does not offer STARTTLS. Choose TLS or check the port.".into());
+        }
+        client.command("STARTTLS", &[220], "STARTTLS")?;
+        let tcp = match client.connection {
+            Connection::Plain(tcp) => tcp,
+            Connection::Tls(_) => unreachable!("STARTTLS only runs on plain connections"),
+        };
+        client = SmtpClient { connection: start_tls(tcp, host)? };
+        features = client.command("EHLO localhost", &[250], "EHLO")?;
+    }
+
+    if !settings.username.is_empty() {
+        let password = settings.password();
+        let auth_line = features.lines().find(|l| l.to_uppercase().starts_with("AUTH")).unwrap_or_default().to_uppercase();
+        if auth_line.split_whitespace().any(|m| m == "PLAIN") || !auth_line.contains("LOGIN") {
+            let token = BASE64.encode(format!("\0{}\0{}", settings.username, password));
+            client.command(&format!("AUTH PLAIN {}", token), &[235], "the login")?;
+        } else {
+            client.command("AUTH LOGIN", &[334], "AUTH LOGIN")?;
+            client.command(&BASE64.encode(&settings.username), &[334], "the user name")?;
+            client.command(&BASE64.encode(&password), &[235], "the login")?;
+        }
+    }
+
+    client.command(&format!("MAIL FROM:<{}>", from), &[250], "the sender address")?;
+    for recipient in recipients {
+        client.command(&format!("RCPT TO:<{}>", recipient), &[250, 251], &format!("the recipient {}", recipient))?;
+    }
+    client.command("DATA", &[354], "DATA")?;
+    // Lines starting with "." are doubled so they can't end the message early
+    let mut data = String::with_capacity(message.len() + 16);
+    for line in message.split("\r\n") {
+        if line.starts_with('.') {
+            data.push('.');
+        }
+        data.push_str(line);
+        data.push_str("\r\n");
+    }
+    client.connection.write_all(data.trim_end_matches("\r\n").as_bytes())?;
+    client.command("\r\n.", &[250], "the message")?;
+    let _ = client.command("QUIT", &[221], "QUIT"); // The message is accepted; a failed goodbye doesn't matter
+    Ok(())
+}
+
+// Messages sent from the bulk email thread back to the GUI
+pub enum BulkEmailProgress {
+    Working { done: usize, total: usize },
+    Sent(String, SentEmail), // Invoice number and the send, for the GUI to record
+    Failed(String, String), // Invoice number and error
+    Finished,
+}
+
+// Emails each invoice with its draft on a background thread so the GUI stays responsive
+pub fn spawn_bulk_email(jobs: Vec<(Invoice, EmailDraft)>, company: Company, settings: EmailSettings, options: PdfOptions) -> Receiver<BulkEmailProgress> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let total = jobs.len();
+        let _ = sender.send(BulkEmailProgress::Working { done: 0, total });
+        for (idx, (invoice, draft)) in jobs.iter().enumerate() {
+            let message = match send_invoice_email(&settings, &company, invoice, draft, &options) {
+                Ok(sent) => BulkEmailProgress::Sent(invoice.invoice_number.clone(), sent),
+                Err(e) => BulkEmailProgress::Failed(invoice.invoice_number.clone(), e.to_string()),
+            };
+            let _ = sender.send(message);
+            let _ = sender.send(BulkEmailProgress::Working { done: idx + 1, total });
+        }
+        let _ = sender.send(BulkEmailProgress::Finished);
+    });
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+
+    // A minimal SMTP server that accepts one session and returns every line the client sent
+    fn smtp_stub() -> (u16, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut received = Vec::new();
+            let mut in_data = false;
+            writer.write_all(b"220 stub ready\r\n").unwrap();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_end_matches(['\r', '\n']).to_string();
+                received.push(line.clone());
+                let reply: &[u8] = if in_data {
+                    if line != "." {
+                        continue;
+                    }
+                    in_data = false;
+                    b"250 queued\r\n"
+                } else if line.starts_with("EHLO") {
+                    b"250-stub\r\n250 AUTH PLAIN LOGIN\r\n"
+                } else if line.starts_with("AUTH") {
+                    b"235 accepted\r\n"
+                } else if line == "DATA" {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                } else if line == "QUIT" {
+                    writer.write_all(b"221 bye\r\n").unwrap();
+                    break;
+                } else {
+                    b"250 ok\r\n"
+                };
+                writer.write_all(reply).unwrap();
+            }
+            received
+        });
+        (port, handle)
+    }
+
+    fn stub_settings(port: u16) -> EmailSettings {
+        EmailSettings {
+            smtp_host: "127.0.0.1".to_string(),
+            smtp_port: port,
+            security: SmtpSecurity::None,
+            username: "billing".to_string(),
+            password: "secret".to_string(),
+            from_address: "billing@example.com".to_string(),
+            from_name: "J Matt".to_string(),
+            ..EmailSettings::default()
+        }
+    }
+
+    #[test]
+    fn sends_message_with_attachment() {
+        let (port, stub) = smtp_stub();
+        let settings = stub_settings(port);
+        let draft = EmailDraft { to: "accounts@example.org".to_string(), subject: "Invoice 42".to_string(), body: "Hello".to_string() };
+        let attachment = Attachment { filename: "Invoice_42.pdf".to_string(), content_type: "application/pdf".to_string(), data: b"%PDF-1.7 test".to_vec() };
+        let message = build_message(&settings, &draft, &[attachment], Local::now()).unwrap();
+        let recipients = parse_recipients(&draft.to).unwrap();
+        send_message(&settings, &settings.from_address, &recipients, &message).unwrap();
+
+        let received = stub.join().unwrap();
+        assert_eq!(received[0], "EHLO localhost");
+        let token = BASE64.encode(format!("\0billing\0{}", settings.password()));
+        assert_eq!(received[1], format!("AUTH PLAIN {}", token));
+        assert_eq!(received[2], "MAIL FROM:<billing@example.com>");
+        assert_eq!(received[3], "RCPT TO:<accounts@example.org>");
+        assert_eq!(received[4], "DATA");
+        assert!(received.contains(&"Content-Disposition: attachment; filename=\"Invoice_42.pdf\"".to_string()));
+        assert!(received.contains(&BASE64.encode(b"%PDF-1.7 test")));
+        assert!(received.contains(&BASE64.encode(b"Hello")));
+        assert_eq!(received.last().unwrap(), "QUIT");
+    }
+
+    #[test]
+    fn refuses_plain_login_to_remote_host() {
+        let settings = EmailSettings { smtp_host: "192.0.2.1".to_string(), ..stub_settings(25) };
+        let err = send_message(&settings, &settings.from_address, &["a@example.org".to_string()], "Subject: x\r\n").unwrap_err();
+        assert!(err.to_string().contains("without encryption"), "{}", err);
+    }
+
+    #[test]
+    fn dot_stuffs_lines_starting_with_a_dot() {
+        let (port, stub) = smtp_stub();
+        let settings = EmailSettings { username: String::new(), ..stub_settings(port) };
+        let message = "Subject: dots\r\n\r\n.hidden\r\n.\r\nlast line\r\n";
+        send_message(&settings, &settings.from_address, &["a@example.org".to_string()], message).unwrap();
+
+        let received = stub.join().unwrap();
+        assert!(!received.iter().any(|l| l.starts_with("AUTH")));
+        let data = received.iter().position(|l| l == "DATA").unwrap();
+        assert_eq!(&received[data + 1..], ["Subject: dots", "", "..hidden", "..", "last line", ".", "QUIT"]);
+    }
+}
@@ -0,0 +1,147 @@
+// JSON interchange format for moving customers and invoices between databases.
+//
+// A file is a single JSON object:
+//
+//   {
+//     "format": "jmatt-invoice-interchange",
+//     "version": 1,
+//     "exported_at": "2024-05-01T09:30:00+10:00",
+//     "company": { "name", "abn", "address", "phone", "gst_registered" } or null,
+//     "customers": [ Customer, ... ],
+//     "invoices": [ Invoice, ... ]
+//   }
+//
+// Customers and invoices use the same field names as database.json (see models.rs).
+// GST is carried per invoice ("gst", added to "subtotal" to give "total") and per line
+// ("gst_free" and "gst"). These fields are optional on read: a company without
+// "gst_registered" is not registered, and lines without "gst_free" are GST-free with no GST.
+// Every invoice carries a copy of its customer; exports always include the customer
+// records for the invoices they contain. Readers reject files with a newer `version`;
+// older versions are upgraded on read. Version history:
+//   1 - initial format
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use chrono::{DateTime, Local};
+use serde::{Serialize, Deserialize};
+use crate::models::{Company, Customer, Invoice};
+
+pub const FORMAT_NAME: &str = "jmatt-invoice-interchange";
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct InterchangeFile {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: DateTime<Local>,
+    #[serde(default)]
+    pub company: Option<Company>,
+    #[serde(default)]
+    pub customers: Vec<Customer>,
+    #[serde(default)]
+    pub invoices: Vec<Invoice>,
+}
+
+impl InterchangeFile {
+    pub fn new(company: Option<Company>, customers: Vec<Customer>, invoices: Vec<Invoice>) -> Self {
+        Self {
+            format: FORMAT_NAME.to_string(),
+            version: FORMAT_VERSION,
+            exported_at: Local::now(),
+            company,
+            customers,
+            invoices,
+        }
+    }
+}
+
+pub fn write_interchange(file: &InterchangeFile, path: &Path) -> Result<(), Box<dyn Error>> {
+    fs::write(path, serde_json::to_string_pretty(file)?)?;
+    Ok(())
+}
+
+pub fn read_interchange(path: &Path) -> Result<InterchangeFile, Box<dyn Error>> {
+    let value: serde_json::Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+    if value.get("format").and_then(|f| f.as_str()) != Some(FORMAT_NAME) {
+        return Err("Not an invoice interchange file (missing \"format\": \"jmatt-invoice-interchange\").".into());
+    }
+    let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+    if version == 0 || version > FORMAT_VERSION as u64 {
+        return Err(format!(
+            "Interchange version {} is not supported (this program reads versions 1 to {}).",
+            version, FORMAT_VERSION
+        ).into());
+    }
+    // Upgrades from older versions go here as the format evolves
+    Ok(serde_json::from_value(value)?)
+}
+
+// What to export; with both lists empty everything is exported
+#[derive(Clone, Default)]
+pub struct InterchangeSelection {
+    pub customer_codes: Vec<String>, // These customers and all their invoices
+    pub invoice_numbers: Vec<String>, // Individual invoices (their customers are included)
+}
+
+// What to do with an incoming record
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resolution {
+    Import, // No conflict
+    Skip, // Keep the existing record
+    Overwrite, // Replace the existing record
+    Rename, // Import under the new code/name or invoice number
+}
+
+impl Resolution {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Resolution::Import => "Import",
+            Resolution::Skip => "Skip (keep existing)",
+            Resolution::Overwrite => "Overwrite existing",
+            Resolution::Rename => "Import as new",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PlannedCustomer {
+    pub customer: Customer,
+    pub conflict: Option<String>, // Description of the clash with an existing customer
+    pub identical: bool, // Same as the existing record, nothing to do
+    pub resolution: Resolution,
+    pub new_code: String, // Used with Resolution::Rename
+    pub new_name: String, // Used with Resolution::Rename
+}
+
+#[derive(Clone)]
+pub struct PlannedInvoice {
+    pub invoice: Invoice,
+    pub conflict: Option<String>,
+    pub identical: bool,
+    pub resolution: Resolution,
+    pub new_number: String, // Used with Resolution::Rename
+}
+
+// Incoming records with the conflicts found and how each will be handled.
+// Conflicting records default to Skip so nothing is overwritten without a decision.
+#[derive(Clone, Default)]
+pub struct ImportPlan {
+    pub customers: Vec<PlannedCustomer>,
+    pub invoices: Vec<PlannedInvoice>,
+}
+
+impl ImportPlan {
+    pub fn conflict_count(&self) -> usize {
+        self.customers.iter().filter(|c| c.conflict.is_some()).count()
+            + self.invoices.iter().filter(|i| i.conflict.is_some()).count()
+    }
+}
+
+#[derive(Default)]
+pub struct ImportSummary {
+    pub customers_added: usize,
+    pub customers_updated: usize,
+    pub invoices_added: usize,
+    pub invoices_updated: usize,
+    pub skipped: usize,
+}
@@ -0,0 +1,142 @@
+// Late payment fees and interest on overdue invoices.
+//
+// A fee is worked out from the overdue invoice's balance and billed as a line item, either
+// on a new fee invoice or added to another unpaid invoice for the same customer. Each fee
+// is logged on the overdue invoice with the period it covers, so interest charged again
+// later only covers the days since the last charge.
+use chrono::{Duration, NaiveDate};
+use serde::{Serialize, Deserialize};
+use crate::models::Invoice;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LateFeeMethod {
+    FlatFee, // A fixed amount, charged once per invoice
+    DailyInterest, // Percentage of the balance for each day overdue
+    #[default]
+    AnnualInterest, // Yearly percentage rate, charged per day overdue
+}
+
+impl LateFeeMethod {
+    pub const ALL: [LateFeeMethod; 3] = [LateFeeMethod::FlatFee, LateFeeMethod::DailyInterest, LateFeeMethod::AnnualInterest];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LateFeeMethod::FlatFee => "Flat Fee",
+            LateFeeMethod::DailyInterest => "Daily Interest",
+            LateFeeMethod::AnnualInterest => "Annual Interest",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LateFeeSettings {
+    pub method: LateFeeMethod,
+    pub flat_fee: f64, // Dollars
+    pub daily_rate: f64, // Percent per day
+    pub annual_rate: f64, // Percent per year
+    pub grace_days: i64, // Days after the due date before fees start
+    pub fee_due_days: i64, // Payment terms for a separate fee invoice
+}
+
+impl Default for LateFeeSettings {
+    fn default() -> Self {
+        Self {
+            method: LateFeeMethod::AnnualInterest,
+            flat_fee: 25.0,
+            daily_rate: 0.05,
+            annual_rate: 10.0,
+            grace_days: 0,
+            fee_due_days: 14,
+        }
+    }
+}
+
+impl LateFeeSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        let amount = match self.method {
+            LateFeeMethod::FlatFee => self.flat_fee,
+            LateFeeMethod::DailyInterest => self.daily_rate,
+            LateFeeMethod::AnnualInterest => self.annual_rate,
+        };
+        if !(amount > 0.0 && amount.is_finite()) {
+            return Err(format!("The {} must be a positive number.", self.method.label().to_lowercase()));
+        }
+        if self.grace_days < 0 || self.fee_due_days < 0 {
+            return Err("Grace days and fee invoice terms can't be negative.".to_string());
+        }
+        Ok(())
+    }
+}
+
+// Where the fee is billed
+#[derive(Clone, Debug, PartialEq)]
+pub enum LateFeeTarget {
+    NewInvoice,
+    AddTo(String), // Another unpaid invoice for the same customer
+}
+
+// A calculated fee, for preview before it is applied
+#[derive(Clone, Debug)]
+pub struct LateFeeQuote {
+    pub invoice_number: String,
+    pub balance_due: f64,
+    pub period_from: NaiveDate, // Last day already covered; interest runs from the day after
+    pub period_to: NaiveDate,
+    pub days: i64,
+    pub amount: f64,
+    pub description: String, // Line item text on the invoice that bills the fee
+}
+
+// Works out the fee owing on `invoice` as at the end of `as_at`
+pub fn quote_late_fee(invoice: &Invoice, settings: &LateFeeSettings, as_at: NaiveDate) -> Result<LateFeeQuote, String> {
+    settings.validate()?;
+    let balance = invoice.balance_as_at(as_at);
+    if balance <= 0.0 {
+        return Err(format!("Invoice {} has nothing owing.", invoice.invoice_number));
+    }
+    let fees_start = invoice.due_date.date_naive() + Duration::days(settings.grace_days);
+    let already_to = invoice.late_fees.iter().map(|fee| fee.period_to).max();
+    if settings.method == LateFeeMethod::FlatFee && already_to.is_some() {
+        return Err(format!("A late fee has already been charged on invoice {}.", invoice.invoice_number));
+    }
+    let period_from = already_to.map_or(fees_start, |to| to.max(fees_start));
+    let days = (as_at - period_from).num_days();
+    if days <= 0 {
+        return Err(if already_to.is_some_and(|to| to >= fees_start) {
+            format!("Fees on invoice {} are already charged up to {}.", invoice.invoice_number, period_from.format("%d/%m/%Y"))
+        } else {
+            format!("Invoice {} is not overdue until after {}.", invoice.invoice_number, fees_start.format("%d/%m/%Y"))
+        });
+    }
+
+    let (amount, description) = match settings.method {
+        LateFeeMethod::FlatFee => (settings.flat_fee, format!("Late payment fee on overdue invoice {}", invoice.invoice_number)),
+        LateFeeMethod::DailyInterest => (
+            balance * settings.daily_rate / 100.0 * days as f64,
+            format!("Interest on overdue invoice {}: ${:.2} at {}% per day for {} days", invoice.invoice_number, balance, settings.daily_rate, days),
+        ),
+        LateFeeMethod::AnnualInterest => (
+            balance * settings.annual_rate / 100.0 * days as f64 / 365.0,
+            format!("Interest on overdue invoice {}: ${:.2} at {}% p.a. for {} days", invoice.invoice_number, balance, settings.annual_rate, days),
+        ),
+    };
+    let amount = (amount * 100.0).round() / 100.0;
+    if amount < 0.01 {
+        return Err(format!("The fee on invoice {} comes to less than a cent.", invoice.invoice_number));
+    }
+    let description = if settings.method == LateFeeMethod::FlatFee {
+        description
+    } else {
+        format!("{} ({} to {})", description, (period_from + Duration::days(1)).format("%d/%m/%Y"), as_at.format("%d/%m/%Y"))
+    };
+    Ok(LateFeeQuote {
+        invoice_number: invoice.invoice_number.clone(),
+        balance_due: balance,
+        period_from,
+        period_to: as_at,
+        days,
+        amount,
+        description,
+    })
+}
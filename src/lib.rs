@@ -0,0 +1,28 @@
+// Invoice management library: the domain model, the JSON database and the PDF, HTML,
+// spreadsheet and e-invoice renderers. The GUI (src/bin/jmatt_invoice) and the command
+// line tool (src/bin/jmatt_invoice_cli) are thin front ends over these modules.
+pub mod database;
+pub mod models;
+pub mod pdf_generator;
+pub mod utils;
+pub mod batch_export;
+pub mod statement;
+pub mod renderer;
+pub mod bank_details;
+pub mod peppol;
+pub mod customer_import;
+pub mod spreadsheet;
+pub mod accounting_export;
+pub mod bank_import;
+pub mod interchange;
+pub mod email;
+pub mod reminders;
+pub mod late_fees;
+pub mod aged_receivables;
+pub mod bas;
+pub mod dashboard;
+pub mod search;
+
+pub use database::{Database, DatabaseError, InvoiceFilter, InvoiceSort, InvoiceStatusFilter};
+pub use models::{Company, Customer, Invoice, InvoiceItem, Payment, PaymentKind};
+pub use pdf_generator::{generate_pdf, PdfOptions};
@@ -1,12 +1,17 @@
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, NaiveDate};
 use serde::{Serialize, Deserialize};
 
+// GST charged on taxable lines when the company is registered for GST
+pub const GST_RATE: f64 = 0.10;
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Company {
     pub name: String,
     pub abn: String,
     pub address: String,
     pub phone: String,
+    #[serde(default)] // Older databases never charged GST
+    pub gst_registered: bool,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -18,6 +23,8 @@ pub struct Customer {
     pub contact_phone: String,
     pub email: String,
     pub code: String,
+    #[serde(default)] // Optional; needed as the Peppol endpoint for e-invoicing
+    pub abn: String,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -26,6 +33,14 @@ pub struct InvoiceItem {
     pub quantity: u32,
     pub rate: f64,
     pub amount: f64,
+    #[serde(default = "legacy_gst_free")] // Lines saved before GST was recorded carried none
+    pub gst_free: bool,
+    #[serde(default)]
+    pub gst: f64, // GST on this line, in dollars and cents
+}
+
+fn legacy_gst_free() -> bool {
+    true
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -36,7 +51,123 @@ pub struct Invoice {
     pub customer: Customer,
     pub items: Vec<InvoiceItem>,
     pub subtotal: f64,
+    #[serde(default)]
+    pub gst: f64, // Sum of the line GST; total is subtotal plus GST
     pub total: f64,
     pub notes: String,
     pub paid: bool,
+    #[serde(default)] // Older databases have no payment records
+    pub payments: Vec<Payment>,
+    #[serde(default)]
+    pub emails: Vec<SentEmail>, // Times the invoice was emailed, oldest first
+    #[serde(default)]
+    pub reminders: Vec<SentReminder>, // Payment reminders sent, oldest first
+    #[serde(default)]
+    pub late_fees: Vec<LateFee>, // Late fees charged on this invoice, oldest first
+}
+
+// A payment received, or a credit applied, against an invoice
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum PaymentKind {
+    #[default]
+    Payment,
+    Credit,
+}
+
+impl PaymentKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PaymentKind::Payment => "Payment",
+            PaymentKind::Credit => "Credit",
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Payment {
+    pub date: DateTime<Local>,
+    pub amount: f64,
+    #[serde(default)]
+    pub kind: PaymentKind,
+    #[serde(default)]
+    pub reference: String,
+}
+
+// Record of an invoice being emailed
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SentEmail {
+    pub sent_at: DateTime<Local>,
+    pub to: String,
+    pub subject: String,
+}
+
+// Record of a payment reminder being emailed
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SentReminder {
+    pub sent_at: DateTime<Local>,
+    pub days_from_due: i64, // The reminder rule that was sent
+    pub to: String,
+}
+
+// A late fee charged on an overdue invoice, and the invoice it was billed on
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LateFee {
+    pub applied_at: DateTime<Local>,
+    pub amount: f64,
+    pub period_from: NaiveDate,
+    pub period_to: NaiveDate, // Interest is covered up to the end of this day
+    pub billed_on: String, // Invoice number carrying the fee line
+    pub description: String,
+}
+
+impl Invoice {
+    // Amount of the lines that carry no GST
+    pub fn gst_free_subtotal(&self) -> f64 {
+        self.items.iter().filter(|item| item.gst_free).fold(0.0, |sum, item| sum + item.amount)
+    }
+
+    // Sum of all payments and credits recorded against the invoice
+    pub fn amount_paid(&self) -> f64 {
+        self.payments.iter().fold(0.0, |sum, p| sum + p.amount) // fold rather than sum(), which gives -0.0 when empty
+    }
+
+    // "Paid", "Part Paid" or "Unpaid", as shown in invoice lists and exports
+    pub fn status_label(&self) -> &'static str {
+        if self.paid {
+            "Paid"
+        } else if self.amount_paid() > 0.0 {
+            "Part Paid"
+        } else {
+            "Unpaid"
+        }
+    }
+
+    // Unpaid after its due date. Due dates are whole days, so an invoice due today isn't overdue.
+    pub fn is_overdue(&self, today: NaiveDate) -> bool {
+        !self.paid && self.due_date.date_naive() < today
+    }
+
+    // Outstanding amount. Invoices marked paid are settled even without payment records.
+    pub fn balance_due(&self) -> f64 {
+        if self.paid {
+            0.0
+        } else {
+            (self.total - self.amount_paid()).max(0.0)
+        }
+    }
+
+    // Outstanding amount at the end of `date`.
+    // Invoices marked paid before payments were recorded count as settled on their due date.
+    pub fn balance_as_at(&self, date: NaiveDate) -> f64 {
+        if self.date.date_naive() > date {
+            return 0.0;
+        }
+        let paid_by_date: f64 = self.payments.iter()
+            .filter(|p| p.date.date_naive() <= date)
+            .map(|p| p.amount)
+            .sum();
+        let unrecorded = if self.paid { (self.total - self.amount_paid()).max(0.0) } else { 0.0 };
+        let settled_unrecorded = if self.due_date.date_naive() <= date { unrecorded } else { 0.0 };
+        (self.total - paid_by_date - settled_unrecorded).max(0.0)
+    }
 }
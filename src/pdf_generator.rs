@@ -1,144 +1,704 @@
 use printpdf::*;
-use crate::models::Invoice;
+use chrono::{DateTime, Local};
+use crate::models::{Company, Invoice};
+use crate::peppol;
+use crate::bank_details::{payment_instructions, BANK_NAME, ACCOUNT_NAME, BSB, ACCOUNT_NUMBER};
+use crate::renderer::{escape_html, format_money, InvoiceView};
+use crate::aged_receivables::{AgedReceivables, BUCKET_LABELS};
+use crate::statement::Statement;
 use crate::utils::wrap_text;
 use prettytable::{Table, Row, Cell, format};
-use std::io::BufWriter;
-use std::fs::File;
+use std::fs;
 use std::error::Error;
+use std::path::Path;
+use qrcode::{QrCode, Color as QrColor};
+
+// Height reserved at the bottom of the page for the tear-off remittance slip
+const REMITTANCE_HEIGHT: f32 = 60.0;
+
+// Optional extras for generated invoices
+#[derive(Clone, Default, PartialEq)]
+pub struct PdfOptions {
+    pub include_remittance: bool, // Tear-off remittance advice at the bottom of the page
+    pub include_payment_qr: bool, // Plain-text QR code with the bank transfer details and reference
+    pub pdf_a: bool, // Archival PDF/A-2b output with embedded fonts and XMP metadata matching the Info dictionary
+    pub embed_einvoice: bool, // Hybrid PDF/A-3 with the UBL e-invoice XML attached
+}
+
+impl PdfOptions {
+    // Both archival variants need embedded fonts
+    pub fn embeds_fonts(&self) -> bool {
+        self.pdf_a || self.embed_einvoice
+    }
+}
+
+// Fonts embedded in PDF/A output (licences alongside in assets/fonts). They are the same
+// Ubuntu Light and Hack that egui draws with, so the preview matches archival PDFs.
+const TEXT_FONT: &[u8] = include_bytes!("../assets/fonts/Ubuntu-Light.ttf");
+const MONO_FONT: &[u8] = include_bytes!("../assets/fonts/Hack-Regular.ttf");
+
+// Builds the text encoded in the payment QR code. Australian banks share no QR format for
+// transfers, so this is plain text: a phone camera shows the lines for the payer to copy
+// into their banking app, but nothing fills in the payment automatically.
+pub fn payment_qr_payload(invoice: &Invoice) -> String {
+    format!(
+        "Bank transfer to {}\nBSB: {}\nAccount: {}\nAmount: {}\nReference: {}",
+        ACCOUNT_NAME, BSB, ACCOUNT_NUMBER, format_money(invoice.balance_due()), invoice.invoice_number
+    )
+}
+
+// A single drawing operation on an A4 page, in millimetres from the bottom-left corner.
+// Invoices are laid out as a list of these so the same layout can be written to PDF or previewed on screen.
+#[derive(Clone)]
+pub enum DrawOp {
+    Text { x: f32, y: f32, text: String, mono: bool },
+    FilledRect { x: f32, y: f32, width: f32, height: f32 },
+    DashedLine { x1: f32, y1: f32, x2: f32, y2: f32 },
+}
+
+// The drawing operations for one page
+pub type PageLayout = Vec<DrawOp>;
+
+pub const PAGE_WIDTH_MM: f32 = 210.0;
+pub const PAGE_HEIGHT_MM: f32 = 297.0;
+pub const FONT_SIZE: f32 = 10.0; // Points
+
+// Advance widths of the standard Helvetica glyphs for ' ' to '~', in 1/1000 of the font size (Adobe AFM)
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556,
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556,
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556,
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+// How far a character advances the pen, in points, with the standard Helvetica and Courier
+// fonts, so the on-screen preview can place glyphs where a PDF viewer will
+pub fn standard_font_advance(c: char, mono: bool) -> f32 {
+    let units = match c {
+        _ if mono => 600, // Courier is fixed pitch
+        ' '..='~' => HELVETICA_WIDTHS[c as usize - ' ' as usize],
+        _ => 556,
+    };
+    f32::from(units) * FONT_SIZE / 1000.0
+}
+
+fn add_text(page: &mut PageLayout, text: &str, x: f32, y: f32, mono: bool) {
+    page.push(DrawOp::Text { x, y, text: text.to_string(), mono });
+}
+
+// Adds a QR code for `data` with its bottom-left corner at (x, y), `size` mm wide
+fn add_qr_code(page: &mut PageLayout, data: &str, x: f32, y: f32, size: f32) -> Result<(), Box<dyn Error>> {
+    let code = QrCode::new(data.as_bytes())?;
+    let width = code.width();
+    let module = size / width as f32;
+    for (idx, color) in code.to_colors().iter().enumerate() {
+        if *color == QrColor::Dark {
+            let col = (idx % width) as f32;
+            let row = (idx / width) as f32;
+            // QR rows run top to bottom, PDF y runs bottom to top
+            page.push(DrawOp::FilledRect {
+                x: x + col * module,
+                y: y + size - (row + 1.0) * module,
+                width: module,
+                height: module,
+            });
+        }
+    }
+    Ok(())
+}
+
+// Fonts used for the invoice text and the monospaced item table
+struct InvoiceFonts {
+    text: IndirectFontRef,
+    mono: IndirectFontRef,
+}
+
+// Application recorded as the creator of generated documents
+const PDF_CREATOR: &str = "jmatt_invoice";
+
+// Document properties, written to the Info dictionary and, for PDF/A, to the XMP packet
+struct DocumentMeta {
+    title: String,
+    author: String, // The company issuing the document
+    subject: String,
+    keywords: Vec<String>,
+    created: DateTime<Local>, // Also used as the modification date
+}
+
+impl DocumentMeta {
+    fn keywords(&self) -> String {
+        self.keywords.join(", ")
+    }
+}
+
+// Creates an A4 document with metadata and fonts set up for `options`
+fn create_document(
+    meta: &DocumentMeta,
+    options: &PdfOptions,
+) -> Result<(PdfDocumentReference, PdfLayerReference, InvoiceFonts), Box<dyn Error>> {
+    // Initialize PDF document (A4 size: 210mm x 297mm)
+    let (doc, page1, layer1) = PdfDocument::new(meta.title.clone(), Mm(PAGE_WIDTH_MM), Mm(PAGE_HEIGHT_MM), "Layer 1");
+
+    // Document metadata for the info dictionary; PDF/A output rewrites it with the XMP packet
+    let creation_date = OffsetDateTime::from_unix_timestamp(meta.created.timestamp())
+        .unwrap_or_else(|_| OffsetDateTime::now_utc());
+    let mut doc = doc
+        .with_author(meta.author.clone())
+        .with_subject(meta.subject.clone())
+        .with_keywords(meta.keywords.clone())
+        .with_creator(PDF_CREATOR)
+        .with_creation_date(creation_date)
+        .with_mod_date(creation_date)
+        .with_metadata_date(creation_date);
+    if options.embed_einvoice {
+        doc = doc.with_conformance(PdfConformance::A3_2012_PDF_1_7);
+    } else if options.pdf_a {
+        doc = doc.with_conformance(PdfConformance::A2B_2011_PDF_1_7);
+    }
+
+    let layer = doc.get_page(page1).get_layer(layer1);
+    // PDF/A does not allow the non-embedded standard fonts, so embed our own instead
+    let fonts = if options.embeds_fonts() {
+        InvoiceFonts {
+            text: doc.add_external_font(TEXT_FONT).map_err(|e| e.to_string())?,
+            mono: doc.add_external_font(MONO_FONT).map_err(|e| e.to_string())?,
+        }
+    } else {
+        InvoiceFonts {
+            text: doc.add_builtin_font(BuiltinFont::Helvetica).map_err(|e| e.to_string())?, // Use map_err for error conversion
+            mono: doc.add_builtin_font(BuiltinFont::Courier).map_err(|e| e.to_string())?,
+        }
+    };
+    Ok((doc, layer, fonts))
+}
+
+// Serialises the finished document, adding the metadata PDF/A-2b needs
+fn document_bytes(doc: PdfDocumentReference, meta: &DocumentMeta, options: &PdfOptions) -> Result<Vec<u8>, Box<dyn Error>> {
+    let bytes = doc.save_to_bytes().map_err(|e| e.to_string())?;
+    if !options.pdf_a || options.embed_einvoice {
+        return Ok(bytes); // Hybrid e-invoices get their metadata when the XML is attached
+    }
+    let mut doc = printpdf::lopdf::Document::load_mem(&bytes)?;
+    set_archival_metadata(&mut doc, meta, 2)?;
+    let mut out = Vec::new();
+    doc.save_to(&mut out)?;
+    Ok(out)
+}
+
+// Saves the finished document to `filename`
+fn save_document(doc: PdfDocumentReference, meta: &DocumentMeta, options: &PdfOptions, filename: &Path) -> Result<(), Box<dyn Error>> {
+    fs::write(filename, document_bytes(doc, meta, options)?)?;
+    Ok(())
+}
+
+// A PDF text string: PDFDocEncoding covers ASCII, anything else is written as UTF-16BE
+fn pdf_text(text: &str) -> printpdf::lopdf::Object {
+    use printpdf::lopdf::{Object, StringFormat};
+    if text.is_ascii() {
+        return Object::string_literal(text);
+    }
+    let mut bytes = vec![0xFE, 0xFF];
+    bytes.extend(text.encode_utf16().flat_map(|unit| unit.to_be_bytes()));
+    Object::String(bytes, StringFormat::Hexadecimal)
+}
+
+// XMP packet for PDF/A `part` (conformance level B), mirroring the Info dictionary written by
+// `set_archival_metadata` entry for entry: Title, Author, Subject, Keywords, Creator,
+// Producer, CreationDate and ModDate
+fn xmp_packet(meta: &DocumentMeta, part: u8) -> String {
+    let date = meta.created.format("%Y-%m-%dT%H:%M:%S%:z");
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+         <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+         <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+         <rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\" \
+         xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\" xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\" \
+         xmlns:pdfaid=\"http://www.aiim.org/pdfa/ns/id/\">\n\
+         <dc:format>application/pdf</dc:format>\n\
+         <dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{title}</rdf:li></rdf:Alt></dc:title>\n\
+         <dc:creator><rdf:Seq><rdf:li>{author}</rdf:li></rdf:Seq></dc:creator>\n\
+         <dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{subject}</rdf:li></rdf:Alt></dc:description>\n\
+         <pdf:Keywords>{keywords}</pdf:Keywords>\n\
+         <pdf:Producer>{creator}</pdf:Producer>\n\
+         <xmp:CreatorTool>{creator}</xmp:CreatorTool>\n\
+         <xmp:CreateDate>{date}</xmp:CreateDate>\n\
+         <xmp:ModifyDate>{date}</xmp:ModifyDate>\n\
+         <xmp:MetadataDate>{date}</xmp:MetadataDate>\n\
+         <pdfaid:part>{part}</pdfaid:part>\n\
+         <pdfaid:conformance>B</pdfaid:conformance>\n\
+         </rdf:Description>\n\
+         </rdf:RDF>\n\
+         </x:xmpmeta>\n\
+         <?xpacket end=\"w\"?>",
+        title = escape_html(&meta.title),
+        author = escape_html(&meta.author),
+        subject = escape_html(&meta.subject),
+        keywords = escape_html(&meta.keywords()),
+        creator = PDF_CREATOR,
+        date = date,
+        part = part,
+    )
+}
+
+// Replaces printpdf's Info dictionary (which carries PDF/X keys and no XMP for PDF/A) with one
+// built from `meta`, attaches the matching XMP packet as the catalog /Metadata and marks the
+// output intent as a PDF/A one
+fn set_archival_metadata(doc: &mut printpdf::lopdf::Document, meta: &DocumentMeta, part: u8) -> Result<(), Box<dyn Error>> {
+    use printpdf::lopdf::{dictionary, Object, Stream};
+
+    doc.compress(); // printpdf writes the embedded fonts, most of the file, uncompressed
+    let date = Object::string_literal(pdf_date(meta.created));
+    let info = dictionary! {
+        "Title" => pdf_text(&meta.title),
+        "Author" => pdf_text(&meta.author),
+        "Subject" => pdf_text(&meta.subject),
+        "Keywords" => pdf_text(&meta.keywords()),
+        "Creator" => Object::string_literal(PDF_CREATOR),
+        "Producer" => Object::string_literal(PDF_CREATOR),
+        "CreationDate" => date.clone(),
+        "ModDate" => date,
+    };
+    match doc.trailer.get(b"Info").and_then(Object::as_reference) {
+        Ok(info_id) => {
+            doc.objects.insert(info_id, Object::Dictionary(info));
+        }
+        Err(_) => {
+            let info_id = doc.add_object(info);
+            doc.trailer.set("Info", info_id);
+        }
+    }
+
+    let mut metadata = Stream::new(
+        dictionary! { "Type" => "Metadata", "Subtype" => "XML" },
+        xmp_packet(meta, part).into_bytes(),
+    );
+    metadata.allows_compression = false; // PDF/A wants the packet readable without decoding
+    let metadata_id = doc.add_object(metadata);
+
+    let catalog = doc.catalog_mut()?;
+    catalog.set("Metadata", metadata_id);
+    if let Ok(Object::Array(intents)) = catalog.get_mut(b"OutputIntents") {
+        for intent in intents {
+            if let Object::Dictionary(intent) = intent {
+                intent.set("S", Object::Name(b"GTS_PDFA1".to_vec()));
+            }
+        }
+    }
+    Ok(())
+}
 
 // Updated function signature to accept filename and return Result
 pub fn generate_pdf(
-    invoice: &Invoice, 
-    company_name: &str, 
-    company_abn: &str, 
-    company_address: &str, 
-    company_phone: &str,
-    filename: &str // Added filename parameter
+    invoice: &Invoice,
+    company: &Company,
+    filename: &Path, // Added filename parameter
+    options: &PdfOptions,
 ) -> Result<(), Box<dyn Error>> { // Return Result
-    // Initialize PDF document (A4 size: 210mm x 297mm)
-    let (doc, page1, layer1) = PdfDocument::new(
-        format!("Invoice #{}", invoice.invoice_number),
-        Mm(210.0),
-        Mm(297.0),
-        "Layer 1",
-    );
-    let layer = doc.get_page(page1).get_layer(layer1);
-    let helvetica_font = doc.add_builtin_font(BuiltinFont::Helvetica).map_err(|e| e.to_string())?; // Use map_err for error conversion
-    let courier_font = doc.add_builtin_font(BuiltinFont::Courier).map_err(|e| e.to_string())?;
-    let font_size = 10.0;
+    let bytes = generate_pdf_bytes(invoice, company, options)?;
+    // Save PDF using the provided filename
+    fs::write(filename, bytes)?;
+    Ok(())
+}
+
+// Renders an invoice to PDF bytes in memory (used for ZIP archives and attachments)
+pub fn generate_pdf_bytes(
+    invoice: &Invoice,
+    company: &Company,
+    options: &PdfOptions,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let meta = DocumentMeta {
+        title: format!("Invoice #{}", invoice.invoice_number),
+        author: company.name.clone(),
+        subject: invoice.invoice_number.clone(),
+        keywords: vec![
+            "Invoice".to_string(),
+            invoice.invoice_number.clone(),
+            invoice.customer.name.clone(),
+            invoice.customer.code.clone(),
+        ],
+        created: invoice.date,
+    };
+    let (doc, layer, fonts) = create_document(&meta, options)?;
+    let pages = layout_invoice(invoice, &company.name, &company.abn, &company.address, &company.phone, options)?;
+    write_pages(&doc, &layer, &fonts, &pages);
+
+    let bytes = document_bytes(doc, &meta, options)?;
+    if options.embed_einvoice {
+        let attachment = einvoice_attachment(invoice, company)?;
+        return attach_xml_files(&bytes, &[attachment], &meta);
+    }
+    Ok(bytes)
+}
+
+// File name and validated UBL XML for an invoice's embedded e-invoice
+fn einvoice_attachment(invoice: &Invoice, company: &Company) -> Result<(String, String), Box<dyn Error>> {
+    let xml = peppol::export_invoice(invoice, company)
+        .map_err(|errors| format!("E-invoice for #{} failed validation: {}", invoice.invoice_number, errors.join("; ")))?;
+    Ok((format!("Invoice-{}-ubl.xml", invoice.invoice_number), xml))
+}
+
+// Date in PDF string format, e.g. D:20240131093000+10'00'
+fn pdf_date(date: DateTime<Local>) -> String {
+    let offset = date.format("%z").to_string(); // +1000
+    format!("D:{}{}'{}'", date.format("%Y%m%d%H%M%S"), &offset[..3], &offset[3..])
+}
+
+// Turns a saved PDF/A-3 document into a hybrid e-invoice: each XML file is embedded as an
+// associated file (AFRelationship /Alternative, i.e. the structured form of the visual invoice),
+// listed in the EmbeddedFiles name tree and the catalog /AF array, and the PDF/A-3B Info
+// dictionary and XMP packet are written from `meta`.
+fn attach_xml_files(pdf: &[u8], files: &[(String, String)], meta: &DocumentMeta) -> Result<Vec<u8>, Box<dyn Error>> {
+    use printpdf::lopdf::{dictionary, Document, Object, Stream};
+
+    let mut doc = Document::load_mem(pdf)?;
+    let mut names = Vec::new();
+    let mut associated = Vec::new();
+    for (name, xml) in files {
+        let mut file_stream = Stream::new(
+            dictionary! {
+                "Type" => "EmbeddedFile",
+                "Subtype" => Object::Name(b"text/xml".to_vec()),
+                "Params" => dictionary! {
+                    "Size" => xml.len() as i64,
+                    "ModDate" => Object::string_literal(pdf_date(meta.created)),
+                },
+            },
+            xml.as_bytes().to_vec(),
+        );
+        file_stream.compress()?;
+        let file_id = doc.add_object(file_stream);
+        let spec_id = doc.add_object(dictionary! {
+            "Type" => "Filespec",
+            "F" => Object::string_literal(name.as_str()),
+            "UF" => Object::string_literal(name.as_str()),
+            "Desc" => Object::string_literal("Peppol A-NZ PINT UBL e-invoice"),
+            "AFRelationship" => "Alternative",
+            "EF" => dictionary! { "F" => file_id, "UF" => file_id },
+        });
+        names.push(Object::string_literal(name.as_str()));
+        names.push(spec_id.into());
+        associated.push(Object::from(spec_id));
+    }
+
+    set_archival_metadata(&mut doc, meta, 3)?;
+    let catalog = doc.catalog_mut()?;
+    catalog.set("AF", associated);
+    catalog.set("Names", dictionary! { "EmbeddedFiles" => dictionary! { "Names" => names } });
+
+    let mut out = Vec::new();
+    doc.save_to(&mut out)?;
+    Ok(out)
+}
+
+// Writes several invoices into one PDF, each starting on a new page
+pub fn generate_merged_pdf(
+    invoices: &[Invoice],
+    company: &Company,
+    filename: &Path,
+    options: &PdfOptions,
+) -> Result<(), Box<dyn Error>> {
+    if invoices.is_empty() {
+        return Err("No invoices to export.".into());
+    }
+    let numbers: Vec<String> = invoices.iter().map(|inv| inv.invoice_number.clone()).collect();
+    let title = format!("Invoices {} - {}", numbers[0], numbers[numbers.len() - 1]);
+    let meta = DocumentMeta {
+        title: title.clone(),
+        author: company.name.clone(),
+        subject: numbers.join(", "),
+        keywords: std::iter::once("Invoice".to_string()).chain(numbers.iter().cloned()).collect(),
+        created: Local::now(),
+    };
+    let (doc, first_layer, fonts) = create_document(&meta, options)?;
+
+    let mut pages = Vec::new();
+    for invoice in invoices {
+        pages.extend(layout_invoice(invoice, &company.name, &company.abn, &company.address, &company.phone, options)?);
+    }
+    write_pages(&doc, &first_layer, &fonts, &pages);
+
+    if options.embed_einvoice {
+        let attachments = invoices.iter()
+            .map(|invoice| einvoice_attachment(invoice, company))
+            .collect::<Result<Vec<_>, _>>()?;
+        let bytes = document_bytes(doc, &meta, options)?;
+        fs::write(filename, attach_xml_files(&bytes, &attachments, &meta)?)?;
+        return Ok(());
+    }
+    save_document(doc, &meta, options, filename)
+}
+
+// Writes page layouts into the document, the first one on `first_layer`'s page
+fn write_pages(doc: &PdfDocumentReference, first_layer: &PdfLayerReference, fonts: &InvoiceFonts, pages: &[PageLayout]) {
+    for (idx, page) in pages.iter().enumerate() {
+        let layer = if idx == 0 {
+            first_layer.clone()
+        } else {
+            let (page_index, layer_index) = doc.add_page(Mm(PAGE_WIDTH_MM), Mm(PAGE_HEIGHT_MM), "Layer 1");
+            doc.get_page(page_index).get_layer(layer_index)
+        };
+        // Grey rather than RGB black: PDF/A only allows DeviceRGB with an RGB output intent
+        layer.set_fill_color(printpdf::Color::Greyscale(Greyscale::new(0.0, None)));
+        for op in page {
+            match op {
+                DrawOp::Text { x, y, text, mono } => {
+                    let font = if *mono { &fonts.mono } else { &fonts.text };
+                    layer.use_text(text.as_str(), FONT_SIZE, Mm(*x), Mm(*y), font);
+                }
+                DrawOp::FilledRect { x, y, width, height } => {
+                    layer.add_rect(Rect::new(Mm(*x), Mm(*y), Mm(x + width), Mm(y + height)));
+                }
+                DrawOp::DashedLine { x1, y1, x2, y2 } => {
+                    layer.set_line_dash_pattern(LineDashPattern { dash_1: Some(3), gap_1: Some(2), ..Default::default() });
+                    layer.add_line(Line {
+                        points: vec![
+                            (Point::new(Mm(*x1), Mm(*y1)), false),
+                            (Point::new(Mm(*x2), Mm(*y2)), false),
+                        ],
+                        is_closed: false,
+                    });
+                    layer.set_line_dash_pattern(LineDashPattern::default());
+                }
+            }
+        }
+    }
+}
+
+// Lays out a single invoice. Usually one page, plus a second one when the
+// remittance slip does not fit below the invoice body.
+pub fn layout_invoice(
+    invoice: &Invoice,
+    company_name: &str,
+    company_abn: &str,
+    company_address: &str,
+    company_phone: &str,
+    options: &PdfOptions,
+) -> Result<Vec<PageLayout>, Box<dyn Error>> {
+    let mut layer = PageLayout::new();
     let line_height = 4.23; // ~12pt for 10pt font (1pt = 0.3527mm)
     let mut y_pos = 280.0; // Start near top of page
 
-    // Helper to add text at specific positions with specified font
-    let add_text = |layer: &PdfLayerReference, text: &str, x: Mm, y: f32, font: &IndirectFontRef| {
-        layer.use_text(text, font_size, x, Mm(y), font);
-    };
+    let view = InvoiceView::new(invoice, company_name, company_abn, company_address, company_phone);
 
     // Company Header (Helvetica)
-    add_text(&layer, company_name, Mm(15.0), y_pos, &helvetica_font);
-    y_pos -= line_height;
-    add_text(&layer, &format!("A.B.N. {}", company_abn), Mm(15.0), y_pos, &helvetica_font);
-    y_pos -= line_height;
-    add_text(&layer, company_address, Mm(15.0), y_pos, &helvetica_font);
-    y_pos -= line_height;
-    add_text(&layer, &format!("Ph: {}", company_phone), Mm(15.0), y_pos, &helvetica_font);
-    y_pos -= line_height;
-    add_text(&layer, &format!("Invoice #{}", invoice.invoice_number), Mm(15.0), y_pos, &helvetica_font);
+    for line in &view.company_lines {
+        add_text(&mut layer, line, 15.0, y_pos, false);
+        y_pos -= line_height;
+    }
+    add_text(&mut layer, &view.title, 15.0, y_pos, false);
     y_pos -= line_height;
-    add_text(&layer, &format!("Date: {}", invoice.date.format("%b %d, %Y")), Mm(15.0), y_pos, &helvetica_font);
+    add_text(&mut layer, &format!("Date: {}", view.issue_date), 15.0, y_pos, false);
     y_pos -= 2.0 * line_height; // Extra spacing
 
     // Bill To and Payment Terms
     let bill_to_y = y_pos;
-    add_text(&layer, "Bill To:", Mm(15.0), bill_to_y, &helvetica_font);
+    add_text(&mut layer, "Bill To:", 15.0, bill_to_y, false);
     y_pos -= line_height;
-    // Corrected: Use string literal "\n\n" for split
-    for line in invoice.customer.name.split("\n\n") {
-        add_text(&layer, line, Mm(15.0), y_pos, &helvetica_font);
+    for line in &view.bill_to_lines {
+        add_text(&mut layer, line, 15.0, y_pos, false);
         y_pos -= line_height;
     }
-    add_text(&layer, &invoice.customer.address, Mm(15.0), y_pos, &helvetica_font);
-    y_pos -= line_height;
-    add_text(&layer, &format!("Phone: {}", invoice.customer.phone), Mm(15.0), y_pos, &helvetica_font);
-    y_pos -= line_height;
 
-    // Corrected: Use string literal "\n\n" for split
-    let contact_lines: Vec<&str> = invoice.customer.contact_person.split("\n\n").collect();
-    // Corrected: Use string literal "\n\n" for split
-    let email_lines: Vec<&str> = invoice.customer.email.split("\n\n").collect();
+    let bill_to_y_end = y_pos;
+    y_pos = bill_to_y;
+    for line in &view.terms_lines {
+        add_text(&mut layer, line, 150.0, y_pos, false);
+        y_pos -= line_height;
+    }
+    y_pos += line_height;
+
+    y_pos = bill_to_y_end.min(y_pos);
+    y_pos -= 2.0 * line_height;
+    add_text(&mut layer, &format!("(Current Date: {})", view.issue_date), 15.0, y_pos, false);
+    y_pos -= 2.0 * line_height;
+
+    // Items table (Courier)
+    for line in view.items_table().lines() {
+        add_text(&mut layer, line, 15.0, y_pos, true);
+        y_pos -= line_height;
+    }
 
-    let mut attn_line = String::from("Attn - ");
-    if !contact_lines.is_empty() {
-        attn_line.push_str(contact_lines[0]);
+    y_pos -= 3.0 * line_height;
+    for (label, amount) in &view.gst_lines {
+        add_text(&mut layer, &format!("{}:", label), 73.0, y_pos, false);
+        add_text(&mut layer, amount, 87.0, y_pos, true);
+        y_pos -= line_height;
     }
-    let wrapped_attn_lines = wrap_text(&attn_line, 80);
-    for line in wrapped_attn_lines {
-        add_text(&layer, &line, Mm(15.0), y_pos, &helvetica_font);
+    add_text(&mut layer, "Total:", 73.0, y_pos, false);
+    add_text(&mut layer, &view.total, 87.0, y_pos, true);
+    if let Some(paid) = &view.amount_paid {
         y_pos -= line_height;
+        add_text(&mut layer, "Paid:", 73.0, y_pos, false);
+        add_text(&mut layer, paid, 87.0, y_pos, true);
+        y_pos -= line_height;
+        add_text(&mut layer, "Balance Due:", 62.0, y_pos, false);
+        add_text(&mut layer, &view.balance_due, 87.0, y_pos, true);
     }
 
-    add_text(&layer, &format!("Contact Phone: {}", invoice.customer.contact_phone), Mm(15.0), y_pos, &helvetica_font);
+    y_pos -= 2.0 * line_height;
+
+    // Notes
+    add_text(&mut layer, "Notes:", 15.0, y_pos, false);
     y_pos -= line_height;
+    add_text(&mut layer, &view.notes, 15.0, y_pos, false);
+    y_pos -= 2.0 * line_height;
 
-    if !email_lines.is_empty() {
-        let email_line = format!("Email: {}", email_lines[0]);
-        let wrapped_email_lines = wrap_text(&email_line, 80);
-        for line in wrapped_email_lines {
-            add_text(&layer, &line, Mm(15.0), y_pos, &helvetica_font);
-            y_pos -= line_height;
-        }
+    // Payment Instructions
+    let instructions_top = y_pos;
+    for line in &view.payment_lines {
+        add_text(&mut layer, line, 15.0, y_pos, false);
+        y_pos -= line_height;
     }
+    y_pos += line_height;
 
-    for i in 1..contact_lines.len() {
-        add_text(&layer, &format!("       {}", contact_lines[i]), Mm(15.0), y_pos, &helvetica_font);
-        y_pos -= line_height;
+    // Payment QR code beside the payment instructions (skipped here when the slip carries it)
+    if options.include_payment_qr && !options.include_remittance {
+        let qr_size = 30.0;
+        add_qr_code(&mut layer, &payment_qr_payload(invoice), 165.0, instructions_top - qr_size + line_height, qr_size)?;
+        add_text(&mut layer, "Payment details", 168.0, instructions_top - qr_size - line_height, false);
+        y_pos = y_pos.min(instructions_top - qr_size - line_height);
     }
 
-    for i in 1..email_lines.len() {
-        add_text(&layer, &format!("       {}", email_lines[i]), Mm(15.0), y_pos, &helvetica_font);
-        y_pos -= line_height;
+    let mut pages = vec![layer];
+
+    // Tear-off remittance slip at the bottom of the page
+    if options.include_remittance {
+        // Move the slip to its own page if the invoice body already reaches the slip area
+        if y_pos - line_height < REMITTANCE_HEIGHT {
+            pages.push(PageLayout::new());
+        }
+        let slip_layer = pages.last_mut().expect("at least one page");
+
+        // Dashed cut line across the page
+        slip_layer.push(DrawOp::DashedLine { x1: 10.0, y1: REMITTANCE_HEIGHT, x2: 200.0, y2: REMITTANCE_HEIGHT });
+
+        let mut slip_y = REMITTANCE_HEIGHT - 2.0 * line_height;
+        add_text(slip_layer, "REMITTANCE ADVICE - please detach and return with your payment", 15.0, slip_y, false);
+        slip_y -= 2.0 * line_height;
+        add_text(slip_layer, &format!("From: {}", invoice.customer.name), 15.0, slip_y, false);
+        slip_y -= line_height;
+        add_text(slip_layer, &format!("Invoice #{}", invoice.invoice_number), 15.0, slip_y, false);
+        slip_y -= line_height;
+        add_text(slip_layer, &format!("Amount Due: {}", format_money(invoice.balance_due())), 15.0, slip_y, false);
+        slip_y -= line_height;
+        add_text(slip_layer, &format!("Due Date: {}", invoice.due_date.format("%b %d, %Y")), 15.0, slip_y, false);
+        slip_y -= 2.0 * line_height;
+        add_text(slip_layer, &format!("Pay to: {} ({})", company_name, BANK_NAME), 15.0, slip_y, false);
+        slip_y -= line_height;
+        add_text(slip_layer, &format!("Account Name - {}", ACCOUNT_NAME), 15.0, slip_y, false);
+        slip_y -= line_height;
+        add_text(slip_layer, &format!("BSB - {}   Acct Number - {}", BSB, ACCOUNT_NUMBER), 15.0, slip_y, false);
+        slip_y -= line_height;
+        add_text(slip_layer, &format!("Reference - {}", invoice.invoice_number), 15.0, slip_y, false);
+
+        if options.include_payment_qr {
+            let qr_size = 40.0;
+            add_qr_code(slip_layer, &payment_qr_payload(invoice), 155.0, REMITTANCE_HEIGHT - qr_size - 5.0, qr_size)?;
+        }
     }
 
-    let bill_to_y_end = y_pos;
-    y_pos = bill_to_y;
-    add_text(&layer, "Payment Terms: Net 30 Days", Mm(150.0), y_pos, &helvetica_font);
+    Ok(pages)
+}
+
+// Writes a customer account statement to `filename`
+pub fn generate_statement_pdf(
+    statement: &Statement,
+    company_name: &str,
+    company_abn: &str,
+    company_address: &str,
+    company_phone: &str,
+    filename: &str,
+    options: &PdfOptions,
+) -> Result<(), Box<dyn Error>> {
+    let meta = DocumentMeta {
+        title: format!("Statement - {}", statement.customer.name),
+        author: company_name.to_string(),
+        subject: format!("Statement {} to {}", statement.from_date, statement.to_date),
+        keywords: vec!["Statement".to_string(), statement.customer.name.clone(), statement.customer.code.clone()],
+        created: Local::now(),
+    };
+    let (doc, first_layer, fonts) = create_document(&meta, options)?;
+    let font_size = 10.0;
+    let line_height = 4.23;
+    let mut layer = first_layer;
+    let mut y_pos = 280.0;
+
+    // Adds a line of text, starting a new page when the current one is full
+    let mut add_line = |text: &str, x: f32, font: &IndirectFontRef, y_pos: &mut f32| {
+        if *y_pos < 20.0 {
+            let (page, new_layer) = doc.add_page(Mm(210.0), Mm(297.0), "Layer 1");
+            layer = doc.get_page(page).get_layer(new_layer);
+            *y_pos = 280.0;
+        }
+        layer.use_text(text, font_size, Mm(x), Mm(*y_pos), font);
+    };
+
+    // Company header
+    for text in [
+        company_name.to_string(),
+        format!("A.B.N. {}", company_abn),
+        company_address.to_string(),
+        format!("Ph: {}", company_phone),
+    ] {
+        add_line(&text, 15.0, &fonts.text, &mut y_pos);
+        y_pos -= line_height;
+    }
     y_pos -= line_height;
-    add_text(&layer, &format!("Due Date: {}", invoice.due_date.format("%b %d, %Y")), Mm(150.0), y_pos, &helvetica_font);
+    add_line("STATEMENT OF ACCOUNT", 15.0, &fonts.text, &mut y_pos);
     y_pos -= line_height;
-    add_text(&layer, &format!("Balance Due: AU ${:.2}", invoice.total), Mm(150.0), y_pos, &helvetica_font);
-
-    y_pos = bill_to_y_end.min(y_pos);
-    y_pos -= 2.0 * line_height;
-    add_text(&layer, &format!("(Current Date: {})", invoice.date.format("%b %d, %Y")), Mm(15.0), y_pos, &helvetica_font);
+    add_line(
+        &format!("Period: {} to {}", statement.from_date.format("%b %d, %Y"), statement.to_date.format("%b %d, %Y")),
+        15.0, &fonts.text, &mut y_pos,
+    );
     y_pos -= 2.0 * line_height;
 
-    // Create the table
+    // Customer details
+    add_line("Account:", 15.0, &fonts.text, &mut y_pos);
+    y_pos -= line_height;
+    for text in [
+        format!("{} ({})", statement.customer.name, statement.customer.code),
+        statement.customer.address.clone(),
+        format!("Attn - {}", statement.customer.contact_person),
+    ] {
+        add_line(&text, 15.0, &fonts.text, &mut y_pos);
+        y_pos -= line_height;
+    }
+    y_pos -= line_height;
+
+    // Transactions table
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_CLEAN);
     table.set_titles(Row::new(vec![
-        Cell::new("#"),
-        Cell::new("Item"),
-        Cell::new("Qty"),
-        Cell::new("Rate"),
-        Cell::new("Amount"),
+        Cell::new("Date"),
+        Cell::new("Description"),
+        Cell::new("Debit"),
+        Cell::new("Credit"),
+        Cell::new("Balance"),
     ]));
-
-    for (idx, item) in invoice.items.iter().enumerate() {
-        let line_num = idx + 1;
-        let description_lines = wrap_text(&item.description, 50);
-        for (i, line) in description_lines.iter().enumerate() {
+    table.add_row(Row::new(vec![
+        Cell::new(&statement.from_date.format("%d/%m/%Y").to_string()),
+        Cell::new("Opening balance"),
+        Cell::new(""),
+        Cell::new(""),
+        Cell::new(&format!("{:>9.2}", statement.opening_balance)),
+    ]));
+    for line in &statement.lines {
+        let amount = |value: f64| if value > 0.0 { format!("{:>9.2}", value) } else { String::new() };
+        for (i, description) in wrap_text(&line.description, 40).iter().enumerate() {
             if i == 0 {
                 table.add_row(Row::new(vec![
-                    Cell::new(&format!("{:>3}", line_num)),
-                    Cell::new(line),
-                    Cell::new(&format!("{:>6}", item.quantity)),
-                    Cell::new(&format!("AU ${:>6.2}", item.rate)),
-                    Cell::new(&format!("AU ${:>6.2}", item.amount)),
+                    Cell::new(&line.date.format("%d/%m/%Y").to_string()),
+                    Cell::new(description),
+                    Cell::new(&amount(line.debit)),
+                    Cell::new(&amount(line.credit)),
+                    Cell::new(&format!("{:>9.2}", line.balance)),
                 ]));
             } else {
                 table.add_row(Row::new(vec![
                     Cell::new(""),
-                    Cell::new(line),
+                    Cell::new(description),
                     Cell::new(""),
                     Cell::new(""),
                     Cell::new(""),
@@ -146,44 +706,107 @@ pub fn generate_pdf(
             }
         }
     }
+    for text in table.to_string().lines() {
+        add_line(text, 15.0, &fonts.mono, &mut y_pos);
+        y_pos -= line_height;
+    }
+    y_pos -= line_height;
+    add_line(&format!("Closing Balance: {}", format_money(statement.closing_balance)), 15.0, &fonts.text, &mut y_pos);
+    y_pos -= 2.0 * line_height;
 
-    let table_string = table.to_string();
-    let table_lines: Vec<&str> = table_string.lines().collect();
+    // Ageing summary
+    let mut ageing = Table::new();
+    ageing.set_format(*format::consts::FORMAT_CLEAN);
+    ageing.set_titles(Row::new(vec![
+        Cell::new("Current"),
+        Cell::new("30 Days"),
+        Cell::new("60 Days"),
+        Cell::new("90+ Days"),
+        Cell::new("Total Due"),
+    ]));
+    ageing.add_row(Row::new(vec![
+        Cell::new(&format!("{:>9.2}", statement.ageing.current)),
+        Cell::new(&format!("{:>9.2}", statement.ageing.days_30)),
+        Cell::new(&format!("{:>9.2}", statement.ageing.days_60)),
+        Cell::new(&format!("{:>9.2}", statement.ageing.days_90_plus)),
+        Cell::new(&format!("{:>9.2}", statement.closing_balance)),
+    ]));
+    for text in ageing.to_string().lines() {
+        add_line(text, 15.0, &fonts.mono, &mut y_pos);
+        y_pos -= line_height;
+    }
+    y_pos -= line_height;
 
-    for line in table_lines {
-        add_text(&layer, line, Mm(15.0), y_pos, &courier_font);
+    // Payment instructions
+    for text in payment_instructions().into_iter().chain([format!("Reference - {}", statement.customer.code)]) {
+        add_line(&text, 15.0, &fonts.text, &mut y_pos);
         y_pos -= line_height;
     }
 
-    y_pos -= 3.0 * line_height;
-    add_text(&layer, "Total:", Mm(73.0), y_pos, &helvetica_font);
-    add_text(&layer, &format!("AU ${:.2}", invoice.total), Mm(87.0), y_pos, &courier_font);
+    save_document(doc, &meta, options, Path::new(filename))
+}
 
-    y_pos -= 2.0 * line_height;
+// Writes the aged receivables report to `filename`
+pub fn generate_aged_receivables_pdf(
+    report: &AgedReceivables,
+    company_name: &str,
+    company_abn: &str,
+    filename: &str,
+    options: &PdfOptions,
+) -> Result<(), Box<dyn Error>> {
+    let meta = DocumentMeta {
+        title: format!("Aged Receivables - {}", report.as_at),
+        author: company_name.to_string(),
+        subject: format!("Aged receivables as at {}", report.as_at),
+        keywords: vec!["Aged Receivables".to_string(), company_name.to_string()],
+        created: Local::now(),
+    };
+    let (doc, first_layer, fonts) = create_document(&meta, options)?;
+    let font_size = 8.5; // Smaller than statements so the six amount columns fit the page
+    let line_height = 3.8;
+    let mut layer = first_layer;
+    let mut y_pos = 280.0;
 
-    // Notes
-    add_text(&layer, "Notes:", Mm(15.0), y_pos, &helvetica_font);
-    y_pos -= line_height;
-    add_text(&layer, &invoice.notes, Mm(15.0), y_pos, &helvetica_font);
-    y_pos -= 2.0 * line_height;
+    let mut add_line = |text: &str, font: &IndirectFontRef, y_pos: &mut f32| {
+        if *y_pos < 20.0 {
+            let (page, new_layer) = doc.add_page(Mm(210.0), Mm(297.0), "Layer 1");
+            layer = doc.get_page(page).get_layer(new_layer);
+            *y_pos = 280.0;
+        }
+        layer.use_text(text, font_size, Mm(15.0), Mm(*y_pos), font);
+        *y_pos -= line_height;
+    };
 
-    // Payment Instructions
-    add_text(&layer, "Please Pay to by bank transfer to our bank account Commonwealth Bank Tuggeranong.", Mm(15.0), y_pos, &helvetica_font);
-    y_pos -= line_height;
-    add_text(&layer, "Account Name - James Matthews", Mm(15.0), y_pos, &helvetica_font);
-    y_pos -= line_height;
-    add_text(&layer, "BSB - 062692", Mm(15.0), y_pos, &helvetica_font);
+    add_line(company_name, &fonts.text, &mut y_pos);
+    add_line(&format!("A.B.N. {}", company_abn), &fonts.text, &mut y_pos);
     y_pos -= line_height;
-    add_text(&layer, "Acct Number - 33455315", Mm(15.0), y_pos, &helvetica_font);
+    add_line("AGED RECEIVABLES", &fonts.text, &mut y_pos);
+    add_line(&format!("As at {} (days past due date)", report.as_at.format("%b %d, %Y")), &fonts.text, &mut y_pos);
     y_pos -= line_height;
-    add_text(&layer, "Terms:", Mm(15.0), y_pos, &helvetica_font);
-    y_pos -= line_height;
-    add_text(&layer, "Strictly 30 Days Net Full Payment Please", Mm(15.0), y_pos, &helvetica_font);
 
-    // Save PDF using the provided filename
-    let file = File::create(filename)?; // Use filename parameter and propagate error
-    doc.save(&mut BufWriter::new(file)).map_err(|e| e.to_string())?; // Propagate save error
-    
-    Ok(())
-}
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_CLEAN);
+    let mut titles = vec![Cell::new("Customer")];
+    titles.extend(BUCKET_LABELS.iter().map(|label| Cell::new(label)));
+    titles.push(Cell::new("Total"));
+    table.set_titles(Row::new(titles));
+    let amounts_row = |name: &str, buckets: &[f64; 5], total: f64| {
+        let mut cells = vec![Cell::new(&name.chars().take(24).collect::<String>())];
+        cells.extend(buckets.iter().map(|amount| Cell::new(&format!("{:>10.2}", amount))));
+        cells.push(Cell::new(&format!("{:>10.2}", total)));
+        Row::new(cells)
+    };
+    for row in &report.rows {
+        table.add_row(amounts_row(&format!("{} ({})", row.customer_name, row.customer_code), &row.buckets, row.total));
+    }
+    table.add_row(amounts_row("Total", &report.totals, report.total));
+    for text in table.to_string().lines() {
+        add_line(text, &fonts.mono, &mut y_pos);
+    }
+    if report.rows.is_empty() {
+        y_pos -= line_height;
+        add_line("Nothing is owing.", &fonts.text, &mut y_pos);
+    }
 
+    save_document(doc, &meta, options, Path::new(filename))
+}
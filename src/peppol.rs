@@ -0,0 +1,755 @@
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use crate::models::{Company, Invoice, GST_RATE};
+use crate::bank_details::{ACCOUNT_NAME, ACCOUNT_NUMBER, BSB};
+use crate::renderer::escape_html;
+
+// Peppol PINT A-NZ billing profile identifiers
+pub const CUSTOMIZATION_ID: &str = "urn:peppol:pint:billing-1@aunz-1";
+pub const PROFILE_ID: &str = "urn:peppol:bis:billing";
+// Participant identifier scheme for Australian Business Numbers
+pub const ABN_SCHEME: &str = "0151";
+// UNCL4461 code for a credit transfer (direct bank deposit)
+pub const PAYMENT_MEANS_CREDIT_TRANSFER: &str = "30";
+
+const NS_INVOICE: &str = "urn:oasis:names:specification:ubl:schema:xsd:Invoice-2";
+const NS_CAC: &str = "urn:oasis:names:specification:ubl:schema:xsd:CommonAggregateComponents-2";
+const NS_CBC: &str = "urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UblParty {
+    pub endpoint_id: String, // ABN, used with scheme 0151
+    pub name: String,
+    pub street: String,
+    pub country: String,
+    pub gst_registered: bool, // Adds a PartyTaxScheme with the ABN as GST identifier
+    pub contact_name: String,
+    pub telephone: String,
+    pub email: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UblLine {
+    pub id: String,
+    pub description: String,
+    pub quantity: f64,
+    pub unit_price: f64,
+    pub line_amount: f64,
+    pub tax_category: String,
+    pub tax_percent: Option<f64>,
+}
+
+// GST totals for one tax category
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UblTaxSubtotal {
+    pub category: String, // S = GST 10%, Z = GST-free, O = not subject to GST
+    pub percent: Option<f64>,
+    pub exemption_reason: String,
+    pub taxable_amount: f64,
+    pub tax_amount: f64,
+}
+
+// The parts of a UBL 2.1 invoice this application produces and reads back
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UblInvoice {
+    pub customization_id: String,
+    pub profile_id: String,
+    pub id: String,
+    pub issue_date: String, // YYYY-MM-DD
+    pub due_date: String, // YYYY-MM-DD
+    pub note: String,
+    pub currency: String,
+    pub buyer_reference: String,
+    pub supplier: UblParty,
+    pub customer: UblParty,
+    pub payment_means_code: String,
+    pub payment_id: String,
+    pub account_number: String,
+    pub account_name: String,
+    pub bsb: String,
+    pub payment_terms: String,
+    pub tax_subtotals: Vec<UblTaxSubtotal>, // One per category used by the lines
+    pub tax_amount: f64,
+    pub line_extension_amount: f64,
+    pub tax_exclusive_amount: f64,
+    pub tax_inclusive_amount: f64,
+    pub prepaid_amount: f64,
+    pub payable_amount: f64,
+    pub lines: Vec<UblLine>,
+}
+
+// Adding 0.0 turns -0.0 (e.g. an empty sum) into 0.0 so it never prints as "-0.00"
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0 + 0.0
+}
+
+// The category, rate and exemption reason for a line
+fn line_category(gst_registered: bool, gst_free: bool) -> (&'static str, Option<f64>, &'static str) {
+    match (gst_registered, gst_free) {
+        (false, _) => ("O", None, "Not subject to GST"),
+        (true, true) => ("Z", Some(0.0), ""),
+        (true, false) => ("S", Some(round2(GST_RATE * 100.0)), ""),
+    }
+}
+
+// Maps an invoice and the company/customer details onto the UBL structure.
+// Lines carrying GST are standard rated (S) and the rest GST-free (Z); when the company is
+// not registered for GST and the invoice charges none, every line is not subject to GST (O).
+pub fn build_ubl_invoice(invoice: &Invoice, company: &Company) -> UblInvoice {
+    let gst_registered = company.gst_registered || invoice.gst > 0.0;
+    let lines = invoice.items.iter().enumerate().map(|(idx, item)| {
+        let (category, percent, _) = line_category(gst_registered, item.gst_free);
+        UblLine {
+            id: (idx + 1).to_string(),
+            description: item.description.clone(),
+            quantity: item.quantity as f64,
+            unit_price: item.rate, // Unrounded, so quantity x price gives the line amount
+            line_amount: round2(item.amount),
+            tax_category: category.to_string(),
+            tax_percent: percent,
+        }
+    }).collect::<Vec<_>>();
+    let mut tax_subtotals: Vec<UblTaxSubtotal> = Vec::new();
+    for (line, item) in lines.iter().zip(&invoice.items) {
+        let (category, percent, reason) = line_category(gst_registered, item.gst_free);
+        let index = match tax_subtotals.iter().position(|t| t.category == category) {
+            Some(index) => index,
+            None => {
+                tax_subtotals.push(UblTaxSubtotal {
+                    category: category.to_string(),
+                    percent,
+                    exemption_reason: reason.to_string(),
+                    ..UblTaxSubtotal::default()
+                });
+                tax_subtotals.len() - 1
+            }
+        };
+        let subtotal = &mut tax_subtotals[index];
+        subtotal.taxable_amount = round2(subtotal.taxable_amount + line.line_amount);
+        subtotal.tax_amount = round2(subtotal.tax_amount + item.gst);
+    }
+    let gst = round2(invoice.gst);
+    let line_extension_amount = round2(lines.iter().map(|l| l.line_amount).sum());
+    let tax_inclusive_amount = round2(line_extension_amount + gst);
+    // Invoices marked paid without payment records owe nothing, so count as fully prepaid
+    let payable_amount = round2(invoice.balance_due().min(tax_inclusive_amount));
+    let prepaid_amount = round2(tax_inclusive_amount - payable_amount);
+
+    UblInvoice {
+        customization_id: CUSTOMIZATION_ID.to_string(),
+        profile_id: PROFILE_ID.to_string(),
+        id: invoice.invoice_number.clone(),
+        issue_date: invoice.date.format("%Y-%m-%d").to_string(),
+        due_date: invoice.due_date.format("%Y-%m-%d").to_string(),
+        note: invoice.notes.clone(),
+        currency: "AUD".to_string(),
+        buyer_reference: invoice.customer.code.clone(),
+        supplier: UblParty {
+            endpoint_id: company.abn.replace(' ', ""),
+            name: company.name.clone(),
+            street: company.address.clone(),
+            country: "AU".to_string(),
+            gst_registered,
+            contact_name: String::new(),
+            telephone: company.phone.clone(),
+            email: String::new(),
+        },
+        customer: UblParty {
+            endpoint_id: invoice.customer.abn.replace(' ', ""),
+            name: invoice.customer.name.clone(),
+            street: invoice.customer.address.clone(),
+            country: "AU".to_string(),
+            gst_registered: false,
+            contact_name: invoice.customer.contact_person.clone(),
+            telephone: invoice.customer.contact_phone.clone(),
+            email: invoice.customer.email.clone(),
+        },
+        payment_means_code: PAYMENT_MEANS_CREDIT_TRANSFER.to_string(),
+        payment_id: invoice.invoice_number.clone(),
+        account_number: ACCOUNT_NUMBER.to_string(),
+        account_name: ACCOUNT_NAME.to_string(),
+        bsb: BSB.to_string(),
+        payment_terms: "Net 30 Days".to_string(),
+        tax_subtotals,
+        tax_amount: gst,
+        line_extension_amount,
+        tax_exclusive_amount: line_extension_amount,
+        tax_inclusive_amount,
+        prepaid_amount,
+        payable_amount,
+        lines,
+    }
+}
+
+// Small indenting XML writer for the UBL output
+struct XmlWriter {
+    out: String,
+    depth: usize,
+}
+
+impl XmlWriter {
+    fn open(&mut self, tag: &str) {
+        self.out.push_str(&format!("{}<{}>\n", "  ".repeat(self.depth), tag));
+        self.depth += 1;
+    }
+
+    fn close(&mut self, tag: &str) {
+        self.depth -= 1;
+        self.out.push_str(&format!("{}</{}>\n", "  ".repeat(self.depth), tag));
+    }
+
+    fn leaf(&mut self, tag: &str, attrs: &[(&str, &str)], text: &str) {
+        let attrs: String = attrs.iter().map(|(k, v)| format!(" {}=\"{}\"", k, escape_html(v))).collect();
+        self.out.push_str(&format!("{}<{}{}>{}</{}>\n", "  ".repeat(self.depth), tag, attrs, escape_html(text), tag));
+    }
+
+    fn amount(&mut self, tag: &str, currency: &str, value: f64) {
+        self.leaf(tag, &[("currencyID", currency)], &format!("{:.2}", value));
+    }
+
+    // Unit prices keep any extra decimal places, e.g. 50.125
+    fn price(&mut self, tag: &str, currency: &str, value: f64) {
+        let text = if round2(value) == value { format!("{:.2}", value) } else { value.to_string() };
+        self.leaf(tag, &[("currencyID", currency)], &text);
+    }
+
+    fn tax_scheme(&mut self) {
+        self.open("cac:TaxScheme");
+        self.leaf("cbc:ID", &[], "GST");
+        self.close("cac:TaxScheme");
+    }
+
+    fn party(&mut self, party: &UblParty) {
+        self.open("cac:Party");
+        self.leaf("cbc:EndpointID", &[("schemeID", ABN_SCHEME)], &party.endpoint_id);
+        self.open("cac:PartyName");
+        self.leaf("cbc:Name", &[], &party.name);
+        self.close("cac:PartyName");
+        self.open("cac:PostalAddress");
+        self.leaf("cbc:StreetName", &[], &party.street);
+        self.open("cac:Country");
+        self.leaf("cbc:IdentificationCode", &[], &party.country);
+        self.close("cac:Country");
+        self.close("cac:PostalAddress");
+        if party.gst_registered {
+            self.open("cac:PartyTaxScheme");
+            self.leaf("cbc:CompanyID", &[], &party.endpoint_id);
+            self.tax_scheme();
+            self.close("cac:PartyTaxScheme");
+        }
+        self.open("cac:PartyLegalEntity");
+        self.leaf("cbc:RegistrationName", &[], &party.name);
+        self.leaf("cbc:CompanyID", &[("schemeID", ABN_SCHEME)], &party.endpoint_id);
+        self.close("cac:PartyLegalEntity");
+        if !party.contact_name.is_empty() || !party.telephone.is_empty() || !party.email.is_empty() {
+            self.open("cac:Contact");
+            if !party.contact_name.is_empty() {
+                self.leaf("cbc:Name", &[], &party.contact_name);
+            }
+            if !party.telephone.is_empty() {
+                self.leaf("cbc:Telephone", &[], &party.telephone);
+            }
+            if !party.email.is_empty() {
+                self.leaf("cbc:ElectronicMail", &[], &party.email);
+            }
+            self.close("cac:Contact");
+        }
+        self.close("cac:Party");
+    }
+
+    fn tax_category(&mut self, tag: &str, category: &str, percent: Option<f64>, reason: &str) {
+        self.open(tag);
+        self.leaf("cbc:ID", &[], category);
+        if let Some(percent) = percent {
+            self.leaf("cbc:Percent", &[], &format!("{}", percent));
+        }
+        if !reason.is_empty() {
+            self.leaf("cbc:TaxExemptionReason", &[], reason);
+        }
+        self.tax_scheme();
+        self.close(tag);
+    }
+}
+
+// Serialises the invoice as a UBL 2.1 XML document
+pub fn to_xml(ubl: &UblInvoice) -> String {
+    let mut w = XmlWriter { out: String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"), depth: 0 };
+    let cur = ubl.currency.as_str();
+    w.open(&format!("Invoice xmlns=\"{}\" xmlns:cac=\"{}\" xmlns:cbc=\"{}\"", NS_INVOICE, NS_CAC, NS_CBC));
+    w.leaf("cbc:CustomizationID", &[], &ubl.customization_id);
+    w.leaf("cbc:ProfileID", &[], &ubl.profile_id);
+    w.leaf("cbc:ID", &[], &ubl.id);
+    w.leaf("cbc:IssueDate", &[], &ubl.issue_date);
+    w.leaf("cbc:DueDate", &[], &ubl.due_date);
+    w.leaf("cbc:InvoiceTypeCode", &[], "380"); // Commercial invoice
+    if !ubl.note.is_empty() {
+        w.leaf("cbc:Note", &[], &ubl.note);
+    }
+    w.leaf("cbc:DocumentCurrencyCode", &[], cur);
+    w.leaf("cbc:BuyerReference", &[], &ubl.buyer_reference);
+
+    w.open("cac:AccountingSupplierParty");
+    w.party(&ubl.supplier);
+    w.close("cac:AccountingSupplierParty");
+    w.open("cac:AccountingCustomerParty");
+    w.party(&ubl.customer);
+    w.close("cac:AccountingCustomerParty");
+
+    w.open("cac:PaymentMeans");
+    w.leaf("cbc:PaymentMeansCode", &[("name", "Credit transfer")], &ubl.payment_means_code);
+    w.leaf("cbc:PaymentID", &[], &ubl.payment_id);
+    w.open("cac:PayeeFinancialAccount");
+    w.leaf("cbc:ID", &[], &ubl.account_number);
+    w.leaf("cbc:Name", &[], &ubl.account_name);
+    w.open("cac:FinancialInstitutionBranch");
+    w.leaf("cbc:ID", &[], &ubl.bsb);
+    w.close("cac:FinancialInstitutionBranch");
+    w.close("cac:PayeeFinancialAccount");
+    w.close("cac:PaymentMeans");
+    w.open("cac:PaymentTerms");
+    w.leaf("cbc:Note", &[], &ubl.payment_terms);
+    w.close("cac:PaymentTerms");
+
+    w.open("cac:TaxTotal");
+    w.amount("cbc:TaxAmount", cur, ubl.tax_amount);
+    for subtotal in &ubl.tax_subtotals {
+        w.open("cac:TaxSubtotal");
+        w.amount("cbc:TaxableAmount", cur, subtotal.taxable_amount);
+        w.amount("cbc:TaxAmount", cur, subtotal.tax_amount);
+        w.tax_category("cac:TaxCategory", &subtotal.category, subtotal.percent, &subtotal.exemption_reason);
+        w.close("cac:TaxSubtotal");
+    }
+    w.close("cac:TaxTotal");
+
+    w.open("cac:LegalMonetaryTotal");
+    w.amount("cbc:LineExtensionAmount", cur, ubl.line_extension_amount);
+    w.amount("cbc:TaxExclusiveAmount", cur, ubl.tax_exclusive_amount);
+    w.amount("cbc:TaxInclusiveAmount", cur, ubl.tax_inclusive_amount);
+    w.amount("cbc:PrepaidAmount", cur, ubl.prepaid_amount);
+    w.amount("cbc:PayableAmount", cur, ubl.payable_amount);
+    w.close("cac:LegalMonetaryTotal");
+
+    for line in &ubl.lines {
+        w.open("cac:InvoiceLine");
+        w.leaf("cbc:ID", &[], &line.id);
+        w.leaf("cbc:InvoicedQuantity", &[("unitCode", "EA")], &format!("{}", line.quantity));
+        w.amount("cbc:LineExtensionAmount", cur, line.line_amount);
+        w.open("cac:Item");
+        w.leaf("cbc:Name", &[], &line.description);
+        w.tax_category("cac:ClassifiedTaxCategory", &line.tax_category, line.tax_percent, "");
+        w.close("cac:Item");
+        w.open("cac:Price");
+        w.price("cbc:PriceAmount", cur, line.unit_price);
+        w.close("cac:Price");
+        w.close("cac:InvoiceLine");
+    }
+    w.close("Invoice");
+    w.out
+}
+
+// Reads a UBL invoice produced by `to_xml` (or a compatible document) back into the structure
+pub fn parse_xml(xml: &str) -> Result<UblInvoice, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut ubl = UblInvoice::default();
+    let mut path: Vec<String> = Vec::new();
+    // Text of the innermost open element, kept as is. A child element discards it, so only
+    // the indentation between elements is dropped, never spaces inside a value.
+    let mut text: Option<String> = None;
+
+    loop {
+        match reader.read_event().map_err(|e| format!("XML error at position {}: {}", reader.buffer_position(), e))? {
+            Event::Start(e) => {
+                let name = String::from_utf8_lossy(e.local_name().as_ref()).to_string();
+                if name == "InvoiceLine" && path.len() == 1 {
+                    ubl.lines.push(UblLine::default());
+                }
+                if name == "TaxSubtotal" && path.len() == 2 && path[1] == "TaxTotal" {
+                    ubl.tax_subtotals.push(UblTaxSubtotal::default());
+                }
+                if name == "PartyTaxScheme" {
+                    match path.get(1).map(String::as_str) {
+                        Some("AccountingSupplierParty") => ubl.supplier.gst_registered = true,
+                        Some("AccountingCustomerParty") => ubl.customer.gst_registered = true,
+                        _ => {}
+                    }
+                }
+                path.push(name);
+                text = Some(String::new());
+            }
+            Event::End(_) => {
+                if let Some(value) = text.take().filter(|v| !v.is_empty()) {
+                    assign_value(&mut ubl, &path.join("/"), value)?;
+                }
+                path.pop();
+            }
+            Event::Text(t) => {
+                if let Some(value) = text.as_mut() {
+                    value.push_str(&t.unescape().map_err(|e| e.to_string())?);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    if ubl.id.is_empty() {
+        return Err("Document is not a UBL invoice (missing cbc:ID).".to_string());
+    }
+    Ok(ubl)
+}
+
+fn parse_amount(path: &str, text: &str) -> Result<f64, String> {
+    text.trim().parse::<f64>().map_err(|_| format!("Invalid number \"{}\" in {}", text, path))
+}
+
+// Stores the text of an element, identified by its path of local names
+fn assign_value(ubl: &mut UblInvoice, path: &str, text: String) -> Result<(), String> {
+    let party_field = |party: &mut UblParty, rest: &str, text: String| match rest {
+        "Party/EndpointID" => party.endpoint_id = text,
+        "Party/PartyName/Name" => party.name = text,
+        "Party/PostalAddress/StreetName" => party.street = text,
+        "Party/PostalAddress/Country/IdentificationCode" => party.country = text,
+        "Party/Contact/Name" => party.contact_name = text,
+        "Party/Contact/Telephone" => party.telephone = text,
+        "Party/Contact/ElectronicMail" => party.email = text,
+        _ => {}
+    };
+
+    if let Some(rest) = path.strip_prefix("Invoice/AccountingSupplierParty/") {
+        party_field(&mut ubl.supplier, rest, text);
+        return Ok(());
+    }
+    if let Some(rest) = path.strip_prefix("Invoice/AccountingCustomerParty/") {
+        party_field(&mut ubl.customer, rest, text);
+        return Ok(());
+    }
+    if let Some(rest) = path.strip_prefix("Invoice/InvoiceLine/") {
+        let line = match ubl.lines.last_mut() {
+            Some(line) => line,
+            None => return Ok(()),
+        };
+        match rest {
+            "ID" => line.id = text,
+            "InvoicedQuantity" => line.quantity = parse_amount(path, &text)?,
+            "LineExtensionAmount" => line.line_amount = parse_amount(path, &text)?,
+            "Item/Name" => line.description = text,
+            "Item/ClassifiedTaxCategory/ID" => line.tax_category = text,
+            "Item/ClassifiedTaxCategory/Percent" => line.tax_percent = Some(parse_amount(path, &text)?),
+            "Price/PriceAmount" => line.unit_price = parse_amount(path, &text)?,
+            _ => {}
+        }
+        return Ok(());
+    }
+    if let Some(rest) = path.strip_prefix("Invoice/TaxTotal/TaxSubtotal/") {
+        let subtotal = match ubl.tax_subtotals.last_mut() {
+            Some(subtotal) => subtotal,
+            None => return Ok(()),
+        };
+        match rest {
+            "TaxableAmount" => subtotal.taxable_amount = parse_amount(path, &text)?,
+            "TaxAmount" => subtotal.tax_amount = parse_amount(path, &text)?,
+            "TaxCategory/ID" => subtotal.category = text,
+            "TaxCategory/Percent" => subtotal.percent = Some(parse_amount(path, &text)?),
+            "TaxCategory/TaxExemptionReason" => subtotal.exemption_reason = text,
+            _ => {}
+        }
+        return Ok(());
+    }
+
+    match path {
+        "Invoice/CustomizationID" => ubl.customization_id = text,
+        "Invoice/ProfileID" => ubl.profile_id = text,
+        "Invoice/ID" => ubl.id = text,
+        "Invoice/IssueDate" => ubl.issue_date = text,
+        "Invoice/DueDate" => ubl.due_date = text,
+        "Invoice/Note" => ubl.note = text,
+        "Invoice/DocumentCurrencyCode" => ubl.currency = text,
+        "Invoice/BuyerReference" => ubl.buyer_reference = text,
+        "Invoice/PaymentMeans/PaymentMeansCode" => ubl.payment_means_code = text,
+        "Invoice/PaymentMeans/PaymentID" => ubl.payment_id = text,
+        "Invoice/PaymentMeans/PayeeFinancialAccount/ID" => ubl.account_number = text,
+        "Invoice/PaymentMeans/PayeeFinancialAccount/Name" => ubl.account_name = text,
+        "Invoice/PaymentMeans/PayeeFinancialAccount/FinancialInstitutionBranch/ID" => ubl.bsb = text,
+        "Invoice/PaymentTerms/Note" => ubl.payment_terms = text,
+        "Invoice/TaxTotal/TaxAmount" => ubl.tax_amount = parse_amount(path, &text)?,
+        "Invoice/LegalMonetaryTotal/LineExtensionAmount" => ubl.line_extension_amount = parse_amount(path, &text)?,
+        "Invoice/LegalMonetaryTotal/TaxExclusiveAmount" => ubl.tax_exclusive_amount = parse_amount(path, &text)?,
+        "Invoice/LegalMonetaryTotal/TaxInclusiveAmount" => ubl.tax_inclusive_amount = parse_amount(path, &text)?,
+        "Invoice/LegalMonetaryTotal/PrepaidAmount" => ubl.prepaid_amount = parse_amount(path, &text)?,
+        "Invoice/LegalMonetaryTotal/PayableAmount" => ubl.payable_amount = parse_amount(path, &text)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+// Checks an 11 digit ABN against the ATO check digit algorithm
+pub fn is_valid_abn(abn: &str) -> bool {
+    const WEIGHTS: [u32; 11] = [10, 1, 3, 5, 7, 9, 11, 13, 15, 17, 19];
+    let digits: Vec<u32> = abn.chars().filter(|c| !c.is_whitespace()).filter_map(|c| c.to_digit(10)).collect();
+    if digits.len() != 11 || abn.chars().any(|c| !c.is_ascii_digit() && !c.is_whitespace()) || digits[0] == 0 {
+        return false;
+    }
+    let sum: u32 = digits.iter().enumerate()
+        .map(|(i, d)| if i == 0 { (d - 1) * WEIGHTS[i] } else { d * WEIGHTS[i] })
+        .sum();
+    sum % 89 == 0
+}
+
+fn amounts_differ(a: f64, b: f64) -> bool {
+    (a - b).abs() > 0.005
+}
+
+// Checks the business rules of the A-NZ PINT profile that apply to our invoices.
+// Returns a list of problems; an empty list means the document is valid.
+pub fn validate(ubl: &UblInvoice) -> Vec<String> {
+    let mut errors = Vec::new();
+    let date = |value: &str| chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").ok();
+
+    if ubl.customization_id != CUSTOMIZATION_ID {
+        errors.push(format!("CustomizationID must be \"{}\".", CUSTOMIZATION_ID));
+    }
+    if ubl.profile_id != PROFILE_ID {
+        errors.push(format!("ProfileID must be \"{}\".", PROFILE_ID));
+    }
+    if ubl.id.trim().is_empty() {
+        errors.push("Invoice number is missing.".to_string());
+    }
+    match (date(&ubl.issue_date), date(&ubl.due_date)) {
+        (Some(issued), Some(due)) if due < issued => errors.push("Due date is before the issue date.".to_string()),
+        (None, _) => errors.push("Issue date must be in YYYY-MM-DD format.".to_string()),
+        (_, None) => errors.push("Due date must be in YYYY-MM-DD format.".to_string()),
+        _ => {}
+    }
+    if ubl.currency != "AUD" {
+        errors.push("Document currency must be AUD.".to_string());
+    }
+
+    for (role, party) in [("Supplier", &ubl.supplier), ("Customer", &ubl.customer)] {
+        if party.name.trim().is_empty() {
+            errors.push(format!("{} name is missing.", role));
+        }
+        if party.endpoint_id.is_empty() {
+            errors.push(format!("{} ABN is missing; it is required as the Peppol endpoint.", role));
+        } else if !is_valid_abn(&party.endpoint_id) {
+            errors.push(format!("{} ABN \"{}\" is not a valid ABN.", role, party.endpoint_id));
+        }
+        if party.country != "AU" {
+            errors.push(format!("{} country code must be AU.", role));
+        }
+    }
+
+    if ubl.payment_means_code == PAYMENT_MEANS_CREDIT_TRANSFER {
+        if !(ubl.bsb.len() == 6 && ubl.bsb.chars().all(|c| c.is_ascii_digit())) {
+            errors.push("Payment BSB must be 6 digits.".to_string());
+        }
+        if ubl.account_number.trim().is_empty() {
+            errors.push("Payment account number is missing.".to_string());
+        }
+    }
+
+    for subtotal in &ubl.tax_subtotals {
+        match subtotal.category.as_str() {
+            "S" => {
+                if subtotal.percent != Some(10.0) {
+                    errors.push("Standard rated GST (S) must use a 10% rate.".to_string());
+                }
+                if !ubl.supplier.gst_registered {
+                    errors.push("Supplier GST registration is required when charging GST.".to_string());
+                }
+                if amounts_differ(subtotal.tax_amount, round2(subtotal.taxable_amount * 0.10)) {
+                    errors.push("GST amount must be 10% of the taxable amount.".to_string());
+                }
+            }
+            "O" => {
+                if subtotal.percent.is_some() || amounts_differ(subtotal.tax_amount, 0.0) {
+                    errors.push("Invoices not subject to GST (O) must not have a GST rate or amount.".to_string());
+                }
+                if subtotal.exemption_reason.trim().is_empty() {
+                    errors.push("A tax exemption reason is required for category O.".to_string());
+                }
+                if ubl.tax_subtotals.len() > 1 {
+                    errors.push("Category O cannot be combined with other GST categories.".to_string());
+                }
+            }
+            "Z" | "E" => {
+                if amounts_differ(subtotal.tax_amount, 0.0) {
+                    errors.push(format!("GST-free category {} must not have a GST amount.", subtotal.category));
+                }
+            }
+            other => errors.push(format!("Unknown GST category \"{}\".", other)),
+        }
+        let category_lines = round2(ubl.lines.iter().filter(|l| l.tax_category == subtotal.category).map(|l| l.line_amount).sum());
+        if amounts_differ(subtotal.taxable_amount, category_lines) {
+            errors.push(format!("Taxable amount for category {} does not match its lines.", subtotal.category));
+        }
+    }
+    let subtotal_tax = round2(ubl.tax_subtotals.iter().map(|t| t.tax_amount).sum());
+    if amounts_differ(ubl.tax_amount, subtotal_tax) {
+        errors.push("GST total does not match the sum of the GST subtotals.".to_string());
+    }
+
+    if ubl.lines.is_empty() {
+        errors.push("Invoice must have at least one line.".to_string());
+    }
+    for line in &ubl.lines {
+        if line.description.trim().is_empty() {
+            errors.push(format!("Line {} has no description.", line.id));
+        }
+        if line.quantity <= 0.0 {
+            errors.push(format!("Line {} quantity must be greater than zero.", line.id));
+        }
+        if amounts_differ(line.line_amount, round2(line.quantity * line.unit_price)) {
+            errors.push(format!("Line {} amount does not equal quantity x price.", line.id));
+        }
+        match ubl.tax_subtotals.iter().find(|t| t.category == line.tax_category) {
+            Some(subtotal) if subtotal.percent == line.tax_percent => {}
+            Some(_) => errors.push(format!("Line {} GST rate does not match its category.", line.id)),
+            None => errors.push(format!("Line {} GST category has no GST subtotal.", line.id)),
+        }
+    }
+
+    let line_total = round2(ubl.lines.iter().map(|l| l.line_amount).sum());
+    if amounts_differ(ubl.line_extension_amount, line_total) {
+        errors.push("Sum of line amounts does not match LineExtensionAmount.".to_string());
+    }
+    if amounts_differ(ubl.tax_exclusive_amount, ubl.line_extension_amount) {
+        errors.push("TaxExclusiveAmount must equal LineExtensionAmount.".to_string());
+    }
+    if amounts_differ(ubl.tax_inclusive_amount, ubl.tax_exclusive_amount + ubl.tax_amount) {
+        errors.push("TaxInclusiveAmount must equal TaxExclusiveAmount plus GST.".to_string());
+    }
+    if amounts_differ(ubl.payable_amount, ubl.tax_inclusive_amount - ubl.prepaid_amount) {
+        errors.push("PayableAmount must equal TaxInclusiveAmount less PrepaidAmount.".to_string());
+    }
+    errors
+}
+
+// Builds, serialises and validates the e-invoice. The XML is parsed back and compared
+// with what was built so anything lost in serialisation is caught before sending.
+pub fn export_invoice(invoice: &Invoice, company: &Company) -> Result<String, Vec<String>> {
+    let ubl = build_ubl_invoice(invoice, company);
+    let xml = to_xml(&ubl);
+    let parsed = parse_xml(&xml).map_err(|e| vec![e])?;
+    if parsed != ubl {
+        return Err(vec!["Generated XML did not read back identically.".to_string()]);
+    }
+    let errors = validate(&parsed);
+    if errors.is_empty() {
+        Ok(xml)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Local, TimeZone};
+    use crate::models::{Customer, InvoiceItem, Payment, PaymentKind};
+
+    fn company() -> Company {
+        Company {
+            name: "JMATTS CLEANING Canberra".to_string(),
+            abn: "78734213681".to_string(),
+            address: "40 Wyndham Avenue Denman Prospect, ACT, 2611".to_string(),
+            phone: "0403-491446".to_string(),
+            gst_registered: false,
+        }
+    }
+
+    fn item(description: &str, quantity: u32, rate: f64) -> InvoiceItem {
+        InvoiceItem { description: description.to_string(), quantity, rate, amount: quantity as f64 * rate, gst_free: true, gst: 0.0 }
+    }
+
+    fn invoice(items: Vec<InvoiceItem>) -> Invoice {
+        let subtotal = items.iter().map(|i| i.amount).sum();
+        Invoice {
+            invoice_number: "AC76".to_string(),
+            date: Local.with_ymd_and_hms(2026, 7, 1, 9, 0, 0).unwrap(),
+            due_date: Local.with_ymd_and_hms(2026, 7, 31, 0, 0, 0).unwrap(),
+            customer: Customer {
+                name: "Acme Pty Ltd".to_string(),
+                address: "1 Main St, Canberra ACT 2600".to_string(),
+                phone: "02 6100 0000".to_string(),
+                contact_person: "Bob & Sue".to_string(),
+                contact_phone: "0400 000 000".to_string(),
+                email: "accounts@acme.example".to_string(),
+                code: "AC".to_string(),
+                abn: "51 824 753 556".to_string(),
+            },
+            items,
+            subtotal,
+            gst: 0.0,
+            total: subtotal,
+            notes: "Thanks <for> your business".to_string(),
+            paid: false,
+            payments: Vec::new(),
+            emails: Vec::new(),
+            reminders: Vec::new(),
+            late_fees: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn xml_reads_back_identically() {
+        let mut inv = invoice(vec![item("Office clean", 3, 85.5), item("Windows", 1, 40.0)]);
+        inv.items[0].gst_free = false;
+        inv.items[0].gst = 25.65;
+        inv.gst = 25.65;
+        inv.total += inv.gst;
+        let ubl = build_ubl_invoice(&inv, &Company { gst_registered: true, ..company() });
+        assert_eq!(parse_xml(&to_xml(&ubl)).unwrap(), ubl);
+        assert_eq!(ubl.tax_subtotals.len(), 2);
+        assert!(validate(&ubl).is_empty(), "{:?}", validate(&ubl));
+    }
+
+    #[test]
+    fn surrounding_whitespace_reads_back() {
+        for notes in ["Thanks\n", "  Thanks", " \n "] {
+            let mut inv = invoice(vec![item("Cleaning ", 1, 100.0)]);
+            inv.notes = notes.to_string();
+            inv.customer.address = "1 Main St\nCanberra ACT 2600\n".to_string();
+            assert!(export_invoice(&inv, &company()).is_ok(), "notes {:?}", notes);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_abn() {
+        assert!(is_valid_abn("51 824 753 556"));
+        assert!(!is_valid_abn("51 824 753 557"));
+        let mut ubl = build_ubl_invoice(&invoice(vec![item("Cleaning", 1, 100.0)]), &company());
+        ubl.customer.endpoint_id = "51824753557".to_string();
+        let errors = validate(&ubl);
+        assert!(errors.iter().any(|e| e.contains("not a valid ABN")), "{:?}", errors);
+    }
+
+    #[test]
+    fn rejects_amount_mismatch() {
+        let mut ubl = build_ubl_invoice(&invoice(vec![item("Cleaning", 2, 50.0)]), &company());
+        ubl.lines[0].line_amount = 99.0;
+        let errors = validate(&ubl);
+        assert!(errors.iter().any(|e| e.contains("does not equal quantity x price")), "{:?}", errors);
+        assert!(errors.iter().any(|e| e.contains("LineExtensionAmount")), "{:?}", errors);
+    }
+
+    #[test]
+    fn three_decimal_rate_validates() {
+        let xml = export_invoice(&invoice(vec![item("Cleaning", 2, 50.125)]), &company()).unwrap();
+        assert!(xml.contains(">50.125</cbc:PriceAmount>"));
+        assert!(xml.contains("<cbc:LineExtensionAmount currencyID=\"AUD\">100.25</cbc:LineExtensionAmount>"));
+    }
+
+    #[test]
+    fn payable_follows_balance_due() {
+        let mut part_paid = invoice(vec![item("Cleaning", 1, 100.0)]);
+        part_paid.payments.push(Payment { date: part_paid.date, amount: 30.0, kind: PaymentKind::Payment, reference: String::new() });
+        let ubl = build_ubl_invoice(&part_paid, &company());
+        assert_eq!((ubl.prepaid_amount, ubl.payable_amount), (30.0, 70.0));
+
+        // Marked paid before payments were recorded
+        let mut legacy = invoice(vec![item("Cleaning", 1, 100.0)]);
+        legacy.paid = true;
+        let ubl = build_ubl_invoice(&legacy, &company());
+        assert_eq!((ubl.prepaid_amount, ubl.payable_amount), (100.0, 0.0));
+        assert!(validate(&ubl).is_empty(), "{:?}", validate(&ubl));
+    }
+}
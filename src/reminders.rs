@@ -0,0 +1,184 @@
+// Payment reminders for invoices that are coming due or overdue.
+//
+// Each rule fires a number of days from the due date (negative for before it) while the
+// invoice still has a balance owing. Only the latest rule an invoice has reached is sent,
+// so an invoice first checked 25 days overdue gets the 21 day reminder rather than every
+// earlier one at once, and no rule is sent twice for the same invoice.
+use chrono::NaiveDate;
+use serde::{Serialize, Deserialize};
+use crate::email::{fill_template, EmailDraft};
+use crate::models::{Company, Invoice};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReminderRule {
+    pub enabled: bool,
+    pub days_from_due: i64, // Negative before the due date, positive once overdue
+    pub subject_template: String,
+    pub body_template: String,
+}
+
+impl Default for ReminderRule {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            days_from_due: 0,
+            subject_template: "Reminder: invoice {invoice_number} from {company_name}".to_string(),
+            body_template: String::new(),
+        }
+    }
+}
+
+impl ReminderRule {
+    fn new(days_from_due: i64, subject: &str, body: &str) -> Self {
+        Self {
+            enabled: true,
+            days_from_due,
+            subject_template: subject.to_string(),
+            body_template: body.to_string(),
+        }
+    }
+}
+
+// "3 days before due", "On the due date" or "7 days overdue"
+pub fn stage_label(days_from_due: i64) -> String {
+    match days_from_due {
+        0 => "On the due date".to_string(),
+        -1 => "1 day before due".to_string(),
+        1 => "1 day overdue".to_string(),
+        d if d < 0 => format!("{} days before due", -d),
+        d => format!("{} days overdue", d),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReminderSettings {
+    pub rules: Vec<ReminderRule>,
+    pub run_on_start: bool, // Send due reminders when the desktop app opens
+}
+
+impl Default for ReminderSettings {
+    fn default() -> Self {
+        Self {
+            rules: vec![
+                ReminderRule::new(
+                    -3,
+                    "Invoice {invoice_number} is due on {due_date}",
+                    "Dear {contact_name},\n\n\
+                    This is a friendly reminder that invoice {invoice_number} for {balance_due} \
+                    is due on {due_date}. A copy is attached.\n\n\
+                    If you have already paid, thank you and please disregard this email.\n\n\
+                    {company_name}\n\
+                    {company_phone}\n",
+                ),
+                ReminderRule::new(
+                    7,
+                    "Overdue: invoice {invoice_number} from {company_name}",
+                    "Dear {contact_name},\n\n\
+                    Our records show that invoice {invoice_number} for {balance_due} was due on \
+                    {due_date} and is now {days_overdue} days overdue. A copy is attached.\n\n\
+                    Please arrange payment at your earliest convenience, or let us know if there \
+                    is a problem with the invoice.\n\n\
+                    {company_name}\n\
+                    {company_phone}\n",
+                ),
+                ReminderRule::new(
+                    21,
+                    "Second notice: invoice {invoice_number} is {days_overdue} days overdue",
+                    "Dear {contact_name},\n\n\
+                    Invoice {invoice_number} for {balance_due} is now {days_overdue} days past \
+                    its due date of {due_date}, and we have not received payment despite our \
+                    earlier reminder. Our terms are strictly 30 days net.\n\n\
+                    Please pay the outstanding balance within 7 days or contact us to discuss.\n\n\
+                    {company_name}\n\
+                    {company_phone}\n",
+                ),
+            ],
+            run_on_start: false,
+        }
+    }
+}
+
+impl ReminderSettings {
+    // Rules can't share a day, as sent reminders are logged against the rule's day
+    pub fn validate(&self) -> Result<(), String> {
+        for (i, rule) in self.rules.iter().enumerate() {
+            if rule.subject_template.trim().is_empty() {
+                return Err(format!("The reminder \"{}\" needs a subject.", stage_label(rule.days_from_due)));
+            }
+            if self.rules[..i].iter().any(|r| r.days_from_due == rule.days_from_due) {
+                return Err(format!("There is more than one reminder for \"{}\".", stage_label(rule.days_from_due)));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Placeholders available in reminder templates as well as the invoice email ones
+pub const REMINDER_PLACEHOLDERS: [(&str, &str); 2] = [
+    ("{days_overdue}", "Days past the due date"),
+    ("{days_until_due}", "Days left until the due date"),
+];
+
+// A reminder that has reached its day and not been sent yet
+#[derive(Clone, Debug)]
+pub struct PlannedReminder {
+    pub invoice_number: String,
+    pub customer_name: String,
+    pub due_date: NaiveDate,
+    pub balance_due: f64,
+    pub days_from_due: i64, // The rule being sent
+    pub draft: EmailDraft,
+    pub skip_reason: Option<String>, // Set when the reminder can't be sent, e.g. no email address
+}
+
+fn fill_reminder(template: &str, invoice: &Invoice, company: &Company, days_overdue: i64) -> String {
+    let text = template
+        .replace("{days_overdue}", &days_overdue.max(0).to_string())
+        .replace("{days_until_due}", &(-days_overdue).max(0).to_string());
+    fill_template(&text, invoice, company)
+}
+
+// Reminders due on `today` for the given invoices, by invoice number
+pub fn plan_reminders<'a>(
+    invoices: impl IntoIterator<Item = &'a Invoice>,
+    settings: &ReminderSettings,
+    company: &Company,
+    today: NaiveDate,
+) -> Vec<PlannedReminder> {
+    let mut rules: Vec<&ReminderRule> = settings.rules.iter().filter(|r| r.enabled).collect();
+    rules.sort_by_key(|r| r.days_from_due);
+
+    let mut planned = Vec::new();
+    for invoice in invoices {
+        if invoice.balance_due() <= 0.0 || invoice.date.date_naive() > today {
+            continue;
+        }
+        let days_overdue = (today - invoice.due_date.date_naive()).num_days();
+        let rule = match rules.iter().rev().find(|r| r.days_from_due <= days_overdue) {
+            Some(rule) => rule,
+            None => continue,
+        };
+        // Already sent this stage, or a later one before the rules were changed
+        if invoice.reminders.iter().any(|sent| sent.days_from_due >= rule.days_from_due) {
+            continue;
+        }
+        let to = invoice.customer.email.trim().to_string();
+        planned.push(PlannedReminder {
+            invoice_number: invoice.invoice_number.clone(),
+            customer_name: invoice.customer.name.clone(),
+            due_date: invoice.due_date.date_naive(),
+            balance_due: invoice.balance_due(),
+            days_from_due: rule.days_from_due,
+            skip_reason: if to.is_empty() { Some("Customer has no email address".to_string()) } else { None },
+            draft: EmailDraft {
+                to,
+                subject: fill_reminder(&rule.subject_template, invoice, company, days_overdue),
+                body: fill_reminder(&rule.body_template, invoice, company, days_overdue),
+            },
+        });
+    }
+    planned.sort_by(|a, b| a.invoice_number.cmp(&b.invoice_number));
+    planned
+}
@@ -0,0 +1,269 @@
+use std::error::Error;
+use prettytable::{Table, Row, Cell, format};
+use crate::bank_details::payment_instructions;
+use crate::models::{Company, Invoice};
+use crate::pdf_generator::{generate_pdf_bytes, PdfOptions};
+use crate::utils::wrap_text;
+
+// Formats an amount the way it appears on invoices
+pub fn format_money(amount: f64) -> String {
+    format!("AU ${:.2}", amount)
+}
+
+// One line of the items table, already formatted
+pub struct ItemRow {
+    pub number: usize,
+    pub description: String,
+    pub quantity: u32,
+    pub rate: f64,
+    pub amount: f64,
+}
+
+// Invoice content laid out as text, shared by the PDF, HTML and plain-text renderers
+pub struct InvoiceView {
+    pub title: String,
+    pub company_lines: Vec<String>,
+    pub issue_date: String,
+    pub bill_to_lines: Vec<String>,
+    pub terms_lines: Vec<String>,
+    pub items: Vec<ItemRow>,
+    pub gst_lines: Vec<(String, String)>, // Subtotal and GST, only when the invoice charges GST
+    pub total: String,
+    pub amount_paid: Option<String>, // Only set once payments are recorded or the invoice is marked paid
+    pub balance_due: String,
+    pub notes: String,
+    pub payment_lines: Vec<String>,
+}
+
+impl InvoiceView {
+    pub fn new(invoice: &Invoice, company_name: &str, company_abn: &str, company_address: &str, company_phone: &str) -> Self {
+        let mut bill_to_lines = Vec::new();
+        // Corrected: Use string literal "\n\n" for split
+        for line in invoice.customer.name.split("\n\n") {
+            bill_to_lines.push(line.to_string());
+        }
+        bill_to_lines.push(invoice.customer.address.clone());
+        bill_to_lines.push(format!("Phone: {}", invoice.customer.phone));
+
+        let contact_lines: Vec<&str> = invoice.customer.contact_person.split("\n\n").collect();
+        let email_lines: Vec<&str> = invoice.customer.email.split("\n\n").collect();
+
+        let mut attn_line = String::from("Attn - ");
+        if !contact_lines.is_empty() {
+            attn_line.push_str(contact_lines[0]);
+        }
+        bill_to_lines.extend(wrap_text(&attn_line, 80));
+        bill_to_lines.push(format!("Contact Phone: {}", invoice.customer.contact_phone));
+        if !email_lines.is_empty() {
+            bill_to_lines.extend(wrap_text(&format!("Email: {}", email_lines[0]), 80));
+        }
+        for contact_line in contact_lines.iter().skip(1) {
+            bill_to_lines.push(format!("       {}", contact_line));
+        }
+        for email_line in email_lines.iter().skip(1) {
+            bill_to_lines.push(format!("       {}", email_line));
+        }
+
+        let charges_gst = invoice.gst > 0.0;
+        let items = invoice.items.iter().enumerate().map(|(idx, item)| ItemRow {
+            number: idx + 1,
+            description: if charges_gst && item.gst_free { format!("{} (GST-free)", item.description) } else { item.description.clone() },
+            quantity: item.quantity,
+            rate: item.rate,
+            amount: item.amount,
+        }).collect();
+
+        InvoiceView {
+            // Sales that include GST need a tax invoice
+            title: format!("{} #{}", if charges_gst { "Tax Invoice" } else { "Invoice" }, invoice.invoice_number),
+            company_lines: vec![
+                company_name.to_string(),
+                format!("A.B.N. {}", company_abn),
+                company_address.to_string(),
+                format!("Ph: {}", company_phone),
+            ],
+            issue_date: invoice.date.format("%b %d, %Y").to_string(),
+            bill_to_lines,
+            terms_lines: vec![
+                "Payment Terms: Net 30 Days".to_string(),
+                format!("Due Date: {}", invoice.due_date.format("%b %d, %Y")),
+                format!("Balance Due: {}", format_money(invoice.balance_due())),
+            ],
+            items,
+            gst_lines: if charges_gst {
+                vec![
+                    ("Subtotal".to_string(), format_money(invoice.subtotal)),
+                    ("GST".to_string(), format_money(invoice.gst)),
+                ]
+            } else {
+                Vec::new()
+            },
+            total: format_money(invoice.total),
+            amount_paid: if invoice.payments.is_empty() && !invoice.paid { None } else { Some(format_money(invoice.total - invoice.balance_due())) },
+            balance_due: format_money(invoice.balance_due()),
+            notes: invoice.notes.clone(),
+            payment_lines: payment_instructions().into_iter().chain([
+                "Terms:".to_string(),
+                "Strictly 30 Days Net Full Payment Please".to_string(),
+            ]).collect(),
+        }
+    }
+
+    // Fixed-width items table, used by the PDF (in Courier) and the plain-text output
+    pub fn items_table(&self) -> String {
+        let mut table = Table::new();
+        table.set_format(*format::consts::FORMAT_CLEAN);
+        table.set_titles(Row::new(vec![
+            Cell::new("#"),
+            Cell::new("Item"),
+            Cell::new("Qty"),
+            Cell::new("Rate"),
+            Cell::new("Amount"),
+        ]));
+
+        for item in &self.items {
+            let description_lines = wrap_text(&item.description, 50);
+            for (i, line) in description_lines.iter().enumerate() {
+                if i == 0 {
+                    table.add_row(Row::new(vec![
+                        Cell::new(&format!("{:>3}", item.number)),
+                        Cell::new(line),
+                        Cell::new(&format!("{:>6}", item.quantity)),
+                        Cell::new(&format!("AU ${:>6.2}", item.rate)),
+                        Cell::new(&format!("AU ${:>6.2}", item.amount)),
+                    ]));
+                } else {
+                    table.add_row(Row::new(vec![
+                        Cell::new(""),
+                        Cell::new(line),
+                        Cell::new(""),
+                        Cell::new(""),
+                        Cell::new(""),
+                    ]));
+                }
+            }
+        }
+        table.to_string()
+    }
+}
+
+// Common interface for turning an invoice into a document
+pub trait InvoiceRenderer {
+    type Output;
+
+    fn render(&self, invoice: &Invoice, company: &Company) -> Result<Self::Output, Box<dyn Error>>;
+}
+
+// Renders the invoice as a PDF using `generate_pdf_bytes`
+pub struct PdfRenderer {
+    pub options: PdfOptions,
+}
+
+impl InvoiceRenderer for PdfRenderer {
+    type Output = Vec<u8>;
+
+    fn render(&self, invoice: &Invoice, company: &Company) -> Result<Vec<u8>, Box<dyn Error>> {
+        generate_pdf_bytes(invoice, company, &self.options)
+    }
+}
+
+// Plain-text invoice for terminals and email fallbacks
+pub struct TextRenderer;
+
+impl InvoiceRenderer for TextRenderer {
+    type Output = String;
+
+    fn render(&self, invoice: &Invoice, company: &Company) -> Result<String, Box<dyn Error>> {
+        let view = InvoiceView::new(invoice, &company.name, &company.abn, &company.address, &company.phone);
+        let mut lines: Vec<String> = view.company_lines.clone();
+        lines.push(view.title.clone());
+        lines.push(format!("Date: {}", view.issue_date));
+        lines.push(String::new());
+        lines.push("Bill To:".to_string());
+        lines.extend(view.bill_to_lines.iter().cloned());
+        lines.push(String::new());
+        lines.extend(view.terms_lines.iter().cloned());
+        lines.push(String::new());
+        lines.extend(view.items_table().lines().map(|l| l.to_string()));
+        lines.push(String::new());
+        for (label, amount) in &view.gst_lines {
+            lines.push(format!("{}: {}", label, amount));
+        }
+        lines.push(format!("Total: {}", view.total));
+        if let Some(paid) = &view.amount_paid {
+            lines.push(format!("Paid / Credited: {}", paid));
+            lines.push(format!("Balance Due: {}", view.balance_due));
+        }
+        if !view.notes.is_empty() {
+            lines.push(String::new());
+            lines.push("Notes:".to_string());
+            lines.push(view.notes.clone());
+        }
+        lines.push(String::new());
+        lines.extend(view.payment_lines.iter().cloned());
+        Ok(lines.join("\n") + "\n")
+    }
+}
+
+// Escapes text for inclusion in HTML
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+// Self-contained HTML invoice for email bodies and web previews
+pub struct HtmlRenderer;
+
+impl InvoiceRenderer for HtmlRenderer {
+    type Output = String;
+
+    fn render(&self, invoice: &Invoice, company: &Company) -> Result<String, Box<dyn Error>> {
+        let view = InvoiceView::new(invoice, &company.name, &company.abn, &company.address, &company.phone);
+        let lines_to_html = |lines: &[String]| {
+            lines.iter().map(|l| escape_html(l.trim())).collect::<Vec<_>>().join("<br>\n")
+        };
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str(&format!("<title>{}</title>\n", escape_html(&view.title)));
+        html.push_str("<style>\nbody { font-family: Helvetica, Arial, sans-serif; font-size: 14px; color: #222; }\n");
+        html.push_str("table.items { border-collapse: collapse; width: 100%; margin: 16px 0; }\n");
+        html.push_str("table.items th, table.items td { border-bottom: 1px solid #ddd; padding: 4px 8px; text-align: left; }\n");
+        html.push_str("table.items td.num { text-align: right; }\n.columns { display: flex; justify-content: space-between; }\n</style>\n");
+        html.push_str("</head>\n<body>\n");
+        html.push_str(&format!("<p>{}</p>\n", lines_to_html(&view.company_lines)));
+        html.push_str(&format!("<h2>{}</h2>\n<p>Date: {}</p>\n", escape_html(&view.title), escape_html(&view.issue_date)));
+        html.push_str("<div class=\"columns\">\n");
+        html.push_str(&format!("<div><strong>Bill To:</strong><br>\n{}</div>\n", lines_to_html(&view.bill_to_lines)));
+        html.push_str(&format!("<div>{}</div>\n", lines_to_html(&view.terms_lines)));
+        html.push_str("</div>\n");
+        html.push_str("<table class=\"items\">\n<tr><th>#</th><th>Item</th><th>Qty</th><th>Rate</th><th>Amount</th></tr>\n");
+        for item in &view.items {
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td></tr>\n",
+                item.number,
+                escape_html(&item.description),
+                item.quantity,
+                escape_html(&format_money(item.rate)),
+                escape_html(&format_money(item.amount)),
+            ));
+        }
+        html.push_str("</table>\n");
+        if !view.gst_lines.is_empty() {
+            let gst_lines: Vec<String> = view.gst_lines.iter().map(|(label, amount)| format!("{}: {}", label, amount)).collect();
+            html.push_str(&format!("<p>{}</p>\n", lines_to_html(&gst_lines)));
+        }
+        html.push_str(&format!("<p><strong>Total: {}</strong></p>\n", escape_html(&view.total)));
+        if let Some(paid) = &view.amount_paid {
+            html.push_str(&format!("<p>Paid / Credited: {}<br>\nBalance Due: {}</p>\n", escape_html(paid), escape_html(&view.balance_due)));
+        }
+        if !view.notes.is_empty() {
+            html.push_str(&format!("<p><strong>Notes:</strong><br>\n{}</p>\n", escape_html(&view.notes).replace('\n', "<br>\n")));
+        }
+        html.push_str(&format!("<p>{}</p>\n", lines_to_html(&view.payment_lines)));
+        html.push_str("</body>\n</html>\n");
+        Ok(html)
+    }
+}
@@ -0,0 +1,109 @@
+// Free-text search across customers and invoices.
+//
+// Matching ignores case. Customers match on name, code, email or phone (phone numbers
+// ignore spaces, dashes and brackets). Invoices match on number, line descriptions or
+// notes, or, when the query reads as an amount such as "$1,234.50", on the total,
+// balance owing or a line amount.
+use crate::models::{Customer, Invoice};
+
+// Invoice results kept at most; the rest are only counted
+pub const MAX_INVOICE_RESULTS: usize = 200;
+
+#[derive(Clone)]
+pub struct InvoiceHit {
+    pub invoice: Invoice,
+    pub matched_on: String, // Where the query was found, e.g. "Notes"
+}
+
+#[derive(Clone, Default)]
+pub struct SearchResults {
+    pub customers: Vec<Customer>,
+    pub invoices: Vec<InvoiceHit>, // Newest first
+    pub invoice_total: usize, // Matching invoices, including any over the limit
+}
+
+fn contains(field: &str, query_lower: &str) -> bool {
+    field.to_lowercase().contains(query_lower)
+}
+
+// Digits and a leading "+" only, so "02 6123 4567" matches "(02) 6123-4567"
+fn phone_digits(value: &str) -> String {
+    value.chars().filter(|c| c.is_ascii_digit() || *c == '+').collect()
+}
+
+// The query as an amount, if it is one ("85", "$85.00", "1,234.5")
+fn query_amount(query: &str) -> Option<f64> {
+    let cleaned: String = query.trim().trim_start_matches('$').chars().filter(|c| *c != ',').collect();
+    cleaned.parse::<f64>().ok().filter(|amount| amount.is_finite())
+}
+
+pub fn customer_matches(customer: &Customer, query: &str) -> bool {
+    let query = query.trim();
+    if query.is_empty() {
+        return true;
+    }
+    let lower = query.to_lowercase();
+    if [&customer.name, &customer.code, &customer.email].iter().any(|field| contains(field, &lower)) {
+        return true;
+    }
+    let digits = phone_digits(query);
+    digits.chars().filter(|c| c.is_ascii_digit()).count() >= 3
+        && [&customer.phone, &customer.contact_phone].iter().any(|phone| phone_digits(phone).contains(&digits))
+}
+
+// Where `query` matches `invoice`, or None
+pub fn invoice_match(invoice: &Invoice, query: &str) -> Option<String> {
+    let query = query.trim();
+    if query.is_empty() {
+        return None;
+    }
+    let lower = query.to_lowercase();
+    if contains(&invoice.invoice_number, &lower) {
+        return Some("Number".to_string());
+    }
+    if let Some(item) = invoice.items.iter().find(|item| contains(&item.description, &lower)) {
+        return Some(format!("Line: {}", item.description));
+    }
+    if contains(&invoice.notes, &lower) {
+        return Some("Notes".to_string());
+    }
+    let amount = query_amount(query)?;
+    let same = |value: f64| (value - amount).abs() < 0.005;
+    if same(invoice.total) {
+        Some("Total".to_string())
+    } else if same(invoice.balance_due()) {
+        Some("Balance".to_string())
+    } else {
+        invoice.items.iter()
+            .find(|item| same(item.amount))
+            .map(|item| format!("Line amount: {}", item.description))
+    }
+}
+
+// Customers and invoices matching `query`; `include` narrows the invoices (e.g. by status or date)
+pub fn search<'a>(
+    customers: impl IntoIterator<Item = &'a Customer>,
+    invoices: impl IntoIterator<Item = &'a Invoice>,
+    query: &str,
+    include: impl Fn(&Invoice) -> bool,
+) -> SearchResults {
+    if query.trim().is_empty() {
+        return SearchResults::default();
+    }
+    let mut customers: Vec<Customer> = customers.into_iter()
+        .filter(|c| customer_matches(c, query))
+        .cloned()
+        .collect();
+    customers.sort_by_key(|c| c.name.to_lowercase());
+    let mut hits: Vec<(&Invoice, String)> = invoices.into_iter()
+        .filter(|inv| include(inv))
+        .filter_map(|inv| invoice_match(inv, query).map(|matched_on| (inv, matched_on)))
+        .collect();
+    hits.sort_by(|a, b| b.0.date.cmp(&a.0.date).then_with(|| b.0.invoice_number.cmp(&a.0.invoice_number)));
+    let invoice_total = hits.len();
+    let invoices = hits.into_iter()
+        .take(MAX_INVOICE_RESULTS)
+        .map(|(invoice, matched_on)| InvoiceHit { invoice: invoice.clone(), matched_on })
+        .collect();
+    SearchResults { customers, invoices, invoice_total }
+}
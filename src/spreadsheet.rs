@@ -0,0 +1,288 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use chrono::NaiveDate;
+use zip::write::SimpleFileOptions;
+use crate::models::{Customer, Invoice};
+use crate::renderer::escape_html;
+
+// What to export
+#[derive(Clone, Copy, PartialEq)]
+pub enum ExportKind {
+    Customers,
+    Invoices, // One row per invoice
+    LineItems, // One row per invoice item
+}
+
+impl ExportKind {
+    pub const ALL: [ExportKind; 3] = [ExportKind::Customers, ExportKind::Invoices, ExportKind::LineItems];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportKind::Customers => "Customers",
+            ExportKind::Invoices => "Invoices",
+            ExportKind::LineItems => "Line Items",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Xlsx,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Xlsx => "xlsx",
+        }
+    }
+}
+
+// A typed cell so XLSX output keeps numbers and dates usable in formulas
+#[derive(Clone)]
+pub enum CellValue {
+    Text(String),
+    Number(f64),
+    Money(f64), // Shown with two decimal places
+    Date(NaiveDate),
+}
+
+impl CellValue {
+    fn to_csv(&self) -> String {
+        match self {
+            CellValue::Text(text) => text.clone(),
+            CellValue::Number(value) => value.to_string(),
+            CellValue::Money(value) => format!("{:.2}", value),
+            CellValue::Date(date) => date.format("%Y-%m-%d").to_string(),
+        }
+    }
+}
+
+fn text(value: &str) -> CellValue {
+    CellValue::Text(value.to_string())
+}
+
+pub struct Sheet {
+    pub name: String,
+    pub headers: Vec<&'static str>,
+    pub rows: Vec<Vec<CellValue>>,
+}
+
+pub fn customers_sheet(customers: &[Customer]) -> Sheet {
+    Sheet {
+        name: "Customers".to_string(),
+        headers: vec!["Code", "Name", "ABN", "Address", "Phone", "Contact Person", "Contact Phone", "Email"],
+        rows: customers.iter().map(|c| vec![
+            text(&c.code),
+            text(&c.name),
+            text(&c.abn),
+            text(&c.address),
+            text(&c.phone),
+            text(&c.contact_person),
+            text(&c.contact_phone),
+            text(&c.email),
+        ]).collect(),
+    }
+}
+
+pub fn invoices_sheet(invoices: &[Invoice]) -> Sheet {
+    Sheet {
+        name: "Invoices".to_string(),
+        headers: vec![
+            "Invoice Number", "Date", "Due Date", "Customer Code", "Customer Name",
+            "Subtotal", "GST", "Total", "Amount Paid", "Balance Due", "Status", "Notes",
+        ],
+        rows: invoices.iter().map(|inv| vec![
+            text(&inv.invoice_number),
+            CellValue::Date(inv.date.date_naive()),
+            CellValue::Date(inv.due_date.date_naive()),
+            text(&inv.customer.code),
+            text(&inv.customer.name),
+            CellValue::Money(inv.subtotal),
+            CellValue::Money(inv.gst),
+            CellValue::Money(inv.total),
+            CellValue::Money(inv.amount_paid()),
+            CellValue::Money(inv.balance_due()),
+            text(inv.status_label()),
+            text(&inv.notes),
+        ]).collect(),
+    }
+}
+
+pub fn line_items_sheet(invoices: &[Invoice]) -> Sheet {
+    let mut rows = Vec::new();
+    for inv in invoices {
+        for (idx, item) in inv.items.iter().enumerate() {
+            rows.push(vec![
+                text(&inv.invoice_number),
+                CellValue::Date(inv.date.date_naive()),
+                text(&inv.customer.code),
+                CellValue::Number((idx + 1) as f64),
+                text(&item.description),
+                CellValue::Number(item.quantity as f64),
+                CellValue::Money(item.rate),
+                CellValue::Money(item.amount),
+                CellValue::Money(item.gst),
+            ]);
+        }
+    }
+    Sheet {
+        name: "Line Items".to_string(),
+        headers: vec!["Invoice Number", "Date", "Customer Code", "Line", "Description", "Quantity", "Rate", "Amount", "GST"],
+        rows,
+    }
+}
+
+pub fn write_csv(sheet: &Sheet, filename: &str) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_path(filename)?;
+    writer.write_record(&sheet.headers)?;
+    for row in &sheet.rows {
+        writer.write_record(row.iter().map(CellValue::to_csv))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// Cell reference such as "A1" or "AB12" (zero-based column, one-based row)
+fn cell_ref(col: usize, row: usize) -> String {
+    let mut letters = String::new();
+    let mut n = col + 1;
+    while n > 0 {
+        letters.insert(0, (b'A' + ((n - 1) % 26) as u8) as char);
+        n = (n - 1) / 26;
+    }
+    format!("{}{}", letters, row)
+}
+
+// Spreadsheet serial number (days since 1899-12-30)
+fn date_serial(date: NaiveDate) -> i64 {
+    let epoch = NaiveDate::from_ymd_opt(1899, 12, 30).unwrap_or_default();
+    (date - epoch).num_days()
+}
+
+// Style indexes defined in STYLES_XML
+const STYLE_HEADER: usize = 1;
+const STYLE_DATE: usize = 2;
+const STYLE_MONEY: usize = 3;
+
+const STYLES_XML: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">
+<numFmts count="1"><numFmt numFmtId="164" formatCode="yyyy-mm-dd"/></numFmts>
+<fonts count="2"><font><sz val="11"/><name val="Calibri"/></font><font><b/><sz val="11"/><name val="Calibri"/></font></fonts>
+<fills count="2"><fill><patternFill patternType="none"/></fill><fill><patternFill patternType="gray125"/></fill></fills>
+<borders count="1"><border><left/><right/><top/><bottom/><diagonal/></border></borders>
+<cellStyleXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellStyleXfs>
+<cellXfs count="4">
+<xf numFmtId="0" fontId="0" fillId="0" borderId="0" xfId="0"/>
+<xf numFmtId="0" fontId="1" fillId="0" borderId="0" xfId="0" applyFont="1"/>
+<xf numFmtId="164" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/>
+<xf numFmtId="4" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/>
+</cellXfs>
+</styleSheet>
+"#;
+
+fn sheet_xml(sheet: &Sheet) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <worksheet xmlns=\"http://schemas.openxmlformats.org/spreadsheetml/2006/main\">\n\
+         <sheetViews><sheetView workbookViewId=\"0\"><pane ySplit=\"1\" topLeftCell=\"A2\" activePane=\"bottomLeft\" state=\"frozen\"/></sheetView></sheetViews>\n\
+         <sheetData>\n",
+    );
+    xml.push_str("<row r=\"1\">");
+    for (col, header) in sheet.headers.iter().enumerate() {
+        xml.push_str(&format!(
+            "<c r=\"{}\" s=\"{}\" t=\"inlineStr\"><is><t>{}</t></is></c>",
+            cell_ref(col, 1), STYLE_HEADER, escape_html(header)
+        ));
+    }
+    xml.push_str("</row>\n");
+    for (idx, row) in sheet.rows.iter().enumerate() {
+        let r = idx + 2;
+        xml.push_str(&format!("<row r=\"{}\">", r));
+        for (col, cell) in row.iter().enumerate() {
+            let reference = cell_ref(col, r);
+            xml.push_str(&match cell {
+                CellValue::Text(value) if value.is_empty() => continue,
+                CellValue::Text(value) => format!(
+                    "<c r=\"{}\" t=\"inlineStr\"><is><t xml:space=\"preserve\">{}</t></is></c>",
+                    reference, escape_html(value)
+                ),
+                CellValue::Number(value) => format!("<c r=\"{}\"><v>{}</v></c>", reference, value),
+                CellValue::Money(value) => format!("<c r=\"{}\" s=\"{}\"><v>{:.2}</v></c>", reference, STYLE_MONEY, value),
+                CellValue::Date(date) => format!("<c r=\"{}\" s=\"{}\"><v>{}</v></c>", reference, STYLE_DATE, date_serial(*date)),
+            });
+        }
+        xml.push_str("</row>\n");
+    }
+    xml.push_str("</sheetData>\n</worksheet>\n");
+    xml
+}
+
+// Writes a minimal Office Open XML workbook with one worksheet per sheet
+pub fn write_xlsx(sheets: &[Sheet], filename: &str) -> Result<(), Box<dyn Error>> {
+    let mut archive = zip::ZipWriter::new(File::create(filename)?);
+    let options = SimpleFileOptions::default();
+
+    let overrides: String = (1..=sheets.len()).map(|n| format!(
+        "<Override PartName=\"/xl/worksheets/sheet{}.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml\"/>",
+        n
+    )).collect();
+    archive.start_file("[Content_Types].xml", options)?;
+    write!(archive,
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">\
+         <Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\"/>\
+         <Default Extension=\"xml\" ContentType=\"application/xml\"/>\
+         <Override PartName=\"/xl/workbook.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml\"/>\
+         <Override PartName=\"/xl/styles.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml\"/>\
+         {}</Types>\n",
+        overrides
+    )?;
+
+    archive.start_file("_rels/.rels", options)?;
+    archive.write_all(
+        b"<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+          <Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">\
+          <Relationship Id=\"rId1\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument\" Target=\"xl/workbook.xml\"/>\
+          </Relationships>\n",
+    )?;
+
+    let sheet_entries: String = sheets.iter().enumerate().map(|(idx, sheet)| format!(
+        "<sheet name=\"{}\" sheetId=\"{}\" r:id=\"rId{}\"/>",
+        escape_html(&sheet.name), idx + 1, idx + 1
+    )).collect();
+    archive.start_file("xl/workbook.xml", options)?;
+    write!(archive,
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <workbook xmlns=\"http://schemas.openxmlformats.org/spreadsheetml/2006/main\" \
+         xmlns:r=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships\">\
+         <sheets>{}</sheets></workbook>\n",
+        sheet_entries
+    )?;
+
+    let sheet_rels: String = (1..=sheets.len()).map(|n| format!(
+        "<Relationship Id=\"rId{}\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet\" Target=\"worksheets/sheet{}.xml\"/>",
+        n, n
+    )).collect();
+    archive.start_file("xl/_rels/workbook.xml.rels", options)?;
+    write!(archive,
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">\
+         {}<Relationship Id=\"rId{}\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles\" Target=\"styles.xml\"/>\
+         </Relationships>\n",
+        sheet_rels, sheets.len() + 1
+    )?;
+
+    archive.start_file("xl/styles.xml", options)?;
+    archive.write_all(STYLES_XML.as_bytes())?;
+
+    for (idx, sheet) in sheets.iter().enumerate() {
+        archive.start_file(format!("xl/worksheets/sheet{}.xml", idx + 1), options)?;
+        archive.write_all(sheet_xml(sheet).as_bytes())?;
+    }
+    archive.finish()?;
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use chrono::{Duration, NaiveDate};
+use crate::models::{Customer, Invoice, PaymentKind};
+
+// One row of a customer account statement
+#[derive(Clone)]
+pub struct StatementLine {
+    pub date: NaiveDate,
+    pub description: String,
+    pub debit: f64, // Invoices raised
+    pub credit: f64, // Payments and credits received
+    pub balance: f64, // Running balance after this line
+}
+
+// Outstanding balance at the end of the period, by days since the invoice date
+#[derive(Clone, Default)]
+pub struct AgeingSummary {
+    pub current: f64, // Less than 30 days
+    pub days_30: f64, // 30-59 days
+    pub days_60: f64, // 60-89 days
+    pub days_90_plus: f64, // 90 days and over
+}
+
+#[derive(Clone)]
+pub struct Statement {
+    pub customer: Customer,
+    pub from_date: NaiveDate,
+    pub to_date: NaiveDate,
+    pub opening_balance: f64,
+    pub lines: Vec<StatementLine>,
+    pub closing_balance: f64,
+    pub ageing: AgeingSummary,
+}
+
+// Builds a statement for `customer` covering `from_date` to `to_date` (inclusive).
+// `invoices` should contain all of the customer's invoices, not just those in the period,
+// so the opening balance can be worked out.
+pub fn build_statement(customer: &Customer, invoices: &[Invoice], from_date: NaiveDate, to_date: NaiveDate) -> Statement {
+    let day_before = from_date - Duration::days(1);
+    let opening_balance: f64 = invoices.iter().map(|inv| inv.balance_as_at(day_before)).sum();
+
+    // Collect entries as (date, sort order, description, debit, credit); invoices sort before payments on the same day
+    let mut entries: Vec<(NaiveDate, u8, String, f64, f64)> = Vec::new();
+    let in_period = |date: NaiveDate| date >= from_date && date <= to_date;
+    for invoice in invoices {
+        let issued = invoice.date.date_naive();
+        if in_period(issued) {
+            entries.push((issued, 0, format!("Invoice #{}", invoice.invoice_number), invoice.total, 0.0));
+        }
+        for payment in &invoice.payments {
+            let paid_on = payment.date.date_naive();
+            if in_period(paid_on) {
+                let mut description = format!("{} - Invoice #{}", payment.kind.label(), invoice.invoice_number);
+                if !payment.reference.is_empty() {
+                    description.push_str(&format!(" ({})", payment.reference));
+                }
+                let order = if payment.kind == PaymentKind::Payment { 1 } else { 2 };
+                entries.push((paid_on, order, description, 0.0, payment.amount));
+            }
+        }
+        // Invoices marked paid without payment records are treated as settled on the due date
+        let unrecorded = if invoice.paid { (invoice.total - invoice.amount_paid()).max(0.0) } else { 0.0 };
+        let due = invoice.due_date.date_naive();
+        if unrecorded > 0.0 && in_period(due) {
+            entries.push((due, 1, format!("Payment - Invoice #{}", invoice.invoice_number), 0.0, unrecorded));
+        }
+    }
+    entries.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(&b.1)).then_with(|| a.2.cmp(&b.2)));
+
+    let mut balance = opening_balance;
+    let lines: Vec<StatementLine> = entries.into_iter().map(|(date, _, description, debit, credit)| {
+        balance += debit - credit;
+        StatementLine { date, description, debit, credit, balance }
+    }).collect();
+
+    let mut ageing = AgeingSummary::default();
+    for invoice in invoices {
+        let outstanding = invoice.balance_as_at(to_date);
+        if outstanding <= 0.0 {
+            continue;
+        }
+        let age = (to_date - invoice.date.date_naive()).num_days();
+        match age {
+            i64::MIN..=29 => ageing.current += outstanding,
+            30..=59 => ageing.days_30 += outstanding,
+            60..=89 => ageing.days_60 += outstanding,
+            _ => ageing.days_90_plus += outstanding,
+        }
+    }
+
+    Statement {
+        customer: customer.clone(),
+        from_date,
+        to_date,
+        opening_balance,
+        lines,
+        closing_balance: balance,
+        ageing,
+    }
+}
@@ -1,3 +1,7 @@
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
 // Keep wrap_text as it might be useful, though not currently used by GUI
 pub fn wrap_text(text: &str, max_chars: usize) -> Vec<String> {
     let words: Vec<&str> = text.split_whitespace().collect();
@@ -44,3 +48,43 @@ pub fn wrap_text(text: &str, max_chars: usize) -> Vec<String> {
 // read_optional_customer_code
 // read_date
 
+
+// Opens a file with the system's default application
+pub fn open_file(path: &Path) -> io::Result<()> {
+    #[cfg(target_os = "windows")]
+    Command::new("cmd").args(["/C", "start", ""]).arg(path).spawn()?;
+    #[cfg(target_os = "macos")]
+    Command::new("open").arg(path).spawn()?;
+    #[cfg(all(unix, not(target_os = "macos")))]
+    Command::new("xdg-open").arg(path).spawn()?;
+    Ok(())
+}
+
+// Sends a file to the default printer
+pub fn print_file(path: &Path) -> io::Result<()> {
+    #[cfg(target_os = "windows")]
+    {
+        // -Command takes one script, so the path goes in as a single-quoted literal
+        let path = path.to_str().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("Can't print {}: the path isn't valid Unicode.", path.display()))
+        })?;
+        let script = format!("Start-Process -Verb Print -FilePath '{}'", path.replace('\'', "''"));
+        Command::new("powershell")
+            .args(["-NoProfile", "-Command", &script])
+            .spawn()?;
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        // lp returns once the job is queued, so waiting tells us whether it was accepted
+        let output = Command::new("lp").arg(path).output()?;
+        if !output.status.success() {
+            let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            return Err(io::Error::other(if message.is_empty() {
+                format!("lp failed ({})", output.status)
+            } else {
+                message
+            }));
+        }
+    }
+    Ok(())
+}